@@ -0,0 +1,94 @@
+// times the DRM fdinfo scan of all processes, sequential vs on threads, e.g.:
+//
+//   sudo cargo run --release --example fdinfo_scan -- 20 1,2,4,8
+//
+// runs each scan the given number of times and prints the median, the
+// processes are split in chunks like in DrmClients::scan_procs()
+use std::env;
+use std::thread;
+use std::time;
+
+use anyhow::Result;
+
+use qmlib::drm_fdinfo::DrmFdinfoCache;
+use qmlib::proc_info::ProcInfo;
+
+
+// no cached fdinfos, as on the first scan of each process
+fn nr_fdinfos(proc: &ProcInfo) -> usize
+{
+    proc.drm_fdinfos(&mut DrmFdinfoCache::new())
+        .map_or(0, |fdis| fdis.len())
+}
+
+fn scan(procs: &[ProcInfo], nr_threads: usize) -> usize
+{
+    if nr_threads <= 1 {
+        return procs.iter().map(nr_fdinfos).sum();
+    }
+
+    let chunk_sz = procs.len().div_ceil(nr_threads);
+    thread::scope(|s| {
+        let handles: Vec<_> = procs.chunks(chunk_sz)
+            .map(|chk| s.spawn(move || {
+                chk.iter().map(nr_fdinfos).sum::<usize>()
+            }))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+}
+
+fn median_ms(mut times: Vec<f64>) -> f64
+{
+    times.sort_by(|a, b| a.total_cmp(b));
+
+    times[times.len() / 2]
+}
+
+fn main() -> Result<()>
+{
+    let args: Vec<String> = env::args().collect();
+    let nr_iters: usize = args.get(1).map_or(Ok(20), |v| v.parse())?;
+    let threads: Vec<usize> = match args.get(2) {
+        Some(v) => v.split(',').map(|t| t.parse()).collect::<Result<_, _>>()?,
+        None => vec![1, 2, 4],
+    };
+
+    let procs: Vec<ProcInfo> = ProcInfo::iter_proc_pids()?
+        .filter_map(|p| p.ok())
+        .collect();
+    let nr_fds: usize = procs.iter()
+        .filter_map(|p| p.proc_dir.join("fd").read_dir().ok())
+        .map(|rd| rd.count())
+        .sum();
+    println!("{} processes, {} fds, {} CPUs", procs.len(), nr_fds,
+        thread::available_parallelism().map_or(1, |n| n.get()));
+
+    // cost of just spawning and joining the threads
+    for nr in threads.iter().filter(|nr| **nr > 1) {
+        let times: Vec<f64> = (0..nr_iters).map(|_| {
+            let start = time::Instant::now();
+            thread::scope(|s| {
+                for _ in 0..*nr {
+                    s.spawn(|| {});
+                }
+            });
+            start.elapsed().as_secs_f64() * 1000.0
+        }).collect();
+        println!("spawn {} threads: {:.3} ms", nr, median_ms(times));
+    }
+
+    for nr in threads.iter() {
+        let mut nr_clients = 0;
+        let times: Vec<f64> = (0..nr_iters).map(|_| {
+            let start = time::Instant::now();
+            nr_clients = scan(&procs, *nr);
+            start.elapsed().as_secs_f64() * 1000.0
+        }).collect();
+        let med = median_ms(times);
+        println!("{} thread(s): {:.2} ms ({:.2} us per process, {} DRM fds)",
+            nr, med, med * 1000.0 / procs.len() as f64, nr_clients);
+    }
+
+    Ok(())
+}
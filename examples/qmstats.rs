@@ -0,0 +1,62 @@
+// minimal example of using qmlib to collect DRM devices stats, e.g.:
+//
+//   cargo run --example qmstats -- 1000 5
+//
+// prints memory, engines usage, freqs and power of every device
+// each interval (ms) for the given number of iterations
+use std::env;
+use std::thread;
+use std::time;
+
+use anyhow::Result;
+
+use qmlib::drm_devices::DrmDevices;
+use qmlib::drm_drivers::DrmDriverOptions;
+
+
+fn print_stats(qmds: &DrmDevices)
+{
+    for di in qmds.infos() {
+        println!("{} [{}] {} {}", di.pci_dev, di.drv_name,
+            di.vendor, di.device);
+
+        let mi = &di.mem_info;
+        println!("  smem: {}/{} bytes", mi.smem_used, mi.smem_total);
+        if di.dev_type.is_discrete() {
+            println!("  vram: {}/{} bytes", mi.vram_used, mi.vram_total);
+        }
+
+        for en in di.engines().iter() {
+            println!("  {}: {:.1}%", en, di.eng_utilization(en));
+        }
+        for (fl, fqs) in di.freq_limits.iter().zip(di.freqs.iter()) {
+            println!("  {} freq: {} MHz", fl.name, fqs.act_freq);
+        }
+        println!("  power: {:.2} W", di.power.gpu_cur_power);
+    }
+}
+
+fn main() -> Result<()>
+{
+    let args: Vec<String> = env::args().collect();
+    let ms_interval: u64 = args.get(1).map_or(Ok(1000), |v| v.parse())?;
+    let nr_iters: u64 = args.get(2).map_or(Ok(1), |v| v.parse())?;
+
+    let mut qmds = DrmDevices::find_devices(&DrmDriverOptions::new())?;
+    if qmds.is_empty() {
+        println!("No DRM devices found");
+        return Ok(());
+    }
+    // clients info is needed for engines usage
+    qmds.set_clients_pid_tree("1", true)?;
+
+    let interval = time::Duration::from_millis(ms_interval);
+    qmds.refresh()?;
+    for _ in 0..nr_iters {
+        thread::sleep(interval);
+        qmds.refresh()?;
+        print_stats(&qmds);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use anyhow::{bail, Context, Result};
+
+use crate::app_data::AppDataDeviceState;
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum AlertMetric
+{
+    Smem,               // ratio of used system memory
+    Vram,               // ratio of used device memory
+    GpuPower,           // in W
+    PkgPower,           // in W
+    Temp,               // hottest sensor in C
+    Engine(String),     // usage in %
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AlertOp
+{
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert
+{
+    pub rule: String,
+    pub fail_on: bool,      // failure exit code if triggered
+    metric: AlertMetric,
+    op: AlertOp,
+    value: f64,
+}
+
+impl Alert
+{
+    fn metric_value(&self, dst: &AppDataDeviceState) -> Option<f64>
+    {
+        let dstats = &dst.dev_stats;
+
+        match &self.metric {
+            AlertMetric::Smem | AlertMetric::Vram => {
+                let mi = dstats.mem_info.back()?;
+                let (used, total) = if self.metric == AlertMetric::Smem {
+                    (mi.smem_used, mi.smem_total)
+                } else {
+                    (mi.vram_used, mi.vram_total)
+                };
+                if total == 0 {
+                    return None;
+                }
+                Some(used as f64 / total as f64)
+            },
+            AlertMetric::GpuPower => {
+                Some(dstats.power.back()?.gpu_cur_power)
+            },
+            AlertMetric::PkgPower => {
+                Some(dstats.power.back()?.pkg_cur_power)
+            },
+            AlertMetric::Temp => {
+                let temp = dst.temperature.max();
+                if temp > 0.0 { Some(temp) } else { None }
+            },
+            AlertMetric::Engine(en) => {
+                dstats.eng_usage.get(en)?.back().copied()
+            },
+        }
+    }
+
+    // returns current value if the alert is triggered
+    pub fn check(&self, dst: &AppDataDeviceState) -> Option<f64>
+    {
+        let val = self.metric_value(dst)?;
+        let triggered = match self.op {
+            AlertOp::Greater => val > self.value,
+            AlertOp::GreaterEq => val >= self.value,
+            AlertOp::Less => val < self.value,
+            AlertOp::LessEq => val <= self.value,
+        };
+
+        if triggered { Some(val) } else { None }
+    }
+
+    pub fn from(rule: &str) -> Result<Alert>
+    {
+        // longer operators first
+        let ops = [
+            (">=", AlertOp::GreaterEq),
+            ("<=", AlertOp::LessEq),
+            (">", AlertOp::Greater),
+            ("<", AlertOp::Less),
+        ];
+
+        let mut parsed = None;
+        for (opstr, op) in ops {
+            if let Some((m, v)) = rule.split_once(opstr) {
+                parsed = Some((m.trim(), op, v.trim()));
+                break;
+            }
+        }
+        if parsed.is_none() {
+            bail!("No comparison operator in alert rule {:?}", rule);
+        }
+        let (mstr, op, vstr) = parsed.unwrap();
+
+        let metric = match mstr {
+            "smem" => AlertMetric::Smem,
+            "vram" => AlertMetric::Vram,
+            "power" => AlertMetric::GpuPower,
+            "pkg-power" => AlertMetric::PkgPower,
+            "temp" => AlertMetric::Temp,
+            _ => {
+                if let Some(en) = mstr.strip_prefix("eng-") {
+                    AlertMetric::Engine(en.to_string())
+                } else {
+                    bail!("Unknown metric {:?} in alert rule {:?}",
+                        mstr, rule);
+                }
+            },
+        };
+        let value: f64 = vstr.parse()
+            .with_context(|| format!("Invalid value in alert rule {:?}", rule))?;
+
+        Ok(Alert {
+            rule: rule.to_string(),
+            fail_on: false,
+            metric,
+            op,
+            value,
+        })
+    }
+}
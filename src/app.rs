@@ -1,5 +1,8 @@
 use core::fmt::Debug;
 use std::cell::RefCell;
+use std::cmp::min;
+use std::env;
+use std::io::{self, Write};
 use std::rc::Rc;
 use std::time;
 
@@ -7,18 +10,23 @@ use anyhow::Result;
 
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
 use ratatui::{
+    buffer::Buffer,
     layout::{Alignment, Constraint, Layout, Rect},
-    style::{palette::tailwind, Style, Stylize},
+    style::{palette::tailwind, Color, Modifier, Style, Stylize},
     text::{Span, Line},
-    widgets::{Block, Borders, BorderType, Gauge},
+    symbols,
+    widgets::{Axis, Block, Borders, BorderType, Clear, Dataset, Gauge,
+        GraphType, Paragraph},
     DefaultTerminal, Frame,
 };
 
-use crate::app_data::AppData;
+use crate::app_data::{AppData, AppDataClientStats};
 
 mod main_screen;
 mod drm_client_screen;
+mod log_screen;
 use main_screen::MainScreen;
+use log_screen::LogScreen;
 
 
 #[derive(Debug)]
@@ -38,6 +46,9 @@ pub trait Screen
     fn handle_key_event(&mut self, key_event: KeyEvent) -> Option<ScreenAction>;
 
     fn status_bar_text(&mut self) -> Vec<Span>;
+
+    // (keys, description) for the help overlay
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>;
 }
 
 impl Debug for dyn Screen
@@ -88,25 +99,43 @@ pub struct App
 {
     model: Rc<RefCell<dyn AppData>>,
     screens: AppScreens,
+    light_bg: bool,
+    show_help: bool,
     exit: bool,
 }
 
 impl App
 {
-    fn short_mem_string(val: u64) -> String
+    fn si_units(model: &dyn AppData) -> bool
+    {
+        model.args().units.as_deref() == Some("si")
+    }
+
+    // DRM clients memory shown as used (total mapped) instead of resident
+    fn client_mem_used(model: &dyn AppData) -> bool
+    {
+        model.args().client_mem.as_deref() == Some("used")
+    }
+
+    fn short_mem_string(val: u64, si_units: bool) -> String
     {
         let mut nval = val as f64;
         let mut unit = "";
 
-        if nval >= 1024.0 * 1024.0 * 1024.0 {
-            nval /= 1024.0 * 1024.0 * 1024.0;
-            unit = "G";
-        } else if nval >= 1024.0 * 1024.0 {
-            nval /= 1024.0 * 1024.0;
-            unit = "M";
-        } else if nval >= 1024.0 {
-            nval /= 1024.0;
-            unit = "K";
+        let (base, units) = if si_units {
+            (1000.0, ["kB", "MB", "GB"])
+        } else {
+            (1024.0, ["K", "M", "G"])
+        };
+        if nval >= base * base * base {
+            nval /= base * base * base;
+            unit = units[2];
+        } else if nval >= base * base {
+            nval /= base * base;
+            unit = units[1];
+        } else if nval >= base {
+            nval /= base;
+            unit = units[0];
         }
 
         let mut vstr = format!("{:.0}", nval.round());
@@ -115,6 +144,197 @@ impl App
         vstr
     }
 
+    fn mem_rate_string(rate: f64, si_units: bool) -> String
+    {
+        let sign = if rate > 0.0 { "+" } else if rate < 0.0 { "-" } else { "" };
+
+        format!("{}{}/s", sign,
+            App::short_mem_string(rate.abs().round() as u64, si_units))
+    }
+
+    fn short_age_string(ms: u128) -> String
+    {
+        let secs = ms / 1000;
+
+        if secs >= 24 * 3600 {
+            format!("{}d", secs / (24 * 3600))
+        } else if secs >= 3600 {
+            format!("{}h", secs / 3600)
+        } else if secs >= 60 {
+            format!("{}m", secs / 60)
+        } else {
+            format!("{}s", secs)
+        }
+    }
+
+    fn min_max_avg(vals: impl Iterator<Item = f64>) -> (f64, f64, f64)
+    {
+        let mut minv = f64::MAX;
+        let mut maxv = f64::MIN;
+        let mut sum = 0.0;
+        let mut nr = 0;
+
+        for v in vals {
+            minv = f64::min(minv, v);
+            maxv = f64::max(maxv, v);
+            sum += v;
+            nr += 1;
+        }
+
+        if nr == 0 {
+            return (0.0, 0.0, 0.0);
+        }
+
+        (minv, maxv, sum / nr as f64)
+    }
+
+    fn local_time(ms: u128) -> Option<libc::tm>
+    {
+        let secs = (ms / 1000) as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+            return None;
+        }
+
+        Some(tm)
+    }
+
+    fn wall_clock_string(ms: u128) -> String
+    {
+        if let Some(tm) = App::local_time(ms) {
+            format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
+        } else {
+            String::from("??:??:??")
+        }
+    }
+
+    // current local time to be used in file names (YYYYmmdd-HHMMSS)
+    fn file_time_string() -> String
+    {
+        let now_ms = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+
+        if let Some(tm) = App::local_time(now_ms) {
+            format!("{:04}{:02}{:02}-{:02}{:02}{:02}",
+                tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday,
+                tm.tm_hour, tm.tm_min, tm.tm_sec)
+        } else {
+            format!("{}", now_ms / 1000)
+        }
+    }
+
+    // x-axis for charts in seconds since start or in wall-clock time
+    fn time_axis<'a>(model: &dyn AppData, x_vals: &Vec<f64>) -> Axis<'a>
+    {
+        let wall_ms = model.start_wall_ms();
+        let use_wall = model.args().wall_clock && wall_ms > 0;
+        let ts_str = |secs: f64| -> String {
+            if use_wall {
+                App::wall_clock_string(wall_ms + (secs * 1000.0) as u128)
+            } else {
+                format!("{:.1}", secs)
+            }
+        };
+
+        let x_bounds: [f64; 2];
+        let mut x_labels: Vec<Span>;
+        if x_vals.len() == 1 {
+            let int_secs = model.args().ms_interval as f64 / 1000.0;
+            x_bounds = [x_vals[0], x_vals[0] + int_secs];
+            x_labels = vec![
+                Span::raw(ts_str(x_bounds[0])),
+                Span::raw(ts_str(x_bounds[1])),
+            ];
+        } else {
+            let xvlen = x_vals.len();
+            x_bounds = [x_vals[0], x_vals[xvlen - 1]];
+            x_labels = vec![
+                Span::raw(ts_str(x_vals[0])),
+                Span::raw(ts_str(x_vals[xvlen / 2])),
+            ];
+            if xvlen >= 3 {
+                x_labels.push(Span::raw(ts_str(x_vals[xvlen - 1])));
+            }
+        }
+
+        Axis::default()
+            .title(if use_wall { "Time (HH:MM:SS)" } else { "Time (s)" })
+            .style(Style::new().white())
+            .bounds(x_bounds)
+            .labels(x_labels)
+    }
+
+    // engines not hidden by the user, keeping the given order (unless
+    // asked to sort them alphabetically)
+    fn shown_engines<'a>(model: &dyn AppData,
+        eng_names: impl Iterator<Item = &'a String>) -> Vec<&'a String>
+    {
+        let hidden: Vec<String> = match &model.args().hide_engines {
+            Some(he) => he.split(',').map(|en| en.trim().to_lowercase()).collect(),
+            None => Vec::new(),
+        };
+
+        let mut engs: Vec<&String> = eng_names
+            .filter(|en| !hidden.contains(&en.to_lowercase())).collect();
+        if model.args().engines_order.as_deref() == Some("alpha") {
+            engs.sort();
+        }
+
+        engs
+    }
+
+    // vertical lines at the timeline markers, spanning the y-axis bounds
+    fn marker_lines(model: &dyn AppData,
+        y_bounds: [f64; 2]) -> Vec<(String, [(f64, f64); 2])>
+    {
+        model.markers().iter()
+            .map(|(ts, name)| {
+                let x = *ts as f64 / 1000.0;
+                (format!("| {}", name), [(x, y_bounds[0]), (x, y_bounds[1])])
+            })
+            .collect()
+    }
+
+    fn marker_datasets<'a>(
+        mkrs: &'a Vec<(String, [(f64, f64); 2])>) -> Vec<Dataset<'a>>
+    {
+        mkrs.iter()
+            .map(|(name, pts)| Dataset::default()
+                .name(name.as_str())
+                .marker(symbols::Marker::Braille)
+                .style(Style::new().white())
+                .graph_type(GraphType::Line)
+                .data(pts))
+            .collect()
+    }
+
+    // decimals shown for power, frequencies and percentages
+    fn precision(model: &dyn AppData) -> usize
+    {
+        model.args().precision.unwrap_or(1) as usize
+    }
+
+    // engines usage label, or a placeholder while collecting its first delta
+    fn eng_usage_string(eut: f64, is_ready: bool, prec: usize) -> String
+    {
+        if is_ready {
+            format!("{:.*}%", prec, eut)
+        } else {
+            String::from("...")
+        }
+    }
+
+    // clients only holding memory are dimmed vs. ones using engines
+    fn client_style(cli: &AppDataClientStats) -> Style
+    {
+        if cli.engine_active {
+            Style::new().white()
+        } else {
+            Style::new().dark_gray()
+        }
+    }
+
     fn gauge_colored_from(label: Span, ratio: f64) -> Gauge
     {
         let rt = if ratio > 1.0 { 1.0 } else { ratio };
@@ -133,6 +353,25 @@ impl App
             .ratio(rt)
     }
 
+    // higher is better, e.g. achieved/requested clock
+    fn gauge_efficiency_from(label: Span, ratio: f64) -> Gauge
+    {
+        let rt = if ratio > 1.0 { 1.0 } else { ratio };
+        let gstyle = if rt >= 0.9 {
+            tailwind::GREEN.c500
+        } else if rt >= 0.7 {
+            tailwind::ORANGE.c500
+        } else {
+            tailwind::RED.c500
+        };
+
+        Gauge::default()
+            .label(label)
+            .gauge_style(gstyle)
+            .use_unicode(true)
+            .ratio(rt)
+    }
+
     fn draw(&mut self, frame: &mut Frame)
     {
         // render title/menu & status bar, clean main area background
@@ -156,10 +395,16 @@ impl App
         let st_len = self.screens.len();
         let scr = self.screens.current().unwrap();  // always >= 1 screens
 
+        let in_log = scr.name() == LogScreen::NAME;
+
         let mut st_bar_text = scr.status_bar_text();
+        if !in_log {
+            st_bar_text.push(" (L) Log".white().bold());
+        }
         if st_len > 1 {
             st_bar_text.push(" (Esc) Back".white().bold());
         }
+        st_bar_text.push(" (?) Help".white().bold());
         st_bar_text.push(" (Q) Quit ".white().bold());
 
         let instr = Line::from(st_bar_text).style(Style::new().on_black());
@@ -178,13 +423,125 @@ impl App
 
         // render current screen content into tab and main areas
         scr.draw(frame, tab_area, main_area);
+
+        if self.show_help {
+            let mut keys = scr.key_bindings();
+            if !in_log {
+                keys.push(("L", "Open log screen"));
+            }
+            if st_len > 1 {
+                keys.push(("Esc", "Back to previous screen"));
+            }
+            keys.push(("?", "Show this help"));
+            keys.push(("Q", "Quit"));
+            App::draw_help(frame, scr.name(), &keys);
+        }
+
+        if self.light_bg {
+            App::remap_light_colors(frame.buffer_mut());
+        }
+    }
+
+    // centered list of keys over the dimmed screen, closed by any key
+    fn draw_help(frame: &mut Frame, scr_name: &str, keys: &[(&str, &str)])
+    {
+        let area = frame.area();
+        frame.buffer_mut().set_style(area,
+            Style::new().add_modifier(Modifier::DIM));
+
+        let keys_w = keys.iter()
+            .map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+        let desc_w = keys.iter()
+            .map(|(_, d)| d.chars().count()).max().unwrap_or(0);
+        let title = format!(" {} Keys ", scr_name);
+
+        let width = (keys_w + desc_w + 7)
+            .max(title.chars().count() + 4) as u16;
+        let height = keys.len() as u16 + 4;
+        let help_area = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width: width.min(area.width),
+            height: height.min(area.height),
+        };
+
+        let mut lines: Vec<Line> = keys.iter()
+            .map(|(k, d)| Line::from(vec![
+                format!(" {:>w$}  ", k, w = keys_w).light_yellow().bold(),
+                d.to_string().white(),
+            ]))
+            .collect();
+        lines.push(Line::from(""));
+        lines.push(Line::from("Press any key to close".gray().italic())
+            .alignment(Alignment::Center));
+
+        frame.render_widget(Clear, help_area);
+        frame.render_widget(Paragraph::new(lines)
+            .style(Style::new().on_black())
+            .block(Block::bordered()
+                .border_type(BorderType::Thick)
+                .border_style(Style::new().cyan().bold().on_black())
+                .title_top(Line::from(title.light_blue().bold())
+                    .alignment(Alignment::Center))),
+            help_area);
+    }
+
+    // COLORFGBG is "fg;bg" (or "fg;default;bg"), with light bg colors
+    // being 7 (light gray) and 9-15 (bright ones)
+    fn detect_light_bg() -> bool
+    {
+        let fgbg = match env::var("COLORFGBG") {
+            Ok(fgbg) => fgbg,
+            Err(_) => return false,
+        };
+        let bg: Option<u8> = fgbg.rsplit(';').next()
+            .and_then(|bg| bg.trim().parse().ok());
+
+        bg.is_some_and(|bg| bg == 7 || (9..=15).contains(&bg))
+    }
+
+    // all screens are drawn for dark terminals, so swap the colors that
+    // would be unreadable on a light one after drawing
+    fn remap_light_colors(buf: &mut Buffer)
+    {
+        for cell in buf.content.iter_mut() {
+            cell.fg = match cell.fg {
+                Color::White => Color::Black,
+                Color::Gray => Color::DarkGray,
+                Color::LightYellow => Color::Yellow,
+                Color::LightBlue => Color::Blue,
+                Color::Cyan => Color::Blue,
+                fg => fg,
+            };
+            cell.bg = match cell.bg {
+                Color::Black => Color::White,
+                Color::DarkGray => Color::Gray,
+                bg => bg,
+            };
+        }
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) {
+        // any key closes the help overlay without doing anything else
+        if self.show_help {
+            self.show_help = false;
+            return;
+        }
+
         match key_event.code {
+            KeyCode::Char('?') => {
+                self.show_help = true;
+            },
             KeyCode::Char('q') | KeyCode::Char('Q') => {
                 self.exit = true;
             },
+            KeyCode::Char('l') | KeyCode::Char('L') => {
+                let in_log = self.screens.current()
+                    .is_some_and(|scr| scr.name() == LogScreen::NAME);
+                if !in_log {
+                    self.screens.enter(LogScreen::new());
+                }
+            },
             KeyCode::Esc => {
                 self.screens.exit();
                 if self.screens.current().is_none() {
@@ -220,19 +577,17 @@ impl App
         Ok(())
     }
 
-    fn do_run(&mut self, terminal: &mut DefaultTerminal) -> Result<()>
+    fn main_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()>
     {
-        let mut model = self.model.borrow_mut();
         // get command line options for the main loop
-        let ival = time::Duration::from_millis(model.args().ms_interval);
-        let max_iterations = model.args().nr_iterations;
-
-        // start saving to JSON file (if asked by the user)
-        model.start_json_file()?;
-        drop(model);
+        let max_iterations = self.model.borrow().args().nr_iterations;
+        let max_duration = self.model.borrow().args().max_duration
+            .map(time::Duration::from_secs);
 
-        let mut last_check = time::Instant::now();
+        let start_time = time::Instant::now();
+        let mut last_check = start_time;
         let mut timer = time::Duration::ZERO;
+        let mut sample_timer = time::Duration::ZERO;
         let mut nr = 0;
 
         while !self.exit {
@@ -240,9 +595,16 @@ impl App
                 self.exit = true;
                 break;
             }
+            if max_duration.is_some_and(|md| start_time.elapsed() >= md) {
+                self.exit = true;
+                break;
+            }
 
             let elapsed = last_check.elapsed();
             last_check = time::Instant::now();
+            let sample_ival = self.model.borrow().sample_interval_ms()
+                .map(time::Duration::from_millis);
+            let mut is_sample = false;
 
             if elapsed >= timer {
                 let mut model = self.model.borrow_mut();
@@ -252,24 +614,61 @@ impl App
                     self.exit = true;
                     break;
                 }
-                timer = ival;
+                // interval can be changed at runtime
+                timer = time::Duration::from_millis(model.interval_ms());
+                sample_timer = sample_ival.unwrap_or(timer);
                 nr += 1;
 
                 // write new state to JSON file (if needed)
                 model.update_json_file()?;
 
+                // ring the bell on newly triggered alerts (if asked)
+                if model.args().alert_bell && !model.new_alerts().is_empty() {
+                    print!("\x07");
+                    io::stdout().flush()?;
+                }
+
                 drop(model);
             } else {
                 timer -= elapsed;
+                // fast samples in between refreshes (if asked)
+                if let Some(si) = sample_ival {
+                    if elapsed >= sample_timer {
+                        self.model.borrow_mut().sample()?;
+                        sample_timer = si;
+                        is_sample = true;
+                    } else {
+                        sample_timer -= elapsed;
+                    }
+                }
             }
 
-            terminal.draw(|frame| self.draw(frame))?;
-            self.handle_events(timer)?;
+            // nothing new to show after a fast sample
+            if !is_sample {
+                terminal.draw(|frame| self.draw(frame))?;
+            }
+            self.handle_events(min(timer, sample_timer))?;
         }
 
         Ok(())
     }
 
+    fn do_run(&mut self, terminal: &mut DefaultTerminal) -> Result<()>
+    {
+        // start saving to JSON file (if asked by the user)
+        self.model.borrow_mut().start_json_file()?;
+
+        if let Err(err) = self.main_loop(terminal) {
+            return Err(self.model.borrow_mut().abort_json_file(err));
+        }
+
+        // finish JSON file and save summary of the whole run (if requested)
+        self.model.borrow_mut().finish_json_file()?;
+        self.model.borrow_mut().save_summary_json()?;
+
+        Ok(())
+    }
+
     pub fn run(&mut self) -> Result<()>
     {
         let main_scr = MainScreen::new(self.model.clone());
@@ -284,10 +683,119 @@ impl App
 
     pub fn from(data: Rc<RefCell<dyn AppData>>) -> App
     {
+        let light_bg = data.borrow().args().theme_detect &&
+            App::detect_light_bg();
+
         App {
             model: data,
             screens: AppScreens::new(),
+            light_bg,
+            show_help: false,
             exit: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use ratatui::{backend::TestBackend, Terminal};
+
+    use super::*;
+    use crate::app_data::AppDataJson;
+    use drm_client_screen::{DrmClientScreen, DrmClientSelected};
+
+    // compact replay file with one xe device and one active DRM client
+    const REPLAY_JSON: &str = r#"{
+"args": {"ms_interval": 1500, "all_clients": false, "nr_iterations": -1,
+    "no_tui": false},
+"states": [{
+    "start_wall_ms": 1760000000000,
+    "timestamps": [1500, 3000],
+    "markers": [[2000, "loading"]],
+    "devs_state": [{
+        "pci_dev": "0000:03:00.0",
+        "vdr_dev_rev": "Intel Foo (rev 01)",
+        "dev_type": "Discrete",
+        "drv_name": "xe",
+        "dev_nodes": "card0, renderD128",
+        "eng_names": ["ccs", "rcs"],
+        "freq_limits": [{"name": "gt0", "minimum": 300, "efficient": 300,
+            "maximum": 2400}],
+        "dev_stats": {
+            "freqs": [
+                [{"min_freq": 300, "cur_freq": 1000, "act_freq": 900,
+                    "max_freq": 2400, "throttle_reasons": {"pl1": true,
+                    "pl2": false, "pl4": false, "prochot": false,
+                    "ratl": false, "thermal": false, "vr_tdc": false,
+                    "vr_thermalert": false, "status": false}}],
+                [{"min_freq": 300, "cur_freq": 1200, "act_freq": 1100,
+                    "max_freq": 2400, "throttle_reasons": {"pl1": false,
+                    "pl2": false, "pl4": false, "prochot": false,
+                    "ratl": false, "thermal": false, "vr_tdc": false,
+                    "vr_thermalert": false, "status": false}}]],
+            "power": [{"gpu_cur_power": 10.0, "pkg_cur_power": 20.0},
+                {"gpu_cur_power": 12.0, "pkg_cur_power": 22.0}],
+            "mem_info": [
+                {"smem_total": 17179869184, "smem_used": 1073741824,
+                    "vram_total": 8589934592, "vram_used": 536870912},
+                {"smem_total": 17179869184, "smem_used": 1073741824,
+                    "vram_total": 8589934592, "vram_used": 805306368}],
+            "eng_usage": {"ccs": [0.0, 5.0], "rcs": [20.0, 45.5]}
+        },
+        "clis_stats": [{
+            "drm_minor": 128,
+            "client_id": 7,
+            "pid": 4242,
+            "comm": "vkcube",
+            "cmdline": "vkcube --present-mode 0",
+            "cpu_usage": [12.5, 15.0],
+            "eng_usage": {"ccs": [0.0, 5.0], "rcs": [20.0, 45.5]},
+            "mem_info": [
+                {"smem_used": 4096, "smem_rss": 4096,
+                    "vram_used": 268435456, "vram_rss": 268435456},
+                {"smem_used": 8192, "smem_rss": 8192,
+                    "vram_used": 268435456, "vram_rss": 268435456}],
+            "is_active": true
+        }]
+    }]
+}]
+}"#;
+
+    // every terminal size from 1x1 up to this one is drawn
+    const MAX_WIDTH: u16 = 40;
+    const MAX_HEIGHT: u16 = 15;
+
+    fn draw_all_sizes(app: &mut App)
+    {
+        for w in 1..=MAX_WIDTH {
+            for h in 1..=MAX_HEIGHT {
+                let mut terminal = Terminal::new(TestBackend::new(w, h))
+                    .unwrap();
+                terminal.draw(|frame| app.draw(frame))
+                    .unwrap_or_else(|err| panic!("draw {}x{}: {}", w, h, err));
+            }
+        }
+    }
+
+    #[test]
+    fn draw_screens_small_sizes()
+    {
+        let jsondata: AppDataJson = serde_json::from_str(REPLAY_JSON)
+            .unwrap();
+        let model: Rc<RefCell<dyn AppData>> =
+            Rc::new(RefCell::new(jsondata));
+        let mut app = App::from(model.clone());
+
+        app.screens.enter(MainScreen::new(model.clone()));
+        draw_all_sizes(&mut app);
+
+        let sel = DrmClientSelected::new("0000:03:00.0".to_string(),
+            true, false, 4242, 128, 7);
+        app.screens.enter(DrmClientScreen::new(model.clone(), sel));
+        draw_all_sizes(&mut app);
+
+        app.screens.enter(LogScreen::new());
+        draw_all_sizes(&mut app);
+    }
+}
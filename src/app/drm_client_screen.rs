@@ -11,12 +11,13 @@ use ratatui::{
     style::{palette::tailwind, Color, Style, Stylize}, symbols,
     text::{Span, Line},
     widgets::{Axis, Block, Borders, BorderType, Chart,
-        Dataset, GraphType, LegendPosition, Row, Table},
+        Dataset, Gauge, GraphType, LegendPosition, Row, Table},
     Frame,
 };
 use tui_scrollview::{ScrollView, ScrollViewState, ScrollbarVisibility};
 
 use crate::app_data::{AppData, AppDataClientStats};
+use crate::drm_clients::DrmEngineDelta;
 use crate::app::{App, Screen, ScreenAction};
 
 
@@ -25,6 +26,7 @@ pub struct DrmClientSelected
 {
     pci_dev: String,
     is_dgfx: bool,
+    has_gtt: bool,
     pid: u32,
     drm_minor: u32,
     client_id: u32,
@@ -32,12 +34,13 @@ pub struct DrmClientSelected
 
 impl DrmClientSelected
 {
-    pub fn new(pci_dev: String, is_dgfx: bool,
+    pub fn new(pci_dev: String, is_dgfx: bool, has_gtt: bool,
         pid: u32, drm_minor: u32, client_id: u32) -> DrmClientSelected
     {
         DrmClientSelected {
             pci_dev,
             is_dgfx,
+            has_gtt,
             pid,
             drm_minor,
             client_id,
@@ -46,9 +49,10 @@ impl DrmClientSelected
 }
 
 const CLIENT_STATS_MEMINFO: u8 = 0;
-const CLIENT_STATS_ENGINES: u8 = 1;
-const CLIENT_STATS_CPU: u8 = 2;
-const CLIENT_STATS_TOTAL: u8 = 3;
+const CLIENT_STATS_MEMRATE: u8 = 1;
+const CLIENT_STATS_ENGINES: u8 = 2;
+const CLIENT_STATS_CPU: u8 = 3;
+const CLIENT_STATS_TOTAL: u8 = 4;
 
 const CLIENT_STATS_OP_NEXT: u8 = 0;
 const CLIENT_STATS_OP_PREV: u8 = 1;
@@ -84,10 +88,15 @@ impl ClientStatsState
         }
     }
 
-    fn new() -> ClientStatsState
+    fn from(chart: Option<&str>) -> ClientStatsState
     {
+        let sel = match chart {
+            Some("engines") => CLIENT_STATS_ENGINES,
+            _ => CLIENT_STATS_MEMINFO,
+        };
+
         ClientStatsState {
-            sel: CLIENT_STATS_MEMINFO,
+            sel,
             last_op: CLIENT_STATS_OP_NEXT,
         }
     }
@@ -136,7 +145,7 @@ impl Screen for DrmClientScreen
             .column_spacing(1),
             tab_area);
 
-        let max_chart_height = min(main_area.width / 4, main_area.height - 4);
+        let max_chart_height = min(main_area.width / 4, main_area.height.saturating_sub(4));
         let [cmd_area, table_area, sep, chart_area] = Layout::vertical(vec![
             Constraint::Length(1),
             Constraint::Length(2),
@@ -174,10 +183,10 @@ impl Screen for DrmClientScreen
         // render command scrollview
         self.render_command(sel_cli, frame, cmd_area);
 
-        // skip engines selection if no engines are known
+        // skip engines selection if no engines are known (or shown)
         let mut stats_st = self.stats_state.borrow_mut();
         if stats_st.sel == CLIENT_STATS_ENGINES &&
-            sel_cli.eng_usage.is_empty() {
+            App::shown_engines(&*model, sel_cli.eng_usage.keys()).is_empty() {
             stats_st.repeat_op();
         }
         drop(stats_st);
@@ -228,10 +237,39 @@ impl Screen for DrmClientScreen
             " (< >) Change chart".light_yellow().bold(),
         ]
     }
+
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>
+    {
+        vec![
+            ("← →", "Scroll command line"),
+            ("< >", "Previous/next chart"),
+        ]
+    }
 }
 
 impl DrmClientScreen
 {
+    // engine busy time (in ms) or cycles in the last update
+    fn eng_delta_string(ed: &DrmEngineDelta) -> String
+    {
+        if ed.delta_total_cycles > 0 {
+            let cyc = ed.delta_cycles as f64;
+            if cyc >= 1e9 {
+                format!("{:.1}G cyc", cyc / 1e9)
+            } else if cyc >= 1e6 {
+                format!("{:.1}M cyc", cyc / 1e6)
+            } else if cyc >= 1e3 {
+                format!("{:.1}K cyc", cyc / 1e3)
+            } else {
+                format!("{} cyc", ed.delta_cycles)
+            }
+        } else if ed.delta_time > 0 {
+            format!("{:.1}ms", ed.delta_time as f64 / 1e6)
+        } else {
+            String::new()
+        }
+    }
+
     fn render_command(&self,
         cli: &AppDataClientStats, frame: &mut Frame, area: Rect)
     {
@@ -244,12 +282,13 @@ impl DrmClientScreen
         let label_line = Line::from(label)
             .alignment(Alignment::Left)
             .style(Style::new().magenta().bold());
-        let cmd_line = Line::from(format!("[{}] {}", &cli.comm, &cli.cmdline))
+        let cmd_str = cli.command();
+        let cmd_line = Line::from(cmd_str.as_str())
             .alignment(Alignment::Left)
             .style(Style::new().white());
 
         let mut state = self.cmd_sv_state.borrow_mut();
-        let sv_w = (cli.comm.len() + cli.cmdline.len() + 3) as u16;
+        let sv_w = cmd_str.len() as u16;
         let mut cmd_sv = ScrollView::new(Size::new(sv_w, 1))
             .scrollbars_visibility(ScrollbarVisibility::Never);
         cmd_sv.render_widget(cmd_line, cmd_sv.area());
@@ -258,10 +297,29 @@ impl DrmClientScreen
         frame.render_stateful_widget(cmd_sv, cmd_area, &mut state);
     }
 
+    // resident vs used memory or, if asked, used vs device total memory
+    fn mem_gauge(rss: u64, used: u64, dev_total: u64,
+        show_used: bool, si_units: bool) -> Gauge<'static>
+    {
+        let (val, total) = if show_used {
+            (used, dev_total) } else { (rss, used) };
+        let label = Span::styled(format!("{}/{}",
+            App::short_mem_string(val, si_units),
+            App::short_mem_string(total, si_units)),
+            Style::new().white());
+        let ratio = if total > 0 {
+            f64::min(1.0, val as f64 / total as f64) } else { 0.0 };
+
+        App::gauge_colored_from(label, ratio)
+    }
+
     fn render_stats_table(&self,
         cli: &AppDataClientStats, frame: &mut Frame, area: Rect)
     {
         let stats_st = self.stats_state.borrow();
+        let model = self.model.borrow();
+        let shown_engs = App::shown_engines(&*model, cli.eng_usage.keys().sorted());
+        let si_units = App::si_units(&*model);
 
         let [hdr_area, gauges_area] = Layout::vertical([
             Constraint::Length(1),
@@ -273,14 +331,19 @@ impl DrmClientScreen
         if self.sel.is_dgfx {
             widths.push(Constraint::Length(12));   // VRAM
         }
-        for _ in cli.eng_usage.keys() {
+        if self.sel.has_gtt {
+            widths.push(Constraint::Length(12));   // GTT
+        }
+        widths.push(Constraint::Length(9));    // MEM/s
+        for _ in shown_engs.iter() {
             widths.push(Constraint::Fill(1));  // ENGINES
         }
         widths.push(Constraint::Length(7));    // CPU
 
         let gs_areas = Layout::horizontal(&widths).split(gauges_area);
-        let en_width = if !cli.eng_usage.is_empty() {
-            gs_areas[if self.sel.is_dgfx { 2 } else { 1 }].width as usize
+        let en_width = if !shown_engs.is_empty() {
+            gs_areas[2 + self.sel.is_dgfx as usize +
+                self.sel.has_gtt as usize].width as usize
         } else {
             0
         };
@@ -300,7 +363,17 @@ impl DrmClientScreen
                 .style(if stats_st.sel == CLIENT_STATS_MEMINFO {
                     ly_bold } else { wh_bold }));
         }
-        for en in cli.eng_usage.keys().sorted() {
+        if self.sel.has_gtt {
+            hdrs_lst.push(Line::from("GTT")
+                .alignment(Alignment::Center)
+                .style(if stats_st.sel == CLIENT_STATS_MEMINFO {
+                    ly_bold } else { wh_bold }));
+        }
+        hdrs_lst.push(Line::from("MEM/s")
+            .alignment(Alignment::Center)
+            .style(if stats_st.sel == CLIENT_STATS_MEMRATE {
+                ly_bold } else { wh_bold }));
+        for en in shown_engs.iter() {
             hdrs_lst.push(Line::from(en.to_uppercase())
                 .alignment(if en.len() > en_width {
                     Alignment::Left } else { Alignment::Center })
@@ -322,34 +395,44 @@ impl DrmClientScreen
         let mut stats_gs = Vec::new();
 
         let mi = cli.mem_info.back().unwrap();
-        let smem_label = Span::styled(format!("{}/{}",
-            App::short_mem_string(mi.smem_rss),
-            App::short_mem_string(mi.smem_used)),
-            Style::new().white());
-        let smem_ratio = if mi.smem_used > 0 {
-            mi.smem_rss as f64 / mi.smem_used as f64 } else { 0.0 };
-        stats_gs.push(App::gauge_colored_from(smem_label, smem_ratio));
+        let show_used = App::client_mem_used(&*model);
+        let dmi = model.get_device(&self.sel.pci_dev)
+            .and_then(|di| di.dev_stats.mem_info.back());
+        stats_gs.push(DrmClientScreen::mem_gauge(mi.smem_rss, mi.smem_used,
+            dmi.map_or(0, |dmi| dmi.smem_total), show_used, si_units));
         if self.sel.is_dgfx {
-            let vram_label = Span::styled(format!("{}/{}",
-                App::short_mem_string(mi.vram_rss),
-                App::short_mem_string(mi.vram_used)),
-                Style::new().white());
-            let vram_ratio = if mi.vram_used > 0 {
-                mi.vram_rss as f64 / mi.vram_used as f64 } else { 0.0 };
-            stats_gs.push(App::gauge_colored_from(vram_label, vram_ratio));
+            stats_gs.push(DrmClientScreen::mem_gauge(mi.vram_rss, mi.vram_used,
+                dmi.map_or(0, |dmi| dmi.vram_total), show_used, si_units));
+        }
+        if self.sel.has_gtt {
+            stats_gs.push(DrmClientScreen::mem_gauge(mi.gtt_rss, mi.gtt_used,
+                dmi.map_or(0, |dmi| dmi.gtt_total), show_used, si_units));
         }
+        let mem_rate = cli.mem_rates(model.timestamps())
+            .last().copied().unwrap_or(0.0);
+        let rate_label = Span::styled(
+            App::mem_rate_string(mem_rate, si_units), Style::new().white());
+        stats_gs.push(App::gauge_colored_from(rate_label, 0.0));
 
-        for en in cli.eng_usage.keys().sorted() {
+        for &en in shown_engs.iter() {
             let eut = cli.eng_usage[en].back().unwrap();
-            let label = Span::styled(
-                format!("{:.1}%", eut), Style::new().white());
+            let mut lbl_str = App::eng_usage_string(
+                *eut, cli.eng_ready, App::precision(&*model));
+            // add raw engine time or cycles, if there's space for it
+            if let Some(ed) = cli.eng_delta.get(en).and_then(|v| v.back()) {
+                let ext = DrmClientScreen::eng_delta_string(ed);
+                if !ext.is_empty() && lbl_str.len() + ext.len() + 3 <= en_width {
+                    lbl_str.push_str(&format!(" ({})", ext));
+                }
+            }
+            let label = Span::styled(lbl_str, Style::new().white());
 
             stats_gs.push(App::gauge_colored_from(label, eut/100.0));
         }
 
         let cpu = cli.cpu_usage.back().unwrap();
         let cpu_label = Span::styled(
-            format!("{:.1}%", cpu), Style::new().white());
+            format!("{:.*}%", App::precision(&*model), cpu), Style::new().white());
         stats_gs.push(App::gauge_colored_from(cpu_label, cpu/100.0));
 
         for (st_g, st_a) in stats_gs.iter().zip(gs_areas.iter()) {
@@ -364,6 +447,8 @@ impl DrmClientScreen
         let mut sm_used_vals = Vec::new();
         let mut vr_rss_vals = Vec::new();
         let mut vr_used_vals = Vec::new();
+        let mut gtt_rss_vals = Vec::new();
+        let mut gtt_used_vals = Vec::new();
         let nr_vals = x_vals.len();
 
         let miny = 0;
@@ -379,6 +464,10 @@ impl DrmClientScreen
                     vr_rss_vals.push((x_vals[i], 0.0));
                     vr_used_vals.push((x_vals[i], 0.0));
                 }
+                if self.sel.has_gtt {
+                    gtt_rss_vals.push((x_vals[i], 0.0));
+                    gtt_used_vals.push((x_vals[i], 0.0));
+                }
             }
         }
         for i in idx..nr_vals {
@@ -393,6 +482,11 @@ impl DrmClientScreen
                 vr_used_vals.push((x_vals[i], mi.vram_used as f64));
                 maxy = max(maxy, mi.vram_used);
             }
+            if self.sel.has_gtt {
+                gtt_rss_vals.push((x_vals[i], mi.gtt_rss as f64));
+                gtt_used_vals.push((x_vals[i], mi.gtt_used as f64));
+                maxy = max(maxy, mi.gtt_used);
+            }
         }
         let mut datasets = vec![
             Dataset::default()
@@ -422,12 +516,27 @@ impl DrmClientScreen
                 .graph_type(GraphType::Line)
                 .data(&vr_rss_vals));
         }
+        if self.sel.has_gtt {
+            datasets.push(Dataset::default()
+                .name("GTT USED")
+                .marker(symbols::Marker::Braille)
+                .style(tailwind::PURPLE.c700)
+                .graph_type(GraphType::Line)
+                .data(&gtt_used_vals));
+            datasets.push(Dataset::default()
+                .name("GTT RSS")
+                .marker(symbols::Marker::Braille)
+                .style(tailwind::PINK.c700)
+                .graph_type(GraphType::Line)
+                .data(&gtt_rss_vals));
+        }
 
+        let si_units = App::si_units(&*self.model.borrow());
         let y_bounds = [miny as f64, maxy as f64];
         let y_labels = vec![
-            Span::raw(format!("{}", App::short_mem_string(miny))),
-            Span::raw(format!("{}", App::short_mem_string((miny + maxy) / 2))),
-            Span::raw(format!("{}", App::short_mem_string(maxy))),
+            Span::raw(App::short_mem_string(miny, si_units)),
+            Span::raw(App::short_mem_string((miny + maxy) / 2, si_units)),
+            Span::raw(App::short_mem_string(maxy, si_units)),
         ];
         let y_axis = Axis::default()
             .title("Mem")
@@ -435,6 +544,9 @@ impl DrmClientScreen
             .bounds(y_bounds)
             .labels(y_labels);
 
+        let mkrs = App::marker_lines(&*self.model.borrow(), y_bounds);
+        datasets.extend(App::marker_datasets(&mkrs));
+
         frame.render_widget(Chart::new(datasets)
             .x_axis(x_axis)
             .y_axis(y_axis)
@@ -449,8 +561,10 @@ impl DrmClientScreen
     {
         let mut eng_vals = Vec::new();
         let nr_vals = x_vals.len();
+        let model = self.model.borrow();
+        let shown_engs = App::shown_engines(&*model, cli.eng_usage.keys().sorted());
 
-        for en in cli.eng_usage.keys().sorted() {
+        for &en in shown_engs.iter() {
             let mut nlst = Vec::new();
             let est = &cli.eng_usage[en];
 
@@ -471,7 +585,7 @@ impl DrmClientScreen
         let mut datasets = Vec::new();
         let mut color_idx = 1;
 
-        for (en, ed) in cli.eng_usage.keys().sorted().zip(eng_vals.iter()) {
+        for (en, ed) in shown_engs.iter().zip(eng_vals.iter()) {
             datasets.push(Dataset::default()
                 .name(en.to_uppercase())
                 .marker(symbols::Marker::Braille)
@@ -493,7 +607,66 @@ impl DrmClientScreen
             .bounds(y_bounds)
             .labels(y_labels);
 
-       frame.render_widget(Chart::new(datasets)
+        let mkrs = App::marker_lines(&*self.model.borrow(), y_bounds);
+        datasets.extend(App::marker_datasets(&mkrs));
+
+        frame.render_widget(Chart::new(datasets)
+            .x_axis(x_axis)
+            .y_axis(y_axis)
+            .legend_position(Some(LegendPosition::BottomLeft))
+            .hidden_legend_constraints((Constraint::Min(0), Constraint::Min(0)))
+            .style(Style::new().bold().on_black()),
+            area);
+    }
+
+    fn render_memrate_chart(&self, x_vals: &Vec<f64>, x_axis: Axis,
+        cli: &AppDataClientStats, frame: &mut Frame, area: Rect)
+    {
+        let model = self.model.borrow();
+        let rates = cli.mem_rates(model.timestamps());
+        let mut rate_vals = Vec::new();
+        let nr_vals = x_vals.len();
+        let mut max_y: f64 = 1024.0;
+
+        let mut idx = 0;
+        if rates.len() < nr_vals {
+            idx = nr_vals - rates.len();
+            for i in 0..idx {
+                rate_vals.push((x_vals[i], 0.0));
+            }
+        }
+        for i in idx..nr_vals {
+            let val = rates[i-idx];
+            rate_vals.push((x_vals[i], val));
+            max_y = f64::max(max_y, val.abs());
+        }
+        let mut datasets = vec![
+            Dataset::default()
+                .name("MEM/s (SMEM + VRAM + GTT)")
+                .marker(symbols::Marker::Braille)
+                .style(tailwind::BLUE.c700)
+                .graph_type(GraphType::Line)
+                .data(&rate_vals),
+        ];
+
+        // growing above zero, shrinking below it
+        let si_units = App::si_units(&*model);
+        let y_bounds = [-max_y, max_y];
+        let y_labels = vec![
+            Span::raw(App::mem_rate_string(-max_y, si_units)),
+            Span::raw("0"),
+            Span::raw(App::mem_rate_string(max_y, si_units)),
+        ];
+        let y_axis = Axis::default()
+            .title("Mem Rate")
+            .style(Style::new().white())
+            .bounds(y_bounds)
+            .labels(y_labels);
+
+        let mkrs = App::marker_lines(&*model, y_bounds);
+        datasets.extend(App::marker_datasets(&mkrs));
+
+        frame.render_widget(Chart::new(datasets)
             .x_axis(x_axis)
             .y_axis(y_axis)
             .legend_position(Some(LegendPosition::BottomLeft))
@@ -522,7 +695,7 @@ impl DrmClientScreen
             max_y = f64::max(max_y, val);
         }
         let max_y = f64::max(100.0, max_y);
-        let datasets = vec![
+        let mut datasets = vec![
             Dataset::default()
                 .name("CPU")
                 .marker(symbols::Marker::Braille)
@@ -543,7 +716,10 @@ impl DrmClientScreen
             .bounds(y_bounds)
             .labels(y_labels);
 
-       frame.render_widget(Chart::new(datasets)
+        let mkrs = App::marker_lines(&*self.model.borrow(), y_bounds);
+        datasets.extend(App::marker_datasets(&mkrs));
+
+        frame.render_widget(Chart::new(datasets)
             .x_axis(x_axis)
             .y_axis(y_axis)
             .legend_position(Some(LegendPosition::BottomLeft))
@@ -562,37 +738,16 @@ impl DrmClientScreen
         for ts in tstamps.iter() {
             x_vals.push(*ts as f64 / 1000.0);
         }
-        let x_bounds: [f64; 2];
-        let mut x_labels: Vec<Span>;
-        if x_vals.len() == 1 {
-            let int_secs = model.args().ms_interval as f64 / 1000.0;
-            x_bounds = [x_vals[0], x_vals[0] + int_secs];
-            x_labels = vec![
-                Span::raw(format!("{:.1}", x_bounds[0])),
-                Span::raw(format!("{:.1}", x_bounds[1])),
-            ];
-        } else {
-            let xvlen = x_vals.len();
-            x_bounds = [x_vals[0], x_vals[xvlen - 1]];
-            x_labels = vec![
-                Span::raw(format!("{:.1}", x_vals[0])),
-                Span::raw(format!("{:.1}", x_vals[xvlen / 2])),
-            ];
-            if xvlen >= 3 {
-                x_labels.push(Span::raw(format!("{:.1}", x_vals[xvlen - 1])));
-            }
-        }
-        let x_axis = Axis::default()
-            .title("Time (s)")
-            .style(Style::new().white())
-            .bounds(x_bounds)
-            .labels(x_labels);
+        let x_axis = App::time_axis(&*model, &x_vals);
 
         let stats_st = self.stats_state.borrow();
         match stats_st.sel {
             CLIENT_STATS_MEMINFO => {
                 self.render_meminfo_chart(&x_vals, x_axis, cli, frame, area);
             },
+            CLIENT_STATS_MEMRATE => {
+                self.render_memrate_chart(&x_vals, x_axis, cli, frame, area);
+            },
             CLIENT_STATS_ENGINES => {
                 self.render_engines_chart(&x_vals, x_axis, cli, frame, area);
             },
@@ -608,11 +763,14 @@ impl DrmClientScreen
     pub fn new(model: Rc<RefCell<dyn AppData>>,
         sel: DrmClientSelected) -> Box<dyn Screen>
     {
+        let stats_st = ClientStatsState::from(
+            model.borrow().args().select_chart.as_deref());
+
         Box::new(DrmClientScreen {
             model,
             sel,
             cmd_sv_state: RefCell::new(ScrollViewState::new()),
-            stats_state: RefCell::new(ClientStatsState::new()),
+            stats_state: RefCell::new(stats_st),
         })
     }
 }
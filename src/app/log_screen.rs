@@ -0,0 +1,124 @@
+use std::cmp::min;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::{Alignment, Constraint, Rect},
+    style::{Style, Stylize},
+    text::{Span, Line},
+    widgets::{Paragraph, Row, Table},
+    Frame,
+};
+
+use crate::app_log::AppLogger;
+use crate::app::{Screen, ScreenAction};
+
+
+#[derive(Debug)]
+pub struct LogScreen
+{
+    scroll: usize,      // nr of records from the end, 0 follows new ones
+    page_size: usize,
+}
+
+impl Screen for LogScreen
+{
+    fn name(&self) -> &str
+    {
+        LogScreen::NAME
+    }
+
+    fn draw(&mut self, frame: &mut Frame, tab_area: Rect, main_area: Rect)
+    {
+        let recs = AppLogger::records();
+
+        // render tab area with log level and number of records
+        let widths = vec![Constraint::Fill(1); 2];
+        let rows = [Row::new([
+            Line::from(vec![
+                "LOG LEVEL: ".white().bold(),
+                AppLogger::level().as_str().to_uppercase().into()])
+            .alignment(Alignment::Center),
+            Line::from(vec![
+                "RECORDS: ".white().bold(),
+                format!("{}", recs.len()).into()])
+            .alignment(Alignment::Center),
+        ])];
+        frame.render_widget(Table::new(rows, widths)
+            .style(Style::new().white().on_black())
+            .column_spacing(1),
+            tab_area);
+
+        // render visible window of log records
+        self.page_size = main_area.height as usize;
+        let max_scroll = recs.len().saturating_sub(self.page_size);
+        self.scroll = min(self.scroll, max_scroll);
+
+        let end = recs.len() - self.scroll;
+        let start = end.saturating_sub(self.page_size);
+        let lines: Vec<Line> = recs[start..end].iter()
+            .map(|r| Line::from(r.as_str()))
+            .collect();
+
+        frame.render_widget(Paragraph::new(lines)
+            .style(Style::new().white().on_black()),
+            main_area);
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) -> Option<ScreenAction>
+    {
+        match key_event.code {
+            KeyCode::Up => {
+                self.scroll += 1;
+            },
+            KeyCode::Down => {
+                self.scroll = self.scroll.saturating_sub(1);
+            },
+            KeyCode::PageUp => {
+                self.scroll += self.page_size;
+            },
+            KeyCode::PageDown => {
+                self.scroll = self.scroll.saturating_sub(self.page_size);
+            },
+            KeyCode::End => {
+                self.scroll = 0;
+            },
+            KeyCode::Char('v') | KeyCode::Char('V') => {
+                AppLogger::next_level();
+            },
+            _ => {}
+        }
+
+        None
+    }
+
+    fn status_bar_text(&mut self) -> Vec<Span<'_>>
+    {
+        vec![
+            " (↑↓ PgUp PgDn End) Scroll".white().bold(),
+            " (V) Change level".light_yellow().bold(),
+        ]
+    }
+
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>
+    {
+        vec![
+            ("↑ ↓", "Scroll one record"),
+            ("PgUp PgDn", "Scroll one page"),
+            ("End", "Follow new records"),
+            ("V", "Change log level"),
+        ]
+    }
+}
+
+impl LogScreen
+{
+    pub const NAME: &'static str = "Log Screen";
+
+    pub fn new() -> Box<dyn Screen>
+    {
+        Box::new(LogScreen {
+            scroll: 0,
+            page_size: 0,
+        })
+    }
+}
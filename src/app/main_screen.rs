@@ -2,23 +2,27 @@ use std::cell::RefCell;
 use std::cmp::{max, min};
 use std::collections::VecDeque;
 use std::rc::Rc;
+use std::time;
 
 use itertools::Itertools;
-use log::error;
+use log::{debug, error};
 
-use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
-    layout::{Alignment, Constraint, Layout, Rect, Size},
+    layout::{Alignment, Constraint, Layout, Position, Rect, Size},
     style::{palette::tailwind, Color, Style, Stylize}, symbols,
     text::{Span, Line},
-    widgets::{Axis, Block, Borders, BorderType, Chart,
-        Dataset, Gauge, GraphType, LegendPosition, Row, Table, Tabs},
+    widgets::{Axis, Bar, BarChart, BarGroup, Block, Borders, BorderType,
+        Chart, Dataset, Gauge, GraphType, LegendPosition, Row, Table, Tabs},
     Frame,
 };
 use tui_scrollview::{ScrollView, ScrollViewState, ScrollbarVisibility};
 
 use crate::app_data::{AppData, AppDataDeviceState, AppDataClientStats};
+use crate::drm_devices::{DrmDeviceFreqs, DrmDeviceThrottleReasons};
+use crate::plotter::Plotter;
 use crate::app::{App, Screen, ScreenAction};
+use crate::MIN_MS_INTERVAL;
 use crate::app::drm_client_screen::{DrmClientScreen, DrmClientSelected};
 
 
@@ -55,6 +59,20 @@ impl DevicesTabState
         self.devs.is_empty()
     }
 
+    // keeps the selected device if it's still listed
+    fn update(&mut self, devs: Vec<String>)
+    {
+        if devs == self.devs {
+            return;
+        }
+
+        let sel_dev = self.devs.get(self.sel);
+        self.sel = sel_dev
+            .and_then(|sd| devs.iter().position(|dn| dn == sd))
+            .unwrap_or(0);
+        self.devs = devs;
+    }
+
     fn new(devs: Vec<String>) -> DevicesTabState
     {
         DevicesTabState {
@@ -64,12 +82,27 @@ impl DevicesTabState
     }
 }
 
+// DRM client as (DRM minor, client id)
+type ClientKey = (u32, u32);
+
+const MS_INTERVAL_STEP: u64 = 100;
+const SAVE_MSG_SECS: u64 = 5;
+
 const DEVICE_STATS_FREQS: u8 = 0;
 const DEVICE_STATS_POWER: u8 = 1;
 const DEVICE_STATS_MEMINFO: u8 = 2;
 const DEVICE_STATS_ENGINES: u8 = 3;
 const DEVICE_STATS_TOTAL: u8 = 4;
 
+// engines sub-selections: usage over time, histogram or PMU waits
+const ENGINES_CHART_USAGE: u8 = 0;
+const ENGINES_CHART_HIST: u8 = 1;
+const ENGINES_CHART_SCHED: u8 = 2;
+const ENGINES_HIST_NR_BUCKETS: usize = 10;
+
+// engines gauges narrower than this wrap to extra rows
+const ENGINE_GAUGE_MIN_WIDTH: usize = 10;
+
 const DEVICE_STATS_OP_NEXT: i8 = 0;
 const DEVICE_STATS_OP_PREV: i8 = 1;
 
@@ -127,6 +160,23 @@ impl DeviceStatsState
         self.req_op = -1;
     }
 
+    // only the engines charts are cycled through (engines-only layout)
+    fn exec_req_engines(&mut self, nr_eng_charts: u8)
+    {
+        if self.sel != DEVICE_STATS_ENGINES || self.sub_sel >= nr_eng_charts {
+            self.sel = DEVICE_STATS_ENGINES;
+            self.sub_sel = 0;
+        }
+
+        if nr_eng_charts > 0 && self.req_op == DEVICE_STATS_OP_NEXT {
+            self.sub_sel = (self.sub_sel + 1) % nr_eng_charts;
+        } else if nr_eng_charts > 0 && self.req_op == DEVICE_STATS_OP_PREV {
+            self.sub_sel = if self.sub_sel == 0 {
+                nr_eng_charts - 1 } else { self.sub_sel - 1 };
+        }
+        self.req_op = -1;
+    }
+
     fn req_next(&mut self)
     {
         self.req_op = DEVICE_STATS_OP_NEXT;
@@ -137,10 +187,17 @@ impl DeviceStatsState
         self.req_op = DEVICE_STATS_OP_PREV;
     }
 
-    fn new() -> DeviceStatsState
+    fn from(chart: Option<&str>) -> DeviceStatsState
     {
+        let sel = match chart {
+            Some("power") => DEVICE_STATS_POWER,
+            Some("meminfo") => DEVICE_STATS_MEMINFO,
+            Some("engines") => DEVICE_STATS_ENGINES,
+            _ => DEVICE_STATS_FREQS,
+        };
+
         DeviceStatsState {
-            sel: DEVICE_STATS_FREQS,
+            sel,
             sub_sel: 0,
             req_op: -1,
         }
@@ -208,6 +265,10 @@ pub struct MainScreen
     tab_state: Option<DevicesTabState>,
     dstats_state: RefCell<DeviceStatsState>,
     clis_state: RefCell<ClientsViewState>,
+    save_msg: Option<(time::Instant, String)>,
+    stashed_hide_engs: Option<String>,
+    stats_offset: usize,    // nr of samples the stats window is panned back
+    frozen_at: Option<u128>,    // timestamp of the last sample when frozen
 }
 
 impl Screen for MainScreen
@@ -219,8 +280,9 @@ impl Screen for MainScreen
 
     fn draw(&mut self, frame: &mut Frame, tab_area: Rect, main_area: Rect)
     {
-        // if not done yet, initialize tab state with devices
-        if self.tab_state.is_none() {
+        // initialize tab state with devices or update it, as devices can
+        // start or stop reporting stats (e.g. with --hide-idle)
+        {
             let model = self.model.borrow();
             let mut dv: Vec<String> = Vec::new();
 
@@ -228,11 +290,18 @@ impl Screen for MainScreen
                 dv.push(pdev.clone());
             } else {
                 for di in model.devices() {
+                    if model.args().hide_idle && !di.has_stats() {
+                        continue;
+                    }
                     dv.push(di.pci_dev.clone());
                 }
             }
 
-            self.tab_state = Some(DevicesTabState::new(dv));
+            if let Some(devs_ts) = &mut self.tab_state {
+                devs_ts.update(dv);
+            } else {
+                self.tab_state = Some(DevicesTabState::new(dv));
+            }
         }
 
         // render selected DRM dev and DRM clients on main area
@@ -248,8 +317,18 @@ impl Screen for MainScreen
         let dn = &devs_ts.devs[devs_ts.sel];
         if let Some(dinfo) = model.get_device(dn) {
             self.render_devs_tab(devs_ts, frame, tab_area);
+            // one line summary of every device on multi-GPU systems
+            let strip_h = if devs_ts.devs.len() > 1 {
+                devs_ts.devs.len() as u16 } else { 0 };
+            let [strip_area, dev_area] = Layout::vertical([
+                Constraint::Length(strip_h),
+                Constraint::Fill(1),
+            ]).areas(main_area);
+            if strip_h > 0 {
+                self.render_devs_strip(devs_ts, frame, strip_area);
+            }
             let tstamps = model.timestamps();
-            self.render_drm_device(dinfo, tstamps, frame, main_area);
+            self.render_drm_device(dinfo, tstamps, frame, dev_area);
         } else {
             frame.render_widget(Line::from(
                     format!("No DRM GPU device at PCI slot: {:?}", dn))
@@ -282,6 +361,77 @@ impl Screen for MainScreen
                 let mut st = self.dstats_state.borrow_mut();
                 st.req_previous();
             },
+            KeyCode::Char('+') => {
+                let mut model = self.model.borrow_mut();
+                let args = model.args_mut();
+                args.ms_interval = args.ms_interval
+                    .saturating_add(MS_INTERVAL_STEP);
+            },
+            KeyCode::Char('-') => {
+                let mut model = self.model.borrow_mut();
+                let args = model.args_mut();
+                // never below the floor, nor raised to it if started below,
+                // and kept above the interval between fast samples
+                let mut floor = min(MIN_MS_INTERVAL, args.ms_interval);
+                if let Some(si) = args.sample_interval {
+                    floor = max(floor, si + 1);
+                }
+                args.ms_interval = max(floor,
+                    args.ms_interval.saturating_sub(MS_INTERVAL_STEP));
+            },
+            KeyCode::Char('c') | KeyCode::Char('C') => {
+                let mut model = self.model.borrow_mut();
+                let args = model.args_mut();
+                args.compact = !args.compact;
+            },
+            KeyCode::Char('o') | KeyCode::Char('O') => {
+                let mut model = self.model.borrow_mut();
+                let args = model.args_mut();
+                args.engines_only = !args.engines_only;
+            },
+            KeyCode::Char('s') | KeyCode::Char('S') => {
+                self.save_charts();
+            },
+            KeyCode::Char('e') | KeyCode::Char('E') => {
+                // show/hide again the engines hidden by the user
+                let mut model = self.model.borrow_mut();
+                let args = model.args_mut();
+                if args.hide_engines.is_some() {
+                    self.stashed_hide_engs = args.hide_engines.take();
+                } else {
+                    args.hide_engines = self.stashed_hide_engs.take();
+                }
+            },
+            KeyCode::Char('m') | KeyCode::Char('M') => {
+                self.model.borrow_mut().add_marker();
+            },
+            KeyCode::Char('w') | KeyCode::Char('W') => {
+                let mut model = self.model.borrow_mut();
+                let args = model.args_mut();
+                args.wall_clock = !args.wall_clock;
+            },
+            KeyCode::Char('a') | KeyCode::Char('A') => {
+                let mut model = self.model.borrow_mut();
+                let args = model.args_mut();
+                args.stacked_engines = !args.stacked_engines;
+            },
+            KeyCode::Char('f') | KeyCode::Char('F') => {
+                // unfreezing jumps back to the latest stats
+                if self.frozen_at.is_some() {
+                    self.frozen_at = None;
+                } else {
+                    self.frozen_at = self.model.borrow()
+                        .timestamps().back().copied();
+                }
+            },
+            KeyCode::Left if key_event.modifiers.contains(KeyModifiers::SHIFT) => {
+                let nr_tstamps = self.model.borrow().timestamps().len();
+                self.stats_offset = min(self.stats_offset + 1,
+                    nr_tstamps.saturating_sub(1));
+            },
+            KeyCode::Right if key_event.modifiers.contains(KeyModifiers::SHIFT) => {
+                self.stats_offset = self.stats_offset.saturating_sub(1);
+            },
             KeyCode::Right => {
                 let mut st = self.clis_state.borrow_mut();
                 st.scroll_right();
@@ -314,51 +464,167 @@ impl Screen for MainScreen
 
     fn status_bar_text(&mut self) -> Vec<Span>
     {
-        vec![
+        let ms_ival = self.model.borrow().args().ms_interval;
+        let hides_engs = self.model.borrow().args().hide_engines.is_some();
+
+        let mut res = Vec::new();
+        if let Some((tm, msg)) = &self.save_msg {
+            if tm.elapsed() < time::Duration::from_secs(SAVE_MSG_SECS) {
+                res.push(format!(" [{}]", msg).light_green().bold());
+            } else {
+                self.save_msg = None;
+            }
+        }
+
+        res.extend([
             " (Tab) Next dev".magenta().bold(),
             " (< >) Change chart".light_yellow().bold(),
+            format!(" (+ -) Interval [{}ms]", ms_ival).light_cyan().bold(),
+            " (C) Compact".light_cyan().bold(),
+            " (W) Wall clock".light_cyan().bold(),
+            " (A) Stacked engines".light_cyan().bold(),
+            " (S) Save charts".light_cyan().bold(),
+            " (M) Marker".light_cyan().bold(),
+        ]);
+        if hides_engs || self.stashed_hide_engs.is_some() {
+            res.push(if hides_engs {
+                " (E) Show all engines" } else { " (E) Hide engines" }
+                .light_cyan().bold());
+        }
+        res.push(if self.frozen_at.is_some() {
+            " (F) Unfreeze [frozen]" } else { " (F) Freeze" }
+            .light_cyan().bold());
+        let pan_str = if self.stats_offset > 0 {
+            format!(" (Shift ←→) Pan stats [-{}]", self.stats_offset)
+        } else {
+            String::from(" (Shift ←→) Pan stats")
+        };
+        res.push(pan_str.light_cyan().bold());
+        res.extend([
             " (↑↓←→) Scroll".white().bold(),
             " (Enter) Select".white().bold(),
+        ]);
+
+        res
+    }
+
+    fn key_bindings(&self) -> Vec<(&'static str, &'static str)>
+    {
+        vec![
+            ("Tab / Shift+Tab", "Next/previous device"),
+            ("< >", "Previous/next chart"),
+            ("+ -", "Increase/decrease update interval"),
+            ("C", "Toggle compact mode"),
+            ("O", "Toggle engines-only layout"),
+            ("W", "Toggle wall clock time"),
+            ("A", "Toggle stacked engines chart"),
+            ("E", "Show/hide the hidden engines"),
+            ("F", "Freeze/unfreeze stats"),
+            ("Shift+← →", "Pan stats back/forward"),
+            ("S", "Save charts as SVG files"),
+            ("M", "Add marker"),
+            ("↑ ↓ ← →", "Scroll DRM clients"),
+            ("Enter", "Open selected DRM client"),
         ]
     }
 }
 
 impl MainScreen
 {
-    fn client_pidmem(&self, cli: &AppDataClientStats,
-        is_dgfx: bool, widths: &Vec<Constraint>) -> Table
+    // save SVG charts with the visible stats of the selected device
+    fn save_charts(&mut self)
+    {
+        let devs_ts = match &self.tab_state {
+            Some(devs_ts) if !devs_ts.is_empty() => devs_ts,
+            _ => return,
+        };
+        let dn = &devs_ts.devs[devs_ts.sel];
+
+        let model = self.model.borrow();
+        let dinfo = match model.get_device(dn) {
+            Some(dinfo) => dinfo,
+            None => return,
+        };
+        let prefix = format!("qmassa-{}-{}", dn, App::file_time_string());
+
+        let msg = match Plotter::plot_device_window(
+            &prefix, dinfo, model.timestamps(), model.markers(),
+            App::si_units(&*model)) {
+            Ok(files) => {
+                debug!("INF: saved charts for {:?}: {:?}", dn, files);
+                format!("Saved {} charts to {}-*.svg", files.len(), prefix)
+            },
+            Err(err) => {
+                debug!("ERR: failed to save charts for {:?}: {:?}", dn, err);
+                String::from("Failed to save charts")
+            },
+        };
+        drop(model);
+
+        self.save_msg = Some((time::Instant::now(), msg));
+    }
+
+    fn client_pidmem(&self, cli: &AppDataClientStats, now_ms: u128,
+        is_dgfx: bool, has_gtt: bool, has_ctxs: bool,
+        widths: &Vec<Constraint>) -> Table
     {
         let mem_info = cli.mem_info.back().unwrap();
+        let model = self.model.borrow();
+        let si_units = App::si_units(&*model);
+        let mem_rate = cli.mem_rates(model.timestamps())
+            .last().copied().unwrap_or(0.0);
+
+        let show_used = App::client_mem_used(&*model);
+        let (smem, vram, gtt) = if show_used {
+            (mem_info.smem_used, mem_info.vram_used, mem_info.gtt_used)
+        } else {
+            (mem_info.smem_rss, mem_info.vram_rss, mem_info.gtt_rss)
+        };
 
         let mut lines = vec![
             Line::from(cli.pid.to_string())
                 .alignment(Alignment::Center),
-            Line::from(App::short_mem_string(mem_info.smem_rss))
+            Line::from(App::short_mem_string(smem, si_units))
                 .alignment(Alignment::Center),
         ];
         if is_dgfx {
-            lines.push(Line::from(App::short_mem_string(mem_info.vram_rss))
+            lines.push(Line::from(App::short_mem_string(vram, si_units))
                 .alignment(Alignment::Center));
         }
+        if has_gtt {
+            lines.push(Line::from(App::short_mem_string(gtt, si_units))
+                .alignment(Alignment::Center));
+        }
+        lines.push(Line::from(App::mem_rate_string(mem_rate, si_units))
+            .alignment(Alignment::Center));
         lines.push(Line::from(cli.drm_minor.to_string())
             .alignment(Alignment::Center));
         lines.push(Line::from(cli.client_id.to_string())
             .alignment(Alignment::Center));
+        if has_ctxs {
+            lines.push(Line::from(cli.nr_contexts.to_string())
+                .alignment(Alignment::Center));
+        }
+        lines.push(Line::from(App::short_age_string(
+                now_ms.saturating_sub(cli.first_seen)))
+            .alignment(Alignment::Center));
 
         let rows = [Row::new(lines),];
         Table::new(rows, widths)
             .column_spacing(1)
-            .style(Style::new().white())
+            .style(App::client_style(cli))
     }
 
     fn render_client_engines(&self, cli: &AppDataClientStats,
         constrs: &Vec<Constraint>, clis_sv: &mut ScrollView, area: Rect)
     {
         let mut gauges: Vec<Gauge> = Vec::new();
-        for en in cli.eng_usage.keys().sorted() {
+        let model = self.model.borrow();
+        for en in App::shown_engines(&*model, cli.eng_usage.keys().sorted()) {
             let eut = cli.eng_usage[en].back().unwrap();
             let label = Span::styled(
-                format!("{:.1}%", eut), Style::new().white());
+                App::eng_usage_string(*eut, cli.eng_ready, App::precision(&*model)),
+                Style::new().white());
 
             gauges.push(App::gauge_colored_from(label, eut/100.0));
         }
@@ -375,24 +641,58 @@ impl MainScreen
         let label_str = if cpu > 999.9 {
             format!("{:.0}%", cpu)
         } else {
-            format!("{:.1}%", cpu)
+            format!("{:.*}%", App::precision(&*self.model.borrow()), cpu)
         };
         let label = Span::styled(label_str, Style::new().white());
 
         App::gauge_colored_from(label, cpu/100.0)
     }
 
-    fn client_cmd(&self, cli: &AppDataClientStats) -> Line
+    // marks the focused window's clients and the ones with the peak
+    // engine/memory usage so far
+    fn client_cmd(&self, cli: &AppDataClientStats,
+        is_peak_eng: bool, is_peak_mem: bool) -> Line
     {
-        Line::from(format!("[{}] {}", &cli.comm, &cli.cmdline))
+        let mut spans = Vec::new();
+        if cli.is_focused {
+            spans.push("●FOCUS ".light_green().bold());
+        }
+        if is_peak_eng {
+            spans.push("▲ENG ".light_red().bold());
+        }
+        if is_peak_mem {
+            spans.push("▲MEM ".light_red().bold());
+        }
+        spans.push(cli.command().into());
+
+        Line::from(spans)
             .alignment(Alignment::Left)
-            .style(Style::new().white())
+            .style(App::client_style(cli))
+    }
+
+    // (client with the peak engine usage, client with the peak memory)
+    fn peak_clients(dinfo: &AppDataDeviceState) ->
+        (Option<ClientKey>, Option<ClientKey>)
+    {
+        let peak_eng = dinfo.clis_stats.iter()
+            .filter(|cli| cli.peak_eng_usage > 0.0)
+            .max_by(|a, b| a.peak_eng_usage.total_cmp(&b.peak_eng_usage))
+            .map(|cli| (cli.drm_minor, cli.client_id));
+        let peak_mem = dinfo.clis_stats.iter()
+            .filter(|cli| cli.peak_mem > 0)
+            .max_by_key(|cli| cli.peak_mem)
+            .map(|cli| (cli.drm_minor, cli.client_id));
+
+        (peak_eng, peak_mem)
     }
 
     fn render_drm_clients(&self,
         dinfo: &AppDataDeviceState, frame: &mut Frame, visible_area: Rect)
     {
         let is_dgfx = dinfo.dev_type.is_discrete();
+        let has_gtt = dinfo.dev_stats.mem_info.back().unwrap().has_gtt();
+        // only a few drivers report contexts per client
+        let has_ctxs = dinfo.clis_stats.iter().any(|cli| cli.nr_contexts > 0);
 
         // get all client info and create scrollviews with right size
         let mut cinfos: Vec<&AppDataClientStats> = Vec::new();
@@ -401,12 +701,13 @@ impl MainScreen
         let mut clis_sv_h: u16 = 0;
 
         let model = self.model.borrow();
+        let now_ms = model.timestamps().back().copied().unwrap_or(0);
         for cli in dinfo.clis_stats.iter() {
             if cli.is_active || model.args().all_clients {
                 cinfos.push(cli);
                 constrs.push(Constraint::Length(1));
                 clis_sv_w = max(clis_sv_w,
-                    (90 + cli.comm.len() + cli.cmdline.len() + 3) as u16);
+                    (113 + has_ctxs as usize * 4 + cli.command().len()) as u16);
                 clis_sv_h += 1;
            }
         }
@@ -436,14 +737,21 @@ impl MainScreen
             }
             let sel = cinfos[state.sel_row as usize];
             state.sel_client = Some(DrmClientSelected::new(
-                dinfo.pci_dev.clone(), is_dgfx,
+                dinfo.pci_dev.clone(), is_dgfx, has_gtt,
                 sel.pid, sel.drm_minor, sel.client_id));
 
-            if state.sel_row < y_offset {
-                state.stats_state.scroll_up();
+            // keep the selected row visible, even after a resize, and
+            // no blank rows past the last client
+            let mut y = y_offset;
+            if state.sel_row < y {
+                y = state.sel_row;
+            } else if nr_vis_clis > 0 && state.sel_row >= y + nr_vis_clis {
+                y = state.sel_row + 1 - nr_vis_clis;
             }
-            if state.sel_row >= y_offset + nr_vis_clis {
-                state.stats_state.scroll_down();
+            y = min(y, clis_sv_h.saturating_sub(nr_vis_clis));
+            if y != y_offset {
+                let x = state.stats_state.offset().x;
+                state.stats_state.set_offset(Position::new(x, y));
             }
         }
 
@@ -452,10 +760,12 @@ impl MainScreen
                 .borders(Borders::NONE)
                 .style(Style::new().on_dark_gray()),
                 hdr_sv_area);
-        let max_engs_width = min(dinfo.eng_names.len() as u16 * 12,
+        let shown_engs = App::shown_engines(&*model, dinfo.eng_names.iter());
+        let max_engs_width = min(shown_engs.len() as u16 * 12,
             (visible_area.width as f64 * 0.53) as u16);
         let line_widths = vec![
-            Constraint::Max(if is_dgfx { 27 } else { 21 }),
+            Constraint::Max(if is_dgfx { 40 } else { 34 } +
+                if has_gtt { 6 } else { 0 } + if has_ctxs { 4 } else { 0 }),
             Constraint::Length(1),
             Constraint::Max(max_engs_width),
             Constraint::Max(7),
@@ -477,10 +787,22 @@ impl MainScreen
             texts.push(Line::from("VRAM").alignment(Alignment::Center));
             pidmem_widths.push(Constraint::Min(5));
         }
+        if has_gtt {
+            texts.push(Line::from("GTT").alignment(Alignment::Center));
+            pidmem_widths.push(Constraint::Min(5));
+        }
+        texts.push(Line::from("MEM/s").alignment(Alignment::Center));
+        pidmem_widths.push(Constraint::Min(7));
         texts.push(Line::from("MIN").alignment(Alignment::Center));
         pidmem_widths.push(Constraint::Min(3));
         texts.push(Line::from("ID").alignment(Alignment::Center));
         pidmem_widths.push(Constraint::Min(3));
+        if has_ctxs {
+            texts.push(Line::from("CTX").alignment(Alignment::Center));
+            pidmem_widths.push(Constraint::Min(3));
+        }
+        texts.push(Line::from("AGE").alignment(Alignment::Center));
+        pidmem_widths.push(Constraint::Min(4));
         hdr_sv.render_widget(Table::new([Row::new(texts)], &pidmem_widths)
             .column_spacing(1)
             .block(Block::new()
@@ -490,9 +812,9 @@ impl MainScreen
 
         let mut texts = Vec::new();
         let mut eng_widths = Vec::new();
-        let en_width = if !dinfo.eng_names.is_empty() {
-            engines_hdr.width as usize / dinfo.eng_names.len() } else { 0 };
-        for en in dinfo.eng_names.iter() {
+        let en_width = if !shown_engs.is_empty() {
+            engines_hdr.width as usize / shown_engs.len() } else { 0 };
+        for en in shown_engs.iter() {
             texts.push(Line::from(en.to_uppercase())
                 .alignment(if en.len() > en_width {
                     Alignment::Left } else { Alignment::Center }));
@@ -521,6 +843,7 @@ impl MainScreen
             clis_sv_area);
 
         if !cinfos.is_empty() {
+            let (peak_eng, peak_mem) = MainScreen::peak_clients(dinfo);
             let mut row_nr = 0;
             let clis_area = Layout::vertical(constrs).split(clis_sv_area);
             for (cli, area) in cinfos.iter().zip(clis_area.iter()) {
@@ -534,37 +857,54 @@ impl MainScreen
                     Layout::horizontal(&line_widths).areas(*area);
 
                 clis_sv.render_widget(
-                    self.client_pidmem(cli, is_dgfx, &pidmem_widths),
+                    self.client_pidmem(cli, now_ms, is_dgfx, has_gtt,
+                        has_ctxs, &pidmem_widths),
                     pidmem_area);
                 self.render_client_engines(
                     cli, &eng_widths, &mut clis_sv, engines_area);
                 clis_sv.render_widget(self.client_cpu_usage(cli), cpu_area);
-                clis_sv.render_widget(self.client_cmd(cli), cmd_area);
+                let cli_key = Some((cli.drm_minor, cli.client_id));
+                clis_sv.render_widget(self.client_cmd(cli,
+                    peak_eng == cli_key, peak_mem == cli_key), cmd_area);
 
                 row_nr += 1;
             }
         }
 
-        // render header and clients data to frame's visible area
+        // render header and clients data to frame's visible area, the
+        // scrollbars need at least 2 rows and columns
         frame.render_stateful_widget(
             hdr_sv, vis_hdr_area, &mut state.hdr_state);
-        frame.render_stateful_widget(
-            clis_sv, vis_clis_area, &mut state.stats_state);
+        if vis_clis_area.width > 1 && vis_clis_area.height > 1 {
+            frame.render_stateful_widget(
+                clis_sv, vis_clis_area, &mut state.stats_state);
+        }
     }
 
     fn render_meminfo_chart(&self, x_vals: &Vec<f64>, x_axis: Axis,
         dinfo: &AppDataDeviceState, frame: &mut Frame, area: Rect)
     {
         let is_dgfx = dinfo.dev_type.is_discrete();
+        let lmi = dinfo.dev_stats.mem_info.back().unwrap();
+        let has_gtt = lmi.has_gtt();
+        let has_host = self.model.borrow().args().host_mem && lmi.has_host();
 
         let mut smem_vals = Vec::new();
         let mut vram_vals = Vec::new();
+        let mut gtt_vals = Vec::new();
+        let mut host_vals = Vec::new();
 
         for (mi, xval) in dinfo.dev_stats.mem_info.iter().zip(x_vals.iter()) {
             smem_vals.push((*xval, mi.smem_used as f64));
             if is_dgfx {
                 vram_vals.push((*xval, mi.vram_used as f64));
             }
+            if has_gtt {
+                gtt_vals.push((*xval, mi.gtt_used as f64));
+            }
+            if has_host {
+                host_vals.push((*xval, mi.host_used as f64));
+            }
         }
         let mut datasets = vec![
             Dataset::default()
@@ -582,20 +922,40 @@ impl MainScreen
                 .graph_type(GraphType::Line)
                 .data(&vram_vals));
         }
+        if has_gtt {
+            datasets.push(Dataset::default()
+                .name("GTT")
+                .marker(symbols::Marker::Braille)
+                .style(tailwind::ORANGE.c700)
+                .graph_type(GraphType::Line)
+                .data(&gtt_vals));
+        }
+        if has_host {
+            datasets.push(Dataset::default()
+                .name("HOST")
+                .marker(symbols::Marker::Braille)
+                .style(tailwind::SLATE.c500)
+                .graph_type(GraphType::Line)
+                .data(&host_vals));
+        }
 
-        let lmi = dinfo.dev_stats.mem_info.back().unwrap();
-        let maxy = if is_dgfx {
+        let mut maxy = if is_dgfx {
             max(lmi.smem_total, lmi.vram_total)
         } else {
             lmi.smem_total
         };
+        maxy = max(maxy, lmi.gtt_total);
+        if has_host {
+            maxy = max(maxy, lmi.host_total);
+        }
         let miny = 0;
 
+        let si_units = App::si_units(&*self.model.borrow());
         let y_bounds = [miny as f64, maxy as f64];
         let y_labels = vec![
-            Span::raw(format!("{}", App::short_mem_string(miny))),
-            Span::raw(format!("{}", App::short_mem_string((miny + maxy) / 2))),
-            Span::raw(format!("{}", App::short_mem_string(maxy))),
+            Span::raw(App::short_mem_string(miny, si_units)),
+            Span::raw(App::short_mem_string((miny + maxy) / 2, si_units)),
+            Span::raw(App::short_mem_string(maxy, si_units)),
         ];
         let y_axis = Axis::default()
             .title("Mem Used")
@@ -603,6 +963,9 @@ impl MainScreen
             .bounds(y_bounds)
             .labels(y_labels);
 
+        let mkrs = App::marker_lines(&*self.model.borrow(), y_bounds);
+        datasets.extend(App::marker_datasets(&mkrs));
+
         frame.render_widget(Chart::new(datasets)
             .x_axis(x_axis)
             .y_axis(y_axis)
@@ -617,8 +980,10 @@ impl MainScreen
     {
         let mut eng_vals = Vec::new();
         let nr_vals = x_vals.len();
+        let model = self.model.borrow();
+        let shown_engs = App::shown_engines(&*model, dinfo.eng_names.iter());
 
-        for en in dinfo.eng_names.iter() {
+        for &en in shown_engs.iter() {
             let mut nlst = Vec::new();
             let est = &dinfo.dev_stats.eng_usage[en];
 
@@ -636,18 +1001,164 @@ impl MainScreen
             eng_vals.push(nlst);
         }
 
+        // stacked: each engine is drawn as bars up to the sum of its usage
+        // and all the ones before it, from the top one down
+        let stacked = model.args().stacked_engines;
+        let mut maxy: f64 = 100.0;
+        if stacked {
+            for i in 1..eng_vals.len() {
+                let (prev, cur) = eng_vals.split_at_mut(i);
+                for (cv, pv) in cur[0].iter_mut().zip(prev[i-1].iter()) {
+                    cv.1 += pv.1;
+                }
+            }
+            if let Some(top) = eng_vals.last() {
+                let top_max = top.iter().fold(0.0, |acc, v| f64::max(acc, v.1));
+                maxy = f64::max(maxy, (top_max / 100.0).ceil() * 100.0);
+            }
+        }
+
         let mut datasets = Vec::new();
         let mut color_idx = 1;
+        let prec = App::precision(&*model);
 
-        for (en, ed) in dinfo.eng_names.iter().zip(eng_vals.iter()) {
+        for (&en, ed) in shown_engs.iter().zip(eng_vals.iter()) {
+            let (emin, emax, eavg) = App::min_max_avg(
+                dinfo.dev_stats.eng_usage[en].iter().copied());
             datasets.push(Dataset::default()
-                .name(en.to_uppercase())
+                .name(format!("{} (min {:.*} max {:.*} avg {:.*})",
+                    en.to_uppercase(), prec, emin, prec, emax, prec, eavg))
                 .marker(symbols::Marker::Braille)
                 .style(Color::Indexed(color_idx))
-                .graph_type(GraphType::Line)
+                .graph_type(if stacked { GraphType::Bar } else { GraphType::Line })
                 .data(ed));
             color_idx += 1;
         }
+        if stacked {
+            datasets.reverse();
+        }
+
+        let y_bounds = [0.0, maxy];
+        let y_labels = vec![
+            Span::raw("0"),
+            Span::raw(format!("{}", maxy / 2.0)),
+            Span::raw(format!("{}", maxy)),
+        ];
+        let y_axis = Axis::default()
+            .title(dinfo.eng_usage_title("Usage (%)"))
+            .style(Style::new().white())
+            .bounds(y_bounds)
+            .labels(y_labels);
+
+        let mkrs = App::marker_lines(&*self.model.borrow(), y_bounds);
+        datasets.extend(App::marker_datasets(&mkrs));
+
+        frame.render_widget(Chart::new(datasets)
+            .x_axis(x_axis)
+            .y_axis(y_axis)
+            .legend_position(Some(LegendPosition::BottomLeft))
+            .hidden_legend_constraints((Constraint::Min(0), Constraint::Min(0)))
+            .style(Style::new().bold().on_black()),
+            area);
+    }
+
+    fn render_engines_hist_chart(&self,
+        dinfo: &AppDataDeviceState, frame: &mut Frame, area: Rect)
+    {
+        let model = self.model.borrow();
+        let shown_engs = App::shown_engines(&*model, dinfo.eng_names.iter());
+        let nr_engines = shown_engs.len();
+        let mut ens_widths: Vec<Constraint> = Vec::new();
+        for _ in 0..nr_engines {
+            ens_widths.push(Constraint::Fill(1));
+        }
+        let ens_areas = Layout::horizontal(&ens_widths)
+            .spacing(1)
+            .split(area);
+
+        for (nr, (&en, en_area)) in shown_engs.iter()
+            .zip(ens_areas.iter()).enumerate() {
+            let color_idx = nr as u8 + 1;
+            // count samples in the visible window per 10% usage bucket
+            let mut buckets = [0u64; ENGINES_HIST_NR_BUCKETS];
+            for eut in dinfo.dev_stats.eng_usage[en].iter() {
+                let idx = (*eut / 10.0) as usize;
+                buckets[min(idx, ENGINES_HIST_NR_BUCKETS - 1)] += 1;
+            }
+
+            let mut bars = Vec::new();
+            for (i, cnt) in buckets.iter().enumerate() {
+                bars.push(Bar::default()
+                    .value(*cnt)
+                    .label(Line::from(format!("{}", i * 10)))
+                    .text_value(format!("{}", cnt))
+                    .style(Color::Indexed(color_idx))
+                    .value_style(Style::new().white().bold()
+                        .bg(Color::Indexed(color_idx))));
+            }
+
+            let blk = Block::bordered()
+                .border_type(BorderType::Plain)
+                .border_style(Style::new().white())
+                .title_top(Line::from(format!(" {} ", en.to_uppercase()))
+                    .alignment(Alignment::Center))
+                .title_bottom(Line::from(" Usage (%) ")
+                    .alignment(Alignment::Center));
+            let inner_w = blk.inner(*en_area).width as usize;
+            let gaps = ENGINES_HIST_NR_BUCKETS - 1;
+            let bar_w = max(1, inner_w.saturating_sub(gaps) /
+                ENGINES_HIST_NR_BUCKETS);
+
+            frame.render_widget(BarChart::default()
+                .block(blk)
+                .data(BarGroup::default().bars(&bars))
+                .bar_width(bar_w as u16)
+                .bar_gap(1)
+                .label_style(Style::new().white())
+                .style(Style::new().bold().on_black()),
+                *en_area);
+        }
+    }
+
+    fn render_engines_sched_chart(&self, x_vals: &[f64], x_axis: Axis,
+        dinfo: &AppDataDeviceState, frame: &mut Frame, area: Rect)
+    {
+        let eng_sched = &dinfo.dev_stats.eng_sched;
+        let engs: Vec<&String> = match eng_sched.back() {
+            Some(last) => last.iter().map(|es| &es.engine).collect(),
+            None => return,
+        };
+        // older samples may not have any (e.g. first read or older JSON)
+        let x_idx = x_vals.len().saturating_sub(eng_sched.len());
+
+        let mut sched_vals = Vec::new();
+        for &en in engs.iter() {
+            let mut sema_vals = Vec::new();
+            let mut wait_vals = Vec::new();
+            for (ess, xval) in eng_sched.iter().zip(x_vals[x_idx..].iter()) {
+                if let Some(es) = ess.iter().find(|es| es.engine == *en) {
+                    sema_vals.push((*xval, es.sema));
+                    wait_vals.push((*xval, es.wait));
+                }
+            }
+            sched_vals.push(sema_vals);
+            sched_vals.push(wait_vals);
+        }
+
+        let mut datasets = Vec::new();
+        let prec = App::precision(&*self.model.borrow());
+        for (nr, sv) in sched_vals.iter().enumerate() {
+            let (smin, smax, savg) = App::min_max_avg(sv.iter().map(|v| v.1));
+            datasets.push(Dataset::default()
+                .name(format!("{} {} (min {:.*} max {:.*} avg {:.*})",
+                    engs[nr / 2].to_uppercase(),
+                    if nr % 2 == 0 { "SEMA" } else { "WAIT" },
+                    prec, smin, prec, smax, prec, savg))
+                .marker(symbols::Marker::Braille)
+                .style(Color::Indexed(nr as u8 + 1))
+                .graph_type(GraphType::Line)
+                .data(sv));
+        }
 
         let y_bounds = [0.0, 100.0];
         let y_labels = vec![
@@ -656,11 +1167,14 @@ impl MainScreen
             Span::raw("100"),
         ];
         let y_axis = Axis::default()
-            .title("Usage (%)")
+            .title("Waiting (%)")
             .style(Style::new().white())
             .bounds(y_bounds)
             .labels(y_labels);
 
+        let mkrs = App::marker_lines(&*self.model.borrow(), y_bounds);
+        datasets.extend(App::marker_datasets(&mkrs));
+
         frame.render_widget(Chart::new(datasets)
             .x_axis(x_axis)
             .y_axis(y_axis)
@@ -689,16 +1203,24 @@ impl MainScreen
         if maxy == 0.0 {
             maxy = 100.0;
         }
+        let (pkg_min, pkg_max, pkg_avg) = App::min_max_avg(
+            dinfo.dev_stats.power.iter().map(|p| p.pkg_cur_power));
+        let (gpu_min, gpu_max, gpu_avg) = App::min_max_avg(
+            dinfo.dev_stats.power.iter().map(|p| p.gpu_cur_power));
+        let prec = App::precision(&*self.model.borrow());
 
-        let datasets = vec![
+        let mut datasets = vec![
             Dataset::default()
-                .name(if is_dgfx { "CARD" } else { "PKG" })
+                .name(format!("{:<4} (min {:.*} max {:.*} avg {:.*})",
+                    if is_dgfx { "CARD" } else { "PKG" },
+                    prec, pkg_min, prec, pkg_max, prec, pkg_avg))
                 .marker(symbols::Marker::Braille)
                 .style(tailwind::BLUE.c700)
                 .graph_type(GraphType::Line)
                 .data(&pkg_vals),
             Dataset::default()
-                .name("GPU")
+                .name(format!("{:<4} (min {:.*} max {:.*} avg {:.*})",
+                    "GPU", prec, gpu_min, prec, gpu_max, prec, gpu_avg))
                 .marker(symbols::Marker::Braille)
                 .style(tailwind::GREEN.c700)
                 .graph_type(GraphType::Line)
@@ -707,9 +1229,9 @@ impl MainScreen
 
         let y_bounds = [miny, maxy];
         let y_labels = vec![
-            Span::raw(format!("{:.1}", miny)),
-            Span::raw(format!("{:.1}", (miny + maxy) / 2.0)),
-            Span::raw(format!("{:.1}", maxy)),
+            Span::raw(format!("{:.*}", prec, miny)),
+            Span::raw(format!("{:.*}", prec, (miny + maxy) / 2.0)),
+            Span::raw(format!("{:.*}", prec, maxy)),
         ];
         let y_axis = Axis::default()
             .title("Power (W)")
@@ -717,6 +1239,9 @@ impl MainScreen
             .bounds(y_bounds)
             .labels(y_labels);
 
+        let mkrs = App::marker_lines(&*self.model.borrow(), y_bounds);
+        datasets.extend(App::marker_datasets(&mkrs));
+
         frame.render_widget(Chart::new(datasets)
             .x_axis(x_axis)
             .y_axis(y_axis)
@@ -732,61 +1257,78 @@ impl MainScreen
         let fq_nr = fq_sel as usize;
         let mut cur_freq_ds = Vec::new();
         let mut act_freq_ds = Vec::new();
-        let mut tr_pl1 = Vec::new();
-        let mut tr_status = Vec::new();
 
         let miny = dinfo.freq_limits[fq_nr].minimum as f64;
         let maxy = dinfo.freq_limits[fq_nr].maximum as f64;
 
+        // each throttle reason is a row of markers, stacked at the bottom
+        type TrReason = (&'static str, fn(&DrmDeviceThrottleReasons) -> bool, Color);
+        let reasons: [TrReason; 9] = [
+            ("Status", |tr| tr.status, tailwind::SLATE.c400),
+            ("PL1", |tr| tr.pl1, tailwind::ORANGE.c600),
+            ("PL2", |tr| tr.pl2, tailwind::AMBER.c500),
+            ("PL4", |tr| tr.pl4, tailwind::YELLOW.c400),
+            ("RATL", |tr| tr.ratl, tailwind::LIME.c500),
+            ("VR_TDC", |tr| tr.vr_tdc, tailwind::CYAN.c500),
+            ("VR_THERM", |tr| tr.vr_thermalert, tailwind::VIOLET.c500),
+            ("PROCHOT", |tr| tr.prochot, tailwind::PINK.c500),
+            ("THERMAL", |tr| tr.thermal, tailwind::RED.c600),
+        ];
+        let tr_step = (maxy - miny) * 0.3 / reasons.len() as f64;
+        let mut tr_vals: Vec<Vec<(f64, f64)>> = vec![Vec::new(); reasons.len()];
+
         for (fqs, xval) in dinfo.dev_stats.freqs.iter().zip(x_vals.iter()) {
             cur_freq_ds.push((*xval, fqs[fq_nr].cur_freq as f64));
             act_freq_ds.push((*xval, fqs[fq_nr].act_freq as f64));
 
-            if fqs[fq_nr].throttle_reasons.pl1 {
-                tr_pl1.push((*xval, (miny + maxy) / 2.0));
-            } else {
-                tr_pl1.push((*xval, -1.0));  // hide it
-            }
-            if fqs[fq_nr].throttle_reasons.status {
-                tr_status.push((*xval, (miny + maxy) / 2.0));
-            } else {
-                tr_status.push((*xval, -1.0));  // hide it
+            for (nr, (_, is_on, _)) in reasons.iter().enumerate() {
+                if is_on(&fqs[fq_nr].throttle_reasons) {
+                    tr_vals[nr].push((*xval,
+                        miny + (nr as f64 + 0.5) * tr_step));
+                }
             }
         }
 
         let fq = &dinfo.dev_stats.freqs.back().unwrap()[fq_nr];
-        let datasets = vec![
+        let (cur_min, cur_max, cur_avg) = App::min_max_avg(
+            dinfo.dev_stats.freqs.iter().map(|f| f[fq_nr].cur_freq as f64));
+        let (act_min, act_max, act_avg) = App::min_max_avg(
+            dinfo.dev_stats.freqs.iter().map(|f| f[fq_nr].act_freq as f64));
+        let prec = App::precision(&*self.model.borrow());
+        let mut datasets = vec![
             Dataset::default()
-                .name(format!("Requested [{}]", fq.cur_freq))
+                .name(format!("Requested [{}] (min {:.*} max {:.*} avg {:.*})",
+                    fq.cur_freq, prec, cur_min, prec, cur_max, prec, cur_avg))
                 .marker(symbols::Marker::Braille)
                 .style(tailwind::BLUE.c700)
                 .graph_type(GraphType::Line)
                 .data(&cur_freq_ds),
             Dataset::default()
-                .name(format!("Actual    [{}]", fq.act_freq))
+                .name(format!("Actual    [{}] (min {:.*} max {:.*} avg {:.*})",
+                    fq.act_freq, prec, act_min, prec, act_max, prec, act_avg))
                 .marker(symbols::Marker::Braille)
                 .style(tailwind::GREEN.c700)
                 .graph_type(GraphType::Line)
                 .data(&act_freq_ds),
-            Dataset::default()
-                .name("Throttle: Status")
-                .marker(symbols::Marker::Braille)
-                .style(tailwind::ORANGE.c700)
-                .graph_type(GraphType::Line)
-                .data(&tr_status),
-            Dataset::default()
-                .name("Throttle: PL1")
-                .marker(symbols::Marker::Braille)
-                .style(tailwind::RED.c700)
-                .graph_type(GraphType::Line)
-                .data(&tr_pl1),
         ];
+        // only reasons seen in the stats window, to keep legend short
+        for ((name, _, color), vals) in reasons.iter().zip(tr_vals.iter()) {
+            if vals.is_empty() {
+                continue;
+            }
+            datasets.push(Dataset::default()
+                .name(format!("Throttle: {}", name))
+                .marker(symbols::Marker::Braille)
+                .style(*color)
+                .graph_type(GraphType::Scatter)
+                .data(vals));
+        }
 
         let y_bounds = [miny, maxy];
         let y_labels = vec![
-            Span::raw(format!("{}", miny)),
-            Span::raw(format!("{}", (miny + maxy) / 2.0)),
-            Span::raw(format!("{}", maxy)),
+            Span::raw(format!("{:.*}", prec, miny)),
+            Span::raw(format!("{:.*}", prec, (miny + maxy) / 2.0)),
+            Span::raw(format!("{:.*}", prec, maxy)),
         ];
         let y_axis = Axis::default()
             .title("Freq (MHz)")
@@ -794,6 +1336,9 @@ impl MainScreen
             .bounds(y_bounds)
             .labels(y_labels);
 
+        let mkrs = App::marker_lines(&*self.model.borrow(), y_bounds);
+        datasets.extend(App::marker_datasets(&mkrs));
+
         frame.render_widget(Chart::new(datasets)
             .x_axis(x_axis)
             .y_axis(y_axis)
@@ -803,33 +1348,177 @@ impl MainScreen
             area);
     }
 
-    fn render_dev_stats(&self, dinfo: &AppDataDeviceState,
-        tstamps: &VecDeque<u128>, frame: &mut Frame, area: Rect)
+    fn dev_stats_one_row(&self, dinfo: &AppDataDeviceState, width: u16) -> bool
     {
+        // only engines gauges, no freqs + power row
+        if self.model.borrow().args().engines_only {
+            return true;
+        }
+
         let is_dgfx = dinfo.dev_type.is_discrete();
-        let nr_engines = dinfo.eng_names.len();
+        let nr_engines = App::shown_engines(
+            &*self.model.borrow(), dinfo.eng_names.iter()).len();
         let nr_freqs = dinfo.dev_stats.freqs.back().unwrap().len();
+        let has_gtt = dinfo.dev_stats.mem_info.back().unwrap().has_gtt();
 
-        // nr_stats = smem + vram (if dgfx) + # engines + # freqs + power
-        let nr_stats = 1 + is_dgfx as usize + nr_engines + nr_freqs + 1;
+        // nr_stats = smem + vram (if dgfx) + gtt (if any) + # engines +
+        // # freqs + clock efficiency (if available) + power
+        let nr_stats = 1 + is_dgfx as usize + has_gtt as usize +
+            nr_engines + nr_freqs +
+            MainScreen::has_clock_efficiency(dinfo) as usize + 1;
         // Can stats fit in just a single table row or not?
         // If not, separate meminfo + engines and freqs + power
-        let one_row = nr_stats * 10 <= area.width as usize;
+        nr_stats * 10 <= width as usize
+    }
 
-        let [inf_area, dstats_area, sep, chart_area] = Layout::vertical([
-            Constraint::Length(1),
-            Constraint::Length(if one_row { 2 } else { 4 }),
-            Constraint::Length(1),
-            Constraint::Fill(1),
-        ]).areas(area);
+    // number of engines gauges in each row, the first one shared with
+    // meminfo gauges and any others only with engines
+    fn dev_stats_eng_rows(&self, dinfo: &AppDataDeviceState, width: u16) -> Vec<usize>
+    {
+        let nr_engines = App::shown_engines(
+            &*self.model.borrow(), dinfo.eng_names.iter()).len();
+        let engines_only = self.model.borrow().args().engines_only;
+        if !engines_only && self.dev_stats_one_row(dinfo, width) {
+            return vec![nr_engines];
+        }
+
+        let is_dgfx = dinfo.dev_type.is_discrete();
+        let has_gtt = dinfo.dev_stats.mem_info.back().unwrap().has_gtt();
+        let nr_mem = if engines_only {
+            0 } else { 1 + is_dgfx as usize + has_gtt as usize };
+
+        // each gauge plus column spacing
+        let width = width as usize + 1;
+        let first = width.saturating_sub(nr_mem * 13) /
+            (ENGINE_GAUGE_MIN_WIDTH + 1);
+        let per_row = (width / (ENGINE_GAUGE_MIN_WIDTH + 1)).max(1);
+
+        let mut rows = vec![nr_engines.min(first)];
+        let mut left = nr_engines - rows[0];
+        while left > 0 {
+            let nr = left.min(per_row);
+            rows.push(nr);
+            left -= nr;
+        }
+
+        rows
+    }
+
+    // achieved/requested clock of the least efficient active GT, as GTs
+    // in RC6 (or similar) report no actual freq while requesting one
+    fn clock_efficiency(fqs: &[DrmDeviceFreqs]) -> Option<f64>
+    {
+        fqs.iter()
+            .filter(|fq| fq.cur_freq > 0 && fq.act_freq > 0)
+            .map(|fq| f64::min(fq.act_freq as f64 / fq.cur_freq as f64, 1.0))
+            .min_by(|a, b| a.total_cmp(b))
+    }
+
+    // for now only i915 and Xe report a requested freq, others either have
+    // none or just the actual one
+    fn has_clock_efficiency(dinfo: &AppDataDeviceState) -> bool
+    {
+        !dinfo.dev_stats.freqs.back().unwrap().is_empty() &&
+            (dinfo.drv_name == "i915" || dinfo.drv_name == "xe")
+    }
+
+    // for now only valid on i915 and Xe drivers
+    fn has_throttle_reasons(dinfo: &AppDataDeviceState) -> bool
+    {
+        dinfo.drv_name == "i915" || dinfo.drv_name == "xe"
+    }
+
+    fn render_throttle_badges(&self,
+        dinfo: &AppDataDeviceState, frame: &mut Frame, area: Rect)
+    {
+        // reason is active if any of the freqs is throttled by it
+        let fqs = dinfo.dev_stats.freqs.back().unwrap();
+        let any_tr = |f: fn(&DrmDeviceThrottleReasons) -> bool| {
+            fqs.iter().any(|fq| f(&fq.throttle_reasons))
+        };
+        let badges = [
+            ("THERMAL", any_tr(|tr| tr.thermal), tailwind::RED.c600),
+            ("PL1", any_tr(|tr| tr.pl1), tailwind::ORANGE.c600),
+            ("PL2", any_tr(|tr| tr.pl2), tailwind::ORANGE.c600),
+            ("PL4", any_tr(|tr| tr.pl4), tailwind::ORANGE.c600),
+            ("PROCHOT", any_tr(|tr| tr.prochot), tailwind::RED.c600),
+            ("RATL", any_tr(|tr| tr.ratl), tailwind::ORANGE.c600),
+            ("VR_TDC", any_tr(|tr| tr.vr_tdc), tailwind::ORANGE.c600),
+            ("VR_THERM", any_tr(|tr| tr.vr_thermalert), tailwind::RED.c600),
+        ];
+
+        let mut spans: Vec<Span> = vec!["THROTTLE: ".white().bold()];
+        for (name, active, color) in badges {
+            let bg = if active { color } else { tailwind::GRAY.c700 };
+            spans.push(Span::styled(format!(" {} ", name),
+                Style::new().white().bold().bg(bg)));
+            spans.push(" ".into());
+        }
+
+        // idle (RC6) residency of each GT, if available
+        let prec = App::precision(&*self.model.borrow());
+        let idles: Vec<String> = fqs.iter().enumerate()
+            .filter_map(|(nr, fq)| {
+                let ir = fq.idle_res?;
+                if fqs.len() == 1 {
+                    return Some(format!("{:.*}%", prec, ir));
+                }
+                let name = dinfo.freq_limits.get(nr)
+                    .map_or(String::new(), |fl| fl.name.to_uppercase());
+                Some(format!("{} {:.*}%", name, prec, ir))
+            })
+            .collect();
+        if !idles.is_empty() {
+            spans.push("  IDLE: ".white().bold());
+            spans.push(idles.join(", ").into());
+        }
+
+        frame.render_widget(Line::from(spans)
+            .alignment(Alignment::Center)
+            .style(Style::new().on_black()),
+            area);
+    }
+
+    fn render_dev_stats(&self, dinfo: &AppDataDeviceState,
+        tstamps: &VecDeque<u128>, frame: &mut Frame, area: Rect)
+    {
+        let is_dgfx = dinfo.dev_type.is_discrete();
+        let model = self.model.borrow();
+        let shown_engs = App::shown_engines(&*model, dinfo.eng_names.iter());
+        let si_units = App::si_units(&*model);
+        let prec = App::precision(&*model);
+        let nr_engines = shown_engs.len();
+        let nr_freqs = dinfo.dev_stats.freqs.back().unwrap().len();
+        let has_eff = MainScreen::has_clock_efficiency(dinfo);
+        let has_gtt = dinfo.dev_stats.mem_info.back().unwrap().has_gtt();
+        let one_row = self.dev_stats_one_row(dinfo, area.width);
+        let eng_rows = self.dev_stats_eng_rows(dinfo, area.width);
+        let nr_wrap_rows = eng_rows.len() - 1;
+        // engines-only layout drops meminfo, freqs and power stats
+        let engines_only = model.args().engines_only;
+        let nr_mem = if engines_only {
+            0 } else { 1 + is_dgfx as usize + has_gtt as usize };
+        let has_tr = !engines_only && MainScreen::has_throttle_reasons(dinfo);
+        let has_ntc = !dinfo.notices.is_empty();
+
+        let [inf_area, ntc_area, dstats_area, tr_area, sep, chart_area] =
+            Layout::vertical([
+                Constraint::Length(1),
+                Constraint::Length(has_ntc as u16),
+                Constraint::Length(if one_row { 2 } else { 4 } +
+                    2 * nr_wrap_rows as u16),
+                Constraint::Length(has_tr as u16),
+                Constraint::Length(1),
+                Constraint::Fill(1),
+            ]).areas(area);
 
         // render some device info and mem/engines/freqs/power stats
-        let widths = vec![
+        let mut widths = vec![
             Constraint::Fill(1),
             Constraint::Fill(1),
             Constraint::Fill(2),
         ];
-        let rows = [Row::new([
+        let mut infos = vec![
             Line::from(vec![
                 "DRIVER: ".white().bold(),
                 dinfo.drv_name.clone().into()])
@@ -842,61 +1531,195 @@ impl MainScreen
                 "DEVICE NODES: ".white().bold(),
                 dinfo.dev_nodes.clone().into()])
             .alignment(Alignment::Center),
-        ])];
+        ];
+        if !shown_engs.is_empty() && !dinfo.eng_capacity.is_empty() {
+            let eng_caps: Vec<String> = shown_engs.iter()
+                .map(|en| format!("{} x{}", en.to_uppercase(),
+                    dinfo.eng_capacity.get(*en).unwrap_or(&1)))
+                .collect();
+            widths.push(Constraint::Fill(2));
+            infos.push(Line::from(vec![
+                "ENGINES: ".white().bold(),
+                eng_caps.join(", ").into()])
+            .alignment(Alignment::Center));
+        }
+        let hwi = &dinfo.hwmon_info;
+        if hwi.power_cap > 0.0 {
+            widths.push(Constraint::Fill(1));
+            infos.push(Line::from(vec![
+                "PWR CAP: ".white().bold(),
+                format!("{:.*} W", prec, hwi.power_cap).into()])
+            .alignment(Alignment::Center));
+        }
+        if !hwi.fan_mode.is_empty() {
+            widths.push(Constraint::Fill(1));
+            infos.push(Line::from(vec![
+                "FAN: ".white().bold(),
+                hwi.fan_mode.clone().into()])
+            .alignment(Alignment::Center));
+        }
+        let temp = &dinfo.temperature;
+        if temp.gpu > 0.0 {
+            let mut temp_str = format!("{:.0} C", temp.gpu);
+            let extra: Vec<String> = [("HOT", temp.hotspot), ("MEM", temp.mem)]
+                .iter()
+                .filter(|(_, t)| *t > 0.0)
+                .map(|(n, t)| format!("{} {:.0} C", n, t))
+                .collect();
+            if !extra.is_empty() {
+                temp_str.push_str(&format!(" ({})", extra.join(", ")));
+            }
+            widths.push(Constraint::Fill(2));
+            infos.push(Line::from(vec![
+                "TEMP: ".white().bold(),
+                temp_str.into()])
+            .alignment(Alignment::Center));
+        }
+        if let Some(busy) = dinfo.busy {
+            widths.push(Constraint::Fill(1));
+            infos.push(Line::from(vec![
+                "BUSY: ".white().bold(),
+                format!("{:.*}%", prec, busy).into()])
+            .alignment(Alignment::Center));
+        }
+        if !dinfo.perf_profile.is_empty() {
+            widths.push(Constraint::Fill(1));
+            infos.push(Line::from(vec![
+                "PERF: ".white().bold(),
+                dinfo.perf_profile.clone().into()])
+            .alignment(Alignment::Center));
+        }
+        if !dinfo.displays.is_empty() {
+            let disps: Vec<String> = dinfo.displays.iter()
+                .map(|d| format!("{} {}", d.connector, d.mode))
+                .collect();
+            widths.push(Constraint::Fill(2));
+            infos.push(Line::from(vec![
+                "DISPLAYS: ".white().bold(),
+                format!("{} ({})", disps.len(), disps.join(", ")).into()])
+            .alignment(Alignment::Center));
+        }
+        if !dinfo.firmware.is_empty() {
+            let fws: Vec<String> = dinfo.firmware.iter()
+                .map(|(name, ver)| format!("{} {}", name, ver))
+                .collect();
+            widths.push(Constraint::Fill(2));
+            infos.push(Line::from(vec![
+                "FW: ".white().bold(),
+                fws.join(", ").into()])
+            .alignment(Alignment::Center));
+        }
+        if let Some(rc) = dinfo.reset_count {
+            // flag resets that happened while watching the device
+            let rc_span = if dinfo.new_resets > 0 {
+                format!("{} (+{})", rc, dinfo.new_resets).light_red().bold()
+            } else {
+                rc.to_string().into()
+            };
+            widths.push(Constraint::Fill(1));
+            infos.push(Line::from(vec![
+                "RESETS: ".white().bold(),
+                rc_span])
+            .alignment(Alignment::Center));
+        }
+        for (name, rate) in dinfo.pmu_rates.iter() {
+            widths.push(Constraint::Fill(1));
+            infos.push(Line::from(vec![
+                format!("{}: ", name.to_uppercase()).white().bold(),
+                format!("{:.0}/s", rate).into()])
+            .alignment(Alignment::Center));
+        }
+        let rows = [Row::new(infos)];
         frame.render_widget(Table::new(rows, widths)
             .style(Style::new().white().on_black())
             .column_spacing(1),
             inf_area);
 
+        // render reasons for missing stats (if any)
+        if has_ntc {
+            let ntc_line = Line::from(vec![
+                "NOTE: ".light_yellow().bold(),
+                dinfo.notices.join("; ").into()])
+            .style(Style::new().white().on_black());
+            let ntc_w = ntc_line.width();
+            frame.render_widget(ntc_line.alignment(
+                if ntc_w < ntc_area.width as usize {
+                    Alignment::Center } else { Alignment::Left }),
+                ntc_area);
+        }
+
         // change selected chart, if needed
+        let has_sched = dinfo.dev_stats.eng_sched.back()
+            .is_some_and(|es| !es.is_empty());
         let nr_charts: Vec<u8> = vec![
             nr_freqs as u8,          // FREQS
             1,                       // POWER
             1,                       // MEMINFO
-            (nr_engines > 0) as u8,  // ENGINES
+            // ENGINES (+ histogram and PMU waits, if enabled)
+            if nr_engines > 0 { 2 + has_sched as u8 } else { 0 },
         ];
         let mut ds_st = self.dstats_state.borrow_mut();
-        ds_st.exec_req(&nr_charts);
-
-        let hdr_area: Rect;
-        let mut hdr2_area = Rect::ZERO;
-        let gauges_area: Rect;
-        let mut gauges2_area = Rect::ZERO;
-        if one_row {
-            [hdr_area, gauges_area] = Layout::vertical([
-                Constraint::Length(1),
-                Constraint::Length(1),
-            ]).areas(dstats_area);
+        if engines_only {
+            ds_st.exec_req_engines(nr_charts[DEVICE_STATS_ENGINES as usize]);
         } else {
-            [hdr_area, gauges_area, hdr2_area, gauges2_area] =
-                Layout::vertical([
-                    Constraint::Length(1),
-                    Constraint::Length(1),
-                    Constraint::Length(1),
-                    Constraint::Length(1),
-                ]).areas(dstats_area);
+            ds_st.exec_req(&nr_charts);
         }
+        // selected chart may not be available (e.g. no engines yet)
+        if !engines_only && nr_charts[ds_st.sel as usize] == 0 {
+            ds_st.exec_next(&nr_charts);
+        } else if !engines_only &&
+            ds_st.sub_sel >= nr_charts[ds_st.sel as usize] {
+            ds_st.sub_sel = 0;
+        }
+
+        // header and gauges rows: meminfo + engines, wrapped engines
+        // (if any) and freqs + power (if not in a single row)
+        let nr_rows = 2 * (1 + nr_wrap_rows) + if one_row { 0 } else { 2 };
+        let rows_areas = Layout::vertical(
+            vec![Constraint::Length(1); nr_rows]).split(dstats_area);
+        let hdr_area = rows_areas[0];
+        let gauges_area = rows_areas[1];
+        let (hdr2_area, gauges2_area) = if one_row {
+            (Rect::ZERO, Rect::ZERO)
+        } else {
+            (rows_areas[nr_rows - 2], rows_areas[nr_rows - 1])
+        };
+        let eng_per_row = if nr_wrap_rows > 0 { eng_rows[1] } else { 0 };
+        let wrap_widths = vec![Constraint::Fill(1); eng_per_row];
+        let wrap_gs_areas: Vec<Rc<[Rect]>> = (0..nr_wrap_rows)
+            .map(|wr| Layout::horizontal(&wrap_widths)
+                .split(rows_areas[3 + 2 * wr]))
+            .collect();
 
         let mut dstats_widths: Vec<Constraint> = Vec::new();
         let mut dstats2_widths: Vec<Constraint> = Vec::new();
-        dstats_widths.push(Constraint::Length(12));   // SMEM
-        if is_dgfx {
-            dstats_widths.push(Constraint::Length(12));   // VRAM
+        for _ in 0..nr_mem {
+            dstats_widths.push(Constraint::Length(12));   // SMEM, VRAM, GTT
         }
-        for _ in 0..nr_engines {
+        for _ in 0..eng_rows[0] {
             dstats_widths.push(Constraint::Fill(1));  // ENGINES
         }
         let ds_widths_ref: &mut Vec<Constraint> = if one_row {
             &mut dstats_widths } else { &mut dstats2_widths };
-        for _ in 0..nr_freqs {
-            ds_widths_ref.push(Constraint::Min(10));      // FREQS
+        if !engines_only {
+            for _ in 0..nr_freqs {
+                ds_widths_ref.push(Constraint::Min(10));      // FREQS
+            }
+            if has_eff {
+                ds_widths_ref.push(Constraint::Min(8));       // CLK-EFF
+            }
+            ds_widths_ref.push(Constraint::Min(12));      // POWER
         }
-        ds_widths_ref.push(Constraint::Min(12));      // POWER
 
         // split area for gauges early to calculate max engine name length
         let gs_areas = Layout::horizontal(&dstats_widths).split(gauges_area);
-        let en_width = if nr_engines > 0 {
-            gs_areas[if is_dgfx { 2 } else { 1 }].width as usize } else { 0 };
+        let en_width = if eng_rows[0] > 0 {
+            gs_areas[nr_mem].width as usize
+        } else if nr_wrap_rows > 0 {
+            wrap_gs_areas[0][0].width as usize
+        } else {
+            0
+        };
         let gs2_areas = if one_row {
             Rc::new([])
         } else {
@@ -905,50 +1728,77 @@ impl MainScreen
 
         let mut hdrs_lst: Vec<Line> = Vec::new();
         let mut hdrs2_lst: Vec<Line> = Vec::new();
+        let mut wrap_hdrs: Vec<Vec<Line>> = vec![Vec::new(); nr_wrap_rows];
         let wh_bold = Style::new().white().bold();
         let ly_bold = Style::new().light_yellow().bold();
 
-        hdrs_lst.push(Line::from("SMEM")
-            .alignment(Alignment::Center)
-            .style(if ds_st.sel == DEVICE_STATS_MEMINFO {
-                ly_bold } else { wh_bold }));
-        if is_dgfx {
-            hdrs_lst.push(Line::from("VRAM")
+        if !engines_only {
+            hdrs_lst.push(Line::from("SMEM")
                 .alignment(Alignment::Center)
                 .style(if ds_st.sel == DEVICE_STATS_MEMINFO {
                     ly_bold } else { wh_bold }));
+            if is_dgfx {
+                hdrs_lst.push(Line::from("VRAM")
+                    .alignment(Alignment::Center)
+                    .style(if ds_st.sel == DEVICE_STATS_MEMINFO {
+                        ly_bold } else { wh_bold }));
+            }
+            if has_gtt {
+                hdrs_lst.push(Line::from("GTT")
+                    .alignment(Alignment::Center)
+                    .style(if ds_st.sel == DEVICE_STATS_MEMINFO {
+                        ly_bold } else { wh_bold }));
+            }
         }
-        for en in dinfo.eng_names.iter() {
-            hdrs_lst.push(Line::from(en.to_uppercase())
+        for (nr, en) in shown_engs.iter().enumerate() {
+            let en_hdr = Line::from(en.to_uppercase())
                 .alignment(if en.len() > en_width {
                     Alignment::Left } else { Alignment::Center })
                 .style(if ds_st.sel == DEVICE_STATS_ENGINES {
-                    ly_bold } else { wh_bold }));
+                    ly_bold } else { wh_bold });
+            if nr < eng_rows[0] {
+                hdrs_lst.push(en_hdr);
+            } else {
+                wrap_hdrs[(nr - eng_rows[0]) / eng_per_row].push(en_hdr);
+            }
         }
         let hdrs_lst_ref: &mut Vec<Line> = if one_row {
             &mut hdrs_lst } else { &mut hdrs2_lst };
-        for fq_nr in 0..nr_freqs {
-            let fql = &dinfo.freq_limits[fq_nr];
-            let label = if fql.name.is_empty() {
-                format!("FRQ-{}", fq_nr)
-            } else {
-                format!("FRQ-{}", &fql.name.to_uppercase())
-            };
-            hdrs_lst_ref.push(Line::from(label)
+        if !engines_only {
+            for fq_nr in 0..nr_freqs {
+                let fql = &dinfo.freq_limits[fq_nr];
+                let label = if fql.name.is_empty() {
+                    format!("FRQ-{}", fq_nr)
+                } else {
+                    format!("FRQ-{}", &fql.name.to_uppercase())
+                };
+                hdrs_lst_ref.push(Line::from(label)
+                    .alignment(Alignment::Center)
+                    .style(if ds_st.sel == DEVICE_STATS_FREQS &&
+                        ds_st.sub_sel == fq_nr as u8 { ly_bold } else { wh_bold }));
+            }
+            if has_eff {
+                hdrs_lst_ref.push(Line::from("CLK-EFF")
+                    .alignment(Alignment::Center)
+                    .style(wh_bold));
+            }
+            hdrs_lst_ref.push(Line::from("POWER")
                 .alignment(Alignment::Center)
-                .style(if ds_st.sel == DEVICE_STATS_FREQS &&
-                    ds_st.sub_sel == fq_nr as u8 { ly_bold } else { wh_bold }));
+                .style(if ds_st.sel == DEVICE_STATS_POWER {
+                    ly_bold } else { wh_bold }));
         }
-        hdrs_lst_ref.push(Line::from("POWER")
-            .alignment(Alignment::Center)
-            .style(if ds_st.sel == DEVICE_STATS_POWER {
-                ly_bold } else { wh_bold }));
 
         let dstats_hdr = [Row::new(hdrs_lst)];
         frame.render_widget(Table::new(dstats_hdr, &dstats_widths)
             .style(Style::new().on_dark_gray())
             .column_spacing(1),
             hdr_area);
+        for (wr, w_hdrs) in wrap_hdrs.into_iter().enumerate() {
+            frame.render_widget(Table::new([Row::new(w_hdrs)], &wrap_widths)
+                .style(Style::new().on_dark_gray())
+                .column_spacing(1),
+                rows_areas[2 + 2 * wr]);
+        }
         if !one_row {
             let dstats2_hdr = [Row::new(hdrs2_lst)];
             frame.render_widget(Table::new(dstats2_hdr, &dstats2_widths)
@@ -959,62 +1809,107 @@ impl MainScreen
 
         let mut dstats_gs: Vec<Gauge> = Vec::new();
         let mut dstats2_gs: Vec<Gauge> = Vec::new();
+        let mut wrap_gs: Vec<Vec<Gauge>> = vec![Vec::new(); nr_wrap_rows];
 
-        let mi = dinfo.dev_stats.mem_info.back().unwrap();
-        let smem_label = Span::styled(format!("{}/{}",
-            App::short_mem_string(mi.smem_used),
-            App::short_mem_string(mi.smem_total)),
-            Style::new().white());
-        let smem_ratio = if mi.smem_total > 0 {
-            mi.smem_used as f64 / mi.smem_total as f64 } else { 0.0 };
-        dstats_gs.push(App::gauge_colored_from(smem_label, smem_ratio));
-        if is_dgfx {
-            let vram_label = Span::styled(format!("{}/{}",
-                App::short_mem_string(mi.vram_used),
-                App::short_mem_string(mi.vram_total)),
+        if !engines_only {
+            let mi = dinfo.dev_stats.mem_info.back().unwrap();
+            let smem_label = Span::styled(format!("{}/{}",
+                App::short_mem_string(mi.smem_used, si_units),
+                App::short_mem_string(mi.smem_total, si_units)),
                 Style::new().white());
-            let vram_ratio = if mi.vram_total > 0 {
-                mi.vram_used as f64 / mi.vram_total as f64 } else { 0.0 };
-            dstats_gs.push(App::gauge_colored_from(vram_label, vram_ratio));
+            let smem_ratio = if mi.smem_total > 0 {
+                mi.smem_used as f64 / mi.smem_total as f64 } else { 0.0 };
+            dstats_gs.push(App::gauge_colored_from(smem_label, smem_ratio));
+            if is_dgfx {
+                let vram_label = Span::styled(format!("{}/{}",
+                    App::short_mem_string(mi.vram_used, si_units),
+                    App::short_mem_string(mi.vram_total, si_units)),
+                    Style::new().white());
+                let vram_ratio = if mi.vram_total > 0 {
+                    mi.vram_used as f64 / mi.vram_total as f64 } else { 0.0 };
+                dstats_gs.push(App::gauge_colored_from(vram_label, vram_ratio));
+            }
+            if has_gtt {
+                let gtt_label = Span::styled(format!("{}/{}",
+                    App::short_mem_string(mi.gtt_used, si_units),
+                    App::short_mem_string(mi.gtt_total, si_units)),
+                    Style::new().white());
+                let gtt_ratio = mi.gtt_used as f64 / mi.gtt_total as f64;
+                dstats_gs.push(App::gauge_colored_from(gtt_label, gtt_ratio));
+            }
         }
 
-        for en in dinfo.eng_names.iter() {
+        // first sample has no deltas yet to compute engines usage
+        let engs_ready = model.timestamps().len() > 1;
+        for (nr, &en) in shown_engs.iter().enumerate() {
             let eut = dinfo.dev_stats.eng_usage[en].back().unwrap();
             let label = Span::styled(
-                format!("{:.1}%", eut), Style::new().white());
+                App::eng_usage_string(*eut, engs_ready, prec), Style::new().white());
 
-            dstats_gs.push(App::gauge_colored_from(label, eut/100.0));
+            let en_g = App::gauge_colored_from(label, eut/100.0);
+            if nr < eng_rows[0] {
+                dstats_gs.push(en_g);
+            } else {
+                wrap_gs[(nr - eng_rows[0]) / eng_per_row].push(en_g);
+            }
         }
 
         let ds_gs_ref: &mut Vec<Gauge> = if one_row {
             &mut dstats_gs } else { &mut dstats2_gs };
 
-        for fq in dinfo.dev_stats.freqs.back().unwrap().iter() {
-            let fq_label = Span::styled(
-                format!("{}/{}", fq.act_freq, fq.cur_freq),
+        if !engines_only {
+            let fqs = dinfo.dev_stats.freqs.back().unwrap();
+            for fq in fqs.iter() {
+                let fq_label = Span::styled(
+                    format!("{}/{}", fq.act_freq, fq.cur_freq),
+                    Style::new().white());
+                let fq_ratio = if fq.cur_freq > 0 {
+                    fq.act_freq as f64 / fq.cur_freq as f64 } else { 0.0 };
+                ds_gs_ref.push(App::gauge_colored_from(fq_label, fq_ratio));
+            }
+            if has_eff {
+                // no active GTs, nothing to tell about
+                let eff = MainScreen::clock_efficiency(fqs);
+                let eff_label = Span::styled(eff.map_or(String::from("-"),
+                    |ef| format!("{:.*}%", prec, ef * 100.0)),
+                    Style::new().white());
+                ds_gs_ref.push(App::gauge_efficiency_from(
+                    eff_label, eff.unwrap_or(0.0)));
+            }
+
+            let pwr = dinfo.dev_stats.power.back().unwrap();
+            let pwr_label = Span::styled(
+                format!("{:.*}/{:.*}", prec, pwr.gpu_cur_power, prec, pwr.pkg_cur_power),
                 Style::new().white());
-            let fq_ratio = if fq.cur_freq > 0 {
-                fq.act_freq as f64 / fq.cur_freq as f64 } else { 0.0 };
-            ds_gs_ref.push(App::gauge_colored_from(fq_label, fq_ratio));
+            let pwr_ratio = if pwr.pkg_cur_power > 0.0 {
+                pwr.gpu_cur_power / pwr.pkg_cur_power } else { 0.0 };
+            ds_gs_ref.push(App::gauge_colored_from(pwr_label, pwr_ratio));
         }
 
-        let pwr = dinfo.dev_stats.power.back().unwrap();
-        let pwr_label = Span::styled(
-            format!("{:.1}/{:.1}", pwr.gpu_cur_power, pwr.pkg_cur_power),
-            Style::new().white());
-        let pwr_ratio = if pwr.pkg_cur_power > 0.0 {
-            pwr.gpu_cur_power / pwr.pkg_cur_power } else { 0.0 };
-        ds_gs_ref.push(App::gauge_colored_from(pwr_label, pwr_ratio));
-
         for (ds_g, ds_a) in dstats_gs.iter().zip(gs_areas.iter()) {
             frame.render_widget(ds_g, *ds_a);
         }
+        for (w_gs, w_areas) in wrap_gs.iter().zip(wrap_gs_areas.iter()) {
+            for (w_g, w_a) in w_gs.iter().zip(w_areas.iter()) {
+                frame.render_widget(w_g, *w_a);
+            }
+        }
         if !one_row {
             for (ds2_g, ds2_a) in dstats2_gs.iter().zip(gs2_areas.iter()) {
                 frame.render_widget(ds2_g, *ds2_a);
             }
         }
 
+        // render active throttle reasons (if supported)
+        if has_tr {
+            self.render_throttle_badges(dinfo, frame, tr_area);
+        }
+
+        // no charts in compact layout
+        if model.args().compact {
+            return;
+        }
+
         // render separator line
         frame.render_widget(Block::new().borders(Borders::TOP)
             .border_type(BorderType::Plain)
@@ -1026,32 +1921,7 @@ impl MainScreen
         for ts in tstamps.iter() {
             x_vals.push(*ts as f64 / 1000.0);
         }
-        let x_bounds: [f64; 2];
-        let mut x_labels: Vec<Span>;
-        if x_vals.len() == 1 {
-            let model = self.model.borrow();
-            let int_secs = model.args().ms_interval as f64 / 1000.0;
-            x_bounds = [x_vals[0], x_vals[0] + int_secs];
-            x_labels = vec![
-                Span::raw(format!("{:.1}", x_bounds[0])),
-                Span::raw(format!("{:.1}", x_bounds[1])),
-            ];
-        } else {
-            let xvlen = x_vals.len();
-            x_bounds = [x_vals[0], x_vals[xvlen - 1]];
-            x_labels = vec![
-                Span::raw(format!("{:.1}", x_vals[0])),
-                Span::raw(format!("{:.1}", x_vals[xvlen / 2])),
-            ];
-            if xvlen >= 3 {
-                x_labels.push(Span::raw(format!("{:.1}", x_vals[xvlen - 1])));
-            }
-        }
-        let x_axis = Axis::default()
-            .title("Time (s)")
-            .style(Style::new().white())
-            .bounds(x_bounds)
-            .labels(x_labels);
+        let x_axis = App::time_axis(&*model, &x_vals);
 
         match ds_st.sel {
             DEVICE_STATS_FREQS => {
@@ -1067,8 +1937,15 @@ impl MainScreen
                     &x_vals, x_axis, dinfo, frame, chart_area);
             },
             DEVICE_STATS_ENGINES => {
-                self.render_engines_chart(
-                    &x_vals, x_axis, dinfo, frame, chart_area);
+                if ds_st.sub_sel == ENGINES_CHART_USAGE {
+                    self.render_engines_chart(
+                        &x_vals, x_axis, dinfo, frame, chart_area);
+                } else if ds_st.sub_sel == ENGINES_CHART_HIST {
+                    self.render_engines_hist_chart(dinfo, frame, chart_area);
+                } else if ds_st.sub_sel == ENGINES_CHART_SCHED {
+                    self.render_engines_sched_chart(
+                        &x_vals, x_axis, dinfo, frame, chart_area);
+                }
             },
             _ => {
                 error!("Unknown device stats selection: {:?}", ds_st.sel);
@@ -1079,15 +1956,30 @@ impl MainScreen
     fn render_drm_device(&self, dinfo: &AppDataDeviceState,
         tstamps: &VecDeque<u128>, frame: &mut Frame, area: Rect)
     {
+        // compact layout only has space for device info and gauges
+        let dev_blk_c = if self.model.borrow().args().compact {
+            let one_row = self.dev_stats_one_row(dinfo, area.width);
+            let nr_wrap_rows = self.dev_stats_eng_rows(dinfo, area.width).len() - 1;
+            let has_tr = !self.model.borrow().args().engines_only &&
+                MainScreen::has_throttle_reasons(dinfo);
+            let has_ntc = !dinfo.notices.is_empty();
+            Constraint::Length(if one_row { 4 } else { 6 } +
+                2 * nr_wrap_rows as u16 + has_tr as u16 + has_ntc as u16)
+        } else if self.model.borrow().args().engines_only {
+            // engines chart takes most of the screen
+            Constraint::Percentage(75)
+        } else {
+            Constraint::Max(26)
+        };
         let [dev_blk_area, clis_blk_area] = Layout::vertical([
-            Constraint::Max(26),
+            dev_blk_c,
             Constraint::Min(5),
         ]).areas(area);
 
         // render pci device block and stats
         let [dev_title_area, dev_stats_area] = Layout::vertical([
             Constraint::Length(1),
-            Constraint::Min(5),
+            Constraint::Min(3),
         ]).areas(dev_blk_area);
         let dev_title = Line::from(vec![
             " ".into(),
@@ -1104,7 +1996,22 @@ impl MainScreen
                         Alignment::Left } else { Alignment::Center })),
             dev_title_area);
 
-        self.render_dev_stats(dinfo, tstamps, frame, dev_stats_area);
+        // device stats window can be panned back in the retained samples,
+        // and frozen at the last sample when that was requested
+        let mut offset = self.stats_offset;
+        if let Some(fts) = self.frozen_at {
+            offset += tstamps.iter().filter(|&&ts| ts > fts).count();
+        }
+        offset = min(offset, tstamps.len().saturating_sub(1));
+        if offset > 0 {
+            let odinfo = dinfo.stats_at_offset(offset);
+            let nr_ts = max(tstamps.len().saturating_sub(offset), 1);
+            let otstamps: VecDeque<u128> = tstamps.iter()
+                .take(nr_ts).copied().collect();
+            self.render_dev_stats(&odinfo, &otstamps, frame, dev_stats_area);
+        } else {
+            self.render_dev_stats(dinfo, tstamps, frame, dev_stats_area);
+        }
 
         // render DRM clients block and stats
         let [clis_title_area, clis_stats_area] = Layout::vertical([
@@ -1113,10 +2020,15 @@ impl MainScreen
         ]).areas(clis_blk_area);
         let mut clis_title_str = String::from(" DRM clients ");
         let pid_opt = self.model.borrow().args().pid.clone();
+        let children_only = self.model.borrow().args().pid_children_only;
+        let cgroup_opt = self.model.borrow().args().cgroup.clone();
+        if let Some(cgroup) = cgroup_opt {
+            clis_title_str.push_str(&format!("(cgroup {}) ", &cgroup));
+        }
         if let Some(base_pid) = pid_opt {
             if !base_pid.is_empty() {
-                clis_title_str.push_str(
-                    &format!("(PID tree at {}) ", &base_pid));
+                clis_title_str.push_str(&format!("(PID tree {}at {}) ",
+                    if children_only { "children " } else { "" }, &base_pid));
             }
         }
         let clis_title = Line::from(vec![clis_title_str.into(),])
@@ -1128,6 +2040,14 @@ impl MainScreen
             .title_top(clis_title.alignment(Alignment::Center)),
             clis_title_area);
 
+        if self.model.borrow().args().no_clients {
+            frame.render_widget(Line::from("client scanning disabled")
+                .alignment(Alignment::Center)
+                .style(Style::new().white().on_black()),
+                clis_stats_area);
+            return;
+        }
+
         // if no DRM clients, nothing more to render
         if dinfo.clis_stats.is_empty() {
             return;
@@ -1139,20 +2059,95 @@ impl MainScreen
     fn render_devs_tab(&self,
         devs_ts: &DevicesTabState, frame: &mut Frame, area: Rect)
     {
-        frame.render_widget(Tabs::new(devs_ts.devs.clone())
+        // flash tabs of devices with triggered alerts
+        let model = self.model.borrow();
+        let flash_on = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map_or(true, |d| d.as_millis() / 500 % 2 == 0);
+        let mut tabs: Vec<Line> = Vec::new();
+        for dn in devs_ts.devs.iter() {
+            let dinfo = model.get_device(dn);
+            let has_alerts = dinfo.is_some_and(|di| !di.alerts.is_empty());
+            let dn = if dinfo.is_some_and(|di| di.is_external) {
+                format!("{} (eGPU)", dn) } else { dn.clone() };
+            if has_alerts && flash_on {
+                tabs.push(Line::from(format!("{} !", dn))
+                    .style(Style::new().white().bold().on_red()));
+            } else if has_alerts {
+                tabs.push(Line::from(format!("{} !", dn)));
+            } else {
+                tabs.push(Line::from(dn));
+            }
+        }
+
+        frame.render_widget(Tabs::new(tabs)
             .style(Style::new().white().bold().on_black())
             .highlight_style(Style::new().magenta().bold().on_black())
             .select(devs_ts.sel),
             area);
     }
 
+    fn render_devs_strip(&self,
+        devs_ts: &DevicesTabState, frame: &mut Frame, area: Rect)
+    {
+        let model = self.model.borrow();
+        let prec = App::precision(&*model);
+        let mut rows = Vec::new();
+        for (nr, dn) in devs_ts.devs.iter().enumerate() {
+            let dinfo = match model.get_device(dn) {
+                Some(dinfo) => dinfo,
+                None => continue,
+            };
+            let dstats = &dinfo.dev_stats;
+
+            // busiest engine in the last iteration
+            let busy = dstats.eng_usage.values()
+                .filter_map(|eu| eu.back().copied())
+                .fold(0.0, f64::max);
+            let mem_str = match dstats.mem_info.back() {
+                Some(mi) if dinfo.dev_type.is_discrete() && mi.vram_total > 0 =>
+                    format!("{:.*}%", prec,
+                        mi.vram_used as f64 / mi.vram_total as f64 * 100.0),
+                _ => String::from("-"),
+            };
+            let pwr = dstats.power.back().map_or(0.0, |p| p.gpu_cur_power);
+
+            let style = if nr == devs_ts.sel {
+                Style::new().magenta().bold() } else { Style::new().white() };
+            rows.push(Row::new(vec![
+                Line::from(dn.clone()),
+                Line::from(vec!["BUSY: ".bold(), format!("{:.*}%", prec, busy).into()]),
+                Line::from(vec!["VRAM: ".bold(), mem_str.into()]),
+                Line::from(vec!["POWER: ".bold(), format!("{:.*} W", prec, pwr).into()]),
+            ]).style(style));
+        }
+
+        let widths = [
+            Constraint::Length(14),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+        ];
+        frame.render_widget(Table::new(rows, widths)
+            .column_spacing(1)
+            .style(Style::new().on_black()),
+            area);
+    }
+
     pub fn new(model: Rc<RefCell<dyn AppData>>) -> Box<dyn Screen>
     {
+        let dstats_st = DeviceStatsState::from(
+            model.borrow().args().select_chart.as_deref());
+
         Box::new(MainScreen {
             model,
             tab_state: None,
-            dstats_state: RefCell::new(DeviceStatsState::new()),
+            dstats_state: RefCell::new(dstats_st),
             clis_state: RefCell::new(ClientsViewState::new()),
+            save_msg: None,
+            stashed_hide_engs: None,
+            stats_offset: 0,
+            frozen_at: None,
         })
     }
 }
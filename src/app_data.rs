@@ -1,20 +1,29 @@
 use core::fmt::Debug;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::cell::{RefCell, Ref};
-use std::fs::{self, File};
+use std::cmp::{max, min};
+use std::fs::{self, File, OpenOptions};
 use std::io::{Write, Seek, SeekFrom};
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::time;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use log::warn;
 use serde::{Deserialize, Serialize};
 use serde_json;
 
 use crate::CliArgs;
+use crate::alerts::Alert;
+use crate::focused_window::{self, FocusedWindow};
+use crate::stats_server::StatsServer;
 use crate::drm_devices::{
     DrmDeviceFreqLimits, DrmDeviceFreqs, DrmDevicePower,
-    DrmDeviceMemInfo, DrmDeviceType, DrmDeviceInfo, DrmDevices};
-use crate::drm_clients::{DrmClientMemInfo, DrmClientInfo};
+    DrmDeviceMemInfo, DrmDeviceHwmonInfo, DrmDeviceDisplay, DrmDeviceType,
+    DrmDeviceTemperature, DrmDeviceSources, DrmEngineSched, DrmDeviceInfo,
+    DrmDevices};
+use crate::drm_clients::{DrmClientMemInfo, DrmEngineDelta, DrmClientInfo};
 
 
 const APP_DATA_MAX_NR_STATS: usize = 40;
@@ -27,13 +36,16 @@ fn limited_vec_push<T>(vlst: &mut VecDeque<T>, vitem: T)
     vlst.push_back(vitem);
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppDataDeviceStats
 {
     pub freqs: VecDeque<Vec<DrmDeviceFreqs>>,
     pub power: VecDeque<DrmDevicePower>,
     pub mem_info: VecDeque<DrmDeviceMemInfo>,
     pub eng_usage: HashMap<String, VecDeque<f64>>,
+    // empty samples if the engines sched counters aren't enabled
+    #[serde(default)]
+    pub eng_sched: VecDeque<Vec<DrmEngineSched>>,
 }
 
 impl AppDataDeviceStats
@@ -44,6 +56,7 @@ impl AppDataDeviceStats
         limited_vec_push(&mut self.freqs, dinfo.freqs.clone());
         limited_vec_push(&mut self.power, dinfo.power.clone());
         limited_vec_push(&mut self.mem_info, dinfo.mem_info.clone());
+        limited_vec_push(&mut self.eng_sched, dinfo.eng_sched.clone());
 
         for en in eng_names.iter() {
             if !self.eng_usage.contains_key(en) {
@@ -54,6 +67,24 @@ impl AppDataDeviceStats
         }
     }
 
+    // drops the last nr stats samples, keeping at least one
+    fn truncate_back(&mut self, nr: usize)
+    {
+        let nr = min(nr, self.freqs.len().saturating_sub(1));
+        for _ in 0..nr {
+            self.freqs.pop_back();
+            self.power.pop_back();
+            self.mem_info.pop_back();
+            self.eng_sched.pop_back();
+        }
+
+        // engines seen later on may not have older samples
+        self.eng_usage.retain(|_, eu| eu.len() > nr);
+        for eu in self.eng_usage.values_mut() {
+            eu.truncate(eu.len() - nr);
+        }
+    }
+
     fn new(eng_names: &Vec<String>) -> AppDataDeviceStats
     {
         let mut estats = HashMap::new();
@@ -67,6 +98,7 @@ impl AppDataDeviceStats
             power: VecDeque::new(),
             mem_info: VecDeque::new(),
             eng_usage: estats,
+            eng_sched: VecDeque::new(),
         }
     }
 }
@@ -81,12 +113,102 @@ pub struct AppDataClientStats
     pub cmdline: String,
     pub cpu_usage: VecDeque<f64>,
     pub eng_usage: HashMap<String, VecDeque<f64>>,
+    #[serde(default)]
+    pub eng_delta: HashMap<String, VecDeque<DrmEngineDelta>>,
+    // engine-seconds over the client's lifetime, e.g. for accounting
+    #[serde(default)]
+    pub eng_busy_secs: HashMap<String, f64>,
     pub mem_info: VecDeque<DrmClientMemInfo>,
     pub is_active: bool,
+    // older JSON files don't tell memory-only clients apart
+    #[serde(default = "AppDataClientStats::default_true")]
+    pub engine_active: bool,
+    // false while collecting the first engines usage deltas
+    #[serde(default = "AppDataClientStats::default_true")]
+    pub eng_ready: bool,
+    #[serde(default)]
+    pub label: String,
+    // from the process owning the focused window (or its descendants)
+    #[serde(default)]
+    pub is_focused: bool,
+    #[serde(default)]
+    pub first_seen: u128,   // ms since start when first observed
+    #[serde(default)]
+    pub nr_contexts: u32,   // 0 if not reported by the driver
+    // running maxima over the client's lifetime
+    #[serde(default)]
+    pub peak_eng_usage: f64,
+    #[serde(default)]
+    pub peak_mem: u64,
 }
 
+// best-effort list of known Wayland/X11 compositors and display servers
+const KNOWN_COMPOSITORS: &[&str] = &[
+    "gnome-shell", "kwin_wayland", "kwin_x11", "Xorg", "Xwayland",
+    "sway", "weston", "mutter", "Hyprland", "wayfire", "labwc", "river",
+    "niri", "cosmic-comp", "gamescope", "enlightenment", "xfwm4", "picom",
+];
+
 impl AppDataClientStats
 {
+    // growth rate (bytes/s) of all memory used for each mem info sample,
+    // the latest one matches the latest timestamp
+    pub fn mem_rates(&self, tstamps: &VecDeque<u128>) -> Vec<f64>
+    {
+        let nr_mis = self.mem_info.len();
+        let ts_idx = tstamps.len().saturating_sub(nr_mis);
+        let used = |mi: &DrmClientMemInfo| {
+            (mi.smem_used + mi.vram_used + mi.gtt_used) as f64
+        };
+
+        let mut rates = Vec::with_capacity(nr_mis);
+        for i in 0..nr_mis {
+            if i == 0 || ts_idx + i >= tstamps.len() {
+                rates.push(0.0);
+                continue;
+            }
+            let ms = tstamps[ts_idx + i].saturating_sub(tstamps[ts_idx + i - 1]);
+            if ms == 0 {
+                rates.push(0.0);
+                continue;
+            }
+            let delta = used(&self.mem_info[i]) - used(&self.mem_info[i-1]);
+            rates.push(delta * 1000.0 / ms as f64);
+        }
+
+        rates
+    }
+
+    pub fn command(&self) -> String
+    {
+        if self.label.is_empty() {
+            format!("[{}] {}", &self.comm, &self.cmdline)
+        } else {
+            format!("{} [{}] {}", &self.label, &self.comm, &self.cmdline)
+        }
+    }
+
+    // DRM client ids can be reused after a process exits
+    fn is_same_process(&self, cinfo: &DrmClientInfo) -> bool
+    {
+        self.comm == cinfo.proc.comm && self.cmdline == cinfo.proc.cmdline
+    }
+
+    fn compositor_label(cinfo: &DrmClientInfo) -> String
+    {
+        if KNOWN_COMPOSITORS.contains(&cinfo.proc.comm.as_str()) {
+            return String::from("[compositor]");
+        }
+        // DRM client fd can be shared with the compositor process
+        for (sp, _) in cinfo.shared_procs.iter() {
+            if KNOWN_COMPOSITORS.contains(&sp.comm.as_str()) {
+                return format!("[{}]", &sp.comm);
+            }
+        }
+
+        String::new()
+    }
+
     fn update_stats(&mut self,
         eng_names: &Vec<String>, cinfo: &DrmClientInfo)
     {
@@ -98,14 +220,34 @@ impl AppDataClientStats
             }
             let mut est = self.eng_usage.get_mut(en).unwrap();
             limited_vec_push(&mut est, cinfo.eng_utilization(en));
+
+            let edt = self.eng_delta.entry(en.clone()).or_default();
+            limited_vec_push(edt, cinfo.eng_delta(en));
+
+            *self.eng_busy_secs.entry(en.clone()).or_insert(0.0) +=
+                cinfo.eng_busy_secs(en);
+            if let Some(eu) = self.eng_usage[en].back() {
+                self.peak_eng_usage = f64::max(self.peak_eng_usage, *eu);
+            }
         }
-        limited_vec_push(&mut self.mem_info, cinfo.mem_info());
+        let mi = cinfo.mem_info();
+        self.peak_mem = max(self.peak_mem,
+            mi.smem_used + mi.vram_used + mi.gtt_used);
+        limited_vec_push(&mut self.mem_info, mi);
 
         self.is_active = cinfo.is_active();
+        self.engine_active = cinfo.is_engine_active();
+        self.eng_ready = cinfo.has_eng_usage();
+        self.nr_contexts = cinfo.nr_contexts;
+    }
+
+    fn default_true() -> bool
+    {
+        true
     }
 
     fn from(eng_names: &Vec<String>,
-        cinfo: &DrmClientInfo) -> AppDataClientStats
+        cinfo: &DrmClientInfo, first_seen: u128) -> AppDataClientStats
     {
         let mut estats = HashMap::new();
         for en in eng_names.iter() {
@@ -121,8 +263,18 @@ impl AppDataClientStats
             cmdline: cinfo.proc.cmdline.clone(),
             cpu_usage: VecDeque::new(),
             eng_usage: estats,
+            eng_delta: HashMap::new(),
+            eng_busy_secs: HashMap::new(),
             mem_info: VecDeque::new(),
             is_active: false,
+            engine_active: false,
+            eng_ready: false,
+            label: String::new(),
+            is_focused: false,
+            first_seen,
+            nr_contexts: 0,
+            peak_eng_usage: 0.0,
+            peak_mem: 0,
         }
     }
 }
@@ -132,17 +284,111 @@ pub struct AppDataDeviceState
 {
     pub pci_dev: String,
     pub vdr_dev_rev: String,
+    #[serde(default)]
+    pub pci_ids: String,        // vendor:device, e.g. "8086:9a49"
     pub dev_type: DrmDeviceType,
     pub drv_name: String,
     pub dev_nodes: String,
     pub eng_names: Vec<String>,
+    #[serde(default)]
+    pub eng_capacity: HashMap<String, u32>,
     pub freq_limits: Vec<DrmDeviceFreqLimits>,
+    #[serde(default = "DrmDeviceHwmonInfo::new")]
+    pub hwmon_info: DrmDeviceHwmonInfo,
+    #[serde(default)]
+    pub perf_profile: String,
+    #[serde(default)]
+    pub pmu_rates: Vec<(String, f64)>,
+    #[serde(default)]
+    pub eng_source: String,     // e.g. "fdinfo" (summed from DRM clients)
     pub dev_stats: AppDataDeviceStats,
     pub clis_stats: Vec<AppDataClientStats>,
+    #[serde(default)]
+    pub alerts: Vec<String>,
+    #[serde(default)]
+    pub notices: Vec<String>,
+    #[serde(default)]
+    pub is_external: bool,
+    #[serde(default)]
+    pub displays: Vec<DrmDeviceDisplay>,
+    #[serde(default)]
+    pub reset_count: Option<u64>,
+    #[serde(default)]
+    pub new_resets: u64,        // resets since the start of the capture
+    #[serde(default = "DrmDeviceTemperature::new")]
+    pub temperature: DrmDeviceTemperature,
+    #[serde(default)]
+    pub busy: Option<f64>,
+    #[serde(default = "DrmDeviceSources::new")]
+    pub sources: DrmDeviceSources,
+    #[serde(default)]
+    pub firmware: Vec<(String, String)>,
 }
 
 impl AppDataDeviceState
 {
+    // engines usage title tagged with its source (if known)
+    pub fn eng_usage_title(&self, title: &str) -> String
+    {
+        if self.eng_source.is_empty() {
+            title.to_string()
+        } else {
+            format!("{} ({})", title, &self.eng_source)
+        }
+    }
+
+    // false for devices with no driver support or powered down, as they
+    // don't report anything
+    pub fn has_stats(&self) -> bool
+    {
+        let dstats = &self.dev_stats;
+        let has_mem = dstats.mem_info.back()
+            .is_some_and(|mi| mi.smem_total > 0 || mi.vram_total > 0);
+        let has_power = dstats.power.back()
+            .is_some_and(|p| p.gpu_cur_power > 0.0 || p.pkg_cur_power > 0.0);
+
+        has_mem || has_power ||
+            !self.freq_limits.is_empty() || !self.eng_names.is_empty()
+    }
+
+    // device stats (no clients) without the last offset samples
+    pub fn stats_at_offset(&self, offset: usize) -> AppDataDeviceState
+    {
+        let mut dev_stats = self.dev_stats.clone();
+        dev_stats.truncate_back(offset);
+        let eng_names = self.eng_names.iter()
+            .filter(|en| dev_stats.eng_usage.contains_key(*en))
+            .cloned().collect();
+
+        AppDataDeviceState {
+            pci_dev: self.pci_dev.clone(),
+            vdr_dev_rev: self.vdr_dev_rev.clone(),
+            pci_ids: self.pci_ids.clone(),
+            dev_type: self.dev_type.clone(),
+            drv_name: self.drv_name.clone(),
+            dev_nodes: self.dev_nodes.clone(),
+            eng_names,
+            eng_capacity: self.eng_capacity.clone(),
+            freq_limits: self.freq_limits.clone(),
+            hwmon_info: self.hwmon_info.clone(),
+            perf_profile: self.perf_profile.clone(),
+            pmu_rates: self.pmu_rates.clone(),
+            eng_source: self.eng_source.clone(),
+            dev_stats,
+            clis_stats: Vec::new(),
+            alerts: self.alerts.clone(),
+            notices: self.notices.clone(),
+            is_external: self.is_external,
+            displays: self.displays.clone(),
+            reset_count: self.reset_count,
+            new_resets: self.new_resets,
+            temperature: self.temperature.clone(),
+            busy: self.busy,
+            sources: self.sources.clone(),
+            firmware: self.firmware.clone(),
+        }
+    }
+
     fn remove_client_stat(&mut self,
         minor: u32, id: u32) -> Option<AppDataClientStats>
     {
@@ -177,15 +423,33 @@ impl AppDataDeviceState
         for en in tst.iter() {
             neng_names.push(en.to_string());
         }
-        neng_names.sort();
+        dinfo.sort_engines(&mut neng_names);
+
+        // clients come and go, so keep the max capacity seen so far
+        for en in neng_names.iter() {
+            let cap = dinfo.eng_capacity(en);
+            let ecap = self.eng_capacity.entry(en.clone()).or_insert(cap);
+            *ecap = max(*ecap, cap);
+        }
 
         self.eng_names = neng_names;
     }
 
     fn update_stats(&mut self, dinfo: &DrmDeviceInfo,
-        cinfos_b: &Option<Ref<'_, Vec<DrmClientInfo>>>)
+        cinfos_b: &Option<Ref<'_, Vec<DrmClientInfo>>>, now_ms: u128)
     {
         self.update_eng_names(dinfo);
+        self.hwmon_info = dinfo.hwmon_info.clone();
+        self.perf_profile = dinfo.perf_profile.clone();
+        self.pmu_rates = dinfo.pmu_rates.clone();
+        self.displays = dinfo.displays.clone();
+        if let (Some(old), Some(new)) = (self.reset_count, dinfo.reset_count) {
+            self.new_resets += new.saturating_sub(old);
+        }
+        self.reset_count = dinfo.reset_count;
+        self.temperature = dinfo.temperature.clone();
+        self.busy = dinfo.busy;
+        self.eng_source = dinfo.eng_usage_source().to_string();
 
         self.dev_stats.update_stats(&self.eng_names, dinfo);
 
@@ -193,12 +457,15 @@ impl AppDataDeviceState
         if let Some(clis_b) = cinfos_b {
             for cinf in clis_b.iter() {
                 let mut ncli_st: AppDataClientStats;
-                if let Some(cli_st) = self.remove_client_stat(
-                    cinf.drm_minor, cinf.client_id) {
-                    ncli_st = cli_st;
-                } else {
-                    ncli_st = AppDataClientStats::from(
-                        &self.eng_names, cinf);
+                match self.remove_client_stat(cinf.drm_minor, cinf.client_id) {
+                    Some(cli_st) if cli_st.is_same_process(cinf) => {
+                        ncli_st = cli_st;
+                    },
+                    _ => {
+                        // new client or id reused, start fresh stats
+                        ncli_st = AppDataClientStats::from(
+                            &self.eng_names, cinf, now_ms);
+                    },
                 }
 
                 ncli_st.update_stats(&self.eng_names, cinf);
@@ -209,6 +476,114 @@ impl AppDataDeviceState
         self.clis_stats = ncstats;
     }
 
+    // best-effort post-processing to label compositor clients and the ones
+    // sharing fds with them, grouping all of them at the top of the list
+    fn label_compositor_clients(&mut self,
+        cinfos_b: &Option<Ref<'_, Vec<DrmClientInfo>>>)
+    {
+        if let Some(clis_b) = cinfos_b {
+            for (cli_st, cinf) in self.clis_stats.iter_mut().zip(clis_b.iter()) {
+                cli_st.label = AppDataClientStats::compositor_label(cinf);
+            }
+        }
+
+        self.clis_stats.sort_by_key(|cs| cs.label.is_empty());
+    }
+
+    // averages the fast samples into the latest stats, where the latest
+    // stats cover the last last_ms since the previous sample
+    fn apply_samples(&mut self, dsm: &AppDataDeviceSamples, last_ms: f64)
+    {
+        let wavg = |sum: f64, w: f64, val: f64| {
+            (sum + val * last_ms) / (w + last_ms)
+        };
+
+        let dst = &mut self.dev_stats;
+        if let Some(frs) = dst.freqs.back_mut() {
+            for (i, fr) in frs.iter_mut().enumerate() {
+                if i < dsm.cur_freqs.len() {
+                    fr.cur_freq = wavg(dsm.cur_freqs[i], dsm.weight_ms,
+                        fr.cur_freq as f64) as u64;
+                    fr.act_freq = wavg(dsm.act_freqs[i], dsm.weight_ms,
+                        fr.act_freq as f64) as u64;
+                }
+            }
+        }
+        if let Some(pwr) = dst.power.back_mut() {
+            pwr.gpu_cur_power = wavg(dsm.gpu_power, dsm.weight_ms,
+                pwr.gpu_cur_power);
+            pwr.pkg_cur_power = wavg(dsm.pkg_power, dsm.weight_ms,
+                pwr.pkg_cur_power);
+        }
+        for (en, eu) in dst.eng_usage.iter_mut() {
+            if let (Some(sum), Some(val)) = (dsm.eng_usage.get(en), eu.back_mut()) {
+                *val = wavg(*sum, dsm.weight_ms, *val);
+            }
+        }
+
+        for cli in self.clis_stats.iter_mut() {
+            let csm = dsm.clis.get(&(cli.drm_minor, cli.client_id));
+            if csm.is_none() {
+                continue;
+            }
+            let csm = csm.unwrap();
+
+            if let Some(val) = cli.cpu_usage.back_mut() {
+                *val = wavg(csm.cpu_usage, csm.weight_ms, *val);
+            }
+            for (en, eu) in cli.eng_usage.iter_mut() {
+                if let (Some(sum), Some(val)) = (csm.eng_usage.get(en), eu.back_mut()) {
+                    *val = wavg(*sum, csm.weight_ms, *val);
+                }
+            }
+            for (en, edt) in cli.eng_delta.iter_mut() {
+                if let (Some(sd), Some(ed)) = (csm.eng_delta.get(en), edt.back_mut()) {
+                    ed.delta_time += sd.delta_time;
+                    ed.delta_cycles += sd.delta_cycles;
+                    ed.delta_total_cycles += sd.delta_total_cycles;
+                }
+            }
+            for (en, secs) in csm.eng_busy_secs.iter() {
+                *cli.eng_busy_secs.entry(en.clone()).or_insert(0.0) += secs;
+            }
+        }
+    }
+
+    // best-effort marking of the clients from the focused window's process
+    // tree, or sharing an fd with its process
+    fn mark_focused_clients(&mut self,
+        cinfos_b: &Option<Ref<'_, Vec<DrmClientInfo>>>, fpid: Option<u32>)
+    {
+        if let Some(clis_b) = cinfos_b {
+            for (cli_st, cinf) in self.clis_stats.iter_mut().zip(clis_b.iter()) {
+                cli_st.is_focused = fpid.is_some_and(|fp| {
+                    focused_window::is_focused_tree(cinf.proc.pid, fp) ||
+                        cinf.shared_procs.iter().any(|(sp, _)| sp.pid == fp)
+                });
+            }
+        }
+    }
+
+    // reasons for missing stats from the driver and for no engines usage
+    fn update_notices(&mut self, dinfo: &DrmDeviceInfo, no_clients: bool)
+    {
+        let mut notices = dinfo.notices.clone();
+
+        if self.eng_names.is_empty() {
+            let rsn = if no_clients {
+                "DRM clients scanning is disabled"
+            } else if unsafe { libc::geteuid() } != 0 {
+                "only DRM clients from the user's processes are visible, \
+                run as root to see all of them"
+            } else {
+                "no DRM clients have used it yet"
+            };
+            notices.push(format!("No engines usage: {}", rsn));
+        }
+
+        self.notices = notices;
+    }
+
     fn card_from(devnode: &String) -> &str
     {
         if devnode.starts_with("/dev/dri/") {
@@ -238,13 +613,143 @@ impl AppDataDeviceState
             pci_dev: dinfo.pci_dev.clone(),
             vdr_dev_rev: format!("{} {} (rev {})",
                 dinfo.vendor, dinfo.device, dinfo.revision),
+            pci_ids: format!("{}:{}", dinfo.vendor_id, dinfo.device_id),
             dev_type: dinfo.dev_type.clone(),
             drv_name: dinfo.drv_name.clone(),
             dev_nodes: dnodes,
             eng_names: enames,
+            eng_capacity: HashMap::new(),
             freq_limits: dinfo.freq_limits.clone(),
+            hwmon_info: dinfo.hwmon_info.clone(),
+            perf_profile: dinfo.perf_profile.clone(),
+            pmu_rates: dinfo.pmu_rates.clone(),
+            eng_source: dinfo.eng_usage_source().to_string(),
             dev_stats: dstats,
             clis_stats: Vec::new(),
+            alerts: Vec::new(),
+            notices: Vec::new(),
+            is_external: dinfo.is_external,
+            displays: dinfo.displays.clone(),
+            reset_count: dinfo.reset_count,
+            new_resets: 0,
+            temperature: dinfo.temperature.clone(),
+            busy: dinfo.busy,
+            sources: dinfo.sources.clone(),
+            firmware: dinfo.firmware.clone(),
+        }
+    }
+}
+
+// summary of a device stats over the whole capture
+#[derive(Debug, Serialize)]
+pub struct AppDataDeviceSummary
+{
+    pub pci_dev: String,
+    pub vdr_dev_rev: String,
+    pub nr_samples: u64,
+    pub eng_avg_usage: BTreeMap<String, f64>,
+    pub eng_max_usage: BTreeMap<String, f64>,
+    pub gpu_avg_power: f64,
+    pub gpu_peak_power: f64,
+    pub pkg_avg_power: f64,
+    pub pkg_peak_power: f64,
+    pub smem_peak_used: u64,
+    pub vram_peak_used: u64,
+    pub peak_temp: f64,         // in C, 0.0 if not available
+    pub avg_jitter_ms: f64,     // deviation of samples spacing from interval
+    pub max_jitter_ms: f64,
+    pub jitter_hist: BTreeMap<u64, u64>,    // nr samples by bucket start (ms)
+    #[serde(skip)]
+    eng_sum_usage: BTreeMap<String, f64>,
+    #[serde(skip)]
+    gpu_sum_power: f64,
+    #[serde(skip)]
+    pkg_sum_power: f64,
+    #[serde(skip)]
+    nr_jitters: u64,
+    #[serde(skip)]
+    jitter_sum: f64,
+}
+
+impl AppDataDeviceSummary
+{
+    const JITTER_BUCKETS: [u64; 5] = [0, 10, 50, 100, 500];
+
+    fn update_jitter(&mut self, jitter: f64)
+    {
+        self.nr_jitters += 1;
+        self.jitter_sum += jitter;
+        self.avg_jitter_ms = self.jitter_sum / self.nr_jitters as f64;
+        self.max_jitter_ms = f64::max(self.max_jitter_ms, jitter);
+
+        let bkt = AppDataDeviceSummary::JITTER_BUCKETS.iter()
+            .rev()
+            .find(|&&b| jitter >= b as f64)
+            .copied()
+            .unwrap_or(0);
+        *self.jitter_hist.entry(bkt).or_insert(0) += 1;
+    }
+
+    fn update(&mut self, dst: &AppDataDeviceState, jitter: Option<f64>)
+    {
+        let dstats = &dst.dev_stats;
+        self.nr_samples += 1;
+
+        for en in dst.eng_names.iter() {
+            let eut = *dstats.eng_usage[en].back().unwrap();
+            let esum = self.eng_sum_usage.entry(en.clone()).or_insert(0.0);
+            *esum += eut;
+            let emax = self.eng_max_usage.entry(en.clone()).or_insert(0.0);
+            *emax = f64::max(*emax, eut);
+        }
+        for (en, esum) in self.eng_sum_usage.iter() {
+            self.eng_avg_usage.insert(en.clone(),
+                esum / self.nr_samples as f64);
+        }
+
+        let pwr = dstats.power.back().unwrap();
+        self.gpu_sum_power += pwr.gpu_cur_power;
+        self.pkg_sum_power += pwr.pkg_cur_power;
+        self.gpu_avg_power = self.gpu_sum_power / self.nr_samples as f64;
+        self.pkg_avg_power = self.pkg_sum_power / self.nr_samples as f64;
+        self.gpu_peak_power = f64::max(self.gpu_peak_power, pwr.gpu_cur_power);
+        self.pkg_peak_power = f64::max(self.pkg_peak_power, pwr.pkg_cur_power);
+
+        let mi = dstats.mem_info.back().unwrap();
+        self.smem_peak_used = max(self.smem_peak_used, mi.smem_used);
+        self.vram_peak_used = max(self.vram_peak_used, mi.vram_used);
+        self.peak_temp = f64::max(self.peak_temp, dst.temperature.max());
+
+        if let Some(jt) = jitter {
+            self.update_jitter(jt);
+        }
+    }
+
+    fn from(dst: &AppDataDeviceState) -> AppDataDeviceSummary
+    {
+        AppDataDeviceSummary {
+            pci_dev: dst.pci_dev.clone(),
+            vdr_dev_rev: dst.vdr_dev_rev.clone(),
+            nr_samples: 0,
+            eng_avg_usage: BTreeMap::new(),
+            eng_max_usage: BTreeMap::new(),
+            gpu_avg_power: 0.0,
+            gpu_peak_power: 0.0,
+            pkg_avg_power: 0.0,
+            pkg_peak_power: 0.0,
+            smem_peak_used: 0,
+            vram_peak_used: 0,
+            peak_temp: 0.0,
+            avg_jitter_ms: 0.0,
+            max_jitter_ms: 0.0,
+            jitter_hist: AppDataDeviceSummary::JITTER_BUCKETS.iter()
+                .map(|&b| (b, 0))
+                .collect(),
+            eng_sum_usage: BTreeMap::new(),
+            gpu_sum_power: 0.0,
+            pkg_sum_power: 0.0,
+            nr_jitters: 0,
+            jitter_sum: 0.0,
         }
     }
 }
@@ -252,8 +757,12 @@ impl AppDataDeviceState
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppDataState
 {
+    #[serde(default)]
+    pub start_wall_ms: u128,    // wall-clock ms since UNIX epoch at start
     pub timestamps: VecDeque<u128>,
     pub devs_state: Vec<AppDataDeviceState>,
+    #[serde(default)]
+    pub markers: Vec<(u128, String)>,   // (timestamp, name) in the window
 }
 
 impl AppDataState
@@ -278,8 +787,10 @@ impl AppDataState
     fn new() -> AppDataState
     {
         AppDataState {
+                start_wall_ms: 0,
                 timestamps: VecDeque::new(),
                 devs_state: Vec::new(),
+                markers: Vec::new(),
         }
     }
 }
@@ -296,10 +807,59 @@ pub trait AppData
         Ok(())
     }
 
+    fn finish_json_file(&mut self) -> Result<()>
+    {
+        Ok(())
+    }
+
+    // on errors, the JSON file is closed but not finished, so tell where
+    // the stats written so far are
+    fn abort_json_file(&mut self, err: anyhow::Error) -> anyhow::Error
+    {
+        err
+    }
+
+    fn save_summary_json(&mut self) -> Result<()>
+    {
+        Ok(())
+    }
+
+    fn new_alerts(&self) -> Vec<String>
+    {
+        Vec::new()
+    }
+
+    fn add_marker(&mut self)
+    {
+    }
+
+    // time to wait until the next refresh
+    fn interval_ms(&self) -> u64
+    {
+        self.args().ms_interval
+    }
+
+    // time between fast samples in between refreshes (if any)
+    fn sample_interval_ms(&self) -> Option<u64>
+    {
+        None
+    }
+
+    fn sample(&mut self) -> Result<()>
+    {
+        Ok(())
+    }
+
     fn args(&self) -> &CliArgs;
 
+    fn args_mut(&mut self) -> &mut CliArgs;
+
     fn timestamps(&self) -> &VecDeque<u128>;
 
+    fn start_wall_ms(&self) -> u128;
+
+    fn markers(&self) -> &Vec<(u128, String)>;
+
     fn devices(&self) -> &Vec<AppDataDeviceState>;
 
     fn get_device(&self, dev: &String) -> Option<&AppDataDeviceState>;
@@ -317,17 +877,47 @@ impl Debug for dyn AppData
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AppDataJson
 {
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    run_id: usize,
     args: CliArgs,
     states: VecDeque<AppDataState>,
+    #[serde(skip)]
+    speed: Option<f64>,     // replay pace, None is 1.0
+    #[serde(skip)]
+    realtime: bool,         // replay with recorded deltas
 }
 
 impl AppData for AppDataJson
 {
+    fn interval_ms(&self) -> u64
+    {
+        let mut int_ms = self.args.ms_interval as f64;
+        if self.realtime && self.states.len() > 1 {
+            // append() keeps timestamps monotonic across runs, so only a
+            // broken or edited file falls back to the recorded interval
+            let cur_ts = *self.states[0].timestamps.back().unwrap();
+            let next_ts = *self.states[1].timestamps.back().unwrap();
+            if next_ts > cur_ts {
+                int_ms = (next_ts - cur_ts) as f64;
+            }
+        }
+
+        // a 0ms interval would busy-loop the replay at high speeds
+        (int_ms / self.speed.unwrap_or(1.0)).round().max(1.0) as u64
+    }
+
     fn args(&self) -> &CliArgs
     {
         &self.args
     }
 
+    fn args_mut(&mut self) -> &mut CliArgs
+    {
+        &mut self.args
+    }
+
     fn timestamps(&self) -> &VecDeque<u128>
     {
         let state = self.states.front().unwrap();
@@ -335,6 +925,16 @@ impl AppData for AppDataJson
         &state.timestamps
     }
 
+    fn start_wall_ms(&self) -> u128
+    {
+        self.states.front().unwrap().start_wall_ms
+    }
+
+    fn markers(&self) -> &Vec<(u128, String)>
+    {
+        &self.states.front().unwrap().markers
+    }
+
     fn devices(&self) -> &Vec<AppDataDeviceState>
     {
         let state = self.states.front().unwrap();
@@ -379,23 +979,305 @@ impl AppDataJson
         self.states.is_empty()
     }
 
-    fn new(args: CliArgs) -> AppDataJson
+    // PCI slot of the first device with vendor:device ids (e.g.
+    // "8086:9a49"), never found in JSON files saved without them
+    pub fn find_by_ids(&self, ids: &str) -> Option<String>
+    {
+        self.states.front()?.devs_state.iter()
+            .find(|d| !d.pci_ids.is_empty() &&
+                d.pci_ids.eq_ignore_ascii_case(ids))
+            .map(|d| d.pci_dev.clone())
+    }
+
+    fn new(args: CliArgs, run_id: usize) -> AppDataJson
     {
         AppDataJson {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            run_id,
             args,
             states: VecDeque::new(),
+            speed: None,
+            realtime: false,
+        }
+    }
+
+    pub fn set_replay_pace(&mut self, speed: Option<f64>, realtime: bool)
+    {
+        self.speed = speed;
+        self.realtime = realtime;
+    }
+
+    fn append(&mut self, other: AppDataJson, json_fname: &PathBuf) -> Result<()>
+    {
+        if other.states.is_empty() {
+            return Ok(());
+        }
+        if self.states.is_empty() {
+            self.version = other.version;
+            self.args = other.args;
+            self.states = other.states;
+            return Ok(());
+        }
+
+        // same qmassa version and interval, or the replay pace is wrong
+        if other.version != self.version {
+            bail!("JSON file {:?} has a run from qmassa version {:?} \
+                (expected {:?})", json_fname, other.version, self.version);
+        }
+        if other.args.ms_interval != self.args.ms_interval {
+            bail!("JSON file {:?} has a run with a {}ms interval \
+                (expected {}ms)", json_fname, other.args.ms_interval,
+                self.args.ms_interval);
+        }
+
+        // devices need to match to be analyzed as one timeline
+        let last = self.states.back().unwrap();
+        let devs: Vec<&String> = last.devs_state.iter()
+            .map(|ds| &ds.pci_dev).collect();
+        let odevs: Vec<&String> = other.states.front().unwrap()
+            .devs_state.iter().map(|ds| &ds.pci_dev).collect();
+        if devs != odevs {
+            bail!("JSON file {:?} has different devices {:?} (expected {:?})",
+                json_fname, odevs, devs);
+        }
+
+        // offset timestamps to keep them monotonic
+        let offset = last.timestamps.back().copied().unwrap_or(0);
+        for mut state in other.states.into_iter() {
+            for ts in state.timestamps.iter_mut() {
+                *ts += offset;
+            }
+            // keep wall-clock time of the offset timestamps right
+            state.start_wall_ms = state.start_wall_ms.saturating_sub(offset);
+            for mkr in state.markers.iter_mut() {
+                mkr.0 += offset;
+            }
+            for ds in state.devs_state.iter_mut() {
+                for cs in ds.clis_stats.iter_mut() {
+                    cs.first_seen += offset;
+                }
+            }
+            self.states.push_back(state);
         }
+
+        Ok(())
     }
 
-    pub fn from(json_fname: &str) -> Result<AppDataJson>
+    // number of runs (one JSON object each) in a file
+    fn nr_runs(json_fname: &str) -> Result<usize>
     {
         let json_str = fs::read_to_string(json_fname)?;
-        let res: AppDataJson = serde_json::from_str(&json_str)?;
+        let mut nr = 0;
+        for run in serde_json::Deserializer::from_str(&json_str)
+            .into_iter::<serde::de::IgnoredAny>() {
+            run?;
+            nr += 1;
+        }
+
+        Ok(nr)
+    }
+
+    // run cut short (e.g. qmassa crashed while writing it), keeping all
+    // of its complete states, as each one ends with a "}" line of its own
+    fn from_partial(json_str: &str) -> Result<AppDataJson>
+    {
+        let mut end = json_str.len();
+        while let Some(pos) = json_str[..end].rfind("\n}") {
+            let fixed = format!("{}]\n}}", &json_str[..pos + 2]);
+            if let Ok(jd) = serde_json::from_str::<AppDataJson>(&fixed) {
+                return Ok(jd);
+            }
+            end = pos;
+        }
+
+        bail!("No complete stats states in the partial run");
+    }
+
+    // reads selected run (1-based) or all runs in sequence as one timeline
+    fn from_file(json_fname: &PathBuf, run: Option<usize>) -> Result<AppDataJson>
+    {
+        let json_str = fs::read_to_string(json_fname)?;
+        let mut runs: Vec<AppDataJson> = Vec::new();
+        let mut runs_it = serde_json::Deserializer::from_str(&json_str)
+            .into_iter::<AppDataJson>();
+        loop {
+            let start = runs_it.byte_offset();
+            match runs_it.next() {
+                Some(Ok(jd)) => runs.push(jd),
+                Some(Err(err)) if err.is_eof() => {
+                    let jd = AppDataJson::from_partial(&json_str[start..])
+                        .with_context(|| format!("Truncated run {} in \
+                            JSON file {:?}", runs.len() + 1, json_fname))?;
+                    warn!("Truncated run {} in JSON file {:?}, using its \
+                        first {} states.", runs.len() + 1, json_fname,
+                        jd.states.len());
+                    runs.push(jd);
+                    break;
+                },
+                Some(Err(err)) => return Err(err.into()),
+                None => break,
+            }
+        }
+        if runs.is_empty() {
+            bail!("No runs in JSON file {:?}", json_fname);
+        }
+
+        if let Some(nr) = run {
+            if nr == 0 || nr > runs.len() {
+                bail!("No run {} in JSON file {:?} (has {} runs)",
+                    nr, json_fname, runs.len());
+            }
+            return Ok(runs.swap_remove(nr - 1));
+        }
+
+        let mut runs_it = runs.into_iter();
+        let mut res = runs_it.next().unwrap();
+        for jd in runs_it {
+            res.append(jd, json_fname)?;
+        }
+
+        Ok(res)
+    }
+
+    pub fn from(json_fnames: &str, run: Option<usize>) -> Result<AppDataJson>
+    {
+        // comma-separated list of files or glob patterns
+        let mut fnames: Vec<PathBuf> = Vec::new();
+        for pat in json_fnames.split(',') {
+            let pat = pat.trim();
+            if pat.is_empty() {
+                continue;
+            }
+
+            let mut nr = 0;
+            for entry in glob::glob(pat)? {
+                fnames.push(entry?);
+                nr += 1;
+            }
+            if nr == 0 {
+                bail!("No JSON file found for {:?}", pat);
+            }
+        }
+        if fnames.is_empty() {
+            bail!("No JSON files given");
+        }
+
+        // read all files in sequence as a single timeline
+        let mut res = AppDataJson::from_file(&fnames[0], run)
+            .with_context(|| format!("Failed to read {:?}", fnames[0]))?;
+        for fname in fnames.iter().skip(1) {
+            let jd = AppDataJson::from_file(fname, run)
+                .with_context(|| format!("Failed to read {:?}", fname))?;
+            res.append(jd, fname)?;
+        }
 
         Ok(res)
     }
 }
 
+// time-weighted sums (weights in ms) of a DRM client's fast samples
+#[derive(Debug)]
+struct AppDataClientSamples
+{
+    weight_ms: f64,
+    cpu_usage: f64,
+    eng_usage: HashMap<String, f64>,
+    eng_delta: HashMap<String, DrmEngineDelta>,
+    eng_busy_secs: HashMap<String, f64>,
+}
+
+impl AppDataClientSamples
+{
+    fn add(&mut self, eng_names: &[String], cinfo: &DrmClientInfo, w: f64)
+    {
+        self.weight_ms += w;
+        self.cpu_usage += cinfo.proc.cpu_utilization() * w;
+
+        for en in eng_names.iter() {
+            *self.eng_usage.entry(en.clone()).or_insert(0.0) +=
+                cinfo.eng_utilization(en) * w;
+            *self.eng_busy_secs.entry(en.clone()).or_insert(0.0) +=
+                cinfo.eng_busy_secs(en);
+
+            let ed = cinfo.eng_delta(en);
+            let sd = self.eng_delta.entry(en.clone())
+                .or_default();
+            sd.delta_time += ed.delta_time;
+            sd.delta_cycles += ed.delta_cycles;
+            sd.delta_total_cycles += ed.delta_total_cycles;
+        }
+    }
+
+    fn new() -> AppDataClientSamples
+    {
+        AppDataClientSamples {
+            weight_ms: 0.0,
+            cpu_usage: 0.0,
+            eng_usage: HashMap::new(),
+            eng_delta: HashMap::new(),
+            eng_busy_secs: HashMap::new(),
+        }
+    }
+}
+
+// time-weighted sums (weights in ms) of a device's fast samples taken
+// between two refreshes, averaged into the stats of the next refresh
+#[derive(Debug)]
+struct AppDataDeviceSamples
+{
+    weight_ms: f64,
+    cur_freqs: Vec<f64>,
+    act_freqs: Vec<f64>,
+    gpu_power: f64,
+    pkg_power: f64,
+    eng_usage: HashMap<String, f64>,
+    clis: HashMap<(u32, u32), AppDataClientSamples>,
+}
+
+impl AppDataDeviceSamples
+{
+    fn add(&mut self, dinfo: &DrmDeviceInfo,
+        cinfos: Option<&Vec<DrmClientInfo>>, w: f64)
+    {
+        self.weight_ms += w;
+
+        let nr_gts = dinfo.freqs.len();
+        self.cur_freqs.resize(max(self.cur_freqs.len(), nr_gts), 0.0);
+        self.act_freqs.resize(max(self.act_freqs.len(), nr_gts), 0.0);
+        for (i, fr) in dinfo.freqs.iter().enumerate() {
+            self.cur_freqs[i] += fr.cur_freq as f64 * w;
+            self.act_freqs[i] += fr.act_freq as f64 * w;
+        }
+        self.gpu_power += dinfo.power.gpu_cur_power * w;
+        self.pkg_power += dinfo.power.pkg_cur_power * w;
+
+        let eng_names = dinfo.engines();
+        for en in eng_names.iter() {
+            *self.eng_usage.entry(en.clone()).or_insert(0.0) +=
+                dinfo.eng_utilization(en) * w;
+        }
+
+        for cinf in cinfos.into_iter().flatten() {
+            self.clis.entry((cinf.drm_minor, cinf.client_id))
+                .or_insert_with(AppDataClientSamples::new)
+                .add(&eng_names, cinf, w);
+        }
+    }
+
+    fn new() -> AppDataDeviceSamples
+    {
+        AppDataDeviceSamples {
+            weight_ms: 0.0,
+            cur_freqs: Vec::new(),
+            act_freqs: Vec::new(),
+            gpu_power: 0.0,
+            pkg_power: 0.0,
+            eng_usage: HashMap::new(),
+            clis: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AppDataLive
 {
@@ -403,8 +1285,22 @@ pub struct AppDataLive
     qmds: DrmDevices,
     state: AppDataState,
     start_time: time::Instant,
+    start_wall_ms: u128,
+    last_refresh_ms: Option<u128>,
+    refresh_cost_ms: u128,
+    is_lag_warned: bool,
+    nr_markers: usize,
     json: Option<File>,
     is_json_initial: bool,
+    summaries: Vec<AppDataDeviceSummary>,
+    alerts: Vec<Alert>,
+    new_alerts: Vec<String>,
+    failed_rules: Vec<String>,
+    stats_server: Option<StatsServer>,
+    samples: HashMap<String, AppDataDeviceSamples>,
+    last_sample: time::Instant,
+    anon_clients: HashMap<(u64, String, String), usize>,
+    focused: Option<FocusedWindow>,
 }
 
 impl AppData for AppDataLive
@@ -415,11 +1311,30 @@ impl AppData for AppDataLive
             // create JSON structure, drop saving to JSON & no TUI options
             let mut args = self.args.clone();
             args.to_json = None;
+            args.json_append = false;
+            args.json_atomic = false;
+            args.summary_json = None;
             args.no_tui = false;
-            let jd = AppDataJson::new(args);
 
-            // create file and write initial JSON
-            let mut jf = File::create(fname)?;
+            // create file or, if appending, add a new run at its end
+            let jf_name = AppDataLive::json_write_path(&self.args, fname);
+            let mut jf: File;
+            let mut run_id = 1;
+            if self.args.json_append && Path::new(fname).is_file() {
+                run_id += AppDataJson::nr_runs(fname)
+                    .with_context(|| format!("Failed to read runs from {:?}", fname))?;
+                if self.args.json_atomic {
+                    fs::copy(fname, &jf_name).with_context(|| format!(
+                        "Failed to copy {:?} to {:?}", fname, jf_name))?;
+                }
+                jf = OpenOptions::new().read(true).write(true).open(&jf_name)?;
+                jf.seek(SeekFrom::End(0))?;
+            } else {
+                jf = File::create(&jf_name)?;
+            }
+            let jd = AppDataJson::new(args, run_id);
+
+            // write initial JSON
             serde_json::to_writer_pretty(&mut jf, &jd)?;
             writeln!(jf)?;
 
@@ -432,13 +1347,18 @@ impl AppData for AppDataLive
 
     fn update_json_file(&mut self) -> Result<()>
     {
+        if self.json.is_none() {
+            return Ok(());
+        }
+        let state = self.json_state()?;
+
         if let Some(jf) = &mut self.json {
             // overwrite last 4 bytes ("]\n}\n") with new state
             jf.seek(SeekFrom::End(-4))?;
             if !self.is_json_initial {
                 writeln!(jf, ",")?;
             }
-            serde_json::to_writer_pretty(&mut *jf, &self.state)?;
+            serde_json::to_writer_pretty(&mut *jf, &state)?;
 
             // make it a valid JSON again
             writeln!(jf, "]\n}}")?;
@@ -449,16 +1369,96 @@ impl AppData for AppDataLive
         Ok(())
     }
 
+    // with --json-atomic, the full capture replaces the file only now
+    fn finish_json_file(&mut self) -> Result<()>
+    {
+        if let (Some(jf), Some(fname)) = (self.json.take(), &self.args.to_json) {
+            if self.args.json_atomic {
+                jf.sync_all()?;
+                drop(jf);
+                let jf_name = AppDataLive::json_write_path(&self.args, fname);
+                fs::rename(&jf_name, fname).with_context(|| format!(
+                    "Failed to rename {:?} to {:?}", jf_name, fname))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // with --json-atomic, the ".tmp" file is kept as is and not renamed
+    fn abort_json_file(&mut self, err: anyhow::Error) -> anyhow::Error
+    {
+        if let (Some(jf), Some(fname)) = (self.json.take(), &self.args.to_json) {
+            drop(jf);
+            let jf_name = AppDataLive::json_write_path(&self.args, fname);
+            return err.context(format!(
+                "Stopped with stats so far saved to {:?}", jf_name));
+        }
+
+        err
+    }
+
+    fn save_summary_json(&mut self) -> Result<()>
+    {
+        if let Some(fname) = &self.args.summary_json {
+            let mut summ = serde_json::to_value(&self.summaries)?;
+            if let Some(prec) = self.args.precision {
+                AppDataLive::round_floats(&mut summ, prec as i32);
+            }
+
+            let mut jf = File::create(fname)?;
+            serde_json::to_writer_pretty(&mut jf, &summ)?;
+            writeln!(jf)?;
+        }
+
+        Ok(())
+    }
+
+    fn new_alerts(&self) -> Vec<String>
+    {
+        self.new_alerts.clone()
+    }
+
+    // named by the user's list of marker names, in order, or "M<nr>"
+    fn add_marker(&mut self)
+    {
+        let ts = self.start_time.elapsed().as_millis();
+        self.nr_markers += 1;
+
+        let name = self.args.markers.as_ref()
+            .and_then(|mn| mn.split(',').nth(self.nr_markers - 1))
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty())
+            .unwrap_or(format!("M{}", self.nr_markers));
+
+        self.state.markers.push((ts, name));
+    }
+
     fn args(&self) -> &CliArgs
     {
         &self.args
     }
 
+    fn args_mut(&mut self) -> &mut CliArgs
+    {
+        &mut self.args
+    }
+
     fn timestamps(&self) -> &VecDeque<u128>
     {
         &self.state.timestamps
     }
 
+    fn start_wall_ms(&self) -> u128
+    {
+        self.start_wall_ms
+    }
+
+    fn markers(&self) -> &Vec<(u128, String)>
+    {
+        &self.state.markers
+    }
+
     fn devices(&self) -> &Vec<AppDataDeviceState>
     {
         &self.state.devs_state
@@ -475,11 +1475,53 @@ impl AppData for AppDataLive
         None
     }
 
+    fn sample_interval_ms(&self) -> Option<u64>
+    {
+        self.args.sample_interval
+    }
+
+    // fast sample in between refreshes, kept as time-weighted sums to be
+    // averaged into the next refresh's stats
+    fn sample(&mut self) -> Result<()>
+    {
+        self.qmds.sample()?;
+        let w = self.last_sample.elapsed().as_secs_f64() * 1000.0;
+        self.last_sample = time::Instant::now();
+
+        for d in self.qmds.devices() {
+            let dinfo = self.qmds.device_info(d).unwrap();
+            let up_ref = dinfo.clients().and_then(|cr| cr.upgrade());
+            let cinfos_b = up_ref.as_ref().map(|ur| ur.borrow());
+
+            self.samples.entry(d.clone())
+                .or_insert_with(AppDataDeviceSamples::new)
+                .add(dinfo, cinfos_b.as_deref(), w);
+        }
+
+        Ok(())
+    }
+
     fn refresh(&mut self) -> Result<bool>
     {
+        let refresh_start = time::Instant::now();
         self.qmds.refresh()?;
+        let last_ms = self.last_sample.elapsed().as_secs_f64() * 1000.0;
+        self.last_sample = time::Instant::now();
+        let samples = mem::take(&mut self.samples);
+        let now_ms = self.start_time.elapsed().as_millis();
+        let secs = now_ms as f64 / 1000.0;
+        self.new_alerts.clear();
+
+        // how far actual spacing between samples is from the interval
+        let jitter = self.last_refresh_ms.map(|lr| {
+            ((now_ms - lr) as f64 - self.args.ms_interval as f64).abs()
+        });
+        self.last_refresh_ms = Some(now_ms);
+
+        let fpid = self.focused.as_mut().and_then(|fw| fw.pid());
 
         let mut nstate = AppDataState::new();
+        nstate.start_wall_ms = self.start_wall_ms;
         for d in self.qmds.devices() {
             let dinfo = self.qmds.device_info(d).unwrap();
 
@@ -499,7 +1541,49 @@ impl AppData for AppDataLive
                 ndst = AppDataDeviceState::from(dinfo);
             }
 
-            ndst.update_stats(dinfo, &cinfos_b);
+            let old_resets = ndst.new_resets;
+            ndst.update_stats(dinfo, &cinfos_b, now_ms);
+            if let Some(dsm) = samples.get(d) {
+                ndst.apply_samples(dsm, last_ms);
+            }
+            if ndst.new_resets > old_resets {
+                warn!("GPU reset detected on {:?} ({} since start).",
+                    ndst.pci_dev, ndst.new_resets);
+                self.new_alerts.push(format!(
+                    "[{:.1}s] RESET {}: {} GPU reset(s) ({} since start)",
+                    secs, ndst.pci_dev, ndst.new_resets - old_resets,
+                    ndst.new_resets));
+            }
+            if self.args.focused_window {
+                ndst.mark_focused_clients(&cinfos_b, fpid);
+            }
+            if self.args.label_compositors {
+                ndst.label_compositor_clients(&cinfos_b);
+            }
+            ndst.update_notices(dinfo, self.args.no_clients);
+
+            // check alert rules, noting the newly triggered ones
+            let mut nalerts = Vec::new();
+            for al in self.alerts.iter() {
+                if let Some(val) = al.check(&ndst) {
+                    if !ndst.alerts.contains(&al.rule) {
+                        self.new_alerts.push(format!(
+                            "[{:.1}s] {} {}: {} ({:.2})",
+                            secs, if al.fail_on { "FAIL" } else { "ALERT" },
+                            ndst.pci_dev, al.rule, val));
+                    }
+                    if al.fail_on && !self.failed_rules.contains(&al.rule) {
+                        self.failed_rules.push(al.rule.clone());
+                    }
+                    nalerts.push(al.rule.clone());
+                }
+            }
+            ndst.alerts = nalerts;
+
+            if self.args.summary_json.is_some() {
+                AppDataLive::update_summary(
+                    &mut self.summaries, &ndst, jitter);
+            }
             nstate.devs_state.push(ndst);
         }
 
@@ -507,23 +1591,243 @@ impl AppData for AppDataLive
         limited_vec_push(&mut nstate.timestamps,
             self.start_time.elapsed().as_millis());
 
+        // keep only the markers in the stats window
+        let first_ts = *nstate.timestamps.front().unwrap();
+        nstate.markers.append(&mut self.state.markers);
+        nstate.markers.retain(|(ts, _)| *ts >= first_ts);
+
         self.state = nstate;
 
+        if self.stats_server.is_some() {
+            let state = serde_json::to_string(&self.json_state()?)?;
+            self.stats_server.as_ref().unwrap().update(state);
+        }
+
+        // warn once if stats gathering can't keep up with the interval
+        self.refresh_cost_ms = refresh_start.elapsed().as_millis();
+        if !self.is_lag_warned &&
+            self.refresh_cost_ms > self.args.ms_interval as u128 {
+            warn!("Refresh took {}ms, longer than the {}ms interval.",
+                self.refresh_cost_ms, self.args.ms_interval);
+            self.is_lag_warned = true;
+        }
+
         Ok(true)
     }
 }
 
 impl AppDataLive
 {
+    // file being written to, renamed to the final one at the end if atomic
+    fn json_write_path(args: &CliArgs, fname: &str) -> String
+    {
+        if args.json_atomic {
+            format!("{}.tmp", fname)
+        } else {
+            fname.to_string()
+        }
+    }
+
+    pub fn set_alerts(&mut self, alerts: Vec<Alert>)
+    {
+        self.alerts = alerts;
+    }
+
+    pub fn set_stats_server(&mut self, srv: StatsServer)
+    {
+        self.stats_server = Some(srv);
+    }
+
+    // same state saved to the JSON file and served over HTTP
+    fn json_state(&mut self) -> Result<serde_json::Value>
+    {
+        if self.args.anonymize {
+            self.anonymized_state()
+        } else {
+            Ok(serde_json::to_value(&self.state)?)
+        }
+    }
+
+    // time spent gathering stats in the last refresh
+    pub fn refresh_cost_ms(&self) -> u128
+    {
+        self.refresh_cost_ms
+    }
+
+    // fail-on rules triggered at least once during the run
+    pub fn failed_rules(&self) -> &Vec<String>
+    {
+        &self.failed_rules
+    }
+
+    // replace process names & command lines with sequential labels (not
+    // hashes, that could be reversed for known names), so the same client
+    // keeps the same name through the whole capture
+    fn anonymized_state(&mut self) -> Result<serde_json::Value>
+    {
+        let mut val = serde_json::to_value(&self.state)?;
+
+        let devs = val["devs_state"].as_array_mut();
+        for dst in devs.into_iter().flatten() {
+            let clis = dst["clis_stats"].as_array_mut();
+            for cli in clis.into_iter().flatten() {
+                let key = (cli["pid"].as_u64().unwrap_or(0),
+                    cli["comm"].as_str().unwrap_or("").to_string(),
+                    cli["cmdline"].as_str().unwrap_or("").to_string());
+                let nr_clis = self.anon_clients.len();
+                let nr = *self.anon_clients.entry(key).or_insert(nr_clis + 1);
+
+                cli["comm"] = serde_json::Value::String(
+                    format!("client-{}", nr));
+                cli["cmdline"] = serde_json::Value::String(String::new());
+            }
+        }
+
+        Ok(val)
+    }
+
+    // round all non-integer numbers to prec decimals
+    fn round_floats(val: &mut serde_json::Value, prec: i32)
+    {
+        match val {
+            serde_json::Value::Number(nr) if nr.is_f64() => {
+                let mul = 10f64.powi(prec);
+                let rv = (nr.as_f64().unwrap() * mul).round() / mul;
+                if let Some(rnr) = serde_json::Number::from_f64(rv) {
+                    *nr = rnr;
+                }
+            },
+            serde_json::Value::Array(arr) => {
+                for v in arr.iter_mut() {
+                    AppDataLive::round_floats(v, prec);
+                }
+            },
+            serde_json::Value::Object(obj) => {
+                for v in obj.values_mut() {
+                    AppDataLive::round_floats(v, prec);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn update_summary(summaries: &mut Vec<AppDataDeviceSummary>,
+        dst: &AppDataDeviceState, jitter: Option<f64>)
+    {
+        let idx = summaries.iter().position(|sm| sm.pci_dev == dst.pci_dev);
+        let smry = if let Some(idx) = idx {
+            &mut summaries[idx]
+        } else {
+            summaries.push(AppDataDeviceSummary::from(dst));
+            summaries.last_mut().unwrap()
+        };
+
+        smry.update(dst, jitter);
+    }
+
     pub fn from(args: CliArgs, qmds: DrmDevices) -> AppDataLive
     {
+        let focused = args.focused_window.then(FocusedWindow::new);
+
         AppDataLive {
             args,
             qmds,
             state: AppDataState::new(),
             start_time: time::Instant::now(),
+            start_wall_ms: time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis()),
+            last_refresh_ms: None,
+            refresh_cost_ms: 0,
+            is_lag_warned: false,
+            nr_markers: 0,
             json: None,
             is_json_initial: true,
+            summaries: Vec::new(),
+            alerts: Vec::new(),
+            new_alerts: Vec::new(),
+            failed_rules: Vec::new(),
+            stats_server: None,
+            samples: HashMap::new(),
+            last_sample: time::Instant::now(),
+            anon_clients: HashMap::new(),
+            focused,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::process;
+
+    use super::*;
+
+    // one run with a state per timestamp, no devices
+    fn run_json(version: &str, ms_interval: u64, tss: &[u128]) -> String
+    {
+        let states: Vec<String> = tss.iter()
+            .map(|ts| format!("{{\"timestamps\": [{}], \"devs_state\": [], \
+                \"markers\": [[{}, \"m{}\"]]}}", ts, ts, ts))
+            .collect();
+
+        format!("{{\"version\": \"{}\", \"args\": {{\"ms_interval\": {}, \
+            \"all_clients\": false, \"nr_iterations\": -1, \
+            \"no_tui\": false}}, \"states\": [{}]}}\n",
+            version, ms_interval, states.join(", "))
+    }
+
+    // loads all the JSON files, in order, as a single timeline
+    fn load_files(name: &str, runs: &[String]) -> Result<AppDataJson>
+    {
+        let paths: Vec<PathBuf> = (0..runs.len())
+            .map(|nr| std::env::temp_dir().join(format!(
+                "qmassa-{}-{}-{}.json", name, nr, process::id())))
+            .collect();
+        for (path, run) in paths.iter().zip(runs.iter()) {
+            fs::write(path, run).unwrap();
+        }
+        let fnames: Vec<String> = paths.iter()
+            .map(|path| path.to_str().unwrap().to_string())
+            .collect();
+        let res = AppDataJson::from(&fnames.join(","), None);
+        for path in paths.iter() {
+            fs::remove_file(path).unwrap();
         }
+
+        res
+    }
+
+    #[test]
+    fn json_files_timeline()
+    {
+        let jd = load_files("timeline", &[
+            run_json("1.0.0", 1000, &[1000, 2000]),
+            run_json("1.0.0", 1000, &[1000, 2000, 3000]),
+        ]).unwrap();
+
+        // second file offset by the last timestamp of the first one
+        let tss: Vec<u128> = jd.states.iter()
+            .map(|st| *st.timestamps.back().unwrap())
+            .collect();
+        assert_eq!(tss, [1000, 2000, 3000, 4000, 5000]);
+        assert_eq!(jd.states[4].markers, [(5000, String::from("m3000"))]);
+        assert_eq!(jd.args.ms_interval, 1000);
+    }
+
+    #[test]
+    fn json_files_mismatch()
+    {
+        let err = load_files("interval", &[
+            run_json("1.0.0", 1000, &[1000]),
+            run_json("1.0.0", 500, &[500]),
+        ]).unwrap_err();
+        assert!(format!("{:#}", err).contains("500ms interval"));
+
+        let err = load_files("version", &[
+            run_json("1.0.0", 1000, &[1000]),
+            run_json("1.1.0", 1000, &[1000]),
+        ]).unwrap_err();
+        assert!(format!("{:#}", err).contains("version \"1.1.0\""));
     }
 }
@@ -0,0 +1,104 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{bail, Result};
+use log::{LevelFilter, Log, Metadata, Record};
+
+
+const APP_LOG_MAX_NR_RECORDS: usize = 1000;
+
+static APP_LOGGER: OnceLock<AppLogger> = OnceLock::new();
+
+// keeps the most recent log records in memory (for the TUI log screen)
+// and forwards them to env_logger's target (if RUST_LOG is used)
+#[derive(Debug)]
+pub struct AppLogger
+{
+    target: Option<env_logger::Logger>,
+    records: Mutex<VecDeque<String>>,
+}
+
+impl Log for AppLogger
+{
+    fn enabled(&self, metadata: &Metadata) -> bool
+    {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record)
+    {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if let Some(tgt) = &self.target {
+            if tgt.matches(record) {
+                tgt.log(record);
+            }
+        }
+
+        let mut recs = self.records.lock().unwrap();
+        if recs.len() == APP_LOG_MAX_NR_RECORDS {
+            recs.pop_front();
+        }
+        recs.push_back(format!("[{:<5} {}] {}",
+            record.level(), record.target(), record.args()));
+    }
+
+    fn flush(&self)
+    {
+        if let Some(tgt) = &self.target {
+            tgt.flush();
+        }
+    }
+}
+
+impl AppLogger
+{
+    pub fn records() -> Vec<String>
+    {
+        if let Some(lg) = APP_LOGGER.get() {
+            let recs = lg.records.lock().unwrap();
+            return recs.iter().cloned().collect();
+        }
+
+        Vec::new()
+    }
+
+    pub fn level() -> LevelFilter
+    {
+        log::max_level()
+    }
+
+    pub fn next_level()
+    {
+        let nlvl = match log::max_level() {
+            LevelFilter::Off => LevelFilter::Error,
+            LevelFilter::Error => LevelFilter::Warn,
+            LevelFilter::Warn => LevelFilter::Info,
+            LevelFilter::Info => LevelFilter::Debug,
+            LevelFilter::Debug => LevelFilter::Trace,
+            LevelFilter::Trace => LevelFilter::Off,
+        };
+
+        log::set_max_level(nlvl);
+    }
+
+    pub fn init(target: Option<env_logger::Logger>) -> Result<()>
+    {
+        // start with RUST_LOG level or off (if not used)
+        let level = if let Some(tgt) = &target {
+            tgt.filter() } else { LevelFilter::Off };
+
+        let logger = APP_LOGGER.get_or_init(|| AppLogger {
+            target,
+            records: Mutex::new(VecDeque::new()),
+        });
+        if log::set_logger(logger).is_err() {
+            bail!("Failed to set up logger");
+        }
+        log::set_max_level(level);
+
+        Ok(())
+    }
+}
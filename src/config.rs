@@ -0,0 +1,143 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use toml_edit::{DocumentMut, Value};
+
+
+// config file with defaults for the command-line options, e.g.:
+//
+//   ms-interval = 1000
+//   all-clients = true
+//   alert = ["vram>0.9", "power>200"]
+//
+// its options are placed before the ones in the command line, so the
+// latter override them
+#[derive(Debug)]
+pub struct ConfigFile
+{
+    pub args: Vec<OsString>,
+}
+
+impl ConfigFile
+{
+    fn default_path() -> Option<PathBuf>
+    {
+        if let Some(cfg_dir) = env::var_os("XDG_CONFIG_HOME") {
+            return Some(Path::new(&cfg_dir).join("qmassa/config.toml"));
+        }
+        if let Some(home_dir) = env::var_os("HOME") {
+            return Some(Path::new(&home_dir).join(".config/qmassa/config.toml"));
+        }
+
+        None
+    }
+
+    fn value_str(key: &str, val: &Value) -> Result<String>
+    {
+        if let Some(s) = val.as_str() {
+            Ok(s.to_string())
+        } else if let Some(i) = val.as_integer() {
+            Ok(i.to_string())
+        } else if let Some(f) = val.as_float() {
+            Ok(f.to_string())
+        } else {
+            bail!("Unsupported value type for option {:?}", key);
+        }
+    }
+
+    fn add_option(&mut self, key: &str, val: &Value) -> Result<()>
+    {
+        let opt = format!("--{}", key.replace('_', "-"));
+        if opt == "--config" {
+            bail!("Option \"config\" can't be used in a config file");
+        }
+
+        if let Some(b) = val.as_bool() {
+            // bool options are flags, only set if true
+            if b {
+                self.args.push(OsString::from(&opt));
+            }
+        } else if let Some(arr) = val.as_array() {
+            // repeat option for every array value
+            for v in arr.iter() {
+                self.args.push(OsString::from(&opt));
+                self.args.push(OsString::from(ConfigFile::value_str(key, v)?));
+            }
+        } else {
+            self.args.push(OsString::from(&opt));
+            self.args.push(OsString::from(ConfigFile::value_str(key, val)?));
+        }
+
+        Ok(())
+    }
+
+    // look for --config in the command-line args before they're parsed
+    fn path_from_args(cli_args: &Vec<OsString>) -> Option<PathBuf>
+    {
+        let mut it = cli_args.iter().skip(1);
+        while let Some(arg) = it.next() {
+            let arg_str = arg.to_string_lossy();
+            if arg_str == "--" {
+                break;
+            }
+            if arg_str == "--config" {
+                return it.next().map(PathBuf::from);
+            }
+            if let Some(fname) = arg_str.strip_prefix("--config=") {
+                return Some(PathBuf::from(fname));
+            }
+        }
+
+        None
+    }
+
+    pub fn from(path: &Path) -> Result<ConfigFile>
+    {
+        let cfg_str = fs::read_to_string(path)?;
+        let doc: DocumentMut = cfg_str.parse()?;
+
+        let mut cfg = ConfigFile {
+            args: Vec::new(),
+        };
+        for (key, item) in doc.iter() {
+            if let Some(val) = item.as_value() {
+                cfg.add_option(key, val)?;
+            } else {
+                bail!("Option {:?} isn't a key = value pair", key);
+            }
+        }
+
+        Ok(cfg)
+    }
+
+    // returns command-line args with the config file options merged in
+    pub fn merge_args(cli_args: Vec<OsString>) -> Result<Vec<OsString>>
+    {
+        let cfg: ConfigFile;
+        if let Some(path) = ConfigFile::path_from_args(&cli_args) {
+            cfg = ConfigFile::from(&path)
+                .with_context(|| format!("Failed to read config file {:?}", path))?;
+        } else if let Some(path) = ConfigFile::default_path() {
+            if !path.is_file() {
+                return Ok(cli_args);
+            }
+            cfg = ConfigFile::from(&path)
+                .with_context(|| format!("Failed to read config file {:?}", path))?;
+        } else {
+            return Ok(cli_args);
+        }
+
+        let mut res = Vec::new();
+        let mut it = cli_args.into_iter();
+        if let Some(prog) = it.next() {
+            res.push(prog);
+        }
+        res.extend(cfg.args);
+        res.extend(it);
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,352 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::app_data::AppDataJson;
+use crate::plotter::{Plotter, Series};
+
+
+// engines, freqs and power curves of a device along one of the runs, in
+// secs since the run's start
+#[derive(Debug)]
+struct RunSeries
+{
+    engines: Vec<Series>,
+    freqs: Vec<Vec<Series>>,    // REQ and ACT of each freqs domain
+    power: Vec<Series>,
+    markers: Vec<(f64, String)>,
+    duration: f64,
+}
+
+impl RunSeries
+{
+    // timestamps are relative to the run's first sample so that both runs
+    // are aligned when compared
+    fn from(jsondata: &AppDataJson, idx: usize) -> RunSeries
+    {
+        let states = jsondata.states();
+        let di = &states.front().unwrap().devs_state[idx];
+        let first_ts = *states.front().unwrap().timestamps.back().unwrap();
+        let rel_secs = |ts: u128| ts.saturating_sub(first_ts) as f64 / 1000.0;
+
+        // engines can show up after the first state (e.g. first client)
+        let mut eng_names: Vec<&String> = Vec::new();
+        for state in states.iter() {
+            for en in state.devs_state[idx].eng_names.iter() {
+                if !eng_names.contains(&en) {
+                    eng_names.push(en);
+                }
+            }
+        }
+        let mut engines: Vec<Series> = eng_names.iter()
+            .map(|en| (en.to_uppercase(), Vec::new()))
+            .collect();
+        let mut freqs: Vec<Vec<Series>> = di.freq_limits.iter()
+            .map(|fl| {
+                let nm = fl.name.to_uppercase();
+                vec![(format!("{} REQ", nm), Vec::new()),
+                    (format!("{} ACT", nm), Vec::new())]
+            })
+            .collect();
+        let mut power: Vec<Series> = vec![
+            (String::from("GPU"), Vec::new()),
+            (String::from("PKG"), Vec::new()),
+        ];
+        let mut markers: Vec<(f64, String)> = Vec::new();
+
+        for state in states.iter() {
+            let xval = rel_secs(*state.timestamps.back().unwrap());
+            let dstats = &state.devs_state[idx].dev_stats;
+
+            for (nr, en) in eng_names.iter().enumerate() {
+                if let Some(eu) = dstats.eng_usage.get(*en).and_then(|v| v.back()) {
+                    engines[nr].1.push((xval, *eu));
+                }
+            }
+            if let Some(fqs) = dstats.freqs.back() {
+                for (nr, fq) in fqs.iter().take(freqs.len()).enumerate() {
+                    freqs[nr][0].1.push((xval, fq.cur_freq as f64));
+                    freqs[nr][1].1.push((xval, fq.act_freq as f64));
+                }
+            }
+            if let Some(pwr) = dstats.power.back() {
+                power[0].1.push((xval, pwr.gpu_cur_power));
+                power[1].1.push((xval, pwr.pkg_cur_power));
+            }
+
+            // markers from all stats windows, each only once
+            for (ts, name) in state.markers.iter() {
+                let mkr = (rel_secs(*ts), name.clone());
+                if !markers.contains(&mkr) {
+                    markers.push(mkr);
+                }
+            }
+        }
+
+        let last_state = states.back().unwrap();
+        let duration = rel_secs(*last_state.timestamps.back().unwrap());
+
+        RunSeries {
+            engines,
+            freqs,
+            power,
+            markers,
+            duration,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Differ
+{
+    runs: [AppDataJson; 2],
+    labels: [String; 2],
+    out_prefix: Option<String>,
+    dev_slots: Option<[String; 2]>,   // device PCI slot in each run
+    prec: Option<usize>,
+    quiet: bool,
+}
+
+impl Differ
+{
+    fn avg_max(pts: &[(f64, f64)]) -> Option<(f64, f64)>
+    {
+        if pts.is_empty() {
+            return None;
+        }
+        let sum: f64 = pts.iter().map(|(_, val)| val).sum();
+        let max = pts.iter().map(|(_, val)| *val).fold(0.0, f64::max);
+
+        Some((sum / pts.len() as f64, max))
+    }
+
+    fn find<'a>(series: &'a [Series], name: &str) -> Option<&'a Series>
+    {
+        series.iter().find(|(nm, _)| nm == name)
+    }
+
+    fn print_row(&self, name: &str, base: Option<f64>, new: Option<f64>)
+    {
+        let prec = self.prec.unwrap_or(2);
+        let pct_prec = self.prec.unwrap_or(1);
+        let fmt_val = |val: Option<f64>| match val {
+            Some(val) => format!("{:.*}", prec, val),
+            None => String::from("-"),
+        };
+        let (delta, pct) = match (base, new) {
+            (Some(bv), Some(nv)) => (format!("{:+.*}", prec, nv - bv),
+                if bv != 0.0 {
+                    format!("{:+.*}%", pct_prec, (nv - bv) * 100.0 / bv)
+                } else {
+                    String::new()
+                }),
+            _ => (String::new(), String::new()),
+        };
+
+        println!("{:<28} {:>14} {:>14} {:>12} {:>9}",
+            name, fmt_val(base), fmt_val(new), delta, pct);
+    }
+
+    // average and maximum of each series in both runs, matched by name
+    fn print_series(&self, title: &str, unit: &str,
+        base: &[Series], new: &[Series])
+    {
+        let mut names: Vec<&String> = base.iter().map(|(nm, _)| nm).collect();
+        for (nm, _) in new.iter() {
+            if !names.contains(&nm) {
+                names.push(nm);
+            }
+        }
+
+        for nm in names.into_iter() {
+            let bs = Differ::find(base, nm).and_then(|s| Differ::avg_max(&s.1));
+            let ns = Differ::find(new, nm).and_then(|s| Differ::avg_max(&s.1));
+            self.print_row(&format!("{} {} avg ({})", nm, title, unit),
+                bs.map(|s| s.0), ns.map(|s| s.0));
+            self.print_row(&format!("{} {} max ({})", nm, title, unit),
+                bs.map(|s| s.1), ns.map(|s| s.1));
+        }
+    }
+
+    fn summary(&self, title: &str, series: &[RunSeries; 2])
+    {
+        let [base, new] = series;
+        let label = |nr: usize| -> String {
+            self.labels[nr].chars().take(14).collect()
+        };
+
+        println!("{}", title);
+        println!("{:<28} {:>14} {:>14} {:>12} {:>9}",
+            "", label(0), label(1), "delta", "");
+        self.print_row("Duration (s)", Some(base.duration), Some(new.duration));
+        self.print_series("usage", "%", &base.engines, &new.engines);
+        let nr_freqs = std::cmp::max(base.freqs.len(), new.freqs.len());
+        for nr in 0..nr_freqs {
+            self.print_series("freq", "MHz",
+                base.freqs.get(nr).map_or(&[], |v| v.as_slice()),
+                new.freqs.get(nr).map_or(&[], |v| v.as_slice()));
+        }
+        self.print_series("power", "W", &base.power, &new.power);
+        println!();
+    }
+
+    fn report_saved(&self, title: &str, out_file: &str)
+    {
+        if !self.quiet {
+            println!("qmassa: Chart {:?} saved to {:?}", title, out_file);
+        }
+    }
+
+    fn plot(&self, out_prefix: &str, pci_dev: &str, title: &str,
+        series: &[RunSeries; 2]) -> Result<()>
+    {
+        let [base, new] = series;
+        let labels = [self.labels[0].as_str(), self.labels[1].as_str()];
+        let x_max = f64::max(base.duration, new.duration);
+        let mut markers = Vec::new();
+        for (nr, rs) in series.iter().enumerate() {
+            for (ts, name) in rs.markers.iter() {
+                markers.push((*ts, format!("{}: {}", labels[nr], name)));
+            }
+        }
+
+        if !base.engines.is_empty() || !new.engines.is_empty() {
+            let out_file = format!("{}-{}-engines.svg", out_prefix, pci_dev);
+            let chart_title = format!("{} - Engines Usage", title);
+            Plotter::plot_overlay(&out_file, &chart_title, "Usage (%)",
+                x_max, 100.0, [(labels[0], &base.engines),
+                (labels[1], &new.engines)], &markers)?;
+            self.report_saved(&chart_title, &out_file);
+        }
+
+        let nr_freqs = std::cmp::min(base.freqs.len(), new.freqs.len());
+        for nr in 0..nr_freqs {
+            let y_max = base.freqs[nr].iter().chain(new.freqs[nr].iter())
+                .flat_map(|(_, pts)| pts.iter().map(|(_, val)| *val))
+                .fold(0.0, f64::max);
+            let dom = base.freqs[nr][0].0.trim_end_matches(" REQ");
+            let out_file = format!("{}-{}-freqs-{}.svg",
+                out_prefix, pci_dev, dom.to_lowercase());
+            let chart_title = format!("{} - {} Frequencies", title, dom);
+            Plotter::plot_overlay(&out_file, &chart_title, "Frequency (MHz)",
+                x_max, y_max, [(labels[0], &base.freqs[nr]),
+                (labels[1], &new.freqs[nr])], &markers)?;
+            self.report_saved(&chart_title, &out_file);
+        }
+
+        let y_max = base.power.iter().chain(new.power.iter())
+            .flat_map(|(_, pts)| pts.iter().map(|(_, val)| *val))
+            .fold(0.0, f64::max);
+        let out_file = format!("{}-{}-power.svg", out_prefix, pci_dev);
+        let chart_title = format!("{} - Power Usage", title);
+        Plotter::plot_overlay(&out_file, &chart_title, "Power (W)",
+            x_max, y_max, [(labels[0], &base.power),
+            (labels[1], &new.power)], &markers)?;
+        self.report_saved(&chart_title, &out_file);
+
+        Ok(())
+    }
+
+    // pairs of devices (by index in each run) to be compared, the selected
+    // one, the same PCI slot in both or the only device each run has
+    fn device_pairs(&self) -> Result<Vec<(usize, usize)>>
+    {
+        let base_devs = &self.runs[0].states().front().unwrap().devs_state;
+        let new_devs = &self.runs[1].states().front().unwrap().devs_state;
+
+        if let Some([bs, ns]) = &self.dev_slots {
+            let bidx = base_devs.iter().position(|d| d.pci_dev == *bs);
+            let nidx = new_devs.iter().position(|d| d.pci_dev == *ns);
+            return match (bidx, nidx) {
+                (Some(bidx), Some(nidx)) => Ok(vec![(bidx, nidx)]),
+                (None, _) => bail!("No DRM GPU device {:?} in the {:?} run",
+                    bs, &self.labels[0]),
+                (_, None) => bail!("No DRM GPU device {:?} in the {:?} run",
+                    ns, &self.labels[1]),
+            };
+        }
+
+        let mut res = Vec::new();
+        for (bidx, bd) in base_devs.iter().enumerate() {
+            if let Some(nidx) = new_devs.iter()
+                .position(|nd| nd.pci_dev == bd.pci_dev) {
+                res.push((bidx, nidx));
+            }
+        }
+        // e.g. runs from different machines
+        if res.is_empty() && base_devs.len() == 1 && new_devs.len() == 1 {
+            res.push((0, 0));
+        }
+        if res.is_empty() {
+            bail!("No DRM GPU devices in common in the JSON files");
+        }
+
+        Ok(res)
+    }
+
+    pub fn diff(&self) -> Result<()>
+    {
+        for (bidx, nidx) in self.device_pairs()?.into_iter() {
+            let bd = &self.runs[0].states().front().unwrap().devs_state[bidx];
+            let nd = &self.runs[1].states().front().unwrap().devs_state[nidx];
+            let series = [
+                RunSeries::from(&self.runs[0], bidx),
+                RunSeries::from(&self.runs[1], nidx),
+            ];
+
+            let title = if bd.pci_dev == nd.pci_dev {
+                format!("{} ({})", &bd.vdr_dev_rev, &bd.pci_dev)
+            } else {
+                format!("{} ({}) vs {} ({})", &bd.vdr_dev_rev, &bd.pci_dev,
+                    &nd.vdr_dev_rev, &nd.pci_dev)
+            };
+            match &self.out_prefix {
+                Some(op) => self.plot(op, &bd.pci_dev, &bd.vdr_dev_rev, &series)?,
+                None => self.summary(&title, &series),
+            }
+        }
+
+        Ok(())
+    }
+
+    // default labels are the JSON file names, "base"/"new" if the same
+    fn default_labels(json_files: &[String]) -> [String; 2]
+    {
+        let stem = |fnames: &str| -> String {
+            Path::new(fnames).file_stem()
+                .map(|st| st.to_string_lossy().to_string())
+                .unwrap_or_else(|| fnames.to_string())
+        };
+        let labels = [stem(&json_files[0]), stem(&json_files[1])];
+        if labels[0] == labels[1] {
+            return [String::from("base"), String::from("new")];
+        }
+
+        labels
+    }
+
+    pub fn from(runs: [AppDataJson; 2], json_files: &[String],
+        labels_opt: Option<String>, out_prefix: Option<String>,
+        dev_slots: Option<[String; 2]>, prec: Option<u8>,
+        quiet: bool) -> Result<Differ>
+    {
+        let labels = if let Some(lbls) = labels_opt {
+            let lbls: Vec<_> = lbls.split(",").collect();
+            if lbls.len() != 2 || lbls.iter().any(|l| l.is_empty()) {
+                bail!("Invalid labels {:?}, need BASE,NEW", lbls.join(","));
+            }
+            [lbls[0].to_string(), lbls[1].to_string()]
+        } else {
+            Differ::default_labels(json_files)
+        };
+
+        Ok(Differ {
+            runs,
+            labels,
+            out_prefix,
+            dev_slots,
+            prec: prec.map(|p| p as usize),
+            quiet,
+        })
+    }
+}
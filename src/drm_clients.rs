@@ -1,7 +1,11 @@
-use std::collections::{VecDeque, HashMap};
+use std::collections::{VecDeque, HashMap, HashSet};
 use std::cell::{RefCell, RefMut};
-use std::path::PathBuf;
-use std::rc::{Rc, Weak};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::sync::{Mutex, Weak};
+use std::thread;
 use std::time;
 
 use anyhow::{bail, Result};
@@ -9,10 +13,16 @@ use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 
 use crate::proc_info::ProcInfo;
-use crate::drm_fdinfo::{DrmEngine, DrmMemRegion, DrmFdinfo};
+use crate::drm_fdinfo::{DrmEngine, DrmMemRegion, DrmFdinfo, DrmFdinfoCache};
 use crate::drm_drivers::DrmDriver;
 
 
+// a process takes ~65us to scan and a thread ~25us to start (see README),
+// so each thread gets enough processes to keep that under 0.5% of its
+// work and there are only a few threads
+const SCAN_MIN_PROCS_PER_THREAD: usize = 256;
+const SCAN_MAX_THREADS: usize = 4;
+
 #[derive(Debug)]
 pub struct DrmEnginesAcum
 {
@@ -21,6 +31,14 @@ pub struct DrmEnginesAcum
     pub acum_total_cycles: u64,
 }
 
+impl Default for DrmEnginesAcum
+{
+    fn default() -> DrmEnginesAcum
+    {
+        DrmEnginesAcum::new()
+    }
+}
+
 impl DrmEnginesAcum
 {
     pub fn new() -> DrmEnginesAcum
@@ -33,7 +51,7 @@ impl DrmEnginesAcum
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DrmEngineDelta
 {
     pub delta_time: u64,
@@ -41,6 +59,14 @@ pub struct DrmEngineDelta
     pub delta_total_cycles: u64,
 }
 
+impl Default for DrmEngineDelta
+{
+    fn default() -> DrmEngineDelta
+    {
+        DrmEngineDelta::new()
+    }
+}
+
 impl DrmEngineDelta
 {
     pub fn new() -> DrmEngineDelta
@@ -60,6 +86,18 @@ pub struct DrmClientMemInfo
     pub smem_rss: u64,
     pub vram_used: u64,
     pub vram_rss: u64,
+    #[serde(default)]
+    pub gtt_used: u64,      // only amdgpu
+    #[serde(default)]
+    pub gtt_rss: u64,
+}
+
+impl Default for DrmClientMemInfo
+{
+    fn default() -> DrmClientMemInfo
+    {
+        DrmClientMemInfo::new()
+    }
 }
 
 impl DrmClientMemInfo
@@ -71,6 +109,8 @@ impl DrmClientMemInfo
             smem_rss: 0,
             vram_used: 0,
             vram_rss: 0,
+            gtt_used: 0,
+            gtt_rss: 0,
         }
     }
 }
@@ -82,6 +122,7 @@ pub struct DrmClientInfo
     pub pci_dev: String,
     pub drm_minor: u32,
     pub client_id: u32,
+    pub nr_contexts: u32,
     pub proc: ProcInfo,
     pub fdinfo_path: PathBuf,
     pub shared_procs: Vec<(ProcInfo, PathBuf)>,
@@ -93,7 +134,7 @@ pub struct DrmClientInfo
     nr_updates: u64,
     ms_elapsed: u64,
     last_update: time::Instant,
-    driver: Option<Weak<RefCell<dyn DrmDriver>>>,
+    driver: Option<Weak<Mutex<dyn DrmDriver>>>,
 }
 
 impl Default for DrmClientInfo
@@ -104,6 +145,7 @@ impl Default for DrmClientInfo
             pci_dev: String::new(),
             drm_minor: 0,
             client_id: 0,
+            nr_contexts: 0,
             proc: ProcInfo::default(),
             fdinfo_path: PathBuf::new(),
             shared_procs: Vec::new(),
@@ -126,7 +168,7 @@ impl DrmClientInfo
     {
         if let Some(w_ref) = &self.driver {
             if let Some(drv_ref) = w_ref.upgrade() {
-                let mut drv_b = drv_ref.borrow_mut();
+                let mut drv_b = drv_ref.lock().unwrap();
                 if let Ok(res) = drv_b.client_mem_info(&self.mem_regions) {
                     return res;
                 }
@@ -141,6 +183,43 @@ impl DrmClientInfo
         DrmClientMemInfo::new()
     }
 
+    pub fn eng_capacity(&self, eng: &String) -> u32
+    {
+        if let Some(eu) = self.engs_last.get(eng) {
+            return eu.capacity;
+        }
+
+        1
+    }
+
+    // raw engine time (ns) and cycles since last update
+    pub fn eng_delta(&self, eng: &String) -> DrmEngineDelta
+    {
+        if let Some(ed) = self.engs_delta.get(eng) {
+            if *self.engs_updates.get(eng).unwrap() >= 2 {
+                return ed.clone();
+            }
+        }
+
+        DrmEngineDelta::new()
+    }
+
+    // engine busy time (s) since last update, summed over all of the
+    // engine class instances (i.e. engine-seconds)
+    pub fn eng_busy_secs(&self, eng: &String) -> f64
+    {
+        let ed = self.eng_delta(eng);
+        if ed.delta_time > 0 {
+            return ed.delta_time as f64 / 1e9;
+        }
+        if ed.delta_total_cycles > 0 {
+            return ed.delta_cycles as f64 / ed.delta_total_cycles as f64 *
+                self.ms_elapsed as f64 / 1000.0;
+        }
+
+        0.0
+    }
+
     pub fn eng_utilization(&self, eng: &String) -> f64
     {
         if !self.engs_last.contains_key(eng) {
@@ -193,6 +272,22 @@ impl DrmClientInfo
         tot
     }
 
+    // engines usage needs deltas, so at least 2 updates
+    pub fn has_eng_usage(&self) -> bool
+    {
+        self.nr_updates >= 2
+    }
+
+    // engines ran in the last update, only once they have a real delta
+    // (the first update's delta is the whole accumulated time)
+    pub fn is_engine_active(&self) -> bool
+    {
+        self.engs_delta.iter()
+            .filter(|(en, _)| self.engs_updates.get(*en)
+                .is_some_and(|nr| *nr >= 2))
+            .any(|(_, ed)| ed.delta_time > 0 || ed.delta_cycles > 0)
+    }
+
     pub fn is_active(&self) -> bool
     {
         let acum = &self.engs_acum;
@@ -208,7 +303,7 @@ impl DrmClientInfo
         false
     }
 
-    pub fn update(&mut self, pinfo: ProcInfo, fdi: DrmFdinfo)
+    pub fn update(&mut self, pinfo: ProcInfo, fdi: &DrmFdinfo)
     {
         // handle process and fdinfo path updates
         if self.proc != pinfo {
@@ -218,7 +313,8 @@ impl DrmClientInfo
             debug!("ERR: failed to update process info for {:?}: {:?}",
                 self.proc, err);
         }
-        self.fdinfo_path = fdi.path;
+        self.fdinfo_path.clone_from(&fdi.path);
+        self.nr_contexts = fdi.nr_contexts;
 
         // handle new engines showing up in a client's DRM fdinfo
         // or the very unlikely (not possible?) removal of an engine
@@ -242,28 +338,48 @@ impl DrmClientInfo
         for (nm, oeng) in self.engs_last.iter_mut() {
             let deng = self.engs_delta.get_mut(nm).unwrap();
             let neng = fdi.engines.get(nm).unwrap();
-
-            if neng.time >= oeng.time {
-                self.engs_acum.acum_time += neng.time;
-                deng.delta_time = neng.time - oeng.time;
-                oeng.time = neng.time;
+            oeng.capacity = neng.capacity;
+
+            // a counter going backwards means it was reset (e.g. GPU
+            // reset), skip that sample and restart from the new value
+            if neng.time < oeng.time ||
+                neng.cycles < oeng.cycles ||
+                neng.total_cycles < oeng.total_cycles {
+                debug!("INF: engine {:?} counters reset in {:?}.",
+                    nm, self.fdinfo_path);
             }
 
-            if neng.cycles >= oeng.cycles {
-                self.engs_acum.acum_cycles += neng.cycles;
-                deng.delta_cycles = neng.cycles - oeng.cycles;
-                oeng.cycles = neng.cycles;
-            }
+            self.engs_acum.acum_time += neng.time;
+            deng.delta_time = neng.time.saturating_sub(oeng.time);
+            oeng.time = neng.time;
 
-            if neng.total_cycles >= oeng.total_cycles {
-                self.engs_acum.acum_total_cycles += neng.total_cycles;
-                deng.delta_total_cycles = neng.total_cycles - oeng.total_cycles;
-                oeng.total_cycles = neng.total_cycles;
-            }
+            self.engs_acum.acum_cycles += neng.cycles;
+            deng.delta_cycles = neng.cycles.saturating_sub(oeng.cycles);
+            oeng.cycles = neng.cycles;
+
+            self.engs_acum.acum_total_cycles += neng.total_cycles;
+            deng.delta_total_cycles =
+                neng.total_cycles.saturating_sub(oeng.total_cycles);
+            oeng.total_cycles = neng.total_cycles;
 
             self.engs_updates.entry(nm.clone()).and_modify(|nr| *nr += 1);
         }
-        self.mem_regions = fdi.mem_regions;
+        // mem regions rarely change, so just update their values
+        self.mem_regions.retain(|k, _| fdi.mem_regions.contains_key(k));
+        for (nm, nmrg) in fdi.mem_regions.iter() {
+            match self.mem_regions.get_mut(nm) {
+                Some(mrg) => {
+                    mrg.total = nmrg.total;
+                    mrg.shared = nmrg.shared;
+                    mrg.resident = nmrg.resident;
+                    mrg.purgeable = nmrg.purgeable;
+                    mrg.active = nmrg.active;
+                },
+                None => {
+                    self.mem_regions.insert(nm.clone(), nmrg.clone());
+                },
+            }
+        }
 
         // one more update for this DRM client
         self.ms_elapsed = self.last_update.elapsed().as_millis() as u64;
@@ -271,12 +387,12 @@ impl DrmClientInfo
         self.nr_updates += 1;
     }
 
-    pub fn set_driver(&mut self, drv_wref: Weak<RefCell<dyn DrmDriver>>)
+    pub fn set_driver(&mut self, drv_wref: Weak<Mutex<dyn DrmDriver>>)
     {
         self.driver = Some(drv_wref);
     }
 
-    pub fn from(pinfo: ProcInfo, fdi: DrmFdinfo) -> DrmClientInfo
+    pub fn from(pinfo: ProcInfo, fdi: &DrmFdinfo) -> DrmClientInfo
     {
         let mut cli = DrmClientInfo {
             pci_dev: fdi.pci_dev.clone(),
@@ -302,12 +418,24 @@ pub struct DrmClients
 {
     base_pid: String,
     infos: HashMap<String, Rc<RefCell<Vec<DrmClientInfo>>>>,
+    fdinfos: HashMap<u32, DrmFdinfoCache>,  // by pid, then client id
+    exclude_self: bool,
+    excluded_pids: HashSet<u32>,
+    minor_devs: HashMap<u32, String>,   // DRM minor -> PCI dev
+    children_only: bool,                // skip base_pid's own clients
+    cgroup: Option<PathBuf>,            // scan only its (and sub) procs
 }
 
 impl DrmClients
 {
+    // used for fdinfos without drm-pdev (e.g. virtio_gpu)
+    pub fn set_minor_devs(&mut self, minor_devs: HashMap<u32, String>)
+    {
+        self.minor_devs = minor_devs;
+    }
+
     pub fn set_dev_clients_driver(&mut self,
-        dev: &String, drv_wref: Weak<RefCell<dyn DrmDriver>>)
+        dev: &String, drv_wref: Weak<Mutex<dyn DrmDriver>>)
     {
         if !self.infos.contains_key(dev) {
             return;
@@ -330,7 +458,7 @@ impl DrmClients
     }
 
     fn map_has_client<'a>(map: &'a mut HashMap<String,
-        Rc<RefCell<Vec<DrmClientInfo>>>>, dev: &'a String,
+        Rc<RefCell<Vec<DrmClientInfo>>>>, dev: &String,
         minor: u32, id: u32) -> Option<RefMut<'a, DrmClientInfo>>
     {
         if !map.contains_key(dev) {
@@ -392,32 +520,45 @@ impl DrmClients
 
     fn process_fdinfos(&mut self,
         ninfos: &mut HashMap<String, Rc<RefCell<Vec<DrmClientInfo>>>>,
+        nfdinfos: &mut HashMap<u32, DrmFdinfoCache>,
         nproc: &ProcInfo, fdinfos: Vec<DrmFdinfo>)
     {
-        for fdi in fdinfos {
+        for mut fdi in fdinfos {
+            if fdi.pci_dev.is_empty() {
+                if let Some(pci_dev) = self.minor_devs.get(&fdi.drm_minor) {
+                    fdi.pci_dev.clone_from(pci_dev);
+                } else {
+                    debug!("INF: DRM fdinfo {:?} with no PCI dev, ignoring.",
+                        fdi.path);
+                    continue;
+                }
+            }
+
             if let Some(mut cliref) = DrmClients::map_has_client(ninfos,
                 &fdi.pci_dev, fdi.drm_minor, fdi.client_id) {
-                cliref.shared_procs.push((nproc.clone(), fdi.path));
+                cliref.shared_procs.push((nproc.clone(), fdi.path.clone()));
                 debug!("INF: repeated drm client/fd info: proc={:?}, drm-minor={:?}, drm-client-id={:?}", nproc, fdi.drm_minor, fdi.client_id);
+                nfdinfos.entry(nproc.pid).or_default().insert(fdi.client_id, fdi);
                 continue;
             }
 
-            let pci_dev = fdi.pci_dev.clone();
             if let Some(mut cli) = DrmClients::map_remove_client(
                 &mut self.infos, &fdi.pci_dev, fdi.drm_minor, fdi.client_id) {
-                cli.update(nproc.clone(), fdi);
-                DrmClients::map_insert_client(ninfos, pci_dev, cli);
+                cli.update(nproc.clone(), &fdi);
+                DrmClients::map_insert_client(ninfos, fdi.pci_dev.clone(), cli);
             } else {
-                let cli = DrmClientInfo::from(nproc.clone(), fdi);
-                DrmClients::map_insert_client(ninfos, pci_dev, cli);
+                let cli = DrmClientInfo::from(nproc.clone(), &fdi);
+                DrmClients::map_insert_client(ninfos, fdi.pci_dev.clone(), cli);
             }
+
+            // reused on the next parse of this client's fdinfo
+            nfdinfos.entry(nproc.pid).or_default().insert(fdi.client_id, fdi);
         }
     }
 
     fn scan_all_pids(&mut self) -> Result<()>
     {
-        let mut ninfos: HashMap<String,
-            Rc<RefCell<Vec<DrmClientInfo>>>> = HashMap::new();
+        let mut procs: Vec<ProcInfo> = Vec::new();
 
         let proc_iter = ProcInfo::iter_proc_pids();
         if let Err(err) = proc_iter {
@@ -432,31 +573,130 @@ impl DrmClients
                     break;
                 }
                 let nproc = nproc.unwrap();
+                if self.excluded_pids.contains(&nproc.pid) {
+                    continue;
+                }
+                procs.push(nproc);
+            }
+        }
+
+        self.scan_procs(procs);
+
+        Ok(())
+    }
+
+    // pids from cgroup.procs of cgroup_dir and all of its sub cgroups
+    // (e.g. the containers of a pod)
+    fn cgroup_pids(cgroup_dir: &Path, pids: &mut Vec<String>)
+    {
+        let procs_path = cgroup_dir.join("cgroup.procs");
+        match fs::read_to_string(&procs_path) {
+            Ok(procs_str) => {
+                pids.extend(procs_str.lines()
+                    .map(|ln| ln.trim().to_string())
+                    .filter(|ln| !ln.is_empty()));
+            },
+            Err(err) => {
+                debug!("ERR: failed to read {:?}: {:?}", procs_path, err);
+            }
+        }
 
-                // search and parse all DRM fdinfo from npid process
-                let fdinfos = nproc.drm_fdinfos();
-                if let Err(err) = fdinfos {
-                    debug!("ERR: failed to get DRM fdinfos from {:?}: {:?}",
-                        nproc.pid, err);
+        if let Ok(rd) = fs::read_dir(cgroup_dir) {
+            for sub in rd.filter_map(|r| r.ok()).map(|r| r.path()) {
+                if sub.is_dir() {
+                    DrmClients::cgroup_pids(&sub, pids);
+                }
+            }
+        }
+    }
+
+    fn scan_cgroup(&mut self) -> Result<()>
+    {
+        let mut pids = Vec::new();
+        DrmClients::cgroup_pids(self.cgroup.as_ref().unwrap(), &mut pids);
+
+        let mut procs: Vec<ProcInfo> = Vec::new();
+        for npid in pids.iter() {
+            // processes can exit between reading cgroup.procs and /proc
+            let nproc = match ProcInfo::from(npid) {
+                Ok(nproc) => nproc,
+                Err(err) => {
+                    debug!("ERR: Couldn't get proc info for {:?}: {:?}",
+                        npid, err);
                     continue;
                 }
-                let fdinfos = fdinfos.unwrap();
+            };
+            if self.excluded_pids.contains(&nproc.pid) {
+                continue;
+            }
+            procs.push(nproc);
+        }
+
+        self.scan_procs(procs);
+
+        Ok(())
+    }
+
+    fn scan_procs(&mut self, procs: Vec<ProcInfo>)
+    {
+        let mut ninfos: HashMap<String,
+            Rc<RefCell<Vec<DrmClientInfo>>>> = HashMap::new();
+        let mut nfdinfos: HashMap<u32, DrmFdinfoCache> = HashMap::new();
+
+        // search and parse all DRM fdinfo from all processes, in parallel
+        // only if there are enough of them to pay for the threads, results
+        // are kept in the same order as the processes list
+        let nr_threads = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(SCAN_MAX_THREADS)
+            .min(procs.len() / SCAN_MIN_PROCS_PER_THREAD);
+        let mut caches: Vec<DrmFdinfoCache> = procs.iter()
+            .map(|p| self.fdinfos.remove(&p.pid).unwrap_or_default())
+            .collect();
+        let all_fdinfos: Vec<Result<Vec<DrmFdinfo>>> = if nr_threads <= 1 {
+            procs.iter().zip(caches.iter_mut())
+                .map(|(p, c)| p.drm_fdinfos(c))
+                .collect()
+        } else {
+            let chunk_sz = procs.len().div_ceil(nr_threads);
+            thread::scope(|s| {
+                let handles: Vec<_> = procs.chunks(chunk_sz)
+                    .zip(caches.chunks_mut(chunk_sz))
+                    .map(|(chk, cchk)| s.spawn(move || {
+                        chk.iter().zip(cchk.iter_mut())
+                            .map(|(p, c)| p.drm_fdinfos(c))
+                            .collect::<Vec<_>>()
+                    }))
+                    .collect();
+
+                handles.into_iter()
+                    .flat_map(|h| h.join().unwrap())
+                    .collect()
+            })
+        };
 
-                // sort out DRM client infos based on DRM fdinfos
-                self.process_fdinfos(&mut ninfos, &nproc, fdinfos);
+        for (nproc, fdinfos) in procs.iter().zip(all_fdinfos) {
+            if let Err(err) = fdinfos {
+                debug!("ERR: failed to get DRM fdinfos from {:?}: {:?}",
+                    nproc.pid, err);
+                continue;
             }
+            let fdinfos = fdinfos.unwrap();
+
+            // sort out DRM client infos based on DRM fdinfos
+            self.process_fdinfos(&mut ninfos, &mut nfdinfos, nproc, fdinfos);
         }
 
         // update DRM client infos
         self.infos = ninfos;
-
-        Ok(())
+        self.fdinfos = nfdinfos;
     }
 
     fn scan_pid_tree(&mut self) -> Result<()>
     {
         let mut ninfos: HashMap<String,
             Rc<RefCell<Vec<DrmClientInfo>>>> = HashMap::new();
+        let mut nfdinfos: HashMap<u32, DrmFdinfoCache> = HashMap::new();
         let mut pidq = VecDeque::from([self.base_pid.clone(),]);
 
         while !pidq.is_empty() {
@@ -469,9 +709,22 @@ impl DrmClients
                 continue;
             }
             let nproc = nproc.unwrap();
+            if self.excluded_pids.contains(&nproc.pid) {
+                continue;
+            }
+
+            // only go through base_pid to get to its children
+            if self.children_only && npid == self.base_pid {
+                if let Ok(mut chids) = nproc.children_pids() {
+                    pidq.append(&mut chids);
+                }
+                continue;
+            }
 
             // search and parse all DRM fdinfo from npid process
-            let fdinfos = nproc.drm_fdinfos();
+            let mut cache = self.fdinfos.remove(&nproc.pid)
+                .unwrap_or_default();
+            let fdinfos = nproc.drm_fdinfos(&mut cache);
             if let Err(err) = fdinfos {
                 debug!("ERR: failed to get DRM fdinfos from {:?}: {:?}",
                     npid, err);
@@ -480,7 +733,7 @@ impl DrmClients
             let fdinfos = fdinfos.unwrap();
 
             // sort out DRM client infos based on DRM fdinfos
-            self.process_fdinfos(&mut ninfos, &nproc, fdinfos);
+            self.process_fdinfos(&mut ninfos, &mut nfdinfos, &nproc, fdinfos);
 
             // add all child processes
             let chids = nproc.children_pids();
@@ -495,13 +748,50 @@ impl DrmClients
 
         // update DRM client infos
         self.infos = ninfos;
+        self.fdinfos = nfdinfos;
 
         Ok(())
     }
 
+    // own process and all its descendants
+    fn self_pids() -> HashSet<u32>
+    {
+        let mut res: HashSet<u32> = HashSet::new();
+        let mut pidq = VecDeque::from([process::id().to_string(),]);
+
+        while !pidq.is_empty() {
+            let npid = pidq.pop_front().unwrap();
+
+            let nproc = ProcInfo::from(&npid);
+            if let Err(err) = nproc {
+                debug!("ERR: Couldn't get proc info for {:?}: {:?}", npid, err);
+                continue;
+            }
+            let nproc = nproc.unwrap();
+            res.insert(nproc.pid);
+
+            if let Ok(mut chids) = nproc.children_pids() {
+                pidq.append(&mut chids);
+            }
+        }
+
+        res
+    }
+
+    pub fn set_children_only(&mut self, children_only: bool)
+    {
+        self.children_only = children_only;
+    }
+
     pub fn refresh(&mut self) -> Result<()>
     {
-        if self.base_pid.is_empty() {
+        if self.exclude_self {
+            self.excluded_pids = DrmClients::self_pids();
+        }
+
+        if self.cgroup.is_some() {
+            self.scan_cgroup()?;
+        } else if self.base_pid.is_empty() {
             self.scan_all_pids()?;
         } else {
             self.scan_pid_tree()?;
@@ -521,7 +811,7 @@ impl DrmClients
         Ok(())
     }
 
-    pub fn from_pid_tree(at_pid: &str) -> Result<DrmClients>
+    pub fn from_pid_tree(at_pid: &str, excl_self: bool) -> Result<DrmClients>
     {
         if !at_pid.is_empty() && !ProcInfo::is_valid_pid(at_pid) {
             bail!("Not a valid PID: {}", at_pid);
@@ -530,6 +820,33 @@ impl DrmClients
         Ok(DrmClients {
             base_pid: at_pid.to_string(),
             infos: HashMap::new(),
+            fdinfos: HashMap::new(),
+            exclude_self: excl_self,
+            excluded_pids: HashSet::new(),
+            minor_devs: HashMap::new(),
+            children_only: false,
+            cgroup: None,
         })
     }
+
+    // cgroup v2 path, absolute or relative to /sys/fs/cgroup
+    // (e.g. "kubepods.slice/kubepods-pod1234.slice")
+    pub fn from_cgroup(cgroup: &str, excl_self: bool) -> Result<DrmClients>
+    {
+        let base_dir = Path::new("/sys/fs/cgroup");
+        let cg_path = Path::new(cgroup);
+        let cg_dir = if cg_path.starts_with(base_dir) {
+            cg_path.to_path_buf()
+        } else {
+            base_dir.join(cgroup.trim_start_matches('/'))
+        };
+        if !cg_dir.join("cgroup.procs").is_file() {
+            bail!("Not a valid cgroup: {}", cgroup);
+        }
+
+        let mut clis = DrmClients::from_pid_tree("", excl_self)?;
+        clis.cgroup = Some(cg_dir);
+
+        Ok(clis)
+    }
 }
@@ -1,6 +1,11 @@
 use std::collections::{HashMap, HashSet};
 use std::cell::RefCell;
+use std::cmp::max;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use anyhow::{bail, Result};
 use libc;
@@ -9,7 +14,9 @@ use serde::{Deserialize, Serialize};
 use udev;
 
 use crate::drm_clients::{DrmClients, DrmClientInfo};
-use crate::drm_drivers::{self, DrmDriver};
+use crate::drm_drivers::{self, DrmDriver, DrmDriverOptions};
+use crate::hwmon::Hwmon;
+use crate::qm_error::{QmError, QmResult};
 
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -58,6 +65,14 @@ pub struct DrmDeviceThrottleReasons
     pub status: bool
 }
 
+impl Default for DrmDeviceThrottleReasons
+{
+    fn default() -> DrmDeviceThrottleReasons
+    {
+        DrmDeviceThrottleReasons::new()
+    }
+}
+
 impl DrmDeviceThrottleReasons
 {
     pub fn new() -> DrmDeviceThrottleReasons
@@ -85,6 +100,14 @@ pub struct DrmDeviceFreqLimits
     pub maximum: u64,
 }
 
+impl Default for DrmDeviceFreqLimits
+{
+    fn default() -> DrmDeviceFreqLimits
+    {
+        DrmDeviceFreqLimits::new()
+    }
+}
+
 impl DrmDeviceFreqLimits
 {
     pub fn new() -> DrmDeviceFreqLimits
@@ -106,6 +129,16 @@ pub struct DrmDeviceFreqs
     pub act_freq: u64,
     pub max_freq: u64,
     pub throttle_reasons: DrmDeviceThrottleReasons,
+    #[serde(default)]
+    pub idle_res: Option<f64>,  // % idle (RC6) since last update
+}
+
+impl Default for DrmDeviceFreqs
+{
+    fn default() -> DrmDeviceFreqs
+    {
+        DrmDeviceFreqs::new()
+    }
 }
 
 impl DrmDeviceFreqs
@@ -118,6 +151,7 @@ impl DrmDeviceFreqs
             act_freq: 0,
             max_freq: 0,
             throttle_reasons: DrmDeviceThrottleReasons::new(),
+            idle_res: None,
         }
     }
 }
@@ -129,6 +163,14 @@ pub struct DrmDevicePower
     pub pkg_cur_power: f64,
 }
 
+impl Default for DrmDevicePower
+{
+    fn default() -> DrmDevicePower
+    {
+        DrmDevicePower::new()
+    }
+}
+
 impl DrmDevicePower
 {
     pub fn new() -> DrmDevicePower
@@ -147,10 +189,36 @@ pub struct DrmDeviceMemInfo
     pub smem_used: u64,
     pub vram_total: u64,
     pub vram_used: u64,
+    #[serde(default)]
+    pub gtt_total: u64,     // GTT as its own category (only amdgpu)
+    #[serde(default)]
+    pub gtt_used: u64,
+    #[serde(default)]
+    pub host_total: u64,    // host RAM from /proc/meminfo (only iGPUs)
+    #[serde(default)]
+    pub host_used: u64,
+}
+
+impl Default for DrmDeviceMemInfo
+{
+    fn default() -> DrmDeviceMemInfo
+    {
+        DrmDeviceMemInfo::new()
+    }
 }
 
 impl DrmDeviceMemInfo
 {
+    pub fn has_gtt(&self) -> bool
+    {
+        self.gtt_total > 0
+    }
+
+    pub fn has_host(&self) -> bool
+    {
+        self.host_total > 0
+    }
+
     pub fn new() -> DrmDeviceMemInfo
     {
         DrmDeviceMemInfo {
@@ -158,6 +226,225 @@ impl DrmDeviceMemInfo
             smem_used: 0,
             vram_total: 0,
             vram_used: 0,
+            gtt_total: 0,
+            gtt_used: 0,
+            host_total: 0,
+            host_used: 0,
+        }
+    }
+}
+
+// where each stats group comes from (e.g. "hwmon", "msr" or "pmu"), as
+// their accuracy differs, empty if not reported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrmDeviceSources
+{
+    pub freqs: String,
+    pub power: String,
+    pub meminfo: String,
+}
+
+impl Default for DrmDeviceSources
+{
+    fn default() -> DrmDeviceSources
+    {
+        DrmDeviceSources::new()
+    }
+}
+
+impl DrmDeviceSources
+{
+    pub fn new() -> DrmDeviceSources
+    {
+        DrmDeviceSources {
+            freqs: String::new(),
+            power: String::new(),
+            meminfo: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrmDeviceTemperature
+{
+    pub gpu: f64,           // in C (edge on dGPUs), 0.0 if not available
+    pub hotspot: f64,
+    pub mem: f64,
+}
+
+impl Default for DrmDeviceTemperature
+{
+    fn default() -> DrmDeviceTemperature
+    {
+        DrmDeviceTemperature::new()
+    }
+}
+
+impl DrmDeviceTemperature
+{
+    // hottest of the reported sensors, 0.0 if none is available
+    pub fn max(&self) -> f64
+    {
+        f64::max(self.gpu, f64::max(self.hotspot, self.mem))
+    }
+
+    pub fn new() -> DrmDeviceTemperature
+    {
+        DrmDeviceTemperature {
+            gpu: 0.0,
+            hotspot: 0.0,
+            mem: 0.0,
+        }
+    }
+}
+
+// time an engine spent waiting on semaphores and on events, in %
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrmEngineSched
+{
+    pub engine: String,     // as named by the PMU, e.g. "rcs0"
+    pub sema: f64,
+    pub wait: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrmDeviceHwmonInfo
+{
+    pub power_cap: f64,     // in W, 0.0 if not available
+    pub fan_mode: String,   // empty if not available
+}
+
+impl Default for DrmDeviceHwmonInfo
+{
+    fn default() -> DrmDeviceHwmonInfo
+    {
+        DrmDeviceHwmonInfo::new()
+    }
+}
+
+impl DrmDeviceHwmonInfo
+{
+    fn fan_mode_from(pwm_enable: u64) -> String
+    {
+        match pwm_enable {
+            0 => String::from("full"),
+            1 => String::from("manual"),
+            2 => String::from("auto"),
+            _ => format!("mode {}", pwm_enable),
+        }
+    }
+
+    pub fn new() -> DrmDeviceHwmonInfo
+    {
+        DrmDeviceHwmonInfo {
+            power_cap: 0.0,
+            fan_mode: String::new(),
+        }
+    }
+}
+
+// how the DRM clients engines usage is combined into the device one: sum
+// is the closest to the engine occupancy (but can go over 100% with
+// overlapping samples), max is the busiest client and mean the average
+// of the clients using the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrmEngineAggregation
+{
+    Sum,
+    Max,
+    Mean,
+}
+
+// device PCI slot, its driver and if it tracks GPU resets
+type DriverRefresh = (String, Arc<Mutex<dyn DrmDriver>>, bool);
+
+// connected and enabled display, e.g. "DP-1" at "2560x1440@60"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrmDeviceDisplay
+{
+    pub connector: String,
+    pub mode: String,       // preferred mode, refresh rate from EDID (if any)
+}
+
+impl DrmDeviceDisplay
+{
+    // refresh rate of the EDID preferred timing (1st detailed timing
+    // descriptor), only if it's the same size as the mode
+    fn edid_refresh(edid: &[u8], mode: &str) -> Option<u32>
+    {
+        let dtd = edid.get(54..72)?;
+        let clk = u16::from_le_bytes([dtd[0], dtd[1]]) as u64 * 10000;
+        let hact = dtd[2] as u64 | ((dtd[4] as u64 & 0xf0) << 4);
+        let hblk = dtd[3] as u64 | ((dtd[4] as u64 & 0x0f) << 8);
+        let vact = dtd[5] as u64 | ((dtd[7] as u64 & 0xf0) << 4);
+        let vblk = dtd[6] as u64 | ((dtd[7] as u64 & 0x0f) << 8);
+
+        let total = (hact + hblk) * (vact + vblk);
+        if clk == 0 || total == 0 || mode != format!("{}x{}", hact, vact) {
+            return None;
+        }
+
+        Some((clk as f64 / total as f64).round() as u32)
+    }
+
+    // None if nothing is connected or it's disabled
+    fn from(conn_dir: &Path) -> Option<DrmDeviceDisplay>
+    {
+        let status = fs::read_to_string(conn_dir.join("status")).ok()?;
+        let enabled = fs::read_to_string(conn_dir.join("enabled")).ok()?;
+        if status.trim() != "connected" || enabled.trim() != "enabled" {
+            return None;
+        }
+
+        // e.g. "card1-HDMI-A-1"
+        let name = conn_dir.file_name()?.to_str()?;
+        let connector = name.split_once('-').map_or(name, |(_, c)| c);
+
+        let modes = fs::read_to_string(
+            conn_dir.join("modes")).unwrap_or_default();
+        let mut mode = modes.lines().next().unwrap_or("").trim().to_string();
+        if let Ok(edid) = fs::read(conn_dir.join("edid")) {
+            if let Some(rr) = DrmDeviceDisplay::edid_refresh(&edid, &mode) {
+                mode = format!("{}@{}", mode, rr);
+            }
+        }
+
+        Some(DrmDeviceDisplay {
+            connector: connector.to_string(),
+            mode,
+        })
+    }
+}
+
+// driver stats read in a refresh, None for skipped groups
+#[derive(Debug)]
+struct DrmDriverStats
+{
+    freqs: Option<Vec<DrmDeviceFreqs>>,
+    power: Option<DrmDevicePower>,
+    mem_info: Option<DrmDeviceMemInfo>,
+    perf_profile: Option<String>,
+    pmu_rates: Option<Vec<(String, f64)>>,
+    eng_sched: Option<Vec<DrmEngineSched>>,
+    temperature: Option<DrmDeviceTemperature>,
+    busy: Option<Option<f64>>,
+    reset_count: Option<Option<u64>>,
+}
+
+impl DrmDriverStats
+{
+    fn new() -> DrmDriverStats
+    {
+        DrmDriverStats {
+            freqs: None,
+            power: None,
+            mem_info: None,
+            perf_profile: None,
+            pmu_rates: None,
+            eng_sched: None,
+            temperature: None,
+            busy: None,
+            reset_count: None,
         }
     }
 }
@@ -211,7 +498,24 @@ pub struct DrmDeviceInfo
     pub freqs: Vec<DrmDeviceFreqs>,
     pub power: DrmDevicePower,
     pub mem_info: DrmDeviceMemInfo,
-    driver: Option<Rc<RefCell<dyn DrmDriver>>>,
+    pub hwmon_info: DrmDeviceHwmonInfo,
+    pub perf_profile: String,
+    pub pmu_rates: Vec<(String, f64)>,
+    pub eng_sched: Vec<DrmEngineSched>,     // empty if not enabled
+    pub temperature: DrmDeviceTemperature,
+    pub busy: Option<f64>,          // overall GFX activity in %, if reported
+    pub sources: DrmDeviceSources,
+    pub notices: Vec<String>,
+    pub firmware: Vec<(String, String)>,    // (name, version)
+    pub is_external: bool,  // hot-pluggable, e.g. Thunderbolt eGPU
+    pub displays: Vec<DrmDeviceDisplay>,
+    pub reset_count: Option<u64>,   // None if not tracked or not exposed
+    track_resets: bool,
+    track_host_mem: bool,
+    eng_aggr: DrmEngineAggregation,
+    card_dir: Option<PathBuf>,  // sysfs dir with the display connectors
+    hwmon: Option<Hwmon>,
+    driver: Option<Arc<Mutex<dyn DrmDriver>>>,
     drm_clis: Option<Rc<RefCell<Vec<DrmClientInfo>>>>,
 }
 
@@ -233,6 +537,23 @@ impl Default for DrmDeviceInfo
             freqs: Vec::new(),
             power: DrmDevicePower::new(),
             mem_info: DrmDeviceMemInfo::new(),
+            hwmon_info: DrmDeviceHwmonInfo::new(),
+            perf_profile: String::new(),
+            pmu_rates: Vec::new(),
+            eng_sched: Vec::new(),
+            temperature: DrmDeviceTemperature::new(),
+            busy: None,
+            sources: DrmDeviceSources::new(),
+            notices: Vec::new(),
+            firmware: Vec::new(),
+            is_external: false,
+            displays: Vec::new(),
+            reset_count: None,
+            track_resets: false,
+            track_host_mem: false,
+            eng_aggr: DrmEngineAggregation::Sum,
+            card_dir: None,
+            hwmon: None,
             driver: None,
             drm_clis: None,
         }
@@ -241,16 +562,73 @@ impl Default for DrmDeviceInfo
 
 impl DrmDeviceInfo
 {
+    // DRM minor of the primary (card) node, e.g. for debugfs paths, as the
+    // render node can be listed first
+    pub fn card_minor(&self) -> u32
+    {
+        self.drm_minors.iter()
+            .find(|mi| mi.devnode.starts_with("/dev/dri/card"))
+            .unwrap_or(&self.drm_minors[0])
+            .drm_minor
+    }
+
+    // engine class capacity is the max # instances seen in clients
+    pub fn eng_capacity(&self, eng: &String) -> u32
+    {
+        let mut cap: u32 = 1;
+
+        if let Some(vref) = &self.drm_clis {
+            for cli in vref.borrow().iter() {
+                cap = max(cap, cli.eng_capacity(eng));
+            }
+        }
+
+        cap
+    }
+
+    // where engines usage comes from, as its accuracy differs from PMUs
+    pub fn eng_usage_source(&self) -> &str
+    {
+        match self.eng_aggr {
+            DrmEngineAggregation::Sum => "fdinfo",
+            DrmEngineAggregation::Max => "fdinfo max",
+            DrmEngineAggregation::Mean => "fdinfo mean",
+        }
+    }
+
     // relies on DRM clients list for now
     // (could store after each refresh and read from driver later, if needed)
     pub fn eng_utilization(&self, eng: &String) -> f64
     {
         if let Some(vref) = &self.drm_clis {
+            let cap = self.eng_capacity(eng);
             let clis_b = vref.borrow();
 
+            // engine names come as is from the clients fdinfo for all
+            // drivers (e.g. rcs/bcs/vcs/vecs/ccs on xe, from drm-cycles
+            // and drm-total-cycles), so no PMU or root is needed here
+            //
+            // client usage is relative to its own capacity, so scale it
+            // to the class capacity before combining them
             let mut res: f64 = 0.0;
+            let mut nr_clis = 0;
             for cli in clis_b.iter() {
-                res += cli.eng_utilization(eng);
+                if !cli.engines().contains(&eng) {
+                    continue;
+                }
+                let eut = cli.eng_utilization(eng) *
+                    cli.eng_capacity(eng) as f64 / cap as f64;
+                match self.eng_aggr {
+                    DrmEngineAggregation::Max => res = f64::max(res, eut),
+                    _ => res += eut,
+                }
+                // mean is over the clients that used the engine
+                if eut > 0.0 {
+                    nr_clis += 1;
+                }
+            }
+            if self.eng_aggr == DrmEngineAggregation::Mean && nr_clis > 0 {
+                res /= nr_clis as f64;
             }
 
             if res > 100.0 {
@@ -283,12 +661,38 @@ impl DrmDeviceInfo
             for en in tst.iter() {
                 engs.push(en.to_string());
             }
-            engs.sort();
+            self.sort_engines(&mut engs);
         }
 
         engs
     }
 
+    // in the driver's hardware order (if any), with unknown engines
+    // sorted alphabetically after the known ones
+    pub fn sort_engines(&self, engs: &mut [String])
+    {
+        engs.sort();
+
+        if let Some(drv_ref) = &self.driver {
+            let drv_b = drv_ref.lock().unwrap();
+            let order = drv_b.engines_order();
+            engs.sort_by_key(|en| order.iter()
+                .position(|oen| *oen == en.as_str())
+                .unwrap_or(order.len()));
+        }
+    }
+
+    // false if the driver isn't supported or failed to initialize
+    pub fn has_driver(&self) -> bool
+    {
+        self.driver.is_some()
+    }
+
+    pub fn hwmon(&self) -> Option<&Hwmon>
+    {
+        self.hwmon.as_ref()
+    }
+
     pub fn clients(&self) -> Option<Weak<RefCell<Vec<DrmClientInfo>>>>
     {
         if let Some(vref) = &self.drm_clis {
@@ -298,19 +702,194 @@ impl DrmDeviceInfo
         None
     }
 
-    pub fn refresh(&mut self) -> Result<()>
+    // only touches the driver, so it can run on its own thread
+    fn read_driver_stats(drv_ref: &Arc<Mutex<dyn DrmDriver>>,
+        skip_stats: &HashSet<String>,
+        track_resets: bool) -> QmResult<DrmDriverStats>
+    {
+        let mut drv_b = drv_ref.lock().unwrap();
+        let mut dst = DrmDriverStats::new();
+
+        // note: dev_type and freq_limits don't change
+        if !skip_stats.contains("freqs") {
+            dst.freqs = Some(drv_b.freqs()?);
+        }
+        if !skip_stats.contains("power") {
+            dst.power = Some(drv_b.power()?);
+        }
+        if !skip_stats.contains("meminfo") {
+            dst.mem_info = Some(drv_b.mem_info()?);
+        }
+        // can be changed by the user at any time
+        if !skip_stats.contains("perf") {
+            dst.perf_profile = Some(drv_b.perf_profile()?);
+        }
+        if !skip_stats.contains("pmu") {
+            dst.pmu_rates = Some(drv_b.pmu_rates()?);
+            dst.eng_sched = Some(drv_b.engines_sched()?);
+        }
+        if !skip_stats.contains("sensors") {
+            dst.temperature = Some(drv_b.temperature()?);
+            dst.busy = Some(drv_b.busy()?);
+        }
+        if track_resets {
+            dst.reset_count = Some(drv_b.reset_count()?);
+        }
+
+        Ok(dst)
+    }
+
+    fn update_driver_stats(&mut self, dst: DrmDriverStats)
+    {
+        if let Some(freqs) = dst.freqs {
+            self.freqs = freqs;
+        }
+        if let Some(power) = dst.power {
+            self.power = power;
+        }
+        if let Some(mem_info) = dst.mem_info {
+            self.mem_info = mem_info;
+            // iGPUs smem competes with all of the host RAM
+            if self.track_host_mem && self.dev_type.is_integrated() {
+                (self.mem_info.host_total, self.mem_info.host_used) =
+                    drm_drivers::system_mem_info();
+            }
+        }
+        if let Some(perf_profile) = dst.perf_profile {
+            self.perf_profile = perf_profile;
+        }
+        if let Some(pmu_rates) = dst.pmu_rates {
+            self.pmu_rates = pmu_rates;
+        }
+        if let Some(eng_sched) = dst.eng_sched {
+            self.eng_sched = eng_sched;
+        }
+        if let Some(temperature) = dst.temperature {
+            self.temperature = temperature;
+        }
+        if let Some(busy) = dst.busy {
+            self.busy = busy;
+        }
+        if let Some(reset_count) = dst.reset_count {
+            self.reset_count = reset_count;
+        }
+    }
+
+    // skipped stats groups (see DRM_DEVICE_STATS_GROUPS) keep old values
+    pub fn refresh(&mut self, skip_stats: &HashSet<String>) -> QmResult<()>
     {
         if let Some(drv_ref) = &self.driver {
-            let mut drv_b = drv_ref.borrow_mut();
+            let dst = DrmDeviceInfo::read_driver_stats(
+                drv_ref, skip_stats, self.track_resets)?;
+            self.update_driver_stats(dst);
+        }
+
+        self.refresh_hwmon(skip_stats)?;
+        self.refresh_displays(skip_stats);
+
+        Ok(())
+    }
+
+    // display-only info from the card connectors in sysfs
+    fn refresh_displays(&mut self, skip_stats: &HashSet<String>)
+    {
+        if skip_stats.contains("displays") {
+            return;
+        }
+        let card_dir = match &self.card_dir {
+            Some(card_dir) => card_dir,
+            None => return,
+        };
+        let prefix = format!("{}-",
+            card_dir.file_name().unwrap().to_str().unwrap());
+
+        let mut conn_dirs: Vec<PathBuf> = match fs::read_dir(card_dir) {
+            Ok(rd) => rd.filter_map(|r| r.ok())
+                .map(|r| r.path())
+                .filter(|p| p.file_name().unwrap()
+                    .to_str().unwrap().starts_with(&prefix))
+                .collect(),
+            Err(err) => {
+                debug!("ERR: failed to read connectors from {:?}: {:?}",
+                    card_dir, err);
+                return;
+            }
+        };
+        conn_dirs.sort();
+
+        self.displays = conn_dirs.iter()
+            .filter_map(|cd| DrmDeviceDisplay::from(cd))
+            .collect();
+    }
+
+    fn refresh_hwmon(&mut self, skip_stats: &HashSet<String>) -> QmResult<()>
+    {
+        // read-only power cap & fan control mode from hwmon (if available)
+        if skip_stats.contains("hwmon") {
+            return Ok(());
+        }
+        if let Some(hwmon) = &self.hwmon {
+            let mut hwi = DrmDeviceHwmonInfo::new();
+
+            // i915 & xe expose PL1 as power1_max
+            let cap_item = if hwmon.has_sensor_item("power1", "cap") {
+                "cap" } else { "max" };
+            if hwmon.has_sensor_item("power1", cap_item) {
+                hwi.power_cap = hwmon.read_sensor(
+                    "power1", cap_item)? as f64 / 1000000.0;
+            }
+            if hwmon.has_sensor_item("pwm1", "enable") {
+                let pwm_en = hwmon.read_sensor("pwm1", "enable")?;
+                hwi.fan_mode = DrmDeviceHwmonInfo::fan_mode_from(pwm_en);
+            }
 
-            // note: dev_type and freq_limits don't change
-            self.freqs = drv_b.freqs()?;
-            self.power = drv_b.power()?;
-            self.mem_info = drv_b.mem_info()?;
+            self.hwmon_info = hwi;
         }
 
         Ok(())
     }
+
+    // best effort: the kernel marks as removable the PCI devices below
+    // external-facing ports (e.g. Thunderbolt), and their bridges
+    fn is_removable(pdev: &udev::Device) -> bool
+    {
+        let mut dev = Some(pdev.clone());
+        while let Some(d) = dev {
+            if d.subsystem().is_some_and(|s| s != "pci") {
+                break;
+            }
+            if d.attribute_value("removable")
+                .is_some_and(|rm| rm == "removable") {
+                return true;
+            }
+            dev = d.parent();
+        }
+
+        false
+    }
+
+    fn find_hwmon(dev_dir: &Path) -> Option<Hwmon>
+    {
+        let base_dir = dev_dir.join("hwmon");
+        if !base_dir.is_dir() {
+            return None;
+        }
+
+        let hwmon_path = fs::read_dir(base_dir).ok()?
+            .filter_map(|r| r.ok())
+            .map(|r| r.path())
+            .find(|r| r.file_name().unwrap()
+                .to_str().unwrap().starts_with("hwmon"))?;
+
+        match Hwmon::from(hwmon_path) {
+            Ok(hwmon) => hwmon,
+            Err(err) => {
+                debug!("ERR: failed to get hwmon info from {:?}: {:?}",
+                    dev_dir, err);
+                None
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -318,8 +897,19 @@ pub struct DrmDevices
 {
     infos: HashMap<String, DrmDeviceInfo>,
     qmclis: Option<DrmClients>,
+    skip_stats: HashSet<String>,
+    is_refreshed: bool,
 }
 
+// device stats groups that can be skipped to lower the refresh cost
+pub const DRM_DEVICE_STATS_GROUPS: &[&str] = &[
+    "freqs", "power", "meminfo", "perf", "pmu", "hwmon", "displays",
+    "sensors",
+];
+
+// device stats groups read by fast samples in between refreshes
+const DRM_DEVICE_SAMPLE_GROUPS: &[&str] = &["freqs", "power"];
+
 impl DrmDevices
 {
     pub fn device_info(&self, dev: &String) -> Option<&DrmDeviceInfo>
@@ -335,52 +925,239 @@ impl DrmDevices
         res
     }
 
+    // devices info sorted by PCI slot
+    pub fn infos(&self) -> Vec<&DrmDeviceInfo>
+    {
+        self.devices().into_iter().map(|d| &self.infos[d]).collect()
+    }
+
     pub fn is_empty(&self) -> bool
     {
         self.infos.is_empty()
     }
 
-    pub fn refresh(&mut self) -> Result<()>
+    // first device (by PCI slot) with vendor:device ids, e.g. "8086:9a49"
+    pub fn find_by_ids(&self, ids: &str) -> Option<&String>
+    {
+        let (vdr_id, dev_id) = ids.split_once(':')?;
+
+        self.devices().into_iter().find(|d| {
+            let di = &self.infos[*d];
+            di.vendor_id.eq_ignore_ascii_case(vdr_id) &&
+                di.device_id.eq_ignore_ascii_case(dev_id)
+        })
+    }
+
+    // update DRM clients information (if possible)
+    fn refresh_clients(&mut self) -> QmResult<()>
     {
-        // update DRM clients information (if possible)
         if let Some(clis) = &mut self.qmclis {
             clis.refresh()?;
 
             for di in self.infos.values_mut() {
                 di.drm_clis = clis.device_clients(&di.pci_dev);
                 if let Some(drv_ref) = &di.driver {
-                    let drv_wref = Rc::downgrade(drv_ref);
+                    let drv_wref = Arc::downgrade(drv_ref);
                     clis.set_dev_clients_driver(&di.pci_dev, drv_wref);
                 }
             }
         }
 
-        // assumes devices don't vanish, so just update their driver-specific
-        // dynamic information (e.g. mem info, engines, freqs, power)
+        Ok(())
+    }
+
+    // fast samples only average the DRM clients, freqs and power, so don't
+    // read the other stats groups (nor hwmon & displays) in between
+    pub fn sample(&mut self) -> QmResult<()>
+    {
+        if !self.is_refreshed {
+            return self.refresh();
+        }
+        self.refresh_clients()?;
+
+        let skip_stats: HashSet<String> = DRM_DEVICE_STATS_GROUPS.iter()
+            .filter(|grp| !DRM_DEVICE_SAMPLE_GROUPS.contains(grp))
+            .map(|grp| grp.to_string())
+            .chain(self.skip_stats.iter().cloned())
+            .collect();
         for di in self.infos.values_mut() {
-            di.refresh()?;
+            if let Some(drv_ref) = &di.driver {
+                let dst = DrmDeviceInfo::read_driver_stats(
+                    drv_ref, &skip_stats, false)?;
+                di.update_driver_stats(dst);
+            }
         }
 
+        Ok(())
+    }
+
+    pub fn refresh(&mut self) -> QmResult<()>
+    {
+        self.refresh_clients()?;
+
+        // assumes devices don't vanish, so just update their driver-specific
+        // dynamic information (e.g. mem info, engines, freqs, power), the
+        // first refresh reads all so skipped groups have initial values
+        let no_skip = HashSet::new();
+        let skip_stats = if self.is_refreshed {
+            &self.skip_stats } else { &no_skip };
+        let drvs: Vec<DriverRefresh> = self.infos.values()
+            .filter_map(|di| di.driver.as_ref()
+                .map(|drv| (di.pci_dev.clone(), drv.clone(), di.track_resets)))
+            .collect();
+        if drvs.len() > 1 {
+            // drivers of different devices don't share any fds, sysfs
+            // files or PMU events, so read their stats in parallel
+            let dsts: Vec<(String, QmResult<DrmDriverStats>)> =
+                thread::scope(|s| {
+                    let hdls: Vec<_> = drvs.iter()
+                        .map(|(pdev, drv, trs)| (pdev, s.spawn(|| {
+                            DrmDeviceInfo::read_driver_stats(
+                                drv, skip_stats, *trs)
+                        })))
+                        .collect();
+                    hdls.into_iter()
+                        .map(|(pdev, hdl)| (pdev.clone(), hdl.join().unwrap()))
+                        .collect()
+                });
+            for (pdev, dst) in dsts {
+                let di = self.infos.get_mut(&pdev).unwrap();
+                di.update_driver_stats(dst?);
+            }
+            for di in self.infos.values_mut() {
+                di.refresh_hwmon(skip_stats)?;
+                di.refresh_displays(skip_stats);
+            }
+        } else {
+            for di in self.infos.values_mut() {
+                di.refresh(skip_stats)?;
+            }
+        }
+        self.is_refreshed = true;
+
         debug!("DRM Devices: {:#?}", self.infos);
 
         Ok(())
     }
 
-    pub fn set_clients_pid_tree(&mut self, at_pid: &str) -> Result<()>
+    pub fn set_skip_stats(&mut self, groups: &Vec<String>) -> QmResult<()>
+    {
+        for grp in groups.iter() {
+            if !DRM_DEVICE_STATS_GROUPS.contains(&grp.as_str()) {
+                return Err(QmError::ParseError(format!(
+                    "Unknown stats group {:?} (expected one of: {})",
+                    grp, DRM_DEVICE_STATS_GROUPS.join(", "))));
+            }
+            self.skip_stats.insert(grp.clone());
+        }
+
+        Ok(())
+    }
+
+    fn minor_devs(&self) -> HashMap<u32, String>
+    {
+        let mut minor_devs = HashMap::new();
+        for di in self.infos.values() {
+            for mi in di.drm_minors.iter() {
+                minor_devs.insert(mi.drm_minor, di.pci_dev.clone());
+            }
+        }
+
+        minor_devs
+    }
+
+    pub fn set_clients_pid_tree(&mut self,
+        at_pid: &str, excl_self: bool) -> QmResult<()>
     {
-        self.qmclis = Some(DrmClients::from_pid_tree(at_pid)?);
+        let mut clis = DrmClients::from_pid_tree(at_pid, excl_self)?;
+        clis.set_minor_devs(self.minor_devs());
+        self.qmclis = Some(clis);
 
         Ok(())
     }
 
+    // only the DRM clients from the processes in a cgroup
+    pub fn set_clients_cgroup(&mut self,
+        cgroup: &str, excl_self: bool) -> QmResult<()>
+    {
+        let mut clis = DrmClients::from_cgroup(cgroup, excl_self)?;
+        clis.set_minor_devs(self.minor_devs());
+        self.qmclis = Some(clis);
+
+        Ok(())
+    }
+
+    // track only the children of the PID tree base (needs the clients
+    // PID tree to be set first)
+    pub fn set_clients_children_only(&mut self, children_only: bool)
+    {
+        if let Some(clis) = &mut self.qmclis {
+            clis.set_children_only(children_only);
+        }
+    }
+
+    pub fn set_engines_aggregation(&mut self, eng_aggr: DrmEngineAggregation)
+    {
+        for di in self.infos.values_mut() {
+            di.eng_aggr = eng_aggr;
+        }
+    }
+
+    // read GPU reset counters on every refresh (where exposed)
+    pub fn set_track_resets(&mut self, track_resets: bool)
+    {
+        for di in self.infos.values_mut() {
+            di.track_resets = track_resets;
+        }
+    }
+
+    // read host RAM usage for integrated GPUs on every refresh
+    pub fn set_track_host_mem(&mut self, track_host_mem: bool)
+    {
+        for di in self.infos.values_mut() {
+            di.track_host_mem = track_host_mem;
+        }
+    }
+
     fn new() -> DrmDevices
     {
         DrmDevices {
             infos: HashMap::new(),
             qmclis: None,
+            skip_stats: HashSet::new(),
+            is_refreshed: false,
         }
     }
 
+    // platform devices have no PCI ids, so use NVIDIA's vendor id (only
+    // tegra for now) and name them after the SoC
+    fn platform_ids(pdev: &udev::Device) ->
+        (String, String, String, String, String)
+    {
+        let soc_attr = |name: &str| {
+            fs::read_to_string(Path::new("/sys/devices/soc0").join(name))
+                .map(|st| st.trim().to_string())
+                .unwrap_or_default()
+        };
+
+        let vendor_id = String::from("10de");
+        let vendor = DrmDevices::find_vendor(&vendor_id);
+        let mut device = soc_attr("machine");
+        if device.is_empty() {
+            // e.g. "nvidia,tegra234-host1x"
+            device = pdev.property_value("OF_COMPATIBLE_0")
+                .and_then(|cp| cp.to_str())
+                .map_or(String::from("Tegra"),
+                    |cp| cp.rsplit(',').next().unwrap().to_string());
+        }
+        let mut revision = soc_attr("revision");
+        if revision.is_empty() {
+            revision = String::from("0");
+        }
+
+        (vendor_id, vendor, String::from("0000"), device, revision)
+    }
+
     fn find_vendor(vendor_id: &String) -> String
     {
         if let Ok(hwdb) = udev::Hwdb::new() {
@@ -412,7 +1189,7 @@ impl DrmDevices
         device_id.clone()
     }
 
-    pub fn find_devices() -> Result<DrmDevices>
+    pub fn find_devices(drv_opts: &DrmDriverOptions) -> QmResult<DrmDevices>
     {
         let mut qmds = DrmDevices::new();
 
@@ -421,32 +1198,56 @@ impl DrmDevices
         enumerator.match_property("DEVNAME", "/dev/dri/*")?;
 
         for d in enumerator.scan_devices()? {
-            let pdev = d.parent().unwrap();
+            let mut pdev = d.parent().unwrap();
+            // e.g. vgem or vkms platform devices have no driver bound
+            let mut drv_name = match pdev.driver().and_then(|d| d.to_str()) {
+                Some(drv) => String::from(drv),
+                None => {
+                    debug!("INF: Ignoring device without driver: {:?}",
+                        pdev.syspath());
+                    continue;
+                },
+            };
+            // virtio devices (e.g. virtio-gpu in VMs) sit on a PCI one
+            if pdev.subsystem().is_some_and(|s| s == "virtio") {
+                if let Some(ppdev) = pdev.parent() {
+                    pdev = ppdev;
+                }
+            }
+            // tegra DRM devices sit on the host1x bus, under its controller
+            if pdev.subsystem().is_some_and(|s| s == "host1x") {
+                drv_name = String::from("tegra");
+                if let Some(ppdev) = pdev.parent() {
+                    pdev = ppdev;
+                }
+            }
             let sysname = String::from(pdev.sysname().to_str().unwrap());
 
             if !qmds.infos.contains_key(&sysname) {
-                let pciid = if let Some(pciid) = pdev.property_value("PCI_ID") {
-                    pciid.to_str().unwrap()
+                let (vendor_id, vendor, device_id, device, revision) =
+                    if let Some(pciid) = pdev.property_value("PCI_ID") {
+                    let pciid = pciid.to_str().unwrap();
+                    let vendor_id = String::from(&pciid[0..4]);
+                    let device_id = String::from(&pciid[5..9]);
+                    let revision = pdev.attribute_value("revision")
+                        .unwrap().to_str().unwrap();
+                    let revision = if revision.starts_with("0x") {
+                        String::from(&revision[2..])
+                    } else {
+                        String::from(revision)
+                    };
+                    (vendor_id.clone(), DrmDevices::find_vendor(&vendor_id),
+                        device_id.clone(),
+                        DrmDevices::find_device(&vendor_id, &device_id),
+                        revision)
+                } else if drm_drivers::is_platform(&drv_name) {
+                    DrmDevices::platform_ids(&pdev)
                 } else {
                     debug!("INF: Ignoring device without PCI_ID: {:?}",
                         pdev.syspath());
                     continue;
                 };
 
-                let vendor_id = String::from(&pciid[0..4]);
-                let vendor = DrmDevices::find_vendor(&vendor_id);
-                let device_id = String::from(&pciid[5..9]);
-                let device = DrmDevices::find_device(&vendor_id, &device_id);
-                let revision = pdev.attribute_value("revision")
-                    .unwrap().to_str().unwrap();
-                let revision = if revision.starts_with("0x") {
-                    String::from(&revision[2..])
-                } else {
-                    String::from(revision)
-                };
-                let drv_name = String::from(pdev.driver()
-                    .unwrap().to_str().unwrap());
-
                 let ndinf = DrmDeviceInfo {
                     pci_dev: sysname.clone(),
                     vendor_id,
@@ -455,6 +1256,8 @@ impl DrmDevices
                     device,
                     revision,
                     drv_name,
+                    is_external: DrmDeviceInfo::is_removable(&pdev),
+                    hwmon: DrmDeviceInfo::find_hwmon(pdev.syspath()),
                     ..Default::default()
                 };
                 qmds.infos.insert(sysname.clone(), ndinf);
@@ -465,16 +1268,33 @@ impl DrmDevices
             let minf = DrmMinorInfo::from(&devnode, devnum)?;
 
             let dinf = qmds.infos.get_mut(&sysname).unwrap();
+            // only primary nodes have connectors
+            if devnode.starts_with("/dev/dri/card") {
+                dinf.card_dir = Some(d.syspath().to_path_buf());
+            }
             dinf.drm_minors.push(minf);
         }
 
         for dinf in qmds.infos.values_mut() {
-            if let Some(drv_ref) = drm_drivers::driver_from(dinf)? {
+            // keep device listed even if its driver can't be used
+            let drv_opt = match drm_drivers::driver_from(dinf, drv_opts) {
+                Ok(drv_opt) => drv_opt,
+                Err(err) => {
+                    debug!("ERR: driver {:?} unavailable for {:?}: {:?}",
+                        dinf.drv_name, dinf.pci_dev, err);
+                    dinf.notices.push(format!("driver unavailable ({})", err));
+                    continue;
+                }
+            };
+            if let Some(drv_ref) = drv_opt {
                 let dref = drv_ref.clone();
-                let mut drv_b = dref.borrow_mut();
+                let mut drv_b = dref.lock().unwrap();
 
                 dinf.dev_type = drv_b.dev_type()?;
                 dinf.freq_limits = drv_b.freq_limits()?;
+                dinf.notices = drv_b.notices();
+                dinf.firmware = drv_b.firmware();
+                dinf.sources = drv_b.stats_sources();
                 dinf.driver = Some(drv_ref);
             }
         }
@@ -482,3 +1302,84 @@ impl DrmDevices
         Ok(qmds)
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use std::process;
+
+    use super::*;
+    use crate::drm_fdinfo::DrmFdinfo;
+    use crate::proc_info::ProcInfo;
+
+    // xe fdinfo with only the keys engines usage needs, as (engine class,
+    // cycles, total cycles, capacity) with capacity 1 not reported
+    fn xe_fdinfo(client_id: u32, engs: &[(&str, u64, u64, u32)]) -> DrmFdinfo
+    {
+        let mut sample = format!("drm-driver:\txe\n\
+            drm-client-id:\t{}\n\
+            drm-pdev:\t0000:03:00.0\n", client_id);
+        for (nm, cycles, tot_cycles, cap) in engs.iter() {
+            sample.push_str(&format!("drm-cycles-{}:\t{}\n", nm, cycles));
+            sample.push_str(&format!("drm-total-cycles-{}:\t{}\n",
+                nm, tot_cycles));
+            if *cap > 1 {
+                sample.push_str(&format!("drm-engine-capacity-{}:\t{}\n",
+                    nm, cap));
+            }
+        }
+
+        let path = std::env::temp_dir()
+            .join(format!("qmassa-xe-{}-fdinfo-{}", client_id, process::id()));
+        fs::write(&path, sample).unwrap();
+        let res = DrmFdinfo::from(&path, 128);
+        fs::remove_file(&path).unwrap();
+
+        res.unwrap()
+    }
+
+    #[test]
+    fn xe_eng_utilization()
+    {
+        // client 57 sees 2 vcs and 4 ccs, client 58 a single ccs
+        let mut cli57 = DrmClientInfo::from(ProcInfo::default(),
+            &xe_fdinfo(57, &[("rcs", 1000000, 7000000, 1),
+                ("vcs", 0, 7000000, 2), ("ccs", 0, 7000000, 4)]));
+        let mut cli58 = DrmClientInfo::from(ProcInfo::default(),
+            &xe_fdinfo(58, &[("rcs", 0, 7000000, 1),
+                ("vcs", 0, 7000000, 2), ("ccs", 0, 7000000, 1)]));
+
+        // next samples, 1000000 total cycles later
+        cli57.update(ProcInfo::default(),
+            &xe_fdinfo(57, &[("rcs", 1250000, 8000000, 1),
+                ("vcs", 1000000, 8000000, 2), ("ccs", 2000000, 8000000, 4)]));
+        cli58.update(ProcInfo::default(),
+            &xe_fdinfo(58, &[("rcs", 250000, 8000000, 1),
+                ("vcs", 0, 8000000, 2), ("ccs", 500000, 8000000, 1)]));
+
+        let ccs = String::from("ccs");
+        assert_eq!(cli57.eng_utilization(&ccs), 50.0);
+        assert_eq!(cli58.eng_utilization(&ccs), 50.0);
+
+        let dinfo = DrmDeviceInfo {
+            pci_dev: String::from("0000:03:00.0"),
+            drv_name: String::from("xe"),
+            drm_clis: Some(Rc::new(RefCell::new(vec![cli57, cli58]))),
+            ..Default::default()
+        };
+
+        let rcs = String::from("rcs");
+        let vcs = String::from("vcs");
+        assert_eq!(dinfo.eng_capacity(&rcs), 1);
+        assert_eq!(dinfo.eng_capacity(&vcs), 2);
+        assert_eq!(dinfo.eng_capacity(&ccs), 4);
+
+        // 25% + 25% of the single rcs
+        assert_eq!(dinfo.eng_utilization(&rcs), 50.0);
+        // 1 of 2 vcs busy, by client 57 alone
+        assert_eq!(dinfo.eng_utilization(&vcs), 50.0);
+        // 2 of 4 ccs by client 57, plus half of client 58's single ccs
+        // which is 12.5% of the 4 ccs
+        assert_eq!(dinfo.eng_utilization(&ccs), 62.5);
+    }
+}
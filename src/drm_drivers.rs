@@ -1,61 +1,188 @@
 use core::fmt::Debug;
 use std::collections::HashMap;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::fs::{self, File};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use log::debug;
+use serde::Serialize;
 
 use crate::drm_devices::{
     DrmDeviceType, DrmDeviceFreqLimits, DrmDeviceFreqs,
-    DrmDevicePower, DrmDeviceMemInfo, DrmDeviceInfo
+    DrmDevicePower, DrmDeviceMemInfo, DrmDeviceTemperature, DrmDeviceSources,
+    DrmEngineSched, DrmDeviceInfo
 };
 use crate::drm_fdinfo::DrmMemRegion;
 use crate::drm_clients::DrmClientMemInfo;
+use crate::qm_error::{QmError, QmResult};
 
 mod helpers;
 mod intel_power;
+mod intel_pmu;
+mod intel_fw;
 mod xe;
 use xe::DrmDriverXe;
 mod i915;
 use i915::DrmDriveri915;
 mod amdgpu;
 use amdgpu::DrmDriverAmdgpu;
+mod virtio_gpu;
+use virtio_gpu::DrmDriverVirtioGpu;
+mod tegra;
+use tegra::DrmDriverTegra;
 
 
-pub trait DrmDriver
+// driver-specific options from the command line, as DRIVER=KEY=VALUE
+#[derive(Debug, Clone, Serialize)]
+pub struct DrmDriverOptions
+{
+    opts: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for DrmDriverOptions
+{
+    fn default() -> DrmDriverOptions
+    {
+        DrmDriverOptions::new()
+    }
+}
+
+impl DrmDriverOptions
+{
+    pub fn get(&self, drv_name: &str, key: &str) -> Option<&String>
+    {
+        self.opts.get(drv_name)?.get(key)
+    }
+
+    pub fn new() -> DrmDriverOptions
+    {
+        DrmDriverOptions {
+            opts: HashMap::new(),
+        }
+    }
+
+    pub fn from(opts_lst: &Vec<String>) -> Result<DrmDriverOptions>
+    {
+        let mut res = DrmDriverOptions::new();
+
+        for opt in opts_lst.iter() {
+            let parts: Vec<&str> = opt.splitn(3, '=')
+                .map(|it| it.trim()).collect();
+            if parts.len() != 3 || parts.iter().any(|it| it.is_empty()) {
+                bail!("Invalid driver option {:?} (expected DRIVER=KEY=VALUE)",
+                    opt);
+            }
+            if !is_supported(parts[0]) {
+                return Err(QmError::UnsupportedDriver(parts[0].to_string()))
+                    .with_context(|| format!("Invalid driver option {:?}", opt));
+            }
+
+            res.opts.entry(parts[0].to_string())
+                .or_default()
+                .insert(parts[1].to_string(), parts[2].to_string());
+        }
+
+        Ok(res)
+    }
+}
+
+// Send so drivers of different devices can be refreshed in parallel
+pub trait DrmDriver: Send
 {
     fn name(&self) -> &str
     {
         "(not implemented)"
     }
 
-    fn dev_type(&mut self) -> Result<DrmDeviceType>
+    fn dev_type(&mut self) -> QmResult<DrmDeviceType>
     {
         Ok(DrmDeviceType::Unknown)
     }
 
-    fn freq_limits(&mut self) -> Result<Vec<DrmDeviceFreqLimits>>
+    fn freq_limits(&mut self) -> QmResult<Vec<DrmDeviceFreqLimits>>
     {
         Ok(vec![DrmDeviceFreqLimits::new(),])
     }
 
-    fn freqs(&mut self) -> Result<Vec<DrmDeviceFreqs>>
+    fn freqs(&mut self) -> QmResult<Vec<DrmDeviceFreqs>>
     {
         Ok(vec![DrmDeviceFreqs::new(),])
     }
 
-    fn power(&mut self) -> Result<DrmDevicePower>
+    fn power(&mut self) -> QmResult<DrmDevicePower>
     {
         Ok(DrmDevicePower::new())
     }
 
-    fn mem_info(&mut self) -> Result<DrmDeviceMemInfo>
+    fn mem_info(&mut self) -> QmResult<DrmDeviceMemInfo>
     {
         Ok(DrmDeviceMemInfo::new())
     }
 
+    // read-only performance level/power profile, empty if not available
+    fn perf_profile(&mut self) -> QmResult<String>
+    {
+        Ok(String::new())
+    }
+
+    // extra PMU counters (e.g. interrupts) as rates per second, if enabled
+    fn pmu_rates(&mut self) -> QmResult<Vec<(String, f64)>>
+    {
+        Ok(Vec::new())
+    }
+
+    // per-engine semaphore and event waits from the PMU, if enabled
+    fn engines_sched(&mut self) -> QmResult<Vec<DrmEngineSched>>
+    {
+        Ok(Vec::new())
+    }
+
+    fn temperature(&mut self) -> QmResult<DrmDeviceTemperature>
+    {
+        Ok(DrmDeviceTemperature::new())
+    }
+
+    // overall GFX activity (in %) reported by the device itself
+    fn busy(&mut self) -> QmResult<Option<f64>>
+    {
+        Ok(None)
+    }
+
+    // GPU resets (full and per engine) since the driver was loaded,
+    // None if not exposed
+    fn reset_count(&mut self) -> QmResult<Option<u64>>
+    {
+        Ok(None)
+    }
+
+    // sources of freqs, power and meminfo (engines come from DRM clients)
+    fn stats_sources(&self) -> DrmDeviceSources
+    {
+        DrmDeviceSources::new()
+    }
+
+    // user-visible reasons for missing stats (e.g. need for root)
+    fn notices(&self) -> Vec<String>
+    {
+        Vec::new()
+    }
+
+    // (firmware, version) read at init, e.g. ("VBIOS", "113-D4120100-100")
+    fn firmware(&self) -> Vec<(String, String)>
+    {
+        Vec::new()
+    }
+
+    // engines in hardware order, empty to sort them alphabetically
+    fn engines_order(&self) -> &[&str]
+    {
+        &[]
+    }
+
     fn client_mem_info(&mut self,
-        mem_regs: &HashMap<String, DrmMemRegion>) -> Result<DrmClientMemInfo>
+        mem_regs: &HashMap<String, DrmMemRegion>) -> QmResult<DrmClientMemInfo>
     {
         if let Some(mrg) = mem_regs.get("memory") {
             let mut cmi = DrmClientMemInfo::new();
@@ -75,22 +202,101 @@ impl Debug for dyn DrmDriver
     }
 }
 
-pub fn driver_from(
-    qmd: &DrmDeviceInfo) -> Result<Option<Rc<RefCell<dyn DrmDriver>>>>
+type DrmDriverNewFn = fn(&DrmDeviceInfo, &DrmDriverOptions) ->
+    QmResult<Arc<Mutex<dyn DrmDriver>>>;
+
+const DRM_DRIVERS: &[(&str, DrmDriverNewFn)] = &[
+    ("xe", DrmDriverXe::new),
+    ("i915", DrmDriveri915::new),
+    ("amdgpu", DrmDriverAmdgpu::new),
+    ("virtio_gpu", DrmDriverVirtioGpu::new),
+    ("tegra", DrmDriverTegra::new),
+];
+
+// drivers for platform (non-PCI) devices, e.g. from the device tree
+const PLATFORM_DRIVERS: &[&str] = &["tegra"];
+
+pub fn is_supported(drv_name: &str) -> bool
 {
-    let drvs: &[(&str,
-        fn(&DrmDeviceInfo) -> Result<Rc<RefCell<dyn DrmDriver>>>)] = &[
-        ("xe", DrmDriverXe::new),
-        ("i915", DrmDriveri915::new),
-        ("amdgpu", DrmDriverAmdgpu::new),
-    ];
-
-    for (dn, drv_newfunc) in drvs {
+    DRM_DRIVERS.iter().any(|(dn, _)| *dn == drv_name)
+}
+
+pub fn is_platform(drv_name: &str) -> bool
+{
+    PLATFORM_DRIVERS.contains(&drv_name)
+}
+
+pub fn driver_from(qmd: &DrmDeviceInfo,
+    opts: &DrmDriverOptions) -> QmResult<Option<Arc<Mutex<dyn DrmDriver>>>>
+{
+    for (dn, drv_newfunc) in DRM_DRIVERS {
         if *dn == qmd.drv_name {
-            let drv = drv_newfunc(qmd)?;
+            let drv = drv_newfunc(qmd, opts)?;
             return Ok(Some(drv));
         }
     }
 
     Ok(None)
 }
+
+// device nodes can fail to open transiently (e.g. device busy at boot), so
+// retry those errors a few times with a short backoff
+const DEVNODE_OPEN_RETRIES: u32 = 3;
+const DEVNODE_OPEN_BACKOFF_MS: u64 = 50;
+
+pub fn open_devnode(devnode: &str) -> Result<File>
+{
+    let mut backoff_ms = DEVNODE_OPEN_BACKOFF_MS;
+    let mut nr_tries = 0;
+
+    loop {
+        match File::open(devnode) {
+            Ok(file) => return Ok(file),
+            Err(err) if nr_tries < DEVNODE_OPEN_RETRIES &&
+                matches!(err.raw_os_error(),
+                    Some(libc::EBUSY) | Some(libc::EAGAIN) |
+                    Some(libc::EINTR)) => {
+                debug!("ERR: failed to open {:?} (retrying in {}ms): {:?}",
+                    devnode, backoff_ms, err);
+                thread::sleep(time::Duration::from_millis(backoff_ms));
+                backoff_ms *= 2;
+                nr_tries += 1;
+            },
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to open {:?}", devnode));
+            },
+        }
+    }
+}
+
+// system memory (total, used) in bytes from /proc/meminfo, for drivers
+// that don't report it
+pub fn system_mem_info() -> (u64, u64)
+{
+    let minfo = match fs::read_to_string("/proc/meminfo") {
+        Ok(minfo) => minfo,
+        Err(err) => {
+            debug!("ERR: failed to read /proc/meminfo: {:?}", err);
+            return (0, 0);
+        }
+    };
+
+    let mut total = 0;
+    let mut avail = 0;
+    for ln in minfo.lines() {
+        let (key, val) = match ln.split_once(':') {
+            Some(kv) => kv,
+            None => continue,
+        };
+        let kbs = val.trim().trim_end_matches("kB").trim();
+        let bytes = kbs.parse::<u64>().unwrap_or(0) * 1024;
+        match key {
+            "MemTotal" => total = bytes,
+            "MemAvailable" => avail = bytes,
+            _ => {},
+        }
+    }
+
+    (total, total.saturating_sub(avail))
+}
@@ -6,24 +6,26 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
 use std::os::fd::{RawFd, AsRawFd};
-use std::cell::RefCell;
-use std::rc::Rc;
 use std::mem;
 use std::io;
+use std::sync::{Arc, Mutex};
+use std::time;
 
 use anyhow::Result;
 use log::{debug, warn};
 use libc;
 
-use crate::drm_drivers::DrmDriver;
+use crate::drm_drivers::{self, DrmDriver, DrmDriverOptions};
 use crate::drm_drivers::helpers::drm_iow;
 use crate::hwmon::Hwmon;
 use crate::drm_devices::{
     DrmDeviceType, DrmDeviceFreqLimits, DrmDeviceFreqs,
-    DrmDevicePower, DrmDeviceMemInfo, DrmDeviceInfo
+    DrmDevicePower, DrmDeviceMemInfo, DrmDeviceTemperature, DrmDeviceSources,
+    DrmDeviceInfo
 };
 use crate::drm_fdinfo::DrmMemRegion;
 use crate::drm_clients::DrmClientMemInfo;
+use crate::qm_error::{QmError, QmResult};
 
 
 // rust-bindgen 0.69.5 on Linux kernel v6.12 uapi amdgpu_drm.h + changes
@@ -241,6 +243,16 @@ struct drm_amdgpu_query_fw {
    _pad: u32,
 }
 
+const AMDGPU_INFO_FW_VERSION: u32 = 0x0e;
+const AMDGPU_INFO_FW_SMC: u32 = 0x0a;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct drm_amdgpu_info_firmware {
+    ver: u32,
+    feature: u32,
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 struct drm_amdgpu_info_vbios_info {
@@ -248,6 +260,35 @@ struct drm_amdgpu_info_vbios_info {
     offset: u32,
 }
 
+const AMDGPU_INFO_VBIOS: u32 = 0x1b;
+const AMDGPU_INFO_VBIOS_INFO: u32 = 0x3;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct drm_amdgpu_info_vbios {
+    name: [u8; 64usize],
+    vbios_pn: [u8; 64usize],
+    version: u32,
+    pad: u32,
+    vbios_ver_str: [u8; 32usize],
+    date: [u8; 32usize],
+}
+
+impl drm_amdgpu_info_vbios
+{
+    fn new() -> drm_amdgpu_info_vbios
+    {
+        drm_amdgpu_info_vbios {
+            name: [0; 64],
+            vbios_pn: [0; 64],
+            version: 0,
+            pad: 0,
+            vbios_ver_str: [0; 32],
+            date: [0; 32],
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 struct drm_amdgpu_info_sensor_info {
@@ -305,6 +346,67 @@ const DRM_AMDGPU_INFO: u64 = 0x05;
 const DRM_IOCTL_AMDGPU_INFO: u64 = drm_iow!(DRM_AMDGPU_INFO,
     mem::size_of::<drm_amdgpu_info>());
 
+// stats read in the same refresh share a single gpu_metrics read
+const GPU_METRICS_MAX_AGE_MS: u128 = 50;
+
+// SMU metrics table from gpu_metrics in sysfs, only with the versions
+// whose layout is known: dGPUs v1.1-1.3 and APUs v2.0-2.2
+#[derive(Debug, Clone)]
+struct GpuMetricsAmdgpu
+{
+    socket_power: Option<f64>,  // in W
+    temperature: DrmDeviceTemperature,
+    gfx_activity: Option<f64>,  // in %
+}
+
+impl GpuMetricsAmdgpu
+{
+    // 0xffff marks the fields not filled by the SMU firmware
+    fn field(buf: &[u8], off: usize) -> Option<f64>
+    {
+        let b = buf.get(off..off + 2)?;
+        let val = u16::from_ne_bytes([b[0], b[1]]);
+        if val == u16::MAX {
+            return None;
+        }
+
+        Some(val as f64)
+    }
+
+    fn from(buf: &[u8]) -> Option<GpuMetricsAmdgpu>
+    {
+        // header: structure_size (u16), format_revision, content_revision
+        let (frev, crev) = (*buf.get(2)?, *buf.get(3)?);
+        let fld = |off| GpuMetricsAmdgpu::field(buf, off);
+
+        match (frev, crev) {
+            (1, 1..=3) => {
+                // temps in C, socket power in W
+                Some(GpuMetricsAmdgpu {
+                    socket_power: fld(22),
+                    temperature: DrmDeviceTemperature {
+                        gpu: fld(4).unwrap_or(0.0),
+                        hotspot: fld(6).unwrap_or(0.0),
+                        mem: fld(8).unwrap_or(0.0),
+                    },
+                    gfx_activity: fld(16),
+                })
+            },
+            (2, 0..=2) => {
+                // after a u64 timestamp, temps in centi-C, power in mW
+                let mut temp = DrmDeviceTemperature::new();
+                temp.gpu = fld(16).map_or(0.0, |t| t / 100.0);
+                Some(GpuMetricsAmdgpu {
+                    socket_power: fld(44).map(|p| p / 1000.0),
+                    temperature: temp,
+                    gfx_activity: fld(40),
+                })
+            },
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DrmDriverAmdgpu
 {
@@ -315,6 +417,10 @@ pub struct DrmDriverAmdgpu
     freq_limits: Option<DrmDeviceFreqLimits>,
     hwmon: Option<Hwmon>,
     sensor: String,
+    sensor_item: String,
+    gpu_metrics: Option<(time::Instant, GpuMetricsAmdgpu)>,
+    has_gpu_metrics: bool,
+    firmware: Vec<(String, String)>,
 }
 
 impl DrmDriver for DrmDriverAmdgpu
@@ -324,7 +430,7 @@ impl DrmDriver for DrmDriverAmdgpu
         "amdgpu"
     }
 
-    fn dev_type(&mut self) -> Result<DrmDeviceType>
+    fn dev_type(&mut self) -> QmResult<DrmDeviceType>
     {
         if let Some(dt) = &self.dev_type {
             return Ok(dt.clone());
@@ -346,7 +452,39 @@ impl DrmDriver for DrmDriverAmdgpu
         Ok(qmdt)
     }
 
-    fn mem_info(&mut self) -> Result<DrmDeviceMemInfo>
+    // e.g. "auto (3D_FULL_SCREEN)", level forced and active profile mode
+    fn perf_profile(&mut self) -> QmResult<String>
+    {
+        let lvl_path = self.freqs_dir.join("power_dpm_force_performance_level");
+        if !lvl_path.is_file() {
+            return Ok(String::new());
+        }
+        let mut res = fs::read_to_string(lvl_path)?.trim().to_string();
+
+        // active mode is marked with "*", right after its name or after a
+        // space on smu7 (e.g. "  1 3D_FULL_SCREEN*:" or "  1 3D_FULL_SCREEN *:")
+        let mode_path = self.freqs_dir.join("pp_power_profile_mode");
+        if let Ok(modes) = fs::read_to_string(mode_path) {
+            let toks: Vec<&str> = modes.split_whitespace().collect();
+            let active = toks.iter().position(|tk| tk.contains('*'))
+                .map(|idx| {
+                    let mode = toks[idx].trim_matches(['*', ':']);
+                    if mode.is_empty() && idx > 0 {
+                        toks[idx - 1].trim_matches(['*', ':'])
+                    } else {
+                        mode
+                    }
+                })
+                .filter(|mode| !mode.is_empty());
+            if let Some(mode) = active {
+                res.push_str(&format!(" ({})", mode));
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn mem_info(&mut self) -> QmResult<DrmDeviceMemInfo>
     {
         let mut qim = drm_amdgpu_memory_info::new();
         let qim_ptr: *mut drm_amdgpu_memory_info = &mut qim;
@@ -356,15 +494,21 @@ impl DrmDriver for DrmDriverAmdgpu
             qim_ptr as u64,
             mem::size_of::<drm_amdgpu_memory_info>() as u32)?;
 
+        // GTT is reported on its own and there's no other system memory
+        // accounting, host RAM is only shown with --host-mem (iGPUs)
         Ok(DrmDeviceMemInfo {
-            smem_total: qim.gtt.total_heap_size,
-            smem_used: qim.gtt.heap_usage,
+            smem_total: 0,
+            smem_used: 0,
             vram_total: qim.vram.total_heap_size,
             vram_used: qim.vram.heap_usage,
+            gtt_total: qim.gtt.total_heap_size,
+            gtt_used: qim.gtt.heap_usage,
+            host_total: 0,
+            host_used: 0,
         })
     }
 
-    fn freq_limits(&mut self) -> Result<Vec<DrmDeviceFreqLimits>>
+    fn freq_limits(&mut self) -> QmResult<Vec<DrmDeviceFreqLimits>>
     {
         if let Some(fls) = &self.freq_limits {
             return Ok(vec![fls.clone(),]);
@@ -386,10 +530,8 @@ impl DrmDriver for DrmDriverAmdgpu
                 continue;
             }
 
-            let mut v = kv[1];
-            if v.ends_with(" *") {
-                v = &kv[1][..kv[1].len() - 2];
-            }
+            // active level is marked with "*", with or without a space
+            let mut v = kv[1].trim_end_matches('*').trim_end();
             if !v.ends_with("Mhz") {
                 warn!("Wrong line [{:?}] from {:?}, aborting.", line, fpath);
                 return Ok(vec![fls,]);
@@ -415,7 +557,7 @@ impl DrmDriver for DrmDriverAmdgpu
         Ok(vec![fls,])
     }
 
-    fn freqs(&mut self) -> Result<Vec<DrmDeviceFreqs>>
+    fn freqs(&mut self) -> QmResult<Vec<DrmDeviceFreqs>>
     {
         // TODO: get non-gfx freqs
         let fpath = self.freqs_dir.join("pp_dpm_sclk");
@@ -423,12 +565,20 @@ impl DrmDriver for DrmDriverAmdgpu
 
         let mut freqs = DrmDeviceFreqs::new();
         for line in sclk_str.lines() {
+            // active level is marked with "*", with or without a space
             let tl = line.trim();
-            if !tl.ends_with("Mhz *") {
+            if !tl.ends_with('*') {
                 continue;
             }
-            let kv: Vec<_> = tl[..tl.len() - 5]
+            let tl = tl.trim_end_matches('*').trim_end();
+            if !tl.ends_with("Mhz") {
+                continue;
+            }
+            let kv: Vec<_> = tl[..tl.len() - 3]
                 .split(':').map(|it| it.trim()).collect();
+            if kv.len() < 2 {
+                continue;
+            }
 
             freqs.act_freq = kv[1].parse()?;
         }
@@ -436,31 +586,81 @@ impl DrmDriver for DrmDriverAmdgpu
         Ok(vec![freqs,])
     }
 
-    fn power(&mut self) -> Result<DrmDevicePower>
+    fn power(&mut self) -> QmResult<DrmDevicePower>
     {
-        if self.hwmon.is_none() || self.sensor.is_empty() {
-            // TODO: need to add integrated support, only hwmon/discrete now
-            return Ok(DrmDevicePower::new());
+        let pwr = match self.gpu_metrics().and_then(|gm| gm.socket_power) {
+            Some(pwr) => pwr,
+            None => {
+                if self.hwmon.is_none() || self.sensor.is_empty() {
+                    return Ok(DrmDevicePower::new());
+                }
+                let hwmon = self.hwmon.as_ref().unwrap();
+
+                let val = hwmon.read_sensor(&self.sensor, &self.sensor_item)?;
+                val as f64 / 1000000.0
+            }
+        };
+
+        // on APUs the SMU reports the whole SoC (package) power
+        let is_dgfx = self.dev_type.as_ref().is_some_and(|dt| dt.is_discrete());
+        if is_dgfx {
+            Ok(DrmDevicePower {
+                gpu_cur_power: pwr,
+                pkg_cur_power: 0.0,
+            })
+        } else {
+            Ok(DrmDevicePower {
+                gpu_cur_power: 0.0,
+                pkg_cur_power: pwr,
+            })
         }
-        let hwmon = self.hwmon.as_ref().unwrap();
+    }
 
-        let val = hwmon.read_sensor(&self.sensor, "average")?;
+    fn firmware(&self) -> Vec<(String, String)>
+    {
+        self.firmware.clone()
+    }
 
-        Ok(DrmDevicePower {
-            gpu_cur_power: val as f64 / 1000000.0,
-            pkg_cur_power: 0.0,
-        })
+    fn stats_sources(&self) -> DrmDeviceSources
+    {
+        let power = if self.has_gpu_metrics {
+            "gpu_metrics"
+        } else if !self.sensor.is_empty() {
+            "hwmon"
+        } else {
+            ""
+        };
+
+        DrmDeviceSources {
+            freqs: String::from("sysfs"),
+            power: String::from(power),
+            meminfo: String::from("ioctl"),
+        }
+    }
+
+    fn temperature(&mut self) -> QmResult<DrmDeviceTemperature>
+    {
+        Ok(self.gpu_metrics()
+            .map_or(DrmDeviceTemperature::new(), |gm| gm.temperature.clone()))
+    }
+
+    fn busy(&mut self) -> QmResult<Option<f64>>
+    {
+        Ok(self.gpu_metrics().and_then(|gm| gm.gfx_activity))
     }
 
     fn client_mem_info(&mut self,
-        mem_regs: &HashMap<String, DrmMemRegion>) -> Result<DrmClientMemInfo>
+        mem_regs: &HashMap<String, DrmMemRegion>) -> QmResult<DrmClientMemInfo>
     {
         let mut cmi = DrmClientMemInfo::new();
 
         for mr in mem_regs.values() {
-            if mr.name.starts_with("cpu") || mr.name.starts_with("gtt") {
+            if mr.name.starts_with("cpu") {
                 cmi.smem_used += mr.total;
                 cmi.smem_rss += mr.resident;
+            } else if mr.name.starts_with("gtt") {
+                cmi.gtt_used += mr.total;
+                cmi.gtt_rss += mr.resident;
             } else if mr.name.starts_with("vram") {
                 cmi.vram_used += mr.total;
                 cmi.vram_rss += mr.resident;
@@ -477,25 +677,108 @@ impl DrmDriver for DrmDriverAmdgpu
 
 impl DrmDriverAmdgpu
 {
+    // power, temps and activity in a single read, instead of one per
+    // hwmon sensor
+    fn gpu_metrics(&mut self) -> Option<&GpuMetricsAmdgpu>
+    {
+        if !self.has_gpu_metrics {
+            return None;
+        }
+        let is_fresh = self.gpu_metrics.as_ref().is_some_and(|(tm, _)|
+            tm.elapsed().as_millis() < GPU_METRICS_MAX_AGE_MS);
+
+        if !is_fresh {
+            let gm_path = self.freqs_dir.join("gpu_metrics");
+            let gm = match fs::read(&gm_path) {
+                Ok(buf) => GpuMetricsAmdgpu::from(&buf),
+                Err(err) => {
+                    debug!("ERR: failed to read {:?}: {:?}", gm_path, err);
+                    None
+                }
+            };
+            if gm.is_none() {
+                // unknown version or unreadable, don't try again
+                debug!("INF: no usable gpu_metrics, using hwmon for power.");
+                self.has_gpu_metrics = false;
+                self.gpu_metrics = None;
+                return None;
+            }
+            self.gpu_metrics = gm.map(|gm| (time::Instant::now(), gm));
+        }
+
+        self.gpu_metrics.as_ref().map(|(_, gm)| gm)
+    }
+
+    // VBIOS (e.g. "113-D4120100-100") and SMU firmware (e.g. "80.79.0"),
+    // the ones missing are skipped
+    fn read_firmware(&self) -> Vec<(String, String)>
+    {
+        let mut res = Vec::new();
+
+        let mut vbios = drm_amdgpu_info_vbios::new();
+        let vbios_ptr: *mut drm_amdgpu_info_vbios = &mut vbios;
+        let mut einfo = drm_amdgpu_info::new().extra;
+        einfo.vbios_info = drm_amdgpu_info_vbios_info {
+            type_: AMDGPU_INFO_VBIOS_INFO, offset: 0 };
+        match self.amdgpu_info_ioctl_extra(AMDGPU_INFO_VBIOS, einfo,
+            vbios_ptr as u64, mem::size_of::<drm_amdgpu_info_vbios>() as u32) {
+            Ok(_) => {
+                let vs = &vbios.vbios_ver_str;
+                let len = vs.iter().position(|&c| c == 0).unwrap_or(vs.len());
+                let ver = String::from_utf8_lossy(&vs[..len]).trim().to_string();
+                if !ver.is_empty() {
+                    res.push((String::from("VBIOS"), ver));
+                }
+            },
+            Err(err) => debug!("ERR: failed to get amdgpu VBIOS info: {:?}", err),
+        }
+
+        let mut fw = drm_amdgpu_info_firmware { ver: 0, feature: 0 };
+        let fw_ptr: *mut drm_amdgpu_info_firmware = &mut fw;
+        let mut einfo = drm_amdgpu_info::new().extra;
+        einfo.query_fw = drm_amdgpu_query_fw {
+            fw_type: AMDGPU_INFO_FW_SMC, ip_instance: 0, index: 0, _pad: 0 };
+        match self.amdgpu_info_ioctl_extra(AMDGPU_INFO_FW_VERSION, einfo,
+            fw_ptr as u64, mem::size_of::<drm_amdgpu_info_firmware>() as u32) {
+            Ok(_) if fw.ver > 0 => {
+                res.push((String::from("SMC"), format!("{}.{}.{}",
+                    (fw.ver >> 16) & 0xff, (fw.ver >> 8) & 0xff, fw.ver & 0xff)));
+            },
+            Ok(_) => {},
+            Err(err) => debug!("ERR: failed to get amdgpu SMC fw: {:?}", err),
+        }
+
+        res
+    }
+
     fn amdgpu_info_ioctl(&self,
         query_id: u32, data: u64, size: u32) -> Result<()>
+    {
+        self.amdgpu_info_ioctl_extra(
+            query_id, drm_amdgpu_info::new().extra, data, size)
+    }
+
+    fn amdgpu_info_ioctl_extra(&self, query_id: u32,
+        extra: drm_amdgpu_info_extra, data: u64, size: u32) -> Result<()>
     {
         let mut qi = drm_amdgpu_info::new();
 
         qi.query = query_id;
+        qi.extra = extra;
         qi.return_pointer = data;
         qi.return_size = size;
 
         let res = unsafe {
             libc::ioctl(self.dn_fd, DRM_IOCTL_AMDGPU_INFO, &mut qi) };
         if res < 0 {
-            return Err(io::Error::last_os_error().into());
+            return Err(QmError::IoctlFailed(io::Error::last_os_error()).into());
         }
 
         Ok(())
     }
 
-    pub fn new(qmd: &DrmDeviceInfo) -> Result<Rc<RefCell<dyn DrmDriver>>>
+    pub fn new(qmd: &DrmDeviceInfo,
+        _opts: &DrmDriverOptions) -> QmResult<Arc<Mutex<dyn DrmDriver>>>
     {
         let mut dn: &str = "";
         for c in qmd.drm_minors.iter() {
@@ -505,7 +788,7 @@ impl DrmDriverAmdgpu
             }
         }
 
-        let file = File::open(dn)?;
+        let file = drm_drivers::open_devnode(dn)?;
         let fd = file.as_raw_fd();
 
         let mut cpath = String::from("/sys/class/drm/");
@@ -520,37 +803,51 @@ impl DrmDriverAmdgpu
             freq_limits: None,
             hwmon: None,
             sensor: String::new(),
+            sensor_item: String::new(),
+            gpu_metrics: None,
+            has_gpu_metrics: Path::new(&cpath)
+                .join("device/gpu_metrics").is_file(),
+            firmware: Vec::new(),
         };
 
         amdgpu.dev_type()?;
         amdgpu.freq_limits()?;
 
-        if amdgpu.dev_type.is_some() &&
-            amdgpu.dev_type.as_ref().unwrap().is_discrete() {
-            let hwmon_path = fs::read_dir(
-                Path::new(&cpath).join("device/hwmon"))?
-                .into_iter()
+        // dGPUs and APUs (newer kernels) report power through hwmon
+        let hwmon_dir = Path::new(&cpath).join("device/hwmon");
+        let hwmon_path = if hwmon_dir.is_dir() {
+            fs::read_dir(hwmon_dir)?
                 .filter(|r| r.is_ok())
                 .map(|r| r.unwrap().path())
                 .find(|r| r.file_name().unwrap()
-                .to_str().unwrap().starts_with("hwmon"));
-
-            if let Some(hmp) = hwmon_path {
-                let hm_opt = Hwmon::from(hmp.to_path_buf())?;
-                if let Some(hwmon) = hm_opt {
-                    let plist = hwmon.sensors("power");
-                    for s in plist.iter() {
-                        if s.has_item("average") {
-                            amdgpu.sensor = s.sensor.clone();
-                        }
+                .to_str().unwrap().starts_with("hwmon"))
+        } else {
+            None
+        };
+
+        if let Some(hmp) = hwmon_path {
+            let hm_opt = Hwmon::from(hmp.to_path_buf())?;
+            if let Some(hwmon) = hm_opt {
+                // pick lowest numbered sensor, power*_average or power*_input
+                let mut plist = hwmon.sensors("power");
+                plist.sort_by(|a, b| a.sensor.cmp(&b.sensor));
+                for s in plist.iter() {
+                    if s.has_item("average") || s.has_item("input") {
+                        amdgpu.sensor = s.sensor.clone();
+                        amdgpu.sensor_item = if s.has_item("average") {
+                            String::from("average") } else { String::from("input") };
+                        break;
                     }
-                    amdgpu.hwmon = Some(hwmon);
                 }
-            } else {
-                debug!("INF: no {:?}/device/hwmon/hwmon* directory.", cpath);
+                amdgpu.hwmon = Some(hwmon);
             }
+        } else {
+            debug!("INF: no {:?}/device/hwmon/hwmon* directory.", cpath);
         }
+        // check if its gpu_metrics can be parsed
+        amdgpu.gpu_metrics();
+        amdgpu.firmware = amdgpu.read_firmware();
 
-        Ok(Rc::new(RefCell::new(amdgpu)))
+        Ok(Arc::new(Mutex::new(amdgpu)))
     }
 }
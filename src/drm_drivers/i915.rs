@@ -6,26 +6,29 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
 use std::os::fd::{RawFd, AsRawFd};
-use std::cell::RefCell;
-use std::rc::Rc;
 use std::alloc;
 use std::mem;
 use std::io;
+use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
 use log::warn;
 use libc;
 
+use crate::perf_event::PerfEvent;
 use crate::drm_drivers::{
-    DrmDriver, helpers::{drm_iowr, __IncompleteArrayField},
-    intel_power::{GpuPowerIntel, IGpuPowerIntel, DGpuPowerIntel},
+    self, DrmDriver, DrmDriverOptions, helpers::{drm_iowr, __IncompleteArrayField},
+    intel_power::{GpuPowerIntel, IGpuPowerIntel, DGpuPowerIntel, GtIdleIntel,
+        ThrottleReasonsIntel},
+    intel_pmu::{ExtraPmuIntel, SchedPmuIntel}, intel_fw,
 };
 use crate::drm_devices::{
     DrmDeviceType, DrmDeviceFreqs, DrmDeviceFreqLimits,
-    DrmDeviceThrottleReasons, DrmDevicePower, DrmDeviceMemInfo, DrmDeviceInfo
+    DrmDevicePower, DrmDeviceMemInfo, DrmDeviceSources, DrmEngineSched,
+    DrmDeviceInfo
 };
 use crate::drm_fdinfo::DrmMemRegion;
 use crate::drm_clients::DrmClientMemInfo;
+use crate::qm_error::{QmError, QmResult};
 
 
 // rust-bindgen 0.69.4 on Linux kernel v6.12 uapi i915_drm.h + changes
@@ -99,10 +102,17 @@ pub struct DrmDriveri915
 {
     _dn_file: File,
     dn_fd: RawFd,
-    base_gts_dir: PathBuf,
+    gt_dirs: Vec<PathBuf>,
     dev_type: Option<DrmDeviceType>,
     freq_limits: Option<Vec<DrmDeviceFreqLimits>>,
     power: Option<Box<dyn GpuPowerIntel>>,
+    gt_idle: GtIdleIntel,
+    throttles: HashMap<PathBuf, ThrottleReasonsIntel>,
+    extra_pmu: Option<ExtraPmuIntel>,
+    sched_pmu: Option<SchedPmuIntel>,
+    reset_info: PathBuf,
+    notices: Vec<String>,
+    firmware: Vec<(String, String)>,
 }
 
 impl DrmDriver for DrmDriveri915
@@ -112,7 +122,7 @@ impl DrmDriver for DrmDriveri915
         "i915"
     }
 
-    fn dev_type(&mut self) -> Result<DrmDeviceType>
+    fn dev_type(&mut self) -> QmResult<DrmDeviceType>
     {
         if let Some(dt) = &self.dev_type {
             return Ok(dt.clone());
@@ -129,7 +139,7 @@ impl DrmDriver for DrmDriveri915
         Ok(qmdt)
     }
 
-    fn mem_info(&mut self) -> Result<DrmDeviceMemInfo>
+    fn mem_info(&mut self) -> QmResult<DrmDeviceMemInfo>
     {
         let mut dqi = drm_i915_query_item {
             query_id: DRM_I915_QUERY_MEMORY_REGIONS,
@@ -148,7 +158,7 @@ impl DrmDriver for DrmDriveri915
         let res = unsafe {
             libc::ioctl(self.dn_fd, DRM_IOCTL_I915_QUERY, &mut dq) };
         if res < 0 {
-            return Err(io::Error::last_os_error().into());
+            return Err(QmError::IoctlFailed(io::Error::last_os_error()));
         }
 
         if dqi.length as usize <= 0 {
@@ -173,7 +183,7 @@ impl DrmDriver for DrmDriveri915
             libc::ioctl(self.dn_fd, DRM_IOCTL_I915_QUERY, &mut dq) };
         if res < 0 {
             unsafe { alloc::dealloc(qmrg as *mut u8, layout); }
-            return Err(io::Error::last_os_error().into());
+            return Err(QmError::IoctlFailed(io::Error::last_os_error()));
         }
         if dqi.length <= 0 {
             warn!("i915 memregions query ioctl() error: {:?}", dqi.length);
@@ -207,19 +217,14 @@ impl DrmDriver for DrmDriveri915
         Ok(qmdmi)
     }
 
-    fn freq_limits(&mut self) -> Result<Vec<DrmDeviceFreqLimits>>
+    fn freq_limits(&mut self) -> QmResult<Vec<DrmDeviceFreqLimits>>
     {
         if let Some(fls) = &self.freq_limits {
             return Ok(fls.clone());
         }
 
         let mut fls = Vec::new();
-        for nr in 0.. {
-            let freqs_dir = self.base_gts_dir.join(format!("gt{}", nr));
-            if !freqs_dir.is_dir() {
-                break;
-            }
-
+        for freqs_dir in self.gt_dirs.iter() {
             let fpath = freqs_dir.join("rps_RPn_freq_mhz");
             let fstr = fs::read_to_string(&fpath)?;
             let rpn_val: u64 = fstr.trim_end().parse()?;
@@ -233,7 +238,7 @@ impl DrmDriver for DrmDriveri915
             let rp0_val: u64 = fstr.trim_end().parse()?;
 
             fls.push(DrmDeviceFreqLimits {
-                name: format!("gt{}", nr),
+                name: freqs_dir.file_name().unwrap().to_str().unwrap().to_string(),
                 minimum: rpn_val,
                 efficient: rp1_val,
                 maximum: rp0_val,
@@ -244,15 +249,15 @@ impl DrmDriver for DrmDriveri915
         Ok(fls)
     }
 
-    fn freqs(&mut self) -> Result<Vec<DrmDeviceFreqs>>
+    fn freqs(&mut self) -> QmResult<Vec<DrmDeviceFreqs>>
     {
-        let mut fqs = Vec::new();
-        for nr in 0.. {
-            let freqs_dir = self.base_gts_dir.join(format!("gt{}", nr));
-            if !freqs_dir.is_dir() {
-                break;
-            }
+        let res_paths: Vec<PathBuf> = self.gt_dirs.iter()
+            .map(|gt_dir| gt_dir.join("rc6_residency_ms"))
+            .collect();
+        let idles = self.gt_idle.idle_residencies(&res_paths);
 
+        let mut fqs = Vec::new();
+        for freqs_dir in self.gt_dirs.iter() {
             let fpath = freqs_dir.join("rps_min_freq_mhz");
             let fstr = fs::read_to_string(&fpath)?;
             let min_val: u64 = fstr.trim_end().parse()?;
@@ -269,44 +274,12 @@ impl DrmDriver for DrmDriveri915
             let fstr = fs::read_to_string(&fpath)?;
             let max_val: u64 = fstr.trim_end().parse()?;
 
-            let fpath = freqs_dir.join("throttle_reason_pl1");
-            let pl1 = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = freqs_dir.join("throttle_reason_pl2");
-            let pl2 = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = freqs_dir.join("throttle_reason_pl4");
-            let pl4 = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = freqs_dir.join("throttle_reason_prochot");
-            let prochot = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = freqs_dir.join("throttle_reason_ratl");
-            let ratl = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = freqs_dir.join("throttle_reason_thermal");
-            let thermal = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = freqs_dir.join("throttle_reason_vr_tdc");
-            let vr_tdc = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = freqs_dir.join("throttle_reason_vr_thermalert");
-            let vr_thermalert = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = freqs_dir.join("throttle_reason_status");
-            let status = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let throttle = DrmDeviceThrottleReasons {
-                pl1,
-                pl2,
-                pl4,
-                prochot,
-                ratl,
-                thermal,
-                vr_tdc,
-                vr_thermalert,
-                status,
-            };
+            if !self.throttles.contains_key(freqs_dir) {
+                self.throttles.insert(freqs_dir.clone(),
+                    ThrottleReasonsIntel::from(freqs_dir,
+                        "throttle_reason_", "throttle_reason_status")?);
+            }
+            let throttle = self.throttles[freqs_dir].read()?;
 
             fqs.push(DrmDeviceFreqs {
                 min_freq: min_val,
@@ -314,23 +287,84 @@ impl DrmDriver for DrmDriveri915
                 act_freq: act_val,
                 max_freq: max_val,
                 throttle_reasons: throttle,
+                idle_res: idles.get(fqs.len()).copied().flatten(),
             });
         }
 
         Ok(fqs)
     }
 
-    fn power(&mut self) -> Result<DrmDevicePower>
+    fn power(&mut self) -> QmResult<DrmDevicePower>
     {
         if self.power.is_none() {
             return Ok(DrmDevicePower::new());
         }
 
-        self.power.as_mut().unwrap().power_usage()
+        Ok(self.power.as_mut().unwrap().power_usage()?)
+    }
+
+    fn pmu_rates(&mut self) -> QmResult<Vec<(String, f64)>>
+    {
+        if let Some(pmu) = &mut self.extra_pmu {
+            return Ok(pmu.rates()?);
+        }
+
+        Ok(Vec::new())
+    }
+
+    fn engines_sched(&mut self) -> QmResult<Vec<DrmEngineSched>>
+    {
+        if let Some(pmu) = &mut self.sched_pmu {
+            return Ok(pmu.engines_sched()?);
+        }
+
+        Ok(Vec::new())
+    }
+
+    // e.g. "full gpu reset = 0" and "rcs0 = 1" lines from debugfs, only
+    // readable by root
+    fn reset_count(&mut self) -> QmResult<Option<u64>>
+    {
+        let info = match fs::read_to_string(&self.reset_info) {
+            Ok(info) => info,
+            Err(_) => return Ok(None),
+        };
+
+        let resets = info.lines()
+            .filter_map(|ln| ln.split_once('='))
+            .filter_map(|(_, val)| val.trim().parse::<u64>().ok())
+            .sum();
+
+        Ok(Some(resets))
+    }
+
+    fn stats_sources(&self) -> DrmDeviceSources
+    {
+        DrmDeviceSources {
+            freqs: String::from("sysfs"),
+            power: self.power.as_ref()
+                .map_or(String::new(), |pwr| pwr.source().to_string()),
+            meminfo: String::from("ioctl"),
+        }
+    }
+
+    fn notices(&self) -> Vec<String>
+    {
+        self.notices.clone()
+    }
+
+    fn firmware(&self) -> Vec<(String, String)>
+    {
+        self.firmware.clone()
+    }
+
+    fn engines_order(&self) -> &[&str]
+    {
+        &["render", "copy", "video", "video-enhance", "compute"]
     }
 
     fn client_mem_info(&mut self,
-        mem_regs: &HashMap<String, DrmMemRegion>) -> Result<DrmClientMemInfo>
+        mem_regs: &HashMap<String, DrmMemRegion>) -> QmResult<DrmClientMemInfo>
     {
         let mut cmi = DrmClientMemInfo::new();
 
@@ -355,9 +389,25 @@ impl DrmDriver for DrmDriveri915
 
 impl DrmDriveri915
 {
-    pub fn new(qmd: &DrmDeviceInfo) -> Result<Rc<RefCell<dyn DrmDriver>>>
+    fn find_gt_dirs(gts_path: &Path) -> Vec<PathBuf>
+    {
+        // all GTs from all tiles are listed in the same gt directory
+        let mut gts = Vec::new();
+        for nr in 0.. {
+            let gt_dir = gts_path.join(format!("gt{}", nr));
+            if !gt_dir.is_dir() {
+                break;
+            }
+            gts.push(gt_dir);
+        }
+
+        gts
+    }
+
+    pub fn new(qmd: &DrmDeviceInfo,
+        opts: &DrmDriverOptions) -> QmResult<Arc<Mutex<dyn DrmDriver>>>
     {
-        let file = File::open(qmd.drm_minors[0].devnode.clone())?;
+        let file = drm_drivers::open_devnode(&qmd.drm_minors[0].devnode)?;
         let fd = file.as_raw_fd();
 
         let mut cpath = String::from("/sys/class/drm/");
@@ -365,14 +415,21 @@ impl DrmDriveri915
             .file_name().unwrap().to_str().unwrap();
         cpath.push_str(card);
 
-        // TODO: handle more than one tile
         let mut i915 = DrmDriveri915 {
             _dn_file: file,
             dn_fd: fd,
-            base_gts_dir: Path::new(&cpath).join("gt"),
+            gt_dirs: DrmDriveri915::find_gt_dirs(&Path::new(&cpath).join("gt")),
             dev_type: None,
             freq_limits: None,
             power: None,
+            gt_idle: GtIdleIntel::new(),
+            throttles: HashMap::new(),
+            extra_pmu: None,
+            sched_pmu: None,
+            reset_info: PathBuf::from(format!(
+                "/sys/kernel/debug/dri/{}/i915_reset_info", qmd.card_minor())),
+            notices: Vec::new(),
+            firmware: intel_fw::uc_versions(qmd.card_minor()),
         };
 
         let dtype = i915.dev_type()?;
@@ -384,7 +441,52 @@ impl DrmDriveri915
         } else {
             None
         };
+        if dtype.is_integrated() && i915.power.is_none() {
+            i915.notices.push(String::from(IGpuPowerIntel::NO_POWER_NOTICE));
+        }
+
+        let gt_ids: Vec<u64> = (0..i915.gt_dirs.len() as u64).collect();
+        i915.gt_idle = GtIdleIntel::from(&ExtraPmuIntel::pmu_dir(
+            "i915", &qmd.pci_dev, dtype.is_integrated()), &gt_ids);
+
+        if let Some(eopt) = opts.get("i915", "extra") {
+            match eopt.as_str() {
+                "pmu" => {
+                    let pmu_dir = ExtraPmuIntel::pmu_dir(
+                        "i915", &qmd.pci_dev, dtype.is_integrated());
+                    i915.extra_pmu = ExtraPmuIntel::from(&pmu_dir)?;
+                    if i915.extra_pmu.is_none() {
+                        warn!("No extra i915 PMU counters available.");
+                        let rsn = PerfEvent::not_capable_reason()
+                            .unwrap_or(String::from("not exposed by driver"));
+                        i915.notices.push(
+                            format!("No extra PMU counters: {}", rsn));
+                    }
+                },
+                _ => return Err(QmError::ParseError(format!(
+                    "Invalid i915 extra option {:?} (pmu)", eopt))),
+            }
+        }
+
+        if let Some(sopt) = opts.get("i915", "sched") {
+            match sopt.as_str() {
+                "pmu" => {
+                    let pmu_dir = ExtraPmuIntel::pmu_dir(
+                        "i915", &qmd.pci_dev, dtype.is_integrated());
+                    i915.sched_pmu = SchedPmuIntel::from(&pmu_dir)?;
+                    if i915.sched_pmu.is_none() {
+                        warn!("No i915 PMU engines sched counters available.");
+                        let rsn = PerfEvent::not_capable_reason()
+                            .unwrap_or(String::from("not exposed by driver"));
+                        i915.notices.push(
+                            format!("No engines sched counters: {}", rsn));
+                    }
+                },
+                _ => return Err(QmError::ParseError(format!(
+                    "Invalid i915 sched option {:?} (pmu)", sopt))),
+            }
+        }
 
-        Ok(Rc::new(RefCell::new(i915)))
+        Ok(Arc::new(Mutex::new(i915)))
     }
 }
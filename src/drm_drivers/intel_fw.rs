@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::Path;
+
+use log::debug;
+
+
+// GuC/HuC/GSC firmware loaded on each GT, from the uC info files i915
+// and xe expose in debugfs (only readable by root)
+const UC_FW_INFOS: &[(&str, &str)] = &[
+    ("GuC", "guc_info"),
+    ("HuC", "huc_info"),
+    ("GSC", "gsc_info"),
+];
+
+// e.g. "\tversion: wanted 70.5, found 70.36.0" -> "70.36.0"
+fn found_version(info: &str) -> Option<String>
+{
+    let ver_ln = info.lines()
+        .find(|ln| ln.trim_start().starts_with("version:"))?;
+    let ver = ver_ln.rsplit("found").next()?.trim();
+    if ver.is_empty() || !ver.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(ver.to_string())
+}
+
+// returns (firmware, version) for all GTs, without repeating the ones
+// loaded with the same version on more than one GT (e.g. media GT GuC),
+// from the debugfs dir named after the primary (card) node minor
+pub fn uc_versions(card_minor: u32) -> Vec<(String, String)>
+{
+    let dbg_dir = Path::new("/sys/kernel/debug/dri").join(card_minor.to_string());
+    let mut res: Vec<(String, String)> = Vec::new();
+
+    let mut gt = 0;
+    while dbg_dir.join(format!("gt{}", gt)).is_dir() {
+        let uc_dir = dbg_dir.join(format!("gt{}/uc", gt));
+        for (name, fname) in UC_FW_INFOS.iter() {
+            let ver = match fs::read_to_string(uc_dir.join(fname)) {
+                Ok(info) => found_version(&info),
+                Err(_) => None,
+            };
+            if let Some(ver) = ver {
+                let fw = (name.to_string(), ver);
+                if !res.contains(&fw) {
+                    res.push(fw);
+                }
+            }
+        }
+        gt += 1;
+    }
+    if res.is_empty() {
+        debug!("INF: no uC firmware versions in {:?}.", dbg_dir);
+    }
+
+    res
+}
@@ -0,0 +1,338 @@
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time;
+
+use anyhow::Result;
+use log::debug;
+
+use crate::perf_event::{
+    perf_event_attr, PERF_SAMPLE_IDENTIFIER, PERF_FORMAT_GROUP, PerfEvent
+};
+use crate::drm_devices::DrmEngineSched;
+use crate::qm_error::QmError;
+
+
+// device-wide counters not exposed anywhere else (only i915 has them
+// for now), the ones missing in the PMU are skipped
+const EXTRA_PMU_EVENTS: &[&str] = &["interrupts"];
+
+// extra counters from the i915 PMU, as rates per second
+#[derive(Debug)]
+pub struct ExtraPmuIntel
+{
+    pf_evt: PerfEvent,
+    names: Vec<String>,
+    last_vals: Vec<u64>,
+    last_time: Option<time::Instant>,
+}
+
+impl ExtraPmuIntel
+{
+    // returns (event name, rate/s) after 2+ reads
+    pub fn rates(&mut self) -> Result<Vec<(String, f64)>>
+    {
+        let vals = self.pf_evt.read(1 + self.names.len())?;
+        let now = time::Instant::now();
+
+        let mut res = Vec::new();
+        if let Some(lt) = self.last_time {
+            let secs = now.duration_since(lt).as_secs_f64();
+            for (i, name) in self.names.iter().enumerate() {
+                let delta = vals[1 + i].saturating_sub(self.last_vals[i]);
+                let rate = if secs > 0.0 { delta as f64 / secs } else { 0.0 };
+                res.push((name.clone(), rate));
+            }
+        }
+        self.last_vals = vals[1..].to_vec();
+        self.last_time = Some(now);
+
+        Ok(res)
+    }
+
+    fn event_config(pmu_dir: &Path, name: &str) -> Result<u64>
+    {
+        // e.g. "config=0x100002" (i915) or "event=0x01" (xe)
+        let cfg_str = fs::read_to_string(pmu_dir.join("events").join(name))?;
+        for kv in cfg_str.trim().split(',') {
+            let kv = kv.trim();
+            if let Some(val) = kv.strip_prefix("config=")
+                .or(kv.strip_prefix("event=")) {
+                return Ok(u64::from_str_radix(
+                    val.trim_start_matches("0x"), 16)?);
+            }
+        }
+
+        Err(QmError::NoPmu(
+            format!("No config in {:?} PMU event", name)).into())
+    }
+
+    // i915 integrated GPUs use the plain driver name for their PMU
+    pub fn pmu_dir(drv_name: &str, pci_dev: &str, is_igfx: bool) -> PathBuf
+    {
+        let pmus_dir = Path::new("/sys/bus/event_source/devices");
+        let dev_dir = pmus_dir.join(
+            format!("{}_{}", drv_name, pci_dev.replace(':', "_")));
+        if !dev_dir.is_dir() && is_igfx {
+            return pmus_dir.join(drv_name);
+        }
+
+        dev_dir
+    }
+
+    pub fn from(pmu_dir: &Path) -> Result<Option<ExtraPmuIntel>>
+    {
+        if !PerfEvent::is_capable() {
+            debug!("INF: no perf event support, no extra PMU counters.");
+            return Ok(None);
+        }
+
+        let names: Vec<String> = EXTRA_PMU_EVENTS.iter()
+            .filter(|en| pmu_dir.join("events").join(en).is_file())
+            .map(|en| en.to_string())
+            .collect();
+        if names.is_empty() {
+            debug!("INF: no extra PMU events in {:?}.", pmu_dir);
+            return Ok(None);
+        }
+
+        Ok(Some(ExtraPmuIntel {
+            pf_evt: ExtraPmuIntel::open_group(pmu_dir, &names)?,
+            last_vals: vec![0; names.len()],
+            names,
+            last_time: None,
+        }))
+    }
+
+    // all events in a single group, so they're read at the same time
+    fn open_group(pmu_dir: &Path, names: &[String]) -> Result<PerfEvent>
+    {
+        let mut cfgs = Vec::new();
+        for name in names.iter() {
+            cfgs.push(ExtraPmuIntel::event_config(pmu_dir, name)?);
+        }
+
+        ExtraPmuIntel::open_configs(pmu_dir, &cfgs)
+    }
+
+    fn open_configs(pmu_dir: &Path, cfgs: &[u64]) -> Result<PerfEvent>
+    {
+        let type_: u32 = fs::read_to_string(
+            pmu_dir.join("type"))?.trim().parse()?;
+        let cpu = PerfEvent::pmu_cpu(pmu_dir)?;
+
+        let mut pf_attr = perf_event_attr::new();
+        pf_attr.type_ = type_;
+        pf_attr.size = mem::size_of::<perf_event_attr>() as u32;
+        pf_attr.sample_type = PERF_SAMPLE_IDENTIFIER;
+        pf_attr.read_format = PERF_FORMAT_GROUP;
+
+        let mut pf_evt: Option<PerfEvent> = None;
+        for cfg in cfgs.iter() {
+            pf_attr.config = *cfg;
+            if let Some(evt) = &mut pf_evt {
+                evt.group_open(&pf_attr, -1, cpu, 0)?;
+            } else {
+                pf_evt = Some(PerfEvent::open(&pf_attr, -1, cpu, 0)?);
+            }
+        }
+
+        Ok(pf_evt.unwrap())
+    }
+}
+
+// per-engine time waiting on semaphores ("<engine>-sema") and on events
+// ("<engine>-wait"), both in ns, so a busy engine that is actually
+// stalled can be told apart from one doing work
+#[derive(Debug)]
+pub struct SchedPmuIntel
+{
+    pf_evt: PerfEvent,
+    engines: Vec<String>,
+    last_vals: Vec<u64>,
+    last_time: Option<time::Instant>,
+}
+
+impl SchedPmuIntel
+{
+    // returns the time waiting in % of the elapsed time after 2+ reads
+    pub fn engines_sched(&mut self) -> Result<Vec<DrmEngineSched>>
+    {
+        let nr_vals = 2 * self.engines.len();
+        let vals = self.pf_evt.read(1 + nr_vals)?;
+        let now = time::Instant::now();
+
+        let mut res = Vec::new();
+        if let Some(lt) = self.last_time {
+            let nsecs = now.duration_since(lt).as_nanos() as f64;
+            let pct = |i: usize| {
+                let delta = vals[1 + i].saturating_sub(self.last_vals[i]);
+                if nsecs > 0.0 {
+                    f64::min(delta as f64 * 100.0 / nsecs, 100.0)
+                } else {
+                    0.0
+                }
+            };
+            for (i, en) in self.engines.iter().enumerate() {
+                res.push(DrmEngineSched {
+                    engine: en.clone(),
+                    sema: pct(2 * i),
+                    wait: pct(2 * i + 1),
+                });
+            }
+        }
+        self.last_vals = vals[1..].to_vec();
+        self.last_time = Some(now);
+
+        Ok(res)
+    }
+
+    pub fn from(pmu_dir: &Path) -> Result<Option<SchedPmuIntel>>
+    {
+        if !PerfEvent::is_capable() {
+            debug!("INF: no perf event support, no engines sched counters.");
+            return Ok(None);
+        }
+
+        // engines with both counters, e.g. "rcs0" for "rcs0-sema"
+        let mut engines = Vec::new();
+        if let Ok(evts) = fs::read_dir(pmu_dir.join("events")) {
+            for evt in evts.flatten() {
+                let evt_name = evt.file_name().to_string_lossy().to_string();
+                if let Some(en) = evt_name.strip_suffix("-sema") {
+                    if pmu_dir.join("events")
+                        .join(format!("{}-wait", en)).is_file() {
+                        engines.push(en.to_string());
+                    }
+                }
+            }
+        }
+        if engines.is_empty() {
+            debug!("INF: no engines sched PMU events in {:?}.", pmu_dir);
+            return Ok(None);
+        }
+        engines.sort();
+
+        let mut names = Vec::new();
+        for en in engines.iter() {
+            names.push(format!("{}-sema", en));
+            names.push(format!("{}-wait", en));
+        }
+
+        Ok(Some(SchedPmuIntel {
+            pf_evt: ExtraPmuIntel::open_group(pmu_dir, &names)?,
+            last_vals: vec![0; names.len()],
+            engines,
+            last_time: None,
+        }))
+    }
+}
+
+// GT idle (RC6) residency from the i915 ("rc6-residency", in ns) or xe
+// ("gt-c6-residency", in ms) PMU, one counter per GT
+#[derive(Debug)]
+pub struct Rc6PmuIntel
+{
+    pf_evt: PerfEvent,
+    unit_ns: f64,
+    last_vals: Vec<u64>,
+    last_time: Option<time::Instant>,
+}
+
+impl Rc6PmuIntel
+{
+    // returns the idle % of the elapsed time per GT, None until 2+ reads
+    pub fn idle_residencies(&mut self) -> Result<Vec<Option<f64>>>
+    {
+        let vals = self.pf_evt.read(1 + self.last_vals.len())?;
+        let now = time::Instant::now();
+
+        let mut res = vec![None; self.last_vals.len()];
+        if let Some(lt) = self.last_time {
+            let nsecs = now.duration_since(lt).as_nanos() as f64;
+            if nsecs > 0.0 {
+                for (i, ir) in res.iter_mut().enumerate() {
+                    let delta = vals[1 + i].saturating_sub(self.last_vals[i]);
+                    *ir = Some(f64::min(
+                        delta as f64 * self.unit_ns * 100.0 / nsecs, 100.0));
+                }
+            }
+        }
+        self.last_vals = vals[1..].to_vec();
+        self.last_time = Some(now);
+
+        Ok(res)
+    }
+
+    fn format_shift(pmu_dir: &Path, name: &str) -> Result<u64>
+    {
+        // e.g. "config:60-63"
+        let fmt_str = fs::read_to_string(pmu_dir.join("format").join(name))?;
+        if let Some(bits) = fmt_str.trim().strip_prefix("config:") {
+            let lo = bits.split('-').next().unwrap_or("");
+            return Ok(lo.parse()?);
+        }
+
+        Err(QmError::NoPmu(format!("Unsupported PMU format {:?} for {:?}",
+            fmt_str.trim(), name)).into())
+    }
+
+    fn unit_ns(pmu_dir: &Path, name: &str) -> f64
+    {
+        let unit = fs::read_to_string(
+            pmu_dir.join("events").join(format!("{}.unit", name)))
+            .unwrap_or_default();
+        match unit.trim() {
+            "ms" => 1_000_000.0,
+            "us" => 1_000.0,
+            _ => 1.0,
+        }
+    }
+
+    pub fn from(pmu_dir: &Path, gt_ids: &[u64]) -> Result<Option<Rc6PmuIntel>>
+    {
+        if !PerfEvent::is_capable() {
+            debug!("INF: no perf event support, no RC6 PMU counters.");
+            return Ok(None);
+        }
+        if gt_ids.is_empty() {
+            return Ok(None);
+        }
+
+        let evts_dir = pmu_dir.join("events");
+        let mut cfgs = Vec::new();
+        let unit_ns = if evts_dir.join("gt-c6-residency").is_file() {
+            // xe selects the GT in the config bits
+            let cfg = ExtraPmuIntel::event_config(pmu_dir, "gt-c6-residency")?;
+            let gt_shift = Rc6PmuIntel::format_shift(pmu_dir, "gt")?;
+            for gt in gt_ids.iter() {
+                cfgs.push((gt << gt_shift) | cfg);
+            }
+            Rc6PmuIntel::unit_ns(pmu_dir, "gt-c6-residency")
+        } else {
+            // i915 has one event per GT on multi-GT devices
+            let names: Vec<String> = if gt_ids.len() == 1 {
+                vec![String::from("rc6-residency")]
+            } else {
+                gt_ids.iter()
+                    .map(|gt| format!("rc6-residency-gt{}", gt))
+                    .collect()
+            };
+            if !names.iter().all(|en| evts_dir.join(en).is_file()) {
+                debug!("INF: no RC6 residency PMU events in {:?}.", pmu_dir);
+                return Ok(None);
+            }
+            for name in names.iter() {
+                cfgs.push(ExtraPmuIntel::event_config(pmu_dir, name)?);
+            }
+            Rc6PmuIntel::unit_ns(pmu_dir, &names[0])
+        };
+
+        Ok(Some(Rc6PmuIntel {
+            pf_evt: ExtraPmuIntel::open_configs(pmu_dir, &cfgs)?,
+            unit_ns,
+            last_vals: vec![0; cfgs.len()],
+            last_time: None,
+        }))
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
 use std::os::fd::{RawFd, AsRawFd};
+use std::os::unix::fs::FileExt;
 use std::time;
 use std::mem;
 use std::io;
@@ -15,12 +16,16 @@ use crate::perf_event::{
     perf_event_attr, PERF_SAMPLE_IDENTIFIER, PERF_FORMAT_GROUP, PerfEvent
 };
 use crate::hwmon::Hwmon;
-use crate::drm_devices::DrmDevicePower;
+use crate::drm_drivers::intel_pmu::Rc6PmuIntel;
+use crate::drm_devices::{DrmDevicePower, DrmDeviceThrottleReasons};
 
 
-pub trait GpuPowerIntel
+pub trait GpuPowerIntel: Send
 {
     fn power_usage(&mut self) -> Result<DrmDevicePower>;
+
+    // e.g. "hwmon" or "msr"
+    fn source(&self) -> &str;
 }
 
 impl Debug for dyn GpuPowerIntel
@@ -77,6 +82,11 @@ impl GpuPowerIntel for DGpuPowerIntel
 
         func(self)
     }
+
+    fn source(&self) -> &str
+    {
+        "hwmon"
+    }
 }
 
 impl DGpuPowerIntel
@@ -124,11 +134,12 @@ impl DGpuPowerIntel
         self.last_update = time::Instant::now();
 
         if self.nr_updates >= 2 {
+            // energy going backwards means a counter reset, skip it
             if gpu_val > 0 {
-                self.delta_gpu_val = gpu_val - self.last_gpu_val;
+                self.delta_gpu_val = gpu_val.saturating_sub(self.last_gpu_val);
             }
             if pkg_val > 0 {
-                self.delta_pkg_val = pkg_val - self.last_pkg_val;
+                self.delta_pkg_val = pkg_val.saturating_sub(self.last_pkg_val);
             }
         }
         self.last_gpu_val = gpu_val;
@@ -355,11 +366,117 @@ impl MsrIntel
     }
 }
 
+#[derive(Debug)]
+struct RaplZone
+{
+    energy_file: PathBuf,
+    max_range: u64,
+    sum: u64,
+    last: u64,
+    nr_reads: u64,
+}
+
+impl RaplZone
+{
+    // accumulated energy in uJ, handling counter wraparound
+    fn read_sum(&mut self) -> Result<u64>
+    {
+        let val: u64 = fs::read_to_string(&self.energy_file)?.trim().parse()?;
+
+        if self.nr_reads > 0 {
+            let delta_val = if val >= self.last {
+                val - self.last
+            } else {
+                self.max_range - self.last + val
+            };
+            self.sum += delta_val;
+        }
+        self.last = val;
+        self.nr_reads += 1;
+
+        Ok(self.sum)
+    }
+
+    fn from(zone_dir: &Path) -> Result<Option<RaplZone>>
+    {
+        let energy_file = zone_dir.join("energy_uj");
+        // often readable by non-root users, but not always
+        if let Err(err) = fs::read_to_string(&energy_file) {
+            debug!("INF: can't read {:?}: {:?}", energy_file, err);
+            return Ok(None);
+        }
+        let max_range: u64 = fs::read_to_string(
+            zone_dir.join("max_energy_range_uj"))?.trim().parse()?;
+
+        Ok(Some(RaplZone {
+            energy_file,
+            max_range,
+            sum: 0,
+            last: 0,
+            nr_reads: 0,
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct PowercapIntel
+{
+    gpu_zone: RaplZone,
+    pkg_zone: RaplZone,
+}
+
+impl PowercapIntel
+{
+    fn zone_name(zone_dir: &Path) -> String
+    {
+        fs::read_to_string(zone_dir.join("name"))
+            .map_or(String::new(), |n| n.trim().to_string())
+    }
+
+    fn from(base_dir: &Path) -> Result<Option<PowercapIntel>>
+    {
+        // package zone on socket 0 and its "uncore" (GPU) subzone
+        let pkg_dir = base_dir.join("intel-rapl:0");
+        if !pkg_dir.is_dir() ||
+            !PowercapIntel::zone_name(&pkg_dir).starts_with("package") {
+            debug!("INF: no intel-rapl package zone in powercap.");
+            return Ok(None);
+        }
+
+        let mut gpu_dir: Option<PathBuf> = None;
+        for entry in fs::read_dir(&pkg_dir)? {
+            let path = entry?.path();
+            let is_sub = path.file_name().unwrap().to_str().unwrap()
+                .starts_with("intel-rapl:0:");
+            if is_sub && PowercapIntel::zone_name(&path) == "uncore" {
+                gpu_dir = Some(path);
+                break;
+            }
+        }
+        if gpu_dir.is_none() {
+            debug!("INF: no intel-rapl uncore zone in powercap.");
+            return Ok(None);
+        }
+
+        let pkg_zone = RaplZone::from(&pkg_dir)?;
+        let gpu_zone = RaplZone::from(&gpu_dir.unwrap())?;
+        if pkg_zone.is_none() || gpu_zone.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(PowercapIntel {
+            gpu_zone: gpu_zone.unwrap(),
+            pkg_zone: pkg_zone.unwrap(),
+        }))
+    }
+}
+
 #[derive(Debug)]
 pub struct IGpuPowerIntel
 {
     pf_evt: Option<PerfEvent>,
     msr: Option<MsrIntel>,
+    powercap: Option<PowercapIntel>,
     last_gpu_val: u64,
     last_pkg_val: u64,
     delta_gpu_val: u64,
@@ -381,10 +498,13 @@ impl GpuPowerIntel for IGpuPowerIntel
             let vals = pf_evt.read(3)?;  // reads #evts, gpu, pkg
             gpu_val = vals[1];
             pkg_val = vals[2];
-        } else {
-            let msr = self.msr.as_mut().unwrap();
+        } else if let Some(msr) = &mut self.msr {
             gpu_val = msr.read_sum(MSR_PP1_ENERGY_STATUS)?;
             pkg_val = msr.read_sum(MSR_PKG_ENERGY_STATUS)?;
+        } else {
+            let pcap = self.powercap.as_mut().unwrap();
+            gpu_val = pcap.gpu_zone.read_sum()?;
+            pkg_val = pcap.pkg_zone.read_sum()?;
         }
         self.nr_updates += 1;
 
@@ -392,8 +512,8 @@ impl GpuPowerIntel for IGpuPowerIntel
         self.last_update = time::Instant::now();
 
         if self.nr_updates >= 2 {
-            self.delta_gpu_val = gpu_val - self.last_gpu_val;
-            self.delta_pkg_val = pkg_val - self.last_pkg_val;
+            self.delta_gpu_val = gpu_val.saturating_sub(self.last_gpu_val);
+            self.delta_pkg_val = pkg_val.saturating_sub(self.last_pkg_val);
         }
         self.last_gpu_val = gpu_val;
         self.last_pkg_val = pkg_val;
@@ -408,6 +528,18 @@ impl GpuPowerIntel for IGpuPowerIntel
             pkg_cur_power: pkg_pwr,
         })
     }
+
+    // RAPL energy counters, in the same order they're tried
+    fn source(&self) -> &str
+    {
+        if self.pf_evt.is_some() {
+            "pmu"
+        } else if self.msr.is_some() {
+            "msr"
+        } else {
+            "powercap"
+        }
+    }
 }
 
 impl IGpuPowerIntel
@@ -480,7 +612,7 @@ impl IGpuPowerIntel
 
         let type_: u32 = fs::read_to_string(
             Path::new("/sys/devices/power/type"))?.trim().parse()?;
-        let cpu: i32 = unsafe { libc::sched_getcpu() };
+        let cpu = PerfEvent::pmu_cpu(Path::new("/sys/devices/power"))?;
 
         let cfg = IGpuPowerIntel::get_perf_config(&evt_dir, "energy-gpu")?;
         if cfg.is_none() {
@@ -516,7 +648,9 @@ impl IGpuPowerIntel
             return Ok(None);
         }
 
-        let cpu = unsafe { libc::sched_getcpu() };
+        // same package as the rapl PMU, not wherever this thread runs
+        let cpu = PerfEvent::pmu_cpu(Path::new("/sys/devices/power"))
+            .unwrap_or_else(|_| unsafe { libc::sched_getcpu() });
         let msr = MsrIntel::from(cpu)?;
 
         if !msr.probe(MSR_RAPL_POWER_UNIT)? ||
@@ -532,10 +666,31 @@ impl IGpuPowerIntel
         Ok(Some((msr, scale, scale)))
     }
 
+    fn new_rapl_powercap() -> Result<Option<(PowercapIntel, f64, f64)>>
+    {
+        let base_dir = Path::new("/sys/class/powercap");
+        if !base_dir.is_dir() {
+            debug!("INF: no powercap sysfs support, no rapl power reporting.");
+            return Ok(None);
+        }
+
+        let pcap = PowercapIntel::from(base_dir)?;
+        if pcap.is_none() {
+            return Ok(None);
+        }
+
+        // energy in uJ
+        Ok(Some((pcap.unwrap(), 1e-6, 1e-6)))
+    }
+
+    pub const NO_POWER_NOTICE: &'static str = "No power stats: RAPL energy \
+        requires root (perf events or MSR) or readable powercap files";
+
     pub fn new() -> Result<Option<Box<dyn GpuPowerIntel>>>
     {
         let mut pf_evt: Option<PerfEvent> = None;
         let mut msr: Option<MsrIntel> = None;
+        let mut powercap: Option<PowercapIntel> = None;
         let gpu_scale: f64;
         let pkg_scale: f64;
 
@@ -543,21 +698,27 @@ impl IGpuPowerIntel
             let pf_evt_obj: PerfEvent;
             (pf_evt_obj, gpu_scale, pkg_scale) = tup_res;
             pf_evt = Some(pf_evt_obj);
-        } else {
+        } else if let Some(tup_res) = IGpuPowerIntel::new_rapl_msr()? {
             // fallback to MSR, if possible
-            let tup_res = IGpuPowerIntel::new_rapl_msr()?;
+            let msr_obj: MsrIntel;
+            (msr_obj, gpu_scale, pkg_scale) = tup_res;
+            msr = Some(msr_obj);
+        } else {
+            // last fallback to powercap sysfs, might work for non-root
+            let tup_res = IGpuPowerIntel::new_rapl_powercap()?;
             if tup_res.is_none() {
                 return Ok(None);
             }
 
-            let msr_obj: MsrIntel;
-            (msr_obj, gpu_scale, pkg_scale) = tup_res.unwrap();
-            msr = Some(msr_obj);
+            let pcap_obj: PowercapIntel;
+            (pcap_obj, gpu_scale, pkg_scale) = tup_res.unwrap();
+            powercap = Some(pcap_obj);
         }
 
         Ok(Some(Box::new(IGpuPowerIntel {
             pf_evt,
             msr,
+            powercap,
             last_gpu_val: 0,
             last_pkg_val: 0,
             delta_gpu_val: 0,
@@ -569,3 +730,132 @@ impl IGpuPowerIntel
         })))
     }
 }
+
+// GT idle (RC6) residency as the % of the time since its last read, from
+// the PMU or from the cumulative ms in sysfs when the PMU isn't available
+#[derive(Debug)]
+pub struct GtIdleIntel
+{
+    pmu: Option<Rc6PmuIntel>,
+    last: HashMap<PathBuf, (u64, time::Instant)>,
+}
+
+impl GtIdleIntel
+{
+    // returns the idle % per GT in res_paths (sysfs) order, None for a GT
+    // until there's a delta for it
+    pub fn idle_residencies(&mut self, res_paths: &[PathBuf]) -> Vec<Option<f64>>
+    {
+        if let Some(pmu) = &mut self.pmu {
+            match pmu.idle_residencies() {
+                Ok(res) => return res,
+                Err(err) => {
+                    debug!("ERR: failed to read RC6 PMU, using sysfs: {:?}", err);
+                    self.pmu = None;
+                },
+            }
+        }
+
+        res_paths.iter().map(|rp| self.sysfs_residency(rp)).collect()
+    }
+
+    fn sysfs_residency(&mut self, res_path: &Path) -> Option<f64>
+    {
+        let res_ms: u64 = fs::read_to_string(res_path).ok()?
+            .trim().parse().ok()?;
+        let now = time::Instant::now();
+
+        let last = self.last.insert(res_path.to_path_buf(), (res_ms, now));
+        let (last_ms, last_tm) = last?;
+        let ms_elapsed = now.duration_since(last_tm).as_millis() as f64;
+        if ms_elapsed <= 0.0 {
+            return None;
+        }
+
+        Some(f64::min(100.0,
+            res_ms.saturating_sub(last_ms) as f64 / ms_elapsed * 100.0))
+    }
+
+    pub fn from(pmu_dir: &Path, gt_ids: &[u64]) -> GtIdleIntel
+    {
+        let pmu = match Rc6PmuIntel::from(pmu_dir, gt_ids) {
+            Ok(pmu) => pmu,
+            Err(err) => {
+                debug!("ERR: failed to open RC6 PMU, using sysfs: {:?}", err);
+                None
+            },
+        };
+
+        GtIdleIntel {
+            pmu,
+            last: HashMap::new(),
+        }
+    }
+
+    pub fn new() -> GtIdleIntel
+    {
+        GtIdleIntel {
+            pmu: None,
+            last: HashMap::new(),
+        }
+    }
+}
+
+// GT throttle reasons, with their sysfs files kept open and re-read from
+// the start, saving an open & close for each of them on every refresh
+#[derive(Debug)]
+pub struct ThrottleReasonsIntel
+{
+    files: Vec<File>,   // same order as DrmDeviceThrottleReasons fields
+}
+
+impl ThrottleReasonsIntel
+{
+    fn read_flag(file: &File) -> Result<bool>
+    {
+        let mut buf = [0u8; 8];
+        let nr = file.read_at(&mut buf, 0)?;
+
+        Ok(nr > 0 && buf[0] == b'1')
+    }
+
+    pub fn read(&self) -> Result<DrmDeviceThrottleReasons>
+    {
+        let mut flags = [false; 9];
+        for (flag, file) in flags.iter_mut().zip(self.files.iter()) {
+            *flag = ThrottleReasonsIntel::read_flag(file)?;
+        }
+
+        Ok(DrmDeviceThrottleReasons {
+            pl1: flags[0],
+            pl2: flags[1],
+            pl4: flags[2],
+            prochot: flags[3],
+            ratl: flags[4],
+            thermal: flags[5],
+            vr_tdc: flags[6],
+            vr_thermalert: flags[7],
+            status: flags[8],
+        })
+    }
+
+    // reasons files are "<prefix><reason>" in dir, plus the status one
+    pub fn from(dir: &Path,
+        prefix: &str, status_fname: &str) -> Result<ThrottleReasonsIntel>
+    {
+        let reasons = [
+            "pl1", "pl2", "pl4", "prochot", "ratl",
+            "thermal", "vr_tdc", "vr_thermalert",
+        ];
+
+        let mut files = Vec::new();
+        for rsn in reasons {
+            files.push(File::open(dir.join(format!("{}{}", prefix, rsn)))?);
+        }
+        files.push(File::open(dir.join(status_fname))?);
+
+        Ok(ThrottleReasonsIntel {
+            files,
+        })
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use log::debug;
+
+use crate::drm_drivers::{self, DrmDriver, DrmDriverOptions};
+use crate::drm_devices::{
+    DrmDeviceType, DrmDeviceFreqLimits, DrmDeviceFreqs, DrmDeviceMemInfo,
+    DrmDeviceSources, DrmDeviceInfo
+};
+use crate::qm_error::QmResult;
+
+
+// the GPU (nvgpu on L4T, e.g. 17000000.ga10b on Orin or 57000000.gpu on
+// Nano) isn't part of the tegra DRM device, it has its own devfreq node
+const DEVFREQ_DIR: &str = "/sys/class/devfreq";
+const GPU_DEVFREQ_NAMES: &[&str] = &[
+    "gpu", "gk20a", "gm20b", "gp10b", "gv11b", "ga10b",
+];
+// older L4T releases
+const NVGPU_LOAD_FILE: &str = "/sys/devices/gpu.0/load";
+
+#[derive(Debug)]
+pub struct DrmDriverTegra
+{
+    devfreq_dir: Option<PathBuf>,
+    load_file: Option<PathBuf>,
+    freq_limits: Option<DrmDeviceFreqLimits>,
+    notices: Vec<String>,
+}
+
+impl DrmDriver for DrmDriverTegra
+{
+    fn name(&self) -> &str
+    {
+        "tegra"
+    }
+
+    fn dev_type(&mut self) -> QmResult<DrmDeviceType>
+    {
+        // GPU memory is carved from system memory on all Jetson boards
+        Ok(DrmDeviceType::Integrated)
+    }
+
+    fn freq_limits(&mut self) -> QmResult<Vec<DrmDeviceFreqLimits>>
+    {
+        if let Some(fls) = &self.freq_limits {
+            return Ok(vec![fls.clone(),]);
+        }
+        if self.devfreq_dir.is_none() {
+            return Ok(vec![DrmDeviceFreqLimits::new(),]);
+        }
+        let df_dir = self.devfreq_dir.as_ref().unwrap();
+
+        // hardware range from the OPP table, or the current policy limits
+        let mut fl = DrmDeviceFreqLimits::new();
+        let avail: Vec<u64> = fs::read_to_string(
+            df_dir.join("available_frequencies")).unwrap_or_default()
+            .split_whitespace()
+            .filter_map(|hz| hz.parse::<u64>().ok())
+            .collect();
+        if let (Some(fmin), Some(fmax)) =
+            (avail.iter().min(), avail.iter().max()) {
+            fl.minimum = fmin / 1000000;
+            fl.maximum = fmax / 1000000;
+        } else {
+            fl.minimum = DrmDriverTegra::read_mhz(df_dir, "min_freq")?;
+            fl.maximum = DrmDriverTegra::read_mhz(df_dir, "max_freq")?;
+        }
+        fl.efficient = fl.minimum;
+        self.freq_limits = Some(fl.clone());
+
+        Ok(vec![fl,])
+    }
+
+    fn freqs(&mut self) -> QmResult<Vec<DrmDeviceFreqs>>
+    {
+        let mut fqs = DrmDeviceFreqs::new();
+        if let Some(df_dir) = &self.devfreq_dir {
+            fqs.min_freq = DrmDriverTegra::read_mhz(df_dir, "min_freq")?;
+            fqs.max_freq = DrmDriverTegra::read_mhz(df_dir, "max_freq")?;
+            // devfreq only has the clock the GPU is running at, there's
+            // no requested one to report as cur_freq
+            fqs.act_freq = DrmDriverTegra::read_mhz(df_dir, "cur_freq")?;
+        }
+
+        Ok(vec![fqs,])
+    }
+
+    fn mem_info(&mut self) -> QmResult<DrmDeviceMemInfo>
+    {
+        // shared with the CPU, no GPU-only accounting in sysfs
+        let mut mi = DrmDeviceMemInfo::new();
+        (mi.smem_total, mi.smem_used) = drm_drivers::system_mem_info();
+
+        Ok(mi)
+    }
+
+    fn busy(&mut self) -> QmResult<Option<f64>>
+    {
+        if let Some(lf) = &self.load_file {
+            // in 0.1% units
+            let load: f64 = fs::read_to_string(lf)?.trim().parse()?;
+            return Ok(Some(load / 10.0));
+        }
+
+        Ok(None)
+    }
+
+    fn stats_sources(&self) -> DrmDeviceSources
+    {
+        let mut srcs = DrmDeviceSources::new();
+        if self.devfreq_dir.is_some() {
+            srcs.freqs = String::from("devfreq");
+        }
+        srcs.meminfo = String::from("procfs");
+
+        srcs
+    }
+
+    fn notices(&self) -> Vec<String>
+    {
+        self.notices.clone()
+    }
+}
+
+impl DrmDriverTegra
+{
+    fn read_mhz(df_dir: &Path, fname: &str) -> Result<u64>
+    {
+        let hz: u64 = fs::read_to_string(df_dir.join(fname))?.trim().parse()?;
+
+        Ok(hz / 1000000)
+    }
+
+    // GPU devfreq node, preferring the one with nvgpu's load file
+    fn find_gpu_devfreq() -> Option<PathBuf>
+    {
+        let mut res: Option<PathBuf> = None;
+        for ent in fs::read_dir(DEVFREQ_DIR).ok()?.flatten() {
+            let df_dir = ent.path();
+            if df_dir.join("device/load").exists() {
+                return Some(df_dir);
+            }
+
+            let name = ent.file_name().to_string_lossy().to_string();
+            let is_gpu = name.rsplit('.').next()
+                .is_some_and(|nm| GPU_DEVFREQ_NAMES.contains(&nm));
+            if is_gpu && res.is_none() {
+                res = Some(df_dir);
+            }
+        }
+
+        res
+    }
+
+    pub fn new(_qmd: &DrmDeviceInfo,
+        _opts: &DrmDriverOptions) -> QmResult<Arc<Mutex<dyn DrmDriver>>>
+    {
+        let devfreq_dir = DrmDriverTegra::find_gpu_devfreq();
+        let load_file = devfreq_dir.as_ref()
+            .map(|dd| dd.join("device/load"))
+            .filter(|lf| lf.exists())
+            .or_else(|| Some(PathBuf::from(NVGPU_LOAD_FILE))
+                .filter(|lf| lf.exists()));
+        debug!("INF: tegra GPU devfreq {:?}, load {:?}.",
+            devfreq_dir, load_file);
+
+        let mut notices = Vec::new();
+        if devfreq_dir.is_none() {
+            notices.push(String::from(
+                "No GPU freqs: no GPU devfreq node found (needs nvgpu)"));
+        }
+        if load_file.is_none() {
+            notices.push(String::from(
+                "No GPU load: no nvgpu load file found"));
+        }
+        notices.push(String::from(
+            "tegra doesn't report power, the GPU load is shown as BUSY"));
+
+        let tegra = DrmDriverTegra {
+            devfreq_dir,
+            load_file,
+            freq_limits: None,
+            notices,
+        };
+
+        Ok(Arc::new(Mutex::new(tegra)))
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+
+use crate::drm_drivers::{self, DrmDriver, DrmDriverOptions};
+use crate::drm_devices::{
+    DrmDeviceType, DrmDeviceMemInfo, DrmDeviceSources, DrmDeviceInfo
+};
+use crate::qm_error::QmResult;
+
+
+#[derive(Debug)]
+pub struct DrmDriverVirtioGpu
+{
+    smem_total: u64,
+    notices: Vec<String>,
+}
+
+impl DrmDriver for DrmDriverVirtioGpu
+{
+    fn name(&self) -> &str
+    {
+        "virtio_gpu"
+    }
+
+    fn dev_type(&mut self) -> QmResult<DrmDeviceType>
+    {
+        // no device memory, buffers live in guest system memory
+        Ok(DrmDeviceType::Integrated)
+    }
+
+    fn mem_info(&mut self) -> QmResult<DrmDeviceMemInfo>
+    {
+        let mut mi = DrmDeviceMemInfo::new();
+        mi.smem_total = self.smem_total;
+
+        Ok(mi)
+    }
+
+    fn stats_sources(&self) -> DrmDeviceSources
+    {
+        let mut srcs = DrmDeviceSources::new();
+        srcs.meminfo = String::from("procfs");
+
+        srcs
+    }
+
+    fn notices(&self) -> Vec<String>
+    {
+        self.notices.clone()
+    }
+}
+
+impl DrmDriverVirtioGpu
+{
+    pub fn new(_qmd: &DrmDeviceInfo,
+        _opts: &DrmDriverOptions) -> QmResult<Arc<Mutex<dyn DrmDriver>>>
+    {
+        let virtio = DrmDriverVirtioGpu {
+            // no mem info in sysfs, use guest total memory
+            smem_total: drm_drivers::system_mem_info().0,
+            notices: vec![String::from(
+                "virtio_gpu doesn't report freqs or power, and only \
+                reports engines usage if the host driver exposes it")],
+        };
+
+        Ok(Arc::new(Mutex::new(virtio)))
+    }
+}
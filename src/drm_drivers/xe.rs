@@ -6,26 +6,32 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
 use std::os::fd::{RawFd, AsRawFd};
-use std::cell::RefCell;
-use std::rc::Rc;
 use std::alloc;
 use std::mem;
 use std::io;
+use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
-use log::warn;
+use log::{debug, warn};
 use libc;
 
+use crate::perf_event::{
+    perf_event_attr, PERF_SAMPLE_IDENTIFIER, PERF_FORMAT_GROUP, PerfEvent
+};
 use crate::drm_drivers::{
-    DrmDriver, helpers::{drm_iowr, __IncompleteArrayField},
-    intel_power::{GpuPowerIntel, IGpuPowerIntel, DGpuPowerIntel},
+    self, DrmDriver, DrmDriverOptions, helpers::{drm_iowr, __IncompleteArrayField},
+    intel_power::{GpuPowerIntel, IGpuPowerIntel, DGpuPowerIntel, GtIdleIntel,
+        ThrottleReasonsIntel},
+    intel_pmu::ExtraPmuIntel, intel_fw,
 };
 use crate::drm_devices::{
     DrmDeviceType, DrmDeviceFreqLimits, DrmDeviceFreqs,
-    DrmDeviceThrottleReasons, DrmDevicePower, DrmDeviceMemInfo, DrmDeviceInfo
+    DrmDevicePower, DrmDeviceMemInfo, DrmDeviceSources,
+    DrmDeviceInfo
 };
 use crate::drm_fdinfo::DrmMemRegion;
 use crate::drm_clients::DrmClientMemInfo;
+use crate::qm_error::{QmError, QmResult};
 
 
 // rust-bindgen 0.69.4 on Linux kernel v6.12 uapi xe_drm.h + changes
@@ -86,15 +92,138 @@ const DRM_XE_DEVICE_QUERY: u64 = 0x00;
 const DRM_IOCTL_XE_DEVICE_QUERY: u64 = drm_iowr!(DRM_XE_DEVICE_QUERY,
     mem::size_of::<drm_xe_device_query>());
 
+// GT frequencies from xe's PMU, which accumulates the instantaneous
+// frequency (in MHz) on every read, so deltas are the current values
+#[derive(Debug)]
+struct XeFreqsPmu
+{
+    pf_evt: PerfEvent,
+    nr_gts: usize,
+    last_vals: Vec<u64>,    // act & req per GT
+    nr_updates: u64,
+}
+
+impl XeFreqsPmu
+{
+    // returns (act, req) frequencies per GT after 2+ reads
+    fn read(&mut self) -> Result<Vec<(u64, u64)>>
+    {
+        let vals = self.pf_evt.read(1 + 2 * self.nr_gts)?;
+        self.nr_updates += 1;
+
+        let mut res = Vec::new();
+        for gt in 0..self.nr_gts {
+            let act = vals[1 + 2 * gt];
+            let req = vals[2 + 2 * gt];
+            res.push((act.saturating_sub(self.last_vals[2 * gt]),
+                req.saturating_sub(self.last_vals[2 * gt + 1])));
+            self.last_vals[2 * gt] = act;
+            self.last_vals[2 * gt + 1] = req;
+        }
+
+        if self.nr_updates < 2 {
+            return Ok(Vec::new());
+        }
+
+        Ok(res)
+    }
+
+    fn event_config(pmu_dir: &Path, name: &str) -> Result<u64>
+    {
+        let cfg_str = fs::read_to_string(pmu_dir.join("events").join(name))?;
+        for kv in cfg_str.trim().split(',') {
+            if let Some(val) = kv.trim().strip_prefix("event=") {
+                return Ok(u64::from_str_radix(
+                    val.trim_start_matches("0x"), 16)?);
+            }
+        }
+
+        Err(QmError::NoPmu(
+            format!("No event id in {:?} PMU event config", name)).into())
+    }
+
+    fn format_shift(pmu_dir: &Path, name: &str) -> Result<u64>
+    {
+        // e.g. "config:60-63"
+        let fmt_str = fs::read_to_string(pmu_dir.join("format").join(name))?;
+        let bits = fmt_str.trim().strip_prefix("config:");
+        if let Some(bits) = bits {
+            let lo = bits.split('-').next().unwrap_or("");
+            return Ok(lo.parse()?);
+        }
+
+        Err(QmError::NoPmu(format!("Unsupported PMU format {:?} for {:?}",
+            fmt_str.trim(), name)).into())
+    }
+
+    fn from(pci_dev: &str, gt_ids: &Vec<u64>) -> Result<Option<XeFreqsPmu>>
+    {
+        if !PerfEvent::is_capable() {
+            debug!("INF: no perf event support, no xe PMU freqs.");
+            return Ok(None);
+        }
+
+        let pmu_dir = Path::new("/sys/bus/event_source/devices")
+            .join(format!("xe_{}", pci_dev.replace(':', "_")));
+        if !pmu_dir.join("events/gt-actual-frequency").exists() ||
+            !pmu_dir.join("events/gt-requested-frequency").exists() {
+            debug!("INF: no xe PMU frequency events in {:?}.", pmu_dir);
+            return Ok(None);
+        }
+        if gt_ids.is_empty() {
+            return Ok(None);
+        }
+
+        let type_: u32 = fs::read_to_string(
+            pmu_dir.join("type"))?.trim().parse()?;
+        let cpu = PerfEvent::pmu_cpu(&pmu_dir)?;
+
+        let act_cfg = XeFreqsPmu::event_config(&pmu_dir, "gt-actual-frequency")?;
+        let req_cfg = XeFreqsPmu::event_config(&pmu_dir,
+            "gt-requested-frequency")?;
+        let gt_shift = XeFreqsPmu::format_shift(&pmu_dir, "gt")?;
+
+        let mut pf_attr = perf_event_attr::new();
+        pf_attr.type_ = type_;
+        pf_attr.size = mem::size_of::<perf_event_attr>() as u32;
+        pf_attr.sample_type = PERF_SAMPLE_IDENTIFIER;
+        pf_attr.read_format = PERF_FORMAT_GROUP;
+
+        let mut pf_evt: Option<PerfEvent> = None;
+        for gt in gt_ids.iter() {
+            for cfg in [act_cfg, req_cfg] {
+                pf_attr.config = (gt << gt_shift) | cfg;
+                if let Some(evt) = &mut pf_evt {
+                    evt.group_open(&pf_attr, -1, cpu, 0)?;
+                } else {
+                    pf_evt = Some(PerfEvent::open(&pf_attr, -1, cpu, 0)?);
+                }
+            }
+        }
+
+        Ok(Some(XeFreqsPmu {
+            pf_evt: pf_evt.unwrap(),
+            nr_gts: gt_ids.len(),
+            last_vals: vec![0; 2 * gt_ids.len()],
+            nr_updates: 0,
+        }))
+    }
+}
+
 #[derive(Debug)]
 pub struct DrmDriverXe
 {
     _dn_file: File,
     dn_fd: RawFd,
-    base_gts_dir: PathBuf,
+    gt_dirs: Vec<PathBuf>,
     dev_type: Option<DrmDeviceType>,
     freq_limits: Option<Vec<DrmDeviceFreqLimits>>,
     power: Option<Box<dyn GpuPowerIntel>>,
+    gt_idle: GtIdleIntel,
+    throttles: HashMap<PathBuf, ThrottleReasonsIntel>,
+    notices: Vec<String>,
+    firmware: Vec<(String, String)>,
+    freqs_pmu: Option<XeFreqsPmu>,
 }
 
 impl DrmDriver for DrmDriverXe
@@ -104,7 +233,26 @@ impl DrmDriver for DrmDriverXe
         "xe"
     }
 
-    fn dev_type(&mut self) -> Result<DrmDeviceType>
+    // GT power profile (newer kernels), selected one is in brackets
+    fn perf_profile(&mut self) -> QmResult<String>
+    {
+        for gt_dir in self.gt_dirs.iter() {
+            let pp_path = gt_dir.join("freq0/power_profile");
+            if !pp_path.is_file() {
+                continue;
+            }
+            let pps = fs::read_to_string(pp_path)?;
+            let sel = pps.split_whitespace()
+                .find(|pp| pp.starts_with('['))
+                .map(|pp| pp.trim_matches(|c| c == '[' || c == ']'));
+
+            return Ok(sel.unwrap_or(pps.trim()).to_string());
+        }
+
+        Ok(String::new())
+    }
+
+    fn dev_type(&mut self) -> QmResult<DrmDeviceType>
     {
         if let Some(dt) = &self.dev_type {
             return Ok(dt.clone());
@@ -121,7 +269,7 @@ impl DrmDriver for DrmDriverXe
         let res = unsafe {
             libc::ioctl(self.dn_fd, DRM_IOCTL_XE_DEVICE_QUERY, &mut dq) };
         if res < 0 {
-            return Err(io::Error::last_os_error().into());
+            return Err(QmError::IoctlFailed(io::Error::last_os_error()));
         }
 
         if dq.size as usize == 0 {
@@ -130,7 +278,7 @@ impl DrmDriver for DrmDriverXe
         }
 
         let layout = alloc::Layout::from_size_align(dq.size as usize,
-            mem::align_of::<u64>())?;
+            mem::align_of::<u64>()).map_err(|err| QmError::Other(err.into()))?;
         let qcfg = unsafe {
             let ptr = alloc::alloc(layout) as *mut drm_xe_query_config;
             if ptr.is_null() {
@@ -145,7 +293,7 @@ impl DrmDriver for DrmDriverXe
             libc::ioctl(self.dn_fd, DRM_IOCTL_XE_DEVICE_QUERY, &mut dq) };
         if res < 0 {
             unsafe { alloc::dealloc(qcfg as *mut u8, layout); }
-            return Err(io::Error::last_os_error().into());
+            return Err(QmError::IoctlFailed(io::Error::last_os_error()));
         }
         let cfg = unsafe { (*qcfg).info.as_slice((*qcfg).num_params as usize) };
         let flags = cfg[DRM_XE_QUERY_CONFIG_FLAGS as usize];
@@ -162,7 +310,7 @@ impl DrmDriver for DrmDriverXe
         Ok(qmdt)
     }
 
-    fn mem_info(&mut self) -> Result<DrmDeviceMemInfo>
+    fn mem_info(&mut self) -> QmResult<DrmDeviceMemInfo>
     {
         let mut dq = drm_xe_device_query {
             extensions: 0,
@@ -175,7 +323,7 @@ impl DrmDriver for DrmDriverXe
         let res = unsafe {
             libc::ioctl(self.dn_fd, DRM_IOCTL_XE_DEVICE_QUERY, &mut dq) };
         if res < 0 {
-            return Err(io::Error::last_os_error().into());
+            return Err(QmError::IoctlFailed(io::Error::last_os_error()));
         }
 
         if dq.size as usize == 0 {
@@ -184,7 +332,7 @@ impl DrmDriver for DrmDriverXe
         }
 
         let layout = alloc::Layout::from_size_align(dq.size as usize,
-            mem::align_of::<u64>())?;
+            mem::align_of::<u64>()).map_err(|err| QmError::Other(err.into()))?;
         let qmrg = unsafe {
             let ptr = alloc::alloc(layout) as *mut drm_xe_query_mem_regions;
             if ptr.is_null() {
@@ -199,7 +347,7 @@ impl DrmDriver for DrmDriverXe
             libc::ioctl(self.dn_fd, DRM_IOCTL_XE_DEVICE_QUERY, &mut dq) };
         if res < 0 {
             unsafe { alloc::dealloc(qmrg as *mut u8, layout); }
-            return Err(io::Error::last_os_error().into());
+            return Err(QmError::IoctlFailed(io::Error::last_os_error()));
         }
         let mrgs = unsafe {
             (*qmrg).mem_regions.as_slice((*qmrg).num_mem_regions as usize) };
@@ -228,17 +376,17 @@ impl DrmDriver for DrmDriverXe
         Ok(qmdmi)
     }
 
-    fn freq_limits(&mut self) -> Result<Vec<DrmDeviceFreqLimits>>
+    fn freq_limits(&mut self) -> QmResult<Vec<DrmDeviceFreqLimits>>
     {
         if let Some(fls) = &self.freq_limits {
             return Ok(fls.clone());
         }
 
         let mut fls = Vec::new();
-        for nr in 0.. {
-            let freqs_dir = self.base_gts_dir.join(format!("gt{}/freq0", nr));
+        for gt_dir in self.gt_dirs.iter() {
+            let freqs_dir = gt_dir.join("freq0");
             if !freqs_dir.is_dir() {
-                break;
+                continue;
             }
 
             let fpath = freqs_dir.join("rpn_freq");
@@ -254,7 +402,7 @@ impl DrmDriver for DrmDriverXe
             let rp0_val: u64 = fstr.trim_end().parse()?;
 
             fls.push(DrmDeviceFreqLimits {
-                name: format!("gt{}", nr),
+                name: gt_dir.file_name().unwrap().to_str().unwrap().to_string(),
                 minimum: rpn_val,
                 efficient: rpe_val,
                 maximum: rp0_val,
@@ -265,13 +413,21 @@ impl DrmDriver for DrmDriverXe
         Ok(fls)
     }
 
-    fn freqs(&mut self) -> Result<Vec<DrmDeviceFreqs>>
+    fn freqs(&mut self) -> QmResult<Vec<DrmDeviceFreqs>>
     {
+        let pmu_fqs = if let Some(fpmu) = &mut self.freqs_pmu {
+            fpmu.read()? } else { Vec::new() };
+        let res_paths: Vec<PathBuf> = self.gt_dirs.iter()
+            .filter(|gt_dir| gt_dir.join("freq0").is_dir())
+            .map(|gt_dir| gt_dir.join("gtidle/idle_residency_ms"))
+            .collect();
+        let idles = self.gt_idle.idle_residencies(&res_paths);
+
         let mut fqs = Vec::new();
-        for nr in 0.. {
-            let freqs_dir = self.base_gts_dir.join(format!("gt{}/freq0", nr));
+        for gt_dir in self.gt_dirs.iter() {
+            let freqs_dir = gt_dir.join("freq0");
             if !freqs_dir.is_dir() {
-                break;
+                continue;
             }
             let throttle_dir = freqs_dir.join("throttle");
 
@@ -279,56 +435,30 @@ impl DrmDriver for DrmDriverXe
             let fstr = fs::read_to_string(&fpath)?;
             let min_val: u64 = fstr.trim_end().parse()?;
 
-            let fpath = freqs_dir.join("cur_freq");
-            let fstr = fs::read_to_string(&fpath)?;
-            let cur_val: u64 = fstr.trim_end().parse()?;
+            let cur_val: u64;
+            let act_val: u64;
+            if fqs.len() < pmu_fqs.len() {
+                (act_val, cur_val) = pmu_fqs[fqs.len()];
+            } else {
+                let fpath = freqs_dir.join("cur_freq");
+                let fstr = fs::read_to_string(&fpath)?;
+                cur_val = fstr.trim_end().parse()?;
 
-            let fpath = freqs_dir.join("act_freq");
-            let fstr = fs::read_to_string(&fpath)?;
-            let act_val: u64 = fstr.trim_end().parse()?;
+                let fpath = freqs_dir.join("act_freq");
+                let fstr = fs::read_to_string(&fpath)?;
+                act_val = fstr.trim_end().parse()?;
+            }
 
             let fpath = freqs_dir.join("max_freq");
             let fstr = fs::read_to_string(&fpath)?;
             let max_val: u64 = fstr.trim_end().parse()?;
 
-            let fpath = throttle_dir.join("reason_pl1");
-            let pl1 = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = throttle_dir.join("reason_pl2");
-            let pl2 = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = throttle_dir.join("reason_pl4");
-            let pl4 = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = throttle_dir.join("reason_prochot");
-            let prochot = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = throttle_dir.join("reason_ratl");
-            let ratl = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = throttle_dir.join("reason_thermal");
-            let thermal = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = throttle_dir.join("reason_vr_tdc");
-            let vr_tdc = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = throttle_dir.join("reason_vr_thermalert");
-            let vr_thermalert = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let fpath = throttle_dir.join("status");
-            let status = fs::read_to_string(&fpath)?.trim() == "1";
-
-            let throttle = DrmDeviceThrottleReasons {
-                pl1,
-                pl2,
-                pl4,
-                prochot,
-                ratl,
-                thermal,
-                vr_tdc,
-                vr_thermalert,
-                status,
-            };
+            if !self.throttles.contains_key(&throttle_dir) {
+                self.throttles.insert(throttle_dir.clone(),
+                    ThrottleReasonsIntel::from(&throttle_dir,
+                        "reason_", "status")?);
+            }
+            let throttle = self.throttles[&throttle_dir].read()?;
 
             fqs.push(DrmDeviceFreqs {
                 min_freq: min_val,
@@ -336,23 +466,52 @@ impl DrmDriver for DrmDriverXe
                 act_freq: act_val,
                 max_freq: max_val,
                 throttle_reasons: throttle,
+                idle_res: idles.get(fqs.len()).copied().flatten(),
             });
         }
 
         Ok(fqs)
     }
 
-    fn power(&mut self) -> Result<DrmDevicePower>
+    fn power(&mut self) -> QmResult<DrmDevicePower>
     {
         if self.power.is_none() {
             return Ok(DrmDevicePower::new());
         }
 
-        self.power.as_mut().unwrap().power_usage()
+        Ok(self.power.as_mut().unwrap().power_usage()?)
+    }
+
+    fn stats_sources(&self) -> DrmDeviceSources
+    {
+        // act/cur freqs from the PMU, if asked, the others from sysfs
+        let freqs = if self.freqs_pmu.is_some() { "pmu" } else { "sysfs" };
+
+        DrmDeviceSources {
+            freqs: String::from(freqs),
+            power: self.power.as_ref()
+                .map_or(String::new(), |pwr| pwr.source().to_string()),
+            meminfo: String::from("ioctl"),
+        }
+    }
+
+    fn notices(&self) -> Vec<String>
+    {
+        self.notices.clone()
+    }
+
+    fn firmware(&self) -> Vec<(String, String)>
+    {
+        self.firmware.clone()
+    }
+
+    fn engines_order(&self) -> &[&str]
+    {
+        &["rcs", "bcs", "vcs", "vecs", "ccs"]
     }
 
     fn client_mem_info(&mut self,
-        mem_regs: &HashMap<String, DrmMemRegion>) -> Result<DrmClientMemInfo>
+        mem_regs: &HashMap<String, DrmMemRegion>) -> QmResult<DrmClientMemInfo>
     {
         let mut cmi = DrmClientMemInfo::new();
 
@@ -383,9 +542,47 @@ impl DrmDriver for DrmDriverXe
 
 impl DrmDriverXe
 {
-    pub fn new(qmd: &DrmDeviceInfo) -> Result<Rc<RefCell<dyn DrmDriver>>>
+    fn find_gt_dirs(dev_path: &Path) -> Result<Vec<PathBuf>>
+    {
+        // GT numbers are unique across all tiles (e.g. tile1 has gt2 & gt3)
+        let mut gts = Vec::new();
+        for tnr in 0.. {
+            let tile_dir = dev_path.join(format!("tile{}", tnr));
+            if !tile_dir.is_dir() {
+                break;
+            }
+
+            for et in tile_dir.read_dir()? {
+                let et = et?;
+                let gt_path = et.path();
+                let gt_nr = gt_path.file_name().unwrap().to_str().unwrap()
+                    .strip_prefix("gt").and_then(|n| n.parse::<u32>().ok());
+
+                if let Some(nr) = gt_nr {
+                    if gt_path.is_dir() {
+                        gts.push((nr, gt_path));
+                    }
+                }
+            }
+        }
+        gts.sort_by_key(|(nr, _)| *nr);
+
+        Ok(gts.into_iter().map(|(_, gt_path)| gt_path).collect())
+    }
+
+    fn gt_ids(&self) -> Vec<u64>
+    {
+        self.gt_dirs.iter()
+            .filter(|gt_dir| gt_dir.join("freq0").is_dir())
+            .filter_map(|gt_dir| gt_dir.file_name()?.to_str()?
+                .strip_prefix("gt")?.parse().ok())
+            .collect()
+    }
+
+    pub fn new(qmd: &DrmDeviceInfo,
+        opts: &DrmDriverOptions) -> QmResult<Arc<Mutex<dyn DrmDriver>>>
     {
-        let file = File::open(qmd.drm_minors[0].devnode.clone())?;
+        let file = drm_drivers::open_devnode(&qmd.drm_minors[0].devnode)?;
         let fd = file.as_raw_fd();
 
         let mut cpath = String::from("/sys/class/drm/");
@@ -394,14 +591,18 @@ impl DrmDriverXe
         cpath.push_str(card);
         let dev_path = Path::new(&cpath).join("device");
 
-        // TODO: handle more than one tile
         let mut xe = DrmDriverXe {
             _dn_file: file,
             dn_fd: fd,
-            base_gts_dir: dev_path.join("tile0"),
+            gt_dirs: DrmDriverXe::find_gt_dirs(&dev_path)?,
             dev_type: None,
             freq_limits: None,
             power: None,
+            gt_idle: GtIdleIntel::new(),
+            throttles: HashMap::new(),
+            notices: Vec::new(),
+            firmware: intel_fw::uc_versions(qmd.card_minor()),
+            freqs_pmu: None,
         };
 
         let dtype = xe.dev_type()?;
@@ -413,7 +614,31 @@ impl DrmDriverXe
         } else {
             None
         };
+        if dtype.is_integrated() && xe.power.is_none() {
+            xe.notices.push(String::from(IGpuPowerIntel::NO_POWER_NOTICE));
+        }
+
+        xe.gt_idle = GtIdleIntel::from(&ExtraPmuIntel::pmu_dir(
+            "xe", &qmd.pci_dev, dtype.is_integrated()), &xe.gt_ids());
+
+        if let Some(fopt) = opts.get("xe", "freqs") {
+            match fopt.as_str() {
+                "pmu" => {
+                    xe.freqs_pmu = XeFreqsPmu::from(&qmd.pci_dev, &xe.gt_ids())?;
+                    if xe.freqs_pmu.is_none() {
+                        warn!("No xe PMU frequency events, using sysfs.");
+                        if let Some(rsn) = PerfEvent::not_capable_reason() {
+                            xe.notices.push(
+                                format!("PMU freqs from sysfs: {}", rsn));
+                        }
+                    }
+                },
+                "sysfs" => {},
+                _ => return Err(QmError::ParseError(format!(
+                    "Invalid xe freqs option {:?} (pmu or sysfs)", fopt))),
+            }
+        }
 
-        Ok(Rc::new(RefCell::new(xe)))
+        Ok(Arc::new(Mutex::new(xe)))
     }
 }
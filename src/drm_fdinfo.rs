@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::mem;
 use std::path::{Path, PathBuf};
 use std::os::linux::fs::MetadataExt;
 use std::fs;
@@ -19,6 +20,7 @@ pub struct DrmEngine
     pub total_cycles: u64,
 }
 
+#[derive(Debug, Clone, Copy)]
 enum EngKvType
 {
     KvTime,
@@ -63,6 +65,7 @@ pub struct DrmMemRegion
     pub active: u64,
 }
 
+#[derive(Debug, Clone, Copy)]
 enum MemRegKvType
 {
     KvTotal,
@@ -98,12 +101,71 @@ impl DrmMemRegion
     }
 }
 
+// engine and memory region names are borrowed from the fdinfo line
+#[derive(Debug)]
+enum DrmFdinfoKey<'a>
+{
+    PciDev,
+    ClientId,
+    NrContexts,
+    Engine(EngKvType, &'a str),
+    MemRegion(MemRegKvType, &'a str),
+    Other,
+}
+
+impl DrmFdinfoKey<'_>
+{
+    fn from(k: &str) -> DrmFdinfoKey<'_>
+    {
+        let eng_kvs = [
+            ("drm-engine-capacity-", EngKvType::KvCapacity),
+            ("drm-engine-", EngKvType::KvTime),
+            ("drm-cycles-", EngKvType::KvCycles),
+            ("drm-total-cycles-", EngKvType::KvTotCycles),
+        ];
+        let mrg_kvs = [
+            ("drm-total-", MemRegKvType::KvTotal),
+            ("drm-shared-", MemRegKvType::KvShared),
+            ("drm-resident-", MemRegKvType::KvResident),
+            ("drm-purgeable-", MemRegKvType::KvPurgeable),
+            ("drm-active-", MemRegKvType::KvActive),
+        ];
+
+        if k.starts_with("drm-pdev") {
+            return DrmFdinfoKey::PciDev;
+        }
+        if k.starts_with("drm-client-id") {
+            return DrmFdinfoKey::ClientId;
+        }
+        if k == "drm-contexts" {
+            return DrmFdinfoKey::NrContexts;
+        }
+        for (pfx, kvt) in eng_kvs {
+            if let Some(en) = k.strip_prefix(pfx) {
+                return DrmFdinfoKey::Engine(kvt, en);
+            }
+        }
+        for (pfx, kvt) in mrg_kvs {
+            if let Some(mrn) = k.strip_prefix(pfx) {
+                return DrmFdinfoKey::MemRegion(kvt, mrn);
+            }
+        }
+
+        DrmFdinfoKey::Other
+    }
+}
+
+// last DRM fdinfos of a process by client id, whose engines and memory
+// regions (names and maps) are reused when parsing the next ones
+pub type DrmFdinfoCache = HashMap<u32, DrmFdinfo>;
+
 #[derive(Debug)]
 pub struct DrmFdinfo
 {
     pub pci_dev: String,
     pub drm_minor: u32,
     pub client_id: u32,
+    pub nr_contexts: u32,   // 0 if not reported by the driver
     pub path: PathBuf,
     pub engines: HashMap<String, DrmEngine>,
     pub mem_regions: HashMap<String, DrmMemRegion>,
@@ -117,6 +179,7 @@ impl Default for DrmFdinfo
             pci_dev: String::new(),
             drm_minor: 0,
             client_id: 0,
+            nr_contexts: 0,
             path: PathBuf::new(),
             engines: HashMap::new(),
             mem_regions: HashMap::new(),
@@ -148,28 +211,49 @@ impl DrmFdinfo
         Ok(false)
     }
 
-    fn update_engine(&mut self, kv_type: EngKvType, eng_name: &str, val: &str) -> Result<()>
+    fn update_engine(&mut self, old_engs: &mut HashMap<String, DrmEngine>,
+        kv_type: EngKvType, eng_name: &str, val: &str) -> Result<()>
     {
         let eng: &mut DrmEngine;
 
         if !self.engines.contains_key(eng_name) {
-            self.engines.insert(eng_name.to_string(), DrmEngine::new(eng_name));
+            // same engine in the last parse, only its values are new
+            let (nm, eng) = match old_engs.remove_entry(eng_name) {
+                Some((nm, mut eng)) => {
+                    eng.capacity = 1;
+                    eng.time = 0;
+                    eng.cycles = 0;
+                    eng.total_cycles = 0;
+                    (nm, eng)
+                },
+                None => (eng_name.to_string(), DrmEngine::new(eng_name)),
+            };
+            self.engines.insert(nm, eng);
         }
         eng = self.engines.get_mut(eng_name).unwrap();
 
+        // drivers differ on units after the value (e.g. amdgpu and xe
+        // report "ns" for time), so only the first token is parsed
+        let nr = match val.split_whitespace().next() {
+            Some(nr) => nr,
+            None => {
+                debug!("INF: empty value for engine {:?}, ignoring it", eng_name);
+                return Ok(());
+            },
+        };
+
         match kv_type {
             EngKvType::KvCapacity => {
-                eng.capacity = val.parse()?;
+                eng.capacity = nr.parse()?;
             },
             EngKvType::KvTime => {
-                let dt: Vec<&str> = val.split_whitespace().collect();
-                eng.time = dt[0].parse()?;  // ignore dt[1] == "ns"
+                eng.time = nr.parse()?;
             },
             EngKvType::KvCycles => {
-                eng.cycles = val.parse()?;
+                eng.cycles = nr.parse()?;
             },
             EngKvType::KvTotCycles => {
-                eng.total_cycles = val.parse()?;
+                eng.total_cycles = nr.parse()?;
             },
         }
 
@@ -186,21 +270,24 @@ impl DrmFdinfo
         }
     }
 
-    fn update_mem_region(&mut self, kv_type: MemRegKvType, mr_name: &str, val: &str) -> Result<()>
+    fn update_mem_region(&mut self, old_mrgs: &mut HashMap<String, DrmMemRegion>,
+        kv_type: MemRegKvType, mr_name: &str, val: &str) -> Result<()>
     {
         let mrg: &mut DrmMemRegion;
 
         if !self.mem_regions.contains_key(mr_name) {
-            self.mem_regions.insert(mr_name.to_string(), DrmMemRegion::new(mr_name));
+            let (nm, mrg) = match old_mrgs.remove_entry(mr_name) {
+                Some((nm, mrg)) => (nm, DrmMemRegion { name: mrg.name,
+                    ..Default::default() }),
+                None => (mr_name.to_string(), DrmMemRegion::new(mr_name)),
+            };
+            self.mem_regions.insert(nm, mrg);
         }
         mrg = self.mem_regions.get_mut(mr_name).unwrap();
 
-        let dt: Vec<&str> = val.split_whitespace().collect();
-        let nr: u64 = dt[0].parse()?;
-        let mut mul: u64 = 1;
-        if dt.len() == 2  {
-            mul = DrmFdinfo::mul_from_unit(dt[1]);
-        }
+        let mut dt = val.split_whitespace();
+        let nr: u64 = dt.next().unwrap_or_default().parse()?;
+        let mul = dt.next().map_or(1, DrmFdinfo::mul_from_unit);
 
         match kv_type {
             MemRegKvType::KvTotal => {
@@ -223,15 +310,28 @@ impl DrmFdinfo
         Ok(())
     }
 
-    pub fn from(fdinfo: &PathBuf, d_minor: u32) -> Result<DrmFdinfo>
+    // client id without parsing the rest, to find its cached fdinfo
+    fn client_id_of(all_str: &str) -> Option<u32>
     {
-        let all_str = fs::read_to_string(fdinfo)?;
+        all_str.lines()
+            .find_map(|ln| ln.trim().strip_prefix("drm-client-id:"))
+            .and_then(|v| v.trim().parse().ok())
+    }
 
-        let mut info = DrmFdinfo {
-            drm_minor: d_minor,
-            path: PathBuf::from(fdinfo),
-            ..Default::default()
-        };
+    // parses into this fdinfo, reusing its engines and memory regions that
+    // are still reported, dropping the ones that aren't
+    fn parse(&mut self, all_str: &str, fdinfo: &PathBuf, d_minor: u32) -> Result<()>
+    {
+        let (nr_engs, nr_mrgs) = (self.engines.len(), self.mem_regions.len());
+        let mut old_engs = mem::replace(&mut self.engines,
+            HashMap::with_capacity(nr_engs));
+        let mut old_mrgs = mem::replace(&mut self.mem_regions,
+            HashMap::with_capacity(nr_mrgs));
+        self.pci_dev.clear();
+        self.drm_minor = d_minor;
+        self.client_id = 0;
+        self.nr_contexts = 0;
+        self.path.clone_from(fdinfo);
 
         for line in all_str.lines() {
             let tl = line.trim();
@@ -241,49 +341,268 @@ impl DrmFdinfo
                 continue;
             }
 
-            let kv: Vec<_> = tl.splitn(2, ':').map(|it| it.trim()).collect();
-            if kv.len() < 2 {
+            let kv = tl.split_once(':');
+            if kv.is_none() {
                 debug!("INF: discarding line without key:value pair [{:?}] from fdinfo {:?}",
                     tl, fdinfo);
                 continue;
             }
-            let k = kv[0];
-            let v = kv[1];
-
-            if k.starts_with("drm-pdev") {
-                info.pci_dev.push_str(v);
-            } else if k.starts_with("drm-client-id") {
-                info.client_id = v.parse()?;
-            } else if k.starts_with("drm-engine-capacity-") {
-                let en = &k["drm-engine-capacity-".len()..];
-                info.update_engine(EngKvType::KvCapacity, en, v)?;
-            } else if k.starts_with("drm-engine-") {
-                let en = &k["drm-engine-".len()..];
-                info.update_engine(EngKvType::KvTime, en, v)?;
-            } else if k.starts_with("drm-cycles-") {
-                let en = &k["drm-cycles-".len()..];
-                info.update_engine(EngKvType::KvCycles, en, v)?;
-            } else if k.starts_with("drm-total-cycles-") {
-                let en = &k["drm-total-cycles-".len()..];
-                info.update_engine(EngKvType::KvTotCycles, en, v)?;
-            } else if k.starts_with("drm-total-") {
-                let mrn = &k["drm-total-".len()..];
-                info.update_mem_region(MemRegKvType::KvTotal, mrn, v)?;
-            } else if k.starts_with("drm-shared-") {
-                let mrn = &k["drm-shared-".len()..];
-                info.update_mem_region(MemRegKvType::KvShared, mrn, v)?;
-            } else if k.starts_with("drm-resident-") {
-                let mrn = &k["drm-resident-".len()..];
-                info.update_mem_region(MemRegKvType::KvResident, mrn, v)?;
-            } else if k.starts_with("drm-purgeable-") {
-                let mrn = &k["drm-purgeable-".len()..];
-                info.update_mem_region(MemRegKvType::KvPurgeable, mrn, v)?;
-            } else if k.starts_with("drm-active-") {
-                let mrn = &k["drm-active-".len()..];
-                info.update_mem_region(MemRegKvType::KvActive, mrn, v)?;
+            let (k, v) = kv.unwrap();
+            let v = v.trim();
+
+            match DrmFdinfoKey::from(k.trim()) {
+                DrmFdinfoKey::PciDev => {
+                    self.pci_dev.push_str(v);
+                },
+                DrmFdinfoKey::ClientId => {
+                    self.client_id = v.parse()?;
+                },
+                DrmFdinfoKey::NrContexts => {
+                    // not a standard DRM key, so don't fail on bad values
+                    if let Ok(nr) = v.parse() {
+                        self.nr_contexts = nr;
+                    } else {
+                        debug!("ERR: wrong drm-contexts value {:?} in {:?}",
+                            v, fdinfo);
+                    }
+                },
+                DrmFdinfoKey::Engine(kvt, en) => {
+                    self.update_engine(&mut old_engs, kvt, en, v)?;
+                },
+                DrmFdinfoKey::MemRegion(kvt, mrn) => {
+                    self.update_mem_region(&mut old_mrgs, kvt, mrn, v)?;
+                },
+                DrmFdinfoKey::Other => {},
             }
         }
 
+        Ok(())
+    }
+
+    // parses reusing the cached fdinfo of the same client (if any)
+    pub fn from_cached(fdinfo: &PathBuf, d_minor: u32,
+        cache: &mut DrmFdinfoCache) -> Result<DrmFdinfo>
+    {
+        let all_str = fs::read_to_string(fdinfo)?;
+        let mut info = DrmFdinfo::client_id_of(&all_str)
+            .and_then(|id| cache.remove(&id))
+            .unwrap_or_default();
+        info.parse(&all_str, fdinfo, d_minor)?;
+
         Ok(info)
     }
+
+    pub fn from(fdinfo: &PathBuf, d_minor: u32) -> Result<DrmFdinfo>
+    {
+        DrmFdinfo::from_cached(fdinfo, d_minor, &mut DrmFdinfoCache::new())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::process;
+
+    use super::*;
+
+    // amdgpu fdinfo as of Linux 6.12 (Vulkan app), with the legacy
+    // drm-memory-* keys still reported next to the standard ones
+    const AMDGPU_FDINFO: &str = "\
+pos:\t0
+flags:\t02100002
+mnt_id:\t24
+ino:\t1089
+drm-driver:\tamdgpu
+drm-client-id:\t1468
+drm-pdev:\t0000:03:00.0
+pasid:\t32773
+drm-total-cpu:\t0
+drm-shared-cpu:\t0
+drm-active-cpu:\t0
+drm-resident-cpu:\t0
+drm-purgeable-cpu:\t0
+drm-total-gtt:\t6760 KiB
+drm-shared-gtt:\t0
+drm-active-gtt:\t0
+drm-resident-gtt:\t6760 KiB
+drm-purgeable-gtt:\t0
+drm-total-vram:\t351208 KiB
+drm-shared-vram:\t2048 KiB
+drm-active-vram:\t12 MiB
+drm-resident-vram:\t351208 KiB
+drm-purgeable-vram:\t0
+drm-memory-vram:\t351208 KiB
+drm-memory-gtt:\t6760 KiB
+drm-memory-cpu:\t0 KiB
+amd-evicted-vram:\t0 KiB
+amd-requested-vram:\t351208 KiB
+amd-requested-gtt:\t6760 KiB
+drm-engine-gfx:\t78258147 ns
+drm-engine-compute:\t0 ns
+drm-engine-dma:\t1392124 ns
+drm-engine-dec:\t0 ns
+drm-engine-enc:\t0 ns
+";
+
+    // xe fdinfo as of Linux 6.12 (discrete GPU), with engines usage as GPU
+    // cycles per engine class instead of busy time
+    const XE_FDINFO: &str = "\
+pos:\t0
+flags:\t0100002
+mnt_id:\t26
+ino:\t1211
+drm-driver:\txe
+drm-client-id:\t57
+drm-pdev:\t0000:03:00.0
+drm-total-system:\t8 MiB
+drm-shared-system:\t0
+drm-active-system:\t0
+drm-resident-system:\t8 MiB
+drm-purgeable-system:\t0
+drm-total-gtt:\t256 KiB
+drm-shared-gtt:\t0
+drm-active-gtt:\t0
+drm-resident-gtt:\t256 KiB
+drm-total-vram0:\t1060 MiB
+drm-shared-vram0:\t4 MiB
+drm-active-vram0:\t0
+drm-resident-vram0:\t1060 MiB
+drm-purgeable-vram0:\t0
+drm-total-stolen:\t0
+drm-shared-stolen:\t0
+drm-active-stolen:\t0
+drm-resident-stolen:\t0
+drm-purgeable-stolen:\t0
+drm-cycles-rcs:\t28257900
+drm-total-cycles-rcs:\t7655183225
+drm-cycles-bcs:\t0
+drm-total-cycles-bcs:\t7655183225
+drm-cycles-vcs:\t1093544
+drm-total-cycles-vcs:\t7655183225
+drm-engine-capacity-vcs:\t2
+drm-cycles-vecs:\t0
+drm-total-cycles-vecs:\t7655183225
+drm-engine-capacity-vecs:\t2
+drm-cycles-ccs:\t0
+drm-total-cycles-ccs:\t7655183225
+drm-engine-capacity-ccs:\t4
+";
+
+    // fdinfo files are only read from a path, so parse a temporary copy
+    fn parse_cached(name: &str, sample: &str,
+        cache: &mut DrmFdinfoCache) -> DrmFdinfo
+    {
+        let path = std::env::temp_dir()
+            .join(format!("qmassa-{}-fdinfo-{}", name, process::id()));
+        fs::write(&path, sample).unwrap();
+        let res = DrmFdinfo::from_cached(&path, 128, cache);
+        fs::remove_file(&path).unwrap();
+
+        res.unwrap()
+    }
+
+    fn parse_sample(name: &str, sample: &str) -> DrmFdinfo
+    {
+        parse_cached(name, sample, &mut DrmFdinfoCache::new())
+    }
+
+    #[test]
+    fn amdgpu_fdinfo()
+    {
+        let info = parse_sample("amdgpu", AMDGPU_FDINFO);
+        assert_eq!(info.pci_dev, "0000:03:00.0");
+        assert_eq!(info.client_id, 1468);
+        assert_eq!(info.drm_minor, 128);
+
+        // busy times in ns, with the unit after the value
+        let mut engs: Vec<(&str, u64)> = info.engines.values()
+            .map(|eng| (eng.name.as_str(), eng.time))
+            .collect();
+        engs.sort();
+        assert_eq!(engs, vec![
+            ("compute", 0), ("dec", 0), ("dma", 1392124),
+            ("enc", 0), ("gfx", 78258147),
+        ]);
+        assert!(info.engines.values().all(|eng| eng.capacity == 1));
+
+        let vram = &info.mem_regions["vram"];
+        assert_eq!(vram.total, 351208 * 1024);
+        assert_eq!(vram.shared, 2048 * 1024);
+        assert_eq!(vram.resident, 351208 * 1024);
+        assert_eq!(vram.active, 12 * 1024 * 1024);
+        assert_eq!(vram.purgeable, 0);
+        let gtt = &info.mem_regions["gtt"];
+        assert_eq!(gtt.total, 6760 * 1024);
+        assert_eq!(gtt.resident, 6760 * 1024);
+        assert_eq!(gtt.shared, 0);
+
+        // legacy drm-memory-* keys don't add regions of their own
+        let mut mrgs: Vec<&String> = info.mem_regions.keys().collect();
+        mrgs.sort();
+        assert_eq!(mrgs, vec!["cpu", "gtt", "vram"]);
+    }
+
+    #[test]
+    fn xe_fdinfo()
+    {
+        let info = parse_sample("xe", XE_FDINFO);
+        assert_eq!(info.pci_dev, "0000:03:00.0");
+        assert_eq!(info.client_id, 57);
+
+        // drm-total-cycles-* are engine keys, not a "cycles-*" mem region
+        let mut engs: Vec<(&str, u64, u64, u64, u32)> = info.engines.values()
+            .map(|eng| (eng.name.as_str(), eng.time, eng.cycles,
+                eng.total_cycles, eng.capacity))
+            .collect();
+        engs.sort();
+        assert_eq!(engs, vec![
+            ("bcs", 0, 0, 7655183225, 1),
+            ("ccs", 0, 0, 7655183225, 4),
+            ("rcs", 0, 28257900, 7655183225, 1),
+            ("vcs", 0, 1093544, 7655183225, 2),
+            ("vecs", 0, 0, 7655183225, 2),
+        ]);
+
+        let vram = &info.mem_regions["vram0"];
+        assert_eq!(vram.total, 1060 * 1024 * 1024);
+        assert_eq!(vram.shared, 4 * 1024 * 1024);
+        assert_eq!(vram.resident, 1060 * 1024 * 1024);
+        let smem = &info.mem_regions["system"];
+        assert_eq!(smem.total, 8 * 1024 * 1024);
+        assert_eq!(smem.resident, 8 * 1024 * 1024);
+
+        let mut mrgs: Vec<&String> = info.mem_regions.keys().collect();
+        mrgs.sort();
+        assert_eq!(mrgs, vec!["gtt", "stolen", "system", "vram0"]);
+    }
+
+    #[test]
+    fn cached_fdinfo()
+    {
+        let mut cache = DrmFdinfoCache::new();
+        let info = parse_cached("cached", XE_FDINFO, &mut cache);
+        let rcs_name = info.engines["rcs"].name.as_ptr();
+        cache.insert(info.client_id, info);
+
+        // next sample: new values, the stolen region is gone
+        let sample = XE_FDINFO
+            .replace("drm-cycles-rcs:\t28257900", "drm-cycles-rcs:\t30000000")
+            .lines()
+            .filter(|ln| !ln.contains("-stolen:"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let info = parse_cached("cached", &sample, &mut cache);
+        assert!(cache.is_empty());
+
+        // same engine name reused, only the values are new
+        let rcs = &info.engines["rcs"];
+        assert_eq!(rcs.name.as_ptr(), rcs_name);
+        assert_eq!(rcs.cycles, 30000000);
+        assert_eq!(info.engines["ccs"].capacity, 4);
+        assert_eq!(info.mem_regions["vram0"].total, 1060 * 1024 * 1024);
+
+        let mut mrgs: Vec<&String> = info.mem_regions.keys().collect();
+        mrgs.sort();
+        assert_eq!(mrgs, vec!["gtt", "system", "vram0"]);
+    }
 }
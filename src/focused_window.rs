@@ -0,0 +1,198 @@
+use std::env;
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time;
+
+use log::debug;
+use serde_json::Value;
+
+
+// enough for e.g. browser -> zygote -> GPU process, or wine trees
+const MAX_TREE_DEPTH: usize = 8;
+
+// query tools are only looked up here, not in a caller-controlled PATH
+const TOOL_DIRS: [&str; 3] = ["/usr/bin", "/usr/local/bin", "/bin"];
+
+// focus doesn't change that often, don't spawn the tools every refresh
+const MIN_QUERY_MS: u128 = 2000;
+
+fn tool_path(name: &str) -> Option<PathBuf>
+{
+    let res = TOOL_DIRS.iter()
+        .map(|dir| Path::new(dir).join(name))
+        .find(|path| path.is_file());
+    if res.is_none() {
+        debug!("INF: no {} in {:?}.", name, TOOL_DIRS);
+    }
+
+    res
+}
+
+// (uid, gid) of the user that ran qmassa with sudo, if running as root
+fn sudo_ids() -> Option<(u32, u32)>
+{
+    if unsafe { libc::geteuid() } != 0 {
+        return None;
+    }
+    let uid = env::var("SUDO_UID").ok()?.parse().ok()?;
+    let gid = env::var("SUDO_GID").ok()?.parse().ok()?;
+
+    Some((uid, gid))
+}
+
+// stdout of a WM/compositor query tool, None if failed
+fn run_tool(cmd: &Path, args: &[&str]) -> Option<String>
+{
+    let mut cmd_b = Command::new(cmd);
+    cmd_b.args(args).stdin(Stdio::null()).stderr(Stdio::null());
+    // don't run the tools as root when started with sudo
+    if let Some((uid, gid)) = sudo_ids() {
+        cmd_b.uid(uid).gid(gid);
+    }
+
+    let res = cmd_b.output();
+    match res {
+        Ok(out) if out.status.success() => String::from_utf8(out.stdout).ok(),
+        Ok(out) => {
+            debug!("ERR: {:?} {:?} failed: {:?}", cmd, args, out.status);
+            None
+        },
+        Err(err) => {
+            debug!("INF: can't run {:?}: {:?}", cmd, err);
+            None
+        },
+    }
+}
+
+fn hyprland_pid(hyprctl: &Path) -> Option<u32>
+{
+    let out = run_tool(hyprctl, &["activewindow", "-j"])?;
+    let win: Value = serde_json::from_str(&out).ok()?;
+
+    win["pid"].as_u64().map(|pid| pid as u32)
+}
+
+fn sway_focused(node: &Value) -> Option<u32>
+{
+    if node["focused"].as_bool() == Some(true) {
+        if let Some(pid) = node["pid"].as_u64() {
+            return Some(pid as u32);
+        }
+    }
+    for key in ["nodes", "floating_nodes"] {
+        if let Some(nodes) = node[key].as_array() {
+            if let Some(pid) = nodes.iter().find_map(sway_focused) {
+                return Some(pid);
+            }
+        }
+    }
+
+    None
+}
+
+fn sway_pid(swaymsg: &Path) -> Option<u32>
+{
+    let out = run_tool(swaymsg, &["-t", "get_tree", "-r"])?;
+    let tree: Value = serde_json::from_str(&out).ok()?;
+
+    sway_focused(&tree)
+}
+
+// also covers Xwayland windows on compositors that set the active one
+fn x11_pid(xprop: &Path) -> Option<u32>
+{
+    // e.g. "_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3e00004"
+    let out = run_tool(xprop, &["-root", "_NET_ACTIVE_WINDOW"])?;
+    let win = out.rsplit('#').next()?.split(',').next()?.trim();
+    if win.is_empty() || win == "0x0" {
+        return None;
+    }
+
+    // e.g. "_NET_WM_PID(CARDINAL) = 12345"
+    let out = run_tool(xprop, &["-id", win, "_NET_WM_PID"])?;
+    out.rsplit('=').next()?.trim().parse().ok()
+}
+
+// best-effort pid of the process owning the focused window, from the
+// compositor (Hyprland, sway) or the X server, None if none is available
+#[derive(Debug)]
+pub struct FocusedWindow
+{
+    hyprctl: Option<PathBuf>,
+    swaymsg: Option<PathBuf>,
+    xprop: Option<PathBuf>,
+    pid: Option<u32>,
+    last_query: Option<time::Instant>,
+}
+
+impl FocusedWindow
+{
+    fn query(&self) -> Option<u32>
+    {
+        let res = self.hyprctl.as_deref().and_then(hyprland_pid)
+            .or_else(|| self.swaymsg.as_deref().and_then(sway_pid))
+            .or_else(|| self.xprop.as_deref().and_then(x11_pid));
+        if res.is_none() {
+            debug!("INF: no focused window pid from the compositor or X.");
+        }
+
+        res
+    }
+
+    // cached pid, queried again at most every MIN_QUERY_MS
+    pub fn pid(&mut self) -> Option<u32>
+    {
+        let is_stale = self.last_query
+            .map_or(true, |lq| lq.elapsed().as_millis() >= MIN_QUERY_MS);
+        if is_stale {
+            self.pid = self.query();
+            self.last_query = Some(time::Instant::now());
+        }
+
+        self.pid
+    }
+
+    // tools are only looked up for the sessions they can query
+    pub fn new() -> FocusedWindow
+    {
+        let find = |var: &str, name: &str| {
+            env::var_os(var).and_then(|_| tool_path(name))
+        };
+
+        FocusedWindow {
+            hyprctl: find("HYPRLAND_INSTANCE_SIGNATURE", "hyprctl"),
+            swaymsg: find("SWAYSOCK", "swaymsg"),
+            xprop: find("DISPLAY", "xprop"),
+            pid: None,
+            last_query: None,
+        }
+    }
+}
+
+fn parent_pid(pid: u32) -> Option<u32>
+{
+    // comm can have spaces, ppid is the 2nd field after it
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let (_, rest) = stat.rsplit_once(')')?;
+
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+// pid is the focused window's process or one of its descendants
+pub fn is_focused_tree(pid: u32, focused: u32) -> bool
+{
+    let mut cur = pid;
+    for _ in 0..MAX_TREE_DEPTH {
+        if cur == focused {
+            return true;
+        }
+        match parent_pid(cur) {
+            Some(ppid) if ppid > 1 => cur = ppid,
+            _ => return false,
+        }
+    }
+
+    false
+}
@@ -1,17 +1,18 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
 use std::fs;
 
 use anyhow::Result;
 use log::debug;
+use serde::Serialize;
 
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Sensor
 {
     pub sensor: String,
     pub label: String,
-    items: HashSet<String>,
+    items: BTreeSet<String>,
 }
 
 impl Sensor
@@ -38,16 +39,17 @@ impl Sensor
         Sensor {
             sensor: String::from(stype),
             label: String::new(),
-            items: HashSet::new(),
+            items: BTreeSet::new(),
         }
     }
 }
 
-#[derive(Debug)]
+// sorted sensors, also dumped as JSON by the hwmon command
+#[derive(Debug, Serialize)]
 pub struct Hwmon
 {
     pub base_dir: PathBuf,
-    sensors: HashMap<String, Sensor>,
+    sensors: BTreeMap<String, Sensor>,
 }
 
 impl Hwmon
@@ -61,6 +63,15 @@ impl Hwmon
         Ok(val)
     }
 
+    pub fn has_sensor_item(&self, sty: &str, item: &str) -> bool
+    {
+        if let Some(sensor) = self.sensors.get(sty) {
+            return sensor.has_item(item);
+        }
+
+        false
+    }
+
     pub fn sensors(&self, stype: &str) -> Vec<&Sensor>
     {
         let mut res = Vec::new();
@@ -119,7 +130,7 @@ impl Hwmon
         // ignoring content of "name" file for now
         let mut hwmon = Hwmon {
             base_dir,
-            sensors: HashMap::new(),
+            sensors: BTreeMap::new(),
         };
 
         hwmon.refresh()?;
@@ -0,0 +1,32 @@
+//! qmlib: qmassa's DRM devices and clients stats collection layer,
+//! without the TUI, e.g.:
+//!
+//! ```no_run
+//! use qmlib::drm_devices::DrmDevices;
+//! use qmlib::drm_drivers::DrmDriverOptions;
+//!
+//! let mut qmds = DrmDevices::find_devices(&DrmDriverOptions::new())?;
+//! qmds.set_clients_pid_tree("1", true)?;
+//! qmds.refresh()?;
+//! for di in qmds.infos() {
+//!     println!("{} {}", di.pci_dev, di.device);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! DrmDevices::refresh() updates all devices and clients dynamic info,
+//! so it should be called once per sampling interval.
+//!
+//! The DrmDevices, DrmDeviceInfo and DrmDriver methods (and
+//! drm_drivers::driver_from()) return qm_error::QmError errors, so callers
+//! can match on the kind of failure (e.g. permission denied when opening a
+//! device, or no PMU support) and get the underlying error from source().
+
+pub mod qm_error;
+pub mod perf_event;
+pub mod hwmon;
+pub mod drm_devices;
+pub mod drm_drivers;
+pub mod drm_fdinfo;
+pub mod proc_info;
+pub mod drm_clients;
@@ -1,41 +1,64 @@
 use std::cell::RefCell;
+use std::cmp::min;
 use std::env;
 use std::fs::File;
 use std::io::{self, IsTerminal};
 use std::path::Path;
 use std::process;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time;
 
 use anyhow::{bail, Context, Result};
 use env_logger;
+use log::warn;
 use clap::{ArgAction, Args, Parser, Subcommand};
 use libc;
 use serde::{Deserialize, Serialize};
 
-mod perf_event;
-mod hwmon;
-mod drm_devices;
-mod drm_drivers;
-mod drm_fdinfo;
-mod proc_info;
-mod drm_clients;
+use qmlib::{drm_clients, drm_devices, drm_drivers, perf_event};
+
+mod alerts;
+mod config;
+mod app_log;
 mod app_data;
 mod app;
 mod plotter;
-
-use drm_devices::DrmDevices;
+mod tracer;
+mod differ;
+mod stats_server;
+mod focused_window;
+
+use drm_devices::{DrmDevices, DrmEngineAggregation};
+use drm_drivers::DrmDriverOptions;
+use perf_event::PerfEvent;
+use alerts::Alert;
+use config::ConfigFile;
+use app_log::AppLogger;
 use app_data::{AppData, AppDataLive, AppDataJson};
 use app::App;
 use plotter::Plotter;
+use tracer::Tracer;
+use differ::Differ;
+use stats_server::StatsServer;
+
+
+// below this, PMU deltas get noisy and /proc scanning dominates
+pub const MIN_MS_INTERVAL: u64 = 100;
 
+// how often a sleeping no TUI loop checks if it was asked to stop
+const STOP_CHECK_INTERVAL: time::Duration = time::Duration::from_millis(50);
 
 /// qmassa! - Display GPUs usage stats
 #[derive(Parser, Clone, Debug, Deserialize, Serialize)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_override_self = true)]
 pub struct CliArgs {
-    /// Show only specific PCI device [default: all devices]
+    /// Config file with default options [default: ~/.config/qmassa/config.toml]
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Show only specific PCI device (slot or vendor:device ids) [default: all devices]
     #[arg(short, long)]
     dev_slot: Option<String>,
 
@@ -43,10 +66,25 @@ pub struct CliArgs {
     #[arg(short, long)]
     pid: Option<String>,
 
+    /// Show only DRM clients from the processes in a cgroup (and its sub cgroups), absolute or relative to /sys/fs/cgroup
+    #[arg(long, conflicts_with = "pid")]
+    #[serde(default)]
+    cgroup: Option<String>,
+
+    /// Show only DRM clients from the children of the process tree base (excluding the --pid process itself)
+    #[arg(long, action = ArgAction::SetTrue, requires = "pid")]
+    #[serde(default)]
+    pid_children_only: bool,
+
     /// Interval between updates in ms
     #[arg(short, long, default_value = "1500")]
     ms_interval: u64,
 
+    /// Interval in ms between fast samples averaged into each update, for more accurate engines usage without more frequent updates [default: no fast samples]
+    #[arg(long, value_name = "MS")]
+    #[serde(default)]
+    sample_interval: Option<u64>,
+
     /// Show all DRM clients [default: only active]
     #[arg(short, long, action = ArgAction::SetTrue)]
     all_clients: bool,
@@ -55,10 +93,176 @@ pub struct CliArgs {
     #[arg(short, long, default_value = "-1")]
     nr_iterations: i32,
 
+    /// Maximum duration of stats updates in seconds [default: no limit]
+    #[arg(short = 'D', long)]
+    max_duration: Option<u64>,
+
+    /// Don't scan processes for DRM clients (only device stats)
+    #[arg(short = 'C', long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    no_clients: bool,
+
+    /// Exclude qmassa and its child processes from DRM clients
+    #[arg(short = 'X', long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    exclude_self: bool,
+
+    /// Device stats not refreshed after the first time, comma-separated (freqs, power, meminfo, perf, pmu, hwmon, displays, sensors)
+    #[arg(long)]
+    #[serde(default)]
+    skip: Option<String>,
+
+    /// Track GPU reset counters and alert when they increase (i915 only, needs root for debugfs)
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    resets: bool,
+
+    /// Label DRM clients from known compositors (and sharing fds with them)
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    label_compositors: bool,
+
+    /// Mark the DRM clients from the focused window's process (best-effort, needs hyprctl, swaymsg or xprop)
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    focused_window: bool,
+
+    /// Hide devices without any stats from the TUI (e.g. no driver support or powered down) [default: show all devices]
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    hide_idle: bool,
+
+    /// Compact TUI layout without charts [default: show charts]
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    compact: bool,
+
+    /// Engines-only TUI layout, no meminfo, freqs or power stats [default: show all stats]
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    engines_only: bool,
+
+    /// Engines to hide in the TUI (comma-separated, e.g. "rcs,bcs") [default: show all engines]
+    #[arg(long)]
+    #[serde(default)]
+    hide_engines: Option<String>,
+
+    /// Engines order in the TUI (hw: hardware order for i915 and Xe, alpha: alphabetical) [default: hw]
+    #[arg(long, value_parser = ["hw", "alpha"])]
+    #[serde(default)]
+    engines_order: Option<String>,
+
+    /// Device engines usage from DRM clients (sum: all clients, max: busiest client, mean: average of clients that used it, idle ones excluded) [default: sum]
+    #[arg(long, value_parser = ["sum", "max", "mean"])]
+    #[serde(default)]
+    engines_aggregation: Option<String>,
+
+    /// Chart selected at start (possible values: freqs, power, meminfo,
+    ///  engines) [default: freqs, or meminfo on DRM client screen]
+    #[arg(long, value_parser = ["freqs", "power", "meminfo", "engines"])]
+    #[serde(default)]
+    select_chart: Option<String>,
+
+    /// Show wall-clock time of day on charts [default: seconds since start]
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    wall_clock: bool,
+
+    /// Stack engines usage as areas in the engines chart [default: lines]
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    stacked_engines: bool,
+
+    /// Units for memory sizes (si: kB/MB/GB, iec: K/M/G in powers of 1024) [default: iec]
+    #[arg(long, value_parser = ["si", "iec"])]
+    #[serde(default)]
+    units: Option<String>,
+
+    /// Show host RAM usage (from /proc/meminfo) in the meminfo chart of integrated GPUs
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    host_mem: bool,
+
+    /// Decimals for power, frequencies and percentages in the TUI and the diff summary, and for the summary JSON values [default: 1 in the TUI, 2 in the diff summary, full precision in the summary JSON]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=6))]
+    #[serde(default)]
+    precision: Option<u8>,
+
+    /// Detect a light terminal background (from COLORFGBG) and adapt the TUI colors to it
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    theme_detect: bool,
+
+    /// DRM clients memory to show (rss: resident, used: total mapped) [default: rss]
+    #[arg(long, value_parser = ["rss", "used"])]
+    #[serde(default)]
+    client_mem: Option<String>,
+
+    /// Names of the timeline markers added with the M key or SIGUSR1, in order (comma-separated) [default: M1, M2, ...]
+    #[arg(long)]
+    #[serde(default)]
+    markers: Option<String>,
+
     /// Save stats to a JSON file
     #[arg(short, long)]
     to_json: Option<String>,
 
+    /// Serve the latest stats as JSON over HTTP on GET /stats (e.g. "127.0.0.1:9400")
+    #[arg(long, value_name = "ADDR:PORT")]
+    #[serde(default)]
+    serve: Option<String>,
+
+    /// Print the resolved options (after config file and defaults), detected devices and their drivers as JSON, then exit
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    print_config: bool,
+
+    /// Append a new run to the JSON file instead of overwriting it
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    json_append: bool,
+
+    /// Write the JSON file as "<file>.tmp" and rename it to the JSON file only on a clean exit
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    json_atomic: bool,
+
+    /// Replace process names and command lines in the JSON file with client-N labels (e.g. to share it)
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    anonymize: bool,
+
+    /// Save summary of device stats to a JSON file at the end
+    #[arg(short = 'S', long)]
+    summary_json: Option<String>,
+
+    /// Alert rule, can be repeated (e.g. "vram>0.9", "power>200",
+    ///  "eng-render>=95", metrics: smem, vram, power, pkg-power, temp, eng-<name>)
+    #[arg(long)]
+    #[serde(default)]
+    alert: Vec<String>,
+
+    /// Alert rule that makes qmassa exit with an error at the end if
+    ///  triggered, can be repeated (same syntax as --alert)
+    #[arg(long)]
+    #[serde(default)]
+    fail_on: Vec<String>,
+
+    /// Ring the terminal bell when an alert is triggered in the TUI
+    #[arg(long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    alert_bell: bool,
+
+    /// Driver option, can be repeated (DRIVER=KEY=VALUE, e.g. "xe=freqs=pmu")
+    #[arg(short = 'o', long)]
+    #[serde(default)]
+    drv_option: Vec<String>,
+
+    /// CPU to open the GPU and RAPL PMU events on (e.g. on multi-socket systems) [default: first CPU in each PMU's cpumask]
+    #[arg(long, value_name = "CPU")]
+    #[serde(default)]
+    pmu_cpu: Option<i32>,
+
     /// File to log to when RUST_LOG is used [default: stderr (if not tty) or qmassa-<pid>.log]
     #[arg(short, long)]
     log_file: Option<String>,
@@ -67,6 +271,11 @@ pub struct CliArgs {
     #[arg(short = 'x', long, action = ArgAction::SetTrue)]
     no_tui: bool,
 
+    /// Don't print informational messages to stdout (no TUI and plot)
+    #[arg(short, long, action = ArgAction::SetTrue)]
+    #[serde(default)]
+    quiet: bool,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -78,24 +287,49 @@ enum Command
     Replay(ReplayArgs),
 
     /// Plot charts from JSON data
-    Plot(PlotArgs)
+    Plot(PlotArgs),
+
+    /// Convert JSON data to a Chrome/Perfetto trace (e.g. for ui.perfetto.dev)
+    Trace(TraceArgs),
+
+    /// Compare two JSON captures (e.g. baseline vs optimized)
+    Diff(DiffArgs),
+
+    /// Print hwmon sensors found for each device as JSON
+    Hwmon(HwmonArgs),
 }
 
 #[derive(Args, Clone, Debug, Deserialize, Serialize)]
 struct ReplayArgs
 {
-    /// Input JSON file
+    /// Input JSON file(s) (comma-separated list or glob pattern)
     #[arg(short, long)]
     json_file: String,
+
+    /// Run to replay from each JSON file (starting at 1) [default: all runs in sequence]
+    #[arg(short, long)]
+    run: Option<usize>,
+
+    /// Replay speed factor (e.g. 2.0 is twice as fast, 0.5 is slow motion) [default: 1.0]
+    #[arg(long)]
+    speed: Option<f64>,
+
+    /// Wait the recorded time between samples instead of the fixed interval
+    #[arg(long, action = ArgAction::SetTrue)]
+    realtime: bool,
 }
 
 #[derive(Args, Clone, Debug, Deserialize, Serialize)]
 struct PlotArgs
 {
-    /// Input JSON file
+    /// Input JSON file(s) (comma-separated list or glob pattern)
     #[arg(short, long)]
     json_file: String,
 
+    /// Run to plot from each JSON file (starting at 1) [default: all runs in sequence]
+    #[arg(short, long)]
+    run: Option<usize>,
+
     /// Prefix for output SVG files
     #[arg(short, long)]
     out_prefix: String,
@@ -110,14 +344,90 @@ struct PlotArgs
     charts: Option<String>,
 }
 
-fn run_replay_cmd(args: ReplayArgs) -> Result<()>
+#[derive(Args, Clone, Debug, Deserialize, Serialize)]
+struct TraceArgs
 {
+    /// Input JSON file(s) (comma-separated list or glob pattern)
+    #[arg(short, long)]
+    json_file: String,
+
+    /// Run to convert from each JSON file (starting at 1) [default: all runs in sequence]
+    #[arg(short, long)]
+    run: Option<usize>,
+
+    /// Output trace JSON file
+    #[arg(short, long)]
+    out_file: String,
+
+    /// Convert only specific PCI device [default: all devices]
+    #[arg(short, long)]
+    dev_slot: Option<String>,
+}
+
+#[derive(Args, Clone, Debug, Deserialize, Serialize)]
+struct DiffArgs
+{
+    /// Input JSON file(s) of each run, baseline first (given twice, each
+    ///  a comma-separated list or glob pattern)
+    #[arg(short, long, required = true)]
+    json_file: Vec<String>,
+
+    /// Run to compare from each JSON file (starting at 1) [default: all runs in sequence]
+    #[arg(short, long)]
+    run: Option<usize>,
+
+    /// Prefix for output SVG files with both runs overlaid [default: print
+    ///  a text summary]
+    #[arg(short, long)]
+    out_prefix: Option<String>,
+
+    /// Compare only specific PCI device [default: all devices in both runs]
+    #[arg(short, long)]
+    dev_slot: Option<String>,
+
+    /// Names of the runs in the summary and chart legends (BASE,NEW)
+    ///  [default: the JSON file names]
+    #[arg(short, long, value_name = "BASE,NEW")]
+    labels: Option<String>,
+}
+
+#[derive(Args, Clone, Debug, Deserialize, Serialize)]
+struct HwmonArgs
+{
+    /// Show only specific PCI device [default: all devices]
+    #[arg(short, long)]
+    dev_slot: Option<String>,
+}
+
+// display options given on the command line override the capture's ones
+fn run_replay_cmd(args: ReplayArgs, cli: &CliArgs) -> Result<()>
+{
+    if args.speed.is_some_and(|sp| !sp.is_finite() || sp <= 0.0) {
+        bail!("Invalid replay speed factor {:?}", args.speed.unwrap());
+    }
+
     // get app data from JSON file
-    let jsondata = AppDataJson::from(&args.json_file)
+    let mut jsondata = AppDataJson::from(&args.json_file, args.run)
         .context("Failed to load data from JSON file")?;
     if jsondata.is_empty() {
         bail!("JSON file is empty!");
     }
+    jsondata.set_replay_pace(args.speed, args.realtime);
+    let jargs = jsondata.args_mut();
+    if cli.units.is_some() {
+        jargs.units = cli.units.clone();
+    }
+    if cli.client_mem.is_some() {
+        jargs.client_mem = cli.client_mem.clone();
+    }
+    if cli.precision.is_some() {
+        jargs.precision = cli.precision;
+    }
+    jargs.theme_detect |= cli.theme_detect;
+    jargs.host_mem |= cli.host_mem;
+    // the capture's own limits would stop the replay before its end
+    jargs.nr_iterations = -1;
+    jargs.max_duration = None;
 
     // create tui app and run the mainloop
     let mut app = App::from(Rc::new(RefCell::new(jsondata)));
@@ -126,44 +436,180 @@ fn run_replay_cmd(args: ReplayArgs) -> Result<()>
     Ok(())
 }
 
-fn run_plot_cmd(args: PlotArgs) -> Result<()>
+fn run_plot_cmd(args: PlotArgs, quiet: bool, si_units: bool) -> Result<()>
 {
-    println!("qmassa: Plotting charts from {:?}", args.json_file);
+    if !quiet {
+        println!("qmassa: Plotting charts from {:?}", args.json_file);
+    }
 
     // get app data from JSON file
-    let jsondata = AppDataJson::from(&args.json_file)
+    let jsondata = AppDataJson::from(&args.json_file, args.run)
         .context("Failed to load data from JSON file")?;
     if jsondata.is_empty() {
         bail!("JSON file is empty!");
     }
 
     // create plotter and plot the charts
+    let dev_slot = json_dev_slot(&jsondata, args.dev_slot)?;
     let plotter = Plotter::from(jsondata,
-        args.out_prefix, args.dev_slot, args.charts)?;
+        args.out_prefix, dev_slot, args.charts, quiet, si_units)?;
     plotter.plot()?;
 
     Ok(())
 }
 
-fn run_notui(mut appdata: AppDataLive) -> Result<()>
+fn run_trace_cmd(args: TraceArgs, quiet: bool) -> Result<()>
 {
-    if appdata.args().to_json.is_none() && appdata.args().log_file.is_none() {
-        println!("qmassa: WARNING: No TUI being rendered but neither \
-            logging nor saving JSON stats are enabled!");
+    if !quiet {
+        println!("qmassa: Converting {:?} to a trace", args.json_file);
+    }
+
+    // get app data from JSON file
+    let jsondata = AppDataJson::from(&args.json_file, args.run)
+        .context("Failed to load data from JSON file")?;
+    if jsondata.is_empty() {
+        bail!("JSON file is empty!");
+    }
+
+    let dev_slot = json_dev_slot(&jsondata, args.dev_slot)?;
+    let tracer = Tracer::from(jsondata, args.out_file, dev_slot, quiet)?;
+    tracer.trace()?;
+
+    Ok(())
+}
+
+fn run_diff_cmd(args: DiffArgs, quiet: bool, prec: Option<u8>) -> Result<()>
+{
+    if args.json_file.len() != 2 {
+        bail!("Diff needs exactly two --json-file, got {}",
+            args.json_file.len());
+    }
+    if !quiet && args.out_prefix.is_some() {
+        println!("qmassa: Plotting {:?} vs {:?}",
+            args.json_file[0], args.json_file[1]);
+    }
+
+    // get app data from both JSON files
+    let mut runs = Vec::new();
+    for jf in args.json_file.iter() {
+        let jsondata = AppDataJson::from(jf, args.run)
+            .with_context(|| format!("Failed to load data from {:?}", jf))?;
+        if jsondata.is_empty() {
+            bail!("JSON file {:?} is empty!", jf);
+        }
+        runs.push(jsondata);
+    }
+    let new_run = runs.pop().unwrap();
+    let base_run = runs.pop().unwrap();
+    // the same device can be in different PCI slots if selected by ids
+    let dev_slots = match args.dev_slot {
+        Some(ds) => Some([
+            json_dev_slot(&base_run, Some(ds.clone()))?.unwrap(),
+            json_dev_slot(&new_run, Some(ds))?.unwrap(),
+        ]),
+        None => None,
+    };
+
+    let differ = Differ::from([base_run, new_run], &args.json_file,
+        args.labels, args.out_prefix, dev_slots, prec, quiet)?;
+    differ.diff()?;
+
+    Ok(())
+}
+
+fn run_hwmon_cmd(args: HwmonArgs) -> Result<()>
+{
+    let qmds = DrmDevices::find_devices(&DrmDriverOptions::new())
+        .context("Failed finding DRM devices")?;
+    if qmds.is_empty() {
+        bail!("No DRM devices found");
+    }
+
+    let dev_slot = match args.dev_slot {
+        Some(dev) if is_vendor_device_ids(&dev) => match qmds.find_by_ids(&dev) {
+            Some(slot) => Some(slot.clone()),
+            None => bail!("No DRM device with vendor:device ids {:?}", dev),
+        },
+        dev_slot => dev_slot,
+    };
+
+    // sensors (type, label, items) of every device, null if no hwmon
+    let mut res = Vec::new();
+    for dn in qmds.devices() {
+        if dev_slot.as_ref().is_some_and(|ds| ds != dn) {
+            continue;
+        }
+        let di = qmds.device_info(dn).unwrap();
+        res.push(serde_json::json!({
+            "pci_dev": dn,
+            "drv_name": di.drv_name,
+            "hwmon": di.hwmon(),
+        }));
+    }
+    if res.is_empty() {
+        bail!("No DRM device {:?} found", dev_slot.unwrap());
     }
 
+    println!("{}", serde_json::to_string_pretty(&res)?);
+
+    Ok(())
+}
+
+// set by SIGUSR1 to add a timeline marker in the no TUI loop
+static MARKER_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn sigusr1_handler(_sig: libc::c_int)
+{
+    MARKER_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+// set by SIGINT/SIGTERM to stop the no TUI loop cleanly
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn sigstop_handler(_sig: libc::c_int)
+{
+    STOP_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+fn notui_loop(appdata: &mut AppDataLive) -> Result<()>
+{
+    let quiet = appdata.args().quiet;
     let ival = time::Duration::from_millis(appdata.args().ms_interval);
+    let sample_ival = appdata.sample_interval_ms()
+        .map(time::Duration::from_millis);
     let max_iterations = appdata.args().nr_iterations;
+    let max_duration = appdata.args().max_duration
+        .map(time::Duration::from_secs);
+
+    let handler = sigusr1_handler as extern "C" fn(libc::c_int);
+    unsafe { libc::signal(libc::SIGUSR1, handler as libc::sighandler_t); }
+    let handler = sigstop_handler as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+    }
 
-    // start saving to JSON file (if requested)
-    appdata.start_json_file()?;
-
-    println!("qmassa: Entering no TUI loop, press Ctrl-C to stop.");
+    if !quiet {
+        println!("qmassa: Entering no TUI loop, press Ctrl-C to stop.");
+    }
+    let start_time = time::Instant::now();
     let mut nr = 0;
+    let mut is_lag_warned = false;
     loop {
         if max_iterations >= 0 && nr == max_iterations {
             break;
         }
+        if max_duration.is_some_and(|md| start_time.elapsed() >= md) {
+            break;
+        }
+        if STOP_REQUESTED.load(Ordering::Relaxed) {
+            break;
+        }
+
+        // add marker requested by SIGUSR1 (if any)
+        if MARKER_REQUESTED.swap(false, Ordering::Relaxed) {
+            appdata.add_marker();
+        }
 
         // refresh stats
         if !appdata.refresh()? {
@@ -171,18 +617,187 @@ fn run_notui(mut appdata: AppDataLive) -> Result<()>
         }
         nr += 1;
 
+        // warn once if refreshing takes longer than the interval
+        if !quiet && !is_lag_warned && appdata.refresh_cost_ms() > ival.as_millis() {
+            eprintln!("qmassa: WARNING: Refresh took {}ms, longer than the \
+                {}ms interval, stats can't keep up.",
+                appdata.refresh_cost_ms(), ival.as_millis());
+            is_lag_warned = true;
+        }
+
         // write new state to JSON file (if needed)
         appdata.update_json_file()?;
 
-        // sleep till next iteration
-        thread::sleep(ival);
+        // report newly triggered alerts
+        for msg in appdata.new_alerts() {
+            eprintln!("qmassa: {}", msg);
+        }
+
+        // sleep till next iteration, waking up early if asked to stop
+        // and taking fast samples in between (if asked)
+        let sleep_end = time::Instant::now() + ival;
+        let mut next_sample = sample_ival.map(|si| time::Instant::now() + si);
+        while !STOP_REQUESTED.load(Ordering::Relaxed) {
+            let now = time::Instant::now();
+            if now >= sleep_end {
+                break;
+            }
+            let mut wait = min(sleep_end - now, STOP_CHECK_INTERVAL);
+            if let (Some(ns), Some(si)) = (next_sample, sample_ival) {
+                if now >= ns {
+                    appdata.sample()?;
+                    // from the end of a slow sample, not a burst of them
+                    next_sample = Some(time::Instant::now() + si);
+                    continue;
+                }
+                wait = min(wait, ns - now);
+            }
+            thread::sleep(wait);
+        }
     }
 
     Ok(())
 }
 
-fn run_default_cmd(args: CliArgs) -> Result<()>
+fn run_notui(mut appdata: AppDataLive) -> Result<()>
+{
+    let quiet = appdata.args().quiet;
+    if !quiet && appdata.args().to_json.is_none() &&
+        appdata.args().log_file.is_none() {
+        println!("qmassa: WARNING: No TUI being rendered but neither \
+            logging nor saving JSON stats are enabled!");
+    }
+
+    // start saving to JSON file (if requested)
+    appdata.start_json_file()?;
+
+    if let Err(err) = notui_loop(&mut appdata) {
+        return Err(appdata.abort_json_file(err));
+    }
+
+    // finish JSON file and save summary of the whole run (if requested)
+    appdata.finish_json_file()?;
+    appdata.save_summary_json()?;
+    if !quiet {
+        if let Some(fname) = &appdata.args().to_json {
+            println!("qmassa: Stats saved to {:?}.", fname);
+        }
+        if let Some(fname) = &appdata.args().summary_json {
+            println!("qmassa: Summary saved to {:?}.", fname);
+        }
+    }
+
+    check_failed_rules(&appdata)
+}
+
+// fail run if any fail-on rule was triggered (e.g. for CI)
+fn check_failed_rules(appdata: &AppDataLive) -> Result<()>
 {
+    if !appdata.failed_rules().is_empty() {
+        bail!("Fail-on rules triggered: {}", appdata.failed_rules().join(", "));
+    }
+
+    Ok(())
+}
+
+fn is_vendor_device_ids(dev: &str) -> bool
+{
+    if let Some((vdr_id, dev_id)) = dev.split_once(':') {
+        return vdr_id.len() == 4 && dev_id.len() == 4 &&
+            vdr_id.chars().all(|c| c.is_ascii_hexdigit()) &&
+            dev_id.chars().all(|c| c.is_ascii_hexdigit());
+    }
+
+    false
+}
+
+// maps vendor:device ids to the PCI slot of the first device with them in
+// the JSON data, as done for live devices
+fn json_dev_slot(jsondata: &AppDataJson,
+    dev_slot: Option<String>) -> Result<Option<String>>
+{
+    match dev_slot {
+        Some(dev) if is_vendor_device_ids(&dev) => match jsondata.find_by_ids(&dev) {
+            Some(slot) => Ok(Some(slot)),
+            None => bail!("No DRM device with vendor:device ids {:?} in \
+                the JSON file (needs one saved by this version or later)", dev),
+        },
+        dev_slot => Ok(dev_slot),
+    }
+}
+
+fn check_ms_interval(ms_interval: u64, quiet: bool) -> Result<()>
+{
+    if ms_interval == 0 {
+        bail!("Interval between updates must be greater than 0ms");
+    }
+    if ms_interval < MIN_MS_INTERVAL {
+        let msg = format!("Interval of {}ms is below {}ms, stats will \
+            be noisy and gathering them costly.", ms_interval, MIN_MS_INTERVAL);
+        if !quiet {
+            eprintln!("qmassa: WARNING: {}", msg);
+        }
+        warn!("{}", msg);
+    }
+
+    Ok(())
+}
+
+// effective settings to reproduce a capture, as saved in the JSON header
+fn print_config(args: &CliArgs,
+    drv_opts: &DrmDriverOptions, qmds: &DrmDevices) -> Result<()>
+{
+    let mut devs = Vec::new();
+    for di in qmds.infos() {
+        let dnodes: Vec<&String> = di.drm_minors.iter()
+            .map(|mi| &mi.devnode).collect();
+        devs.push(serde_json::json!({
+            "pci_dev": di.pci_dev,
+            "vendor_id": di.vendor_id,
+            "device_id": di.device_id,
+            "vdr_dev_rev": format!("{} {} (rev {})",
+                di.vendor, di.device, di.revision),
+            "dev_type": di.dev_type,
+            "drv_name": di.drv_name,
+            "has_driver": di.has_driver(),
+            "dev_nodes": dnodes,
+            "is_external": di.is_external,
+            "eng_source": di.eng_usage_source(),
+            "sources": di.sources,
+            "firmware": di.firmware,
+            "notices": di.notices,
+        }));
+    }
+
+    let res = serde_json::json!({
+        "args": args,
+        "driver_options": drv_opts,
+        "devices": devs,
+    });
+    println!("{}", serde_json::to_string_pretty(&res)?);
+
+    Ok(())
+}
+
+fn run_default_cmd(mut args: CliArgs) -> Result<()>
+{
+    check_ms_interval(args.ms_interval, args.quiet)?;
+    if args.sample_interval.is_some_and(|si| si == 0 || si >= args.ms_interval) {
+        bail!("Interval between fast samples must be greater than 0ms and \
+            less than the {}ms interval between updates", args.ms_interval);
+    }
+
+    // parse alert rules (if any)
+    let mut alerts = Vec::new();
+    for rule in args.alert.iter() {
+        alerts.push(Alert::from(rule).context("Failed to parse alert rule")?);
+    }
+    for rule in args.fail_on.iter() {
+        let mut al = Alert::from(rule).context("Failed to parse fail-on rule")?;
+        al.fail_on = true;
+        alerts.push(al);
+    }
+
     let base_pid: String;
     if args.pid.is_some() {
         base_pid = args.pid.clone().unwrap();
@@ -194,26 +809,79 @@ fn run_default_cmd(args: CliArgs) -> Result<()>
         base_pid = if euid == 0 { String::from("1") } else { String::from("") };
     }
     let no_tui = args.no_tui;
+    let serve_addr = args.serve.clone();
+
+    // parse driver-specific options (if any)
+    let drv_opts = DrmDriverOptions::from(&args.drv_option)
+        .context("Failed to parse driver options")?;
+    if let Some(cpu) = args.pmu_cpu {
+        PerfEvent::set_pmu_cpu(cpu).context("Invalid --pmu-cpu")?;
+    }
 
     // find all DRM subsystem devices
-    let mut qmds = DrmDevices::find_devices()
+    let mut qmds = DrmDevices::find_devices(&drv_opts)
         .context("Failed finding DRM devices")?;
     if qmds.is_empty() {
         bail!("No DRM devices found");
     }
+    // map vendor:device ids to the device PCI slot
+    if let Some(dev) = &args.dev_slot {
+        if is_vendor_device_ids(dev) {
+            if let Some(slot) = qmds.find_by_ids(dev) {
+                args.dev_slot = Some(slot.clone());
+            } else {
+                bail!("No DRM device with vendor:device ids {:?}", dev);
+            }
+        }
+    }
+    // skip refreshing some device stats (if asked)
+    if let Some(skip) = &args.skip {
+        let groups: Vec<String> = skip.split(',')
+            .map(|grp| grp.trim().to_string())
+            .filter(|grp| !grp.is_empty())
+            .collect();
+        qmds.set_skip_stats(&groups)
+            .context("Failed to set skipped device stats")?;
+    }
+    qmds.set_track_resets(args.resets);
+    qmds.set_track_host_mem(args.host_mem);
+    let eng_aggr = match args.engines_aggregation.as_deref() {
+        Some("max") => DrmEngineAggregation::Max,
+        Some("mean") => DrmEngineAggregation::Mean,
+        _ => DrmEngineAggregation::Sum,
+    };
+    qmds.set_engines_aggregation(eng_aggr);
     // get DRM clients from pid process tree starting at base_pid
-    qmds.set_clients_pid_tree(base_pid.as_str())
-        .context("Failed to set DRM clients pid tree")?;
+    if !args.no_clients {
+        if let Some(cgroup) = &args.cgroup {
+            qmds.set_clients_cgroup(cgroup, args.exclude_self)
+                .context("Failed to set DRM clients cgroup")?;
+        } else {
+            qmds.set_clients_pid_tree(base_pid.as_str(), args.exclude_self)
+                .context("Failed to set DRM clients pid tree")?;
+            qmds.set_clients_children_only(args.pid_children_only);
+        }
+    }
+
+    if args.print_config {
+        return print_config(&args, &drv_opts, &qmds);
+    }
 
     // get app data from live system info
-    let appdata = AppDataLive::from(args, qmds);
+    let mut appdata = AppDataLive::from(args, qmds);
+    appdata.set_alerts(alerts);
+    if let Some(addr) = &serve_addr {
+        appdata.set_stats_server(StatsServer::from(addr)?);
+    }
 
     if no_tui {
         run_notui(appdata)?;
     } else {
         // create tui app and run its mainloop
-        let mut app = App::from(Rc::new(RefCell::new(appdata)));
+        let appdata = Rc::new(RefCell::new(appdata));
+        let mut app = App::from(appdata.clone());
         app.run()?;
+        check_failed_rules(&appdata.borrow())?;
     }
 
     Ok(())
@@ -221,17 +889,17 @@ fn run_default_cmd(args: CliArgs) -> Result<()>
 
 fn main() -> Result<()>
 {
-    // parse command-line args
-    let args = CliArgs::parse();
+    // parse command-line args, with defaults from config file (if any)
+    let cli_args = ConfigFile::merge_args(env::args_os().collect())?;
+    let mut args = CliArgs::parse_from(cli_args);
 
     // set up logging for all subcommands (if needed)
+    let mut log_target: Option<env_logger::Logger> = None;
     if env::var_os(env_logger::DEFAULT_FILTER_ENV).is_some() {
         let mut logger = env_logger::Builder::from_default_env();
         let fname: &Path;
 
-        if args.log_file.is_none() && !io::stderr().is_terminal() {
-            logger.init();
-        } else {
+        if args.log_file.is_some() || io::stderr().is_terminal() {
             let mut fnstr: String;
 
             if let Some(log_file) = &args.log_file {
@@ -251,17 +919,30 @@ fn main() -> Result<()>
             let logtarget = Box::new(File::create(fname)
                 .expect("Can't create log file"));
             logger.target(env_logger::Target::Pipe(logtarget));
-            logger.init();
         }
+
+        log_target = Some(logger.build());
     }
+    // always keep recent log records for the TUI log screen
+    AppLogger::init(log_target)?;
 
-    if let Some(cmd) = args.command {
+    if let Some(cmd) = args.command.take() {
         match cmd {
             Command::Replay(cmd_args) => {
-                run_replay_cmd(cmd_args)
+                run_replay_cmd(cmd_args, &args)
             },
             Command::Plot(cmd_args) => {
-                run_plot_cmd(cmd_args)
+                let si_units = args.units.as_deref() == Some("si");
+                run_plot_cmd(cmd_args, args.quiet, si_units)
+            },
+            Command::Trace(cmd_args) => {
+                run_trace_cmd(cmd_args, args.quiet)
+            },
+            Command::Diff(cmd_args) => {
+                run_diff_cmd(cmd_args, args.quiet, args.precision)
+            },
+            Command::Hwmon(cmd_args) => {
+                run_hwmon_cmd(cmd_args)
             },
         }
     } else {
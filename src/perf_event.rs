@@ -3,11 +3,13 @@
 #![allow(non_camel_case_types)]
 #![allow(non_upper_case_globals)]
 
+use std::fs;
 use std::path::Path;
 use std::mem;
 use std::io;
+use std::sync::OnceLock;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::debug;
 use libc;
 
@@ -160,6 +162,14 @@ pub struct perf_event_attr {
     pub config3: u64,
 }
 
+impl Default for perf_event_attr
+{
+    fn default() -> perf_event_attr
+    {
+        perf_event_attr::new()
+    }
+}
+
 impl perf_event_attr {
     #[inline]
     pub fn disabled(&self) -> u64 {
@@ -932,6 +942,9 @@ pub const __NR_perf_event_open: i64 = 336;
         all(not(target_arch = "x86"), not(target_arch = "x86_64"))))]
 pub const __NR_perf_event_open: i64 = 241;
 
+// CPU to open device and uncore PMU events on, if set with --pmu-cpu
+static PMU_CPU: OnceLock<i32> = OnceLock::new();
+
 #[derive(Debug)]
 pub struct PerfEvent
 {
@@ -988,6 +1001,41 @@ impl PerfEvent
         })
     }
 
+    // override the CPU picked from the PMUs' cpumask (once, at start)
+    pub fn set_pmu_cpu(cpu: i32) -> Result<()>
+    {
+        let cpu_dir = format!("/sys/devices/system/cpu/cpu{}", cpu);
+        if cpu < 0 || !Path::new(&cpu_dir).is_dir() {
+            bail!("No CPU {} in the system", cpu);
+        }
+        let online = fs::read_to_string(format!("{}/online", cpu_dir));
+        if online.is_ok_and(|st| st.trim() == "0") {
+            bail!("CPU {} is offline", cpu);
+        }
+
+        if PMU_CPU.set(cpu).is_err() {
+            debug!("ERR: PMU CPU already set, ignoring CPU {}.", cpu);
+        }
+
+        Ok(())
+    }
+
+    // device and uncore PMUs count for the whole device/package from a
+    // single CPU, so always open their events on the same one: the
+    // --pmu-cpu override or the first CPU in the PMU's cpumask
+    pub fn pmu_cpu(pmu_dir: &Path) -> Result<i32>
+    {
+        if let Some(cpu) = PMU_CPU.get() {
+            return Ok(*cpu);
+        }
+
+        let cpumask = fs::read_to_string(pmu_dir.join("cpumask"))?;
+        let cpu: i32 = cpumask.trim().split([',', '-']).next()
+            .unwrap_or("0").parse()?;
+
+        Ok(cpu)
+    }
+
     pub fn is_capable() -> bool
     {
         if !Path::new("/proc/sys/kernel/perf_event_paranoid").exists() {
@@ -1003,4 +1051,18 @@ impl PerfEvent
 
         true
     }
+
+    // actionable reason for not having perf event support (if any)
+    pub fn not_capable_reason() -> Option<String>
+    {
+        if PerfEvent::is_capable() {
+            return None;
+        }
+
+        match std::fs::read_to_string("/proc/sys/kernel/perf_event_paranoid") {
+            Ok(pp) => Some(format!("perf events require root \
+                (perf_event_paranoid is {})", pp.trim())),
+            Err(_) => Some(String::from("no perf events support in the kernel")),
+        }
+    }
 }
@@ -1,9 +1,10 @@
 use std::cmp::max;
+use std::collections::VecDeque;
 
 use anyhow::{bail, Result};
 use plotters::prelude::*;
 
-use crate::app_data::AppDataJson;
+use crate::app_data::{AppDataDeviceState, AppDataJson};
 
 
 #[derive(Debug)]
@@ -11,6 +12,8 @@ struct StatData
 {
     label: String,
     points: Vec<(f64, f64)>,
+    color: Option<usize>,
+    dashed: bool,
 }
 
 impl StatData
@@ -25,10 +28,15 @@ impl StatData
         StatData {
             label: label.to_string(),
             points: Vec::new(),
+            color: None,
+            dashed: false,
         }
     }
 }
 
+// (name, points) of a stat, as compared between runs
+pub type Series = (String, Vec<(f64, f64)>);
+
 const CHART_MEMINFO: usize = 0;
 const CHART_ENGINES: usize = 1;
 const CHART_FREQS: usize = 2;
@@ -42,13 +50,25 @@ pub struct Plotter
     out_prefix: String,
     dev_slot: Option<String>,
     sel_charts: [bool; CHARTS_TOTAL],
+    quiet: bool,
+    si_units: bool,
 }
 
 impl Plotter
 {
-    fn plot_chart(&self, out_file: &str, title: &str,
-        x_desc: &str, y_desc: &str, x_max: f64, y_max: f64,
-        datasets: &Vec<StatData>) -> Result<()>
+    // divisor and unit for the memory charts' values
+    fn mem_unit(si_units: bool) -> (f64, &'static str)
+    {
+        if si_units {
+            (1000.0 * 1000.0, "MB")
+        } else {
+            (1024.0 * 1024.0, "MiB")
+        }
+    }
+
+    fn plot_chart(out_file: &str, title: &str,
+        x_desc: &str, y_desc: &str, x_range: (f64, f64), y_max: f64,
+        datasets: &Vec<StatData>, markers: &Vec<(f64, String)>) -> Result<()>
     {
         let root = SVGBackend::new(out_file, (1200, 720))
             .into_drawing_area();
@@ -59,7 +79,7 @@ impl Plotter
             .set_label_area_size(LabelAreaPosition::Left, (8).percent())
             .set_label_area_size(LabelAreaPosition::Bottom, (4).percent())
             .margin((1).percent())
-            .build_cartesian_2d(0.0..x_max, 0.0..y_max)?;
+            .build_cartesian_2d(x_range.0..x_range.1, 0.0..y_max)?;
         chart
             .configure_mesh()
             .x_desc(x_desc)
@@ -67,23 +87,50 @@ impl Plotter
             .draw()?;
 
         for (idx, ds) in datasets.iter().enumerate() {
-            let color = Palette99::pick(idx).mix(0.9);
-            chart
-                .draw_series(LineSeries::new(
+            let color = Palette99::pick(ds.color.unwrap_or(idx)).mix(0.9);
+            let anno = if ds.dashed {
+                chart.draw_series(DashedLineSeries::new(
+                    ds.points.iter().copied(), 10, 6,
+                    color.stroke_width(3)))?
+            } else {
+                chart.draw_series(LineSeries::new(
                     ds.points.iter().map(|&pt| pt),
                     color.stroke_width(3)))?
+            };
+            // hollow legend boxes for the dashed series
+            let filled = !ds.dashed;
+            anno
                 .label(&ds.label)
                 .legend(move |(x, y)| Rectangle::new([(x, y - 5),
-                    (x + 10, y + 5)], color.filled()));
+                    (x + 10, y + 5)], if filled {
+                        color.filled()
+                    } else {
+                        color.stroke_width(2)
+                    }));
         }
         chart.configure_series_labels().border_style(BLACK).draw()?;
 
+        // vertical lines with names for the timeline markers
+        for (x, name) in markers.iter() {
+            let color = BLACK.mix(0.6);
+            chart.draw_series(std::iter::once(PathElement::new(
+                vec![(*x, 0.0), (*x, y_max)], color.stroke_width(2))))?;
+            chart.draw_series(std::iter::once(Text::new(name.clone(),
+                (*x, y_max), ("sans-serif", 16).into_font().color(&color))))?;
+        }
+
         root.present()?;
-        println!("qmassa: Chart {:?} saved to {:?}", title, out_file);
 
         Ok(())
     }
 
+    fn report_saved(&self, title: &str, out_file: &str)
+    {
+        if !self.quiet {
+            println!("qmassa: Chart {:?} saved to {:?}", title, out_file);
+        }
+    }
+
     // TODO: figure out plotting DRM client stats charts
     pub fn plot(&self) -> Result<()>
     {
@@ -92,6 +139,7 @@ impl Plotter
         let plot_engines = self.sel_charts[CHART_ENGINES];
         let plot_freqs = self.sel_charts[CHART_FREQS];
         let plot_power = self.sel_charts[CHART_POWER];
+        let (mem_div, mem_unit) = Plotter::mem_unit(self.si_units);
         let nr_devices = self.jsondata
             .states().front().unwrap().devs_state.len();
 
@@ -106,12 +154,17 @@ impl Plotter
             let mut freqs: Vec<Vec<StatData>> = Vec::new();
             let mut power: Vec<StatData> = Vec::new();
             let mut max_power = 0.0;
+            let has_gtt = di.dev_stats.mem_info.back()
+                .is_some_and(|mi| mi.has_gtt());
 
             if plot_meminfo {
                 meminfo.push(StatData::new("SMEM"));
                 if di.dev_type.is_discrete() {
                     meminfo.push(StatData::new("VRAM"));
                 }
+                if has_gtt {
+                    meminfo.push(StatData::new("GTT"));
+                }
             }
             if plot_engines {
                 for en in di.eng_names.iter() {
@@ -140,10 +193,14 @@ impl Plotter
                 if plot_meminfo {
                     let mi = dinfo.dev_stats.mem_info.back().unwrap();
                     meminfo[0].add_point((tstamp,
-                        mi.smem_used as f64 / (1024.0 * 1024.0)));
+                        mi.smem_used as f64 / mem_div));
                     if dinfo.dev_type.is_discrete() {
                         meminfo[1].add_point((tstamp,
-                            mi.vram_used as f64 / (1024.0 * 1024.0)));
+                            mi.vram_used as f64 / mem_div));
+                    }
+                    if has_gtt {
+                        meminfo.last_mut().unwrap().add_point((tstamp,
+                            mi.gtt_used as f64 / mem_div));
                     }
                 }
                 if plot_engines {
@@ -174,6 +231,17 @@ impl Plotter
                 }
             }
 
+            // markers from all stats windows, each only once
+            let mut markers: Vec<(f64, String)> = Vec::new();
+            for state in self.jsondata.states().iter() {
+                for (ts, name) in state.markers.iter() {
+                    let mkr = (*ts as f64 / 1000.0, name.clone());
+                    if !markers.contains(&mkr) {
+                        markers.push(mkr);
+                    }
+                }
+            }
+
             let last_state = self.jsondata.states().back().unwrap();
             let x_max = *last_state.timestamps.back().unwrap() as f64 / 1000.0;
 
@@ -181,48 +249,220 @@ impl Plotter
                 let out_file = format!("{}-{}-meminfo.svg",
                     &self.out_prefix, &di.pci_dev);
                 let mi = di.dev_stats.mem_info.back().unwrap();
-                let y_max = max(mi.smem_total, mi.vram_total) as f64 /
-                    (1024.0 * 1024.0);
-                self.plot_chart(
-                    &out_file, &format!("{} - Memory Info", &di.vdr_dev_rev),
-                    "Time (s)", "Memory used (MiB)",
-                    x_max, y_max, &meminfo)?;
+                let y_max = max(max(mi.smem_total, mi.vram_total),
+                    mi.gtt_total) as f64 / mem_div;
+                let title = format!("{} - Memory Info", &di.vdr_dev_rev);
+                Plotter::plot_chart(&out_file, &title,
+                    "Time (s)", &format!("Memory used ({})", mem_unit),
+                    (0.0, x_max), y_max, &meminfo, &markers)?;
+                self.report_saved(&title, &out_file);
             }
             if plot_engines {
                 let out_file = format!("{}-{}-engines.svg",
                     &self.out_prefix, &di.pci_dev);
-                self.plot_chart(
-                    &out_file, &format!("{} - Engines Usage", &di.vdr_dev_rev),
+                let title = format!("{} - {}", &di.vdr_dev_rev,
+                    di.eng_usage_title("Engines Usage"));
+                Plotter::plot_chart(&out_file, &title,
                     "Time (s)", "Usage (%)",
-                    x_max, 100.0, &engines)?;
+                    (0.0, x_max), 100.0, &engines, &markers)?;
+                self.report_saved(&title, &out_file);
             }
             if plot_freqs {
                 for (nr, fl) in di.freq_limits.iter().enumerate() {
                     let out_file = format!("{}-{}-freqs-{}.svg",
                         &self.out_prefix, &di.pci_dev, &fl.name);
-                    self.plot_chart(
-                        &out_file,
-                        &format!("{} - {} Frequencies",
-                            &di.vdr_dev_rev, &fl.name.to_uppercase()),
+                    let title = format!("{} - {} Frequencies",
+                        &di.vdr_dev_rev, &fl.name.to_uppercase());
+                    Plotter::plot_chart(&out_file, &title,
                         "Time (s)", "Frequency (MHz)",
-                        x_max, fl.maximum as f64, &freqs[nr])?;
+                        (0.0, x_max), fl.maximum as f64, &freqs[nr],
+                        &markers)?;
+                    self.report_saved(&title, &out_file);
                 }
             }
             if plot_power {
                 let out_file = format!("{}-{}-power.svg",
                     &self.out_prefix, &di.pci_dev);
-                self.plot_chart(
-                    &out_file, &format!("{} - Power Usage", &di.vdr_dev_rev),
+                let title = format!("{} - Power Usage", &di.vdr_dev_rev);
+                Plotter::plot_chart(&out_file, &title,
                     "Time (s)", "Power (W)",
-                    x_max, max_power, &power)?;
+                    (0.0, x_max), max_power, &power, &markers)?;
+                self.report_saved(&title, &out_file);
             }
         }
 
         Ok(())
     }
 
+    // plots all charts for the in-memory stats window of a device (as seen
+    // in the TUI), returning the names of the saved files
+    pub fn plot_device_window(out_prefix: &str, di: &AppDataDeviceState,
+        tstamps: &VecDeque<u128>, mkrs: &Vec<(u128, String)>,
+        si_units: bool) -> Result<Vec<String>>
+    {
+        if tstamps.is_empty() {
+            bail!("No stats to plot for device {:?}", &di.pci_dev);
+        }
+
+        // stats windows can be shorter than the timestamps one
+        let x_vals: Vec<f64> = tstamps.iter()
+            .map(|ts| *ts as f64 / 1000.0).collect();
+        let x_at = |nr_vals: usize, idx: usize| -> f64 {
+            x_vals[x_vals.len().saturating_sub(nr_vals) + idx]
+        };
+        let x_range = (x_vals[0], f64::max(x_vals[x_vals.len() - 1],
+            x_vals[0] + 1.0));
+        let markers: Vec<(f64, String)> = mkrs.iter()
+            .map(|(ts, name)| (*ts as f64 / 1000.0, name.clone()))
+            .collect();
+        let dstats = &di.dev_stats;
+        let (mem_div, mem_unit) = Plotter::mem_unit(si_units);
+        let mut out_files = Vec::new();
+
+        // memory info
+        let has_gtt = dstats.mem_info.back().is_some_and(|mi| mi.has_gtt());
+        let mut meminfo = vec![StatData::new("SMEM")];
+        if di.dev_type.is_discrete() {
+            meminfo.push(StatData::new("VRAM"));
+        }
+        if has_gtt {
+            meminfo.push(StatData::new("GTT"));
+        }
+        let nr_vals = dstats.mem_info.len();
+        for (idx, mi) in dstats.mem_info.iter().enumerate() {
+            let xval = x_at(nr_vals, idx);
+            meminfo[0].add_point((xval, mi.smem_used as f64 / mem_div));
+            if di.dev_type.is_discrete() {
+                meminfo[1].add_point((xval, mi.vram_used as f64 / mem_div));
+            }
+            if has_gtt {
+                meminfo.last_mut().unwrap()
+                    .add_point((xval, mi.gtt_used as f64 / mem_div));
+            }
+        }
+        if let Some(mi) = dstats.mem_info.back() {
+            let out_file = format!("{}-meminfo.svg", out_prefix);
+            let y_max = max(max(mi.smem_total, mi.vram_total),
+                mi.gtt_total) as f64 / mem_div;
+            Plotter::plot_chart(&out_file,
+                &format!("{} - Memory Info", &di.vdr_dev_rev),
+                "Time (s)", &format!("Memory used ({})", mem_unit),
+                x_range, f64::max(y_max, 1.0), &meminfo, &markers)?;
+            out_files.push(out_file);
+        }
+
+        // engines usage
+        if !di.eng_names.is_empty() {
+            let mut engines = Vec::new();
+            for en in di.eng_names.iter() {
+                let mut sd = StatData::new(&en.to_uppercase());
+                if let Some(eu) = dstats.eng_usage.get(en) {
+                    for (idx, val) in eu.iter().enumerate() {
+                        sd.add_point((x_at(eu.len(), idx), *val));
+                    }
+                }
+                engines.push(sd);
+            }
+            let out_file = format!("{}-engines.svg", out_prefix);
+            Plotter::plot_chart(&out_file,
+                &format!("{} - {}", &di.vdr_dev_rev,
+                    di.eng_usage_title("Engines Usage")),
+                "Time (s)", "Usage (%)",
+                x_range, 100.0, &engines, &markers)?;
+            out_files.push(out_file);
+        }
+
+        // frequencies
+        for (nr, fl) in di.freq_limits.iter().enumerate() {
+            let mut freqs = vec![
+                StatData::new("MIN"),
+                StatData::new("MAX"),
+                StatData::new("REQ"),
+                StatData::new("ACT"),
+            ];
+            let nr_vals = dstats.freqs.len();
+            for (idx, fqs) in dstats.freqs.iter().enumerate() {
+                if nr >= fqs.len() {
+                    continue;
+                }
+                let xval = x_at(nr_vals, idx);
+                freqs[0].add_point((xval, fqs[nr].min_freq as f64));
+                freqs[1].add_point((xval, fqs[nr].max_freq as f64));
+                freqs[2].add_point((xval, fqs[nr].cur_freq as f64));
+                freqs[3].add_point((xval, fqs[nr].act_freq as f64));
+            }
+            let out_file = format!("{}-freqs-{}.svg", out_prefix, &fl.name);
+            Plotter::plot_chart(&out_file,
+                &format!("{} - {} Frequencies",
+                    &di.vdr_dev_rev, &fl.name.to_uppercase()),
+                "Time (s)", "Frequency (MHz)",
+                x_range, f64::max(fl.maximum as f64, 1.0), &freqs,
+                &markers)?;
+            out_files.push(out_file);
+        }
+
+        // power
+        let mut power = vec![StatData::new("GPU"), StatData::new("PKG")];
+        let mut max_power: f64 = 1.0;
+        let nr_vals = dstats.power.len();
+        for (idx, pwr) in dstats.power.iter().enumerate() {
+            let xval = x_at(nr_vals, idx);
+            max_power = f64::max(max_power, pwr.gpu_cur_power);
+            max_power = f64::max(max_power, pwr.pkg_cur_power);
+            power[0].add_point((xval, pwr.gpu_cur_power));
+            power[1].add_point((xval, pwr.pkg_cur_power));
+        }
+        if nr_vals > 0 {
+            let out_file = format!("{}-power.svg", out_prefix);
+            Plotter::plot_chart(&out_file,
+                &format!("{} - Power Usage", &di.vdr_dev_rev),
+                "Time (s)", "Power (W)",
+                x_range, max_power, &power, &markers)?;
+            out_files.push(out_file);
+        }
+
+        Ok(out_files)
+    }
+
+    // plots the same stats of two runs on one chart, the first run with
+    // solid lines and the second one dashed, with matching colors
+    pub fn plot_overlay(out_file: &str, title: &str, y_desc: &str,
+        x_max: f64, y_max: f64, runs: [(&str, &[Series]); 2],
+        markers: &Vec<(f64, String)>) -> Result<()>
+    {
+        let (base_label, base) = runs[0];
+        let (new_label, new) = runs[1];
+
+        let mut datasets = Vec::new();
+        for (idx, (name, pts)) in base.iter().enumerate() {
+            let mut sd = StatData::new(&format!("{} {}", base_label, name));
+            sd.points = pts.clone();
+            sd.color = Some(idx);
+            datasets.push(sd);
+        }
+        let mut nr_extra = 0;
+        for (name, pts) in new.iter() {
+            let mut sd = StatData::new(&format!("{} {}", new_label, name));
+            sd.points = pts.clone();
+            sd.color = match base.iter().position(|(nm, _)| nm == name) {
+                Some(idx) => Some(idx),
+                None => {
+                    nr_extra += 1;
+                    Some(base.len() + nr_extra - 1)
+                },
+            };
+            sd.dashed = true;
+            datasets.push(sd);
+        }
+
+        Plotter::plot_chart(out_file, title, "Time (s)", y_desc,
+            (0.0, f64::max(x_max, 1.0)), f64::max(y_max, 1.0),
+            &datasets, markers)
+    }
+
     pub fn from(jsondata: AppDataJson, out_prefix: String,
-        dev_slot: Option<String>, charts_opt: Option<String>) -> Result<Plotter>
+        dev_slot: Option<String>, charts_opt: Option<String>,
+        quiet: bool, si_units: bool) -> Result<Plotter>
     {
         if let Some(dev) = &dev_slot {
             let mut valid = false;
@@ -262,6 +502,8 @@ impl Plotter
             out_prefix,
             dev_slot,
             sel_charts,
+            quiet,
+            si_units,
         })
     }
 }
@@ -8,7 +8,7 @@ use anyhow::Result;
 use log::{debug, warn};
 use libc;
 
-use crate::drm_fdinfo::DrmFdinfo;
+use crate::drm_fdinfo::{DrmFdinfo, DrmFdinfoCache};
 
 
 thread_local! {
@@ -140,7 +140,8 @@ impl ProcInfo
         Ok(chids)
     }
 
-    pub fn drm_fdinfos(&self) -> Result<Vec<DrmFdinfo>>
+    // cache has the process' last DRM fdinfos (see DrmFdinfoCache)
+    pub fn drm_fdinfos(&self, cache: &mut DrmFdinfoCache) -> Result<Vec<DrmFdinfo>>
     {
         let mut res: Vec<DrmFdinfo> = Vec::new();
         let fddir = self.proc_dir.join("fd");
@@ -161,19 +162,13 @@ impl ProcInfo
             }
 
             let fipath = fdinfodir.join(et.path().file_name().unwrap());
-            let finfo = DrmFdinfo::from(&fipath, mn);
+            let finfo = DrmFdinfo::from_cached(&fipath, mn, cache);
             if let Err(err) = finfo {
                 debug!("ERR: failed to parse DRM fdinfo {:?}: {:?}", fipath, err);
                 continue;
             }
             let finfo = finfo.unwrap();
 
-            if finfo.pci_dev.is_empty() {
-                debug!("INF: DRM fdinfo {:?} with no PCI dev, ignoring.",
-                    fipath);
-                continue;
-            }
-
             res.push(finfo);
         }
 
@@ -0,0 +1,95 @@
+use std::io;
+use std::num::{ParseFloatError, ParseIntError};
+
+use thiserror::Error;
+
+
+// errors returned by the public qmlib APIs (DrmDevices and DrmDriver), so
+// embedders can match on the kind of failure
+#[derive(Debug, Error)]
+pub enum QmError
+{
+    // DRM driver ioctl() failed
+    #[error("ioctl() failed: {0}")]
+    IoctlFailed(#[source] io::Error),
+    // PMU or its events not available
+    #[error("No PMU support: {0}")]
+    NoPmu(String),
+    // unexpected sysfs/procfs/fdinfo data
+    #[error("Parse error: {0}")]
+    ParseError(String),
+    #[error("Parse error: {0}")]
+    ParseInt(#[from] ParseIntError),
+    #[error("Parse error: {0}")]
+    ParseFloat(#[from] ParseFloatError),
+    // usually needs root or CAP_PERFMON
+    #[error("Permission denied: {0}")]
+    PermissionDenied(#[source] io::Error),
+    #[error("I/O error: {0}")]
+    Io(#[source] io::Error),
+    // DRM driver not supported by qmassa
+    #[error("Unsupported DRM driver: {0:?}")]
+    UnsupportedDriver(String),
+    #[error("{0:#}")]
+    Other(#[source] anyhow::Error),
+}
+
+pub type QmResult<T> = std::result::Result<T, QmError>;
+
+impl From<io::Error> for QmError
+{
+    fn from(err: io::Error) -> QmError
+    {
+        if err.kind() == io::ErrorKind::PermissionDenied {
+            QmError::PermissionDenied(err)
+        } else {
+            QmError::Io(err)
+        }
+    }
+}
+
+// internal helpers return anyhow errors, keep their kind if they wrap a
+// QmError or an io::Error (e.g. opening a devnode without permission)
+impl From<anyhow::Error> for QmError
+{
+    fn from(err: anyhow::Error) -> QmError
+    {
+        let err = match err.downcast::<QmError>() {
+            Ok(qerr) => return qerr,
+            Err(err) => err,
+        };
+
+        match err.downcast_ref::<io::Error>() {
+            Some(ioerr) if err.chain().count() > 1 => {
+                io::Error::new(ioerr.kind(), format!("{:#}", err)).into()
+            },
+            Some(_) => err.downcast::<io::Error>().unwrap().into(),
+            None => QmError::Other(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn from_anyhow_keeps_kind()
+    {
+        let err: anyhow::Error = QmError::NoPmu(String::from("no events")).into();
+        assert!(matches!(QmError::from(err), QmError::NoPmu(_)));
+
+        let res: anyhow::Result<()> =
+            Err(io::Error::from(io::ErrorKind::PermissionDenied).into());
+        let err = res.context("Failed to open \"/dev/dri/card0\"").unwrap_err();
+        let qerr = QmError::from(err);
+        assert!(matches!(qerr, QmError::PermissionDenied(_)));
+        assert!(qerr.to_string().contains("/dev/dri/card0"));
+
+        let err = anyhow::anyhow!("something else");
+        assert!(matches!(QmError::from(err), QmError::Other(_)));
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time;
+
+use anyhow::{Context, Result};
+use log::debug;
+
+
+// slow or idle clients can't block the ones after them
+const CLIENT_TIMEOUT_SECS: u64 = 2;
+
+// serves the latest stats state as JSON on GET /stats, from its own thread
+#[derive(Debug)]
+pub struct StatsServer
+{
+    latest: Arc<Mutex<String>>,
+}
+
+impl StatsServer
+{
+    fn response(status: &str, ctype: &str, body: &str) -> String
+    {
+        format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+            Connection: close\r\n\r\n{}", status, ctype, body.len(), body)
+    }
+
+    fn handle_client(stream: TcpStream, latest: &Arc<Mutex<String>>) -> Result<()>
+    {
+        let tmout = Some(time::Duration::from_secs(CLIENT_TIMEOUT_SECS));
+        stream.set_read_timeout(tmout)?;
+        stream.set_write_timeout(tmout)?;
+
+        // only the request line matters, e.g. "GET /stats HTTP/1.1"
+        let mut reader = BufReader::new(&stream);
+        let mut req_line = String::new();
+        reader.read_line(&mut req_line)?;
+        let mut req = req_line.split_whitespace();
+        let (method, path) = (req.next(), req.next());
+
+        let resp = match (method, path) {
+            (Some("GET"), Some("/stats")) => {
+                let body = latest.lock().unwrap();
+                if body.is_empty() {
+                    StatsServer::response("503 Service Unavailable",
+                        "text/plain", "No stats yet\n")
+                } else {
+                    StatsServer::response("200 OK",
+                        "application/json", &body)
+                }
+            },
+            (Some("GET"), _) => StatsServer::response("404 Not Found",
+                "text/plain", "Only /stats is available\n"),
+            _ => StatsServer::response("405 Method Not Allowed",
+                "text/plain", "Only GET is supported\n"),
+        };
+
+        let mut stream = stream;
+        stream.write_all(resp.as_bytes())?;
+        stream.flush()?;
+
+        Ok(())
+    }
+
+    pub fn update(&self, json_state: String)
+    {
+        *self.latest.lock().unwrap() = json_state;
+    }
+
+    pub fn from(addr: &str) -> Result<StatsServer>
+    {
+        let listener = TcpListener::bind(addr)
+            .with_context(|| format!("Failed to listen on {:?}", addr))?;
+        let latest = Arc::new(Mutex::new(String::new()));
+
+        let srv_latest = latest.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let res = stream.map_err(|err| err.into())
+                    .and_then(|st| StatsServer::handle_client(st, &srv_latest));
+                if let Err(err) = res {
+                    debug!("ERR: failed to serve stats client: {:?}", err);
+                }
+            }
+        });
+
+        Ok(StatsServer {
+            latest,
+        })
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+
+use crate::app_data::AppDataJson;
+
+
+// DRM client active period being built, as (start ts, last active ts)
+type ClientPeriod = (u128, u128);
+
+#[derive(Debug)]
+pub struct Tracer
+{
+    jsondata: AppDataJson,
+    out_file: String,
+    dev_slot: Option<String>,
+    quiet: bool,
+}
+
+impl Tracer
+{
+    fn metadata_event(name: &str, pid: usize, tid: usize, val: &str) -> Value
+    {
+        json!({
+            "name": name,
+            "ph": "M",
+            "pid": pid,
+            "tid": tid,
+            "args": { "name": val },
+        })
+    }
+
+    fn counter_event(name: &str, pid: usize, ts: u128, vals: Value) -> Value
+    {
+        json!({
+            "name": name,
+            "ph": "C",
+            "pid": pid,
+            "ts": ts * 1000,    // in us
+            "args": vals,
+        })
+    }
+
+    fn client_slice(name: &str, pid: usize, tid: usize, period: ClientPeriod) -> Value
+    {
+        json!({
+            "name": name,
+            "cat": "drm-client",
+            "ph": "X",
+            "pid": pid,
+            "tid": tid,
+            "ts": period.0 * 1000,
+            "dur": (period.1 - period.0) * 1000,
+        })
+    }
+
+    // one process per device with its counter tracks, and one thread per
+    // DRM client with slices for the periods when it was using engines
+    fn device_events(&self, idx: usize, events: &mut Vec<Value>)
+    {
+        let pid = idx + 1;
+        let states = self.jsondata.states();
+        let di = &states.front().unwrap().devs_state[idx];
+        events.push(Tracer::metadata_event("process_name", pid, 0,
+            &format!("{} ({})", &di.pci_dev, &di.drv_name)));
+
+        let mut cli_tids: HashMap<(u32, u32, u32), usize> = HashMap::new();
+        let mut cli_periods: HashMap<usize, ClientPeriod> = HashMap::new();
+
+        for state in states.iter() {
+            let ts = *state.timestamps.back().unwrap();
+            let dinfo = &state.devs_state[idx];
+            let dstats = &dinfo.dev_stats;
+
+            let mut engs = serde_json::Map::new();
+            for en in dinfo.eng_names.iter() {
+                if let Some(eu) = dstats.eng_usage.get(en).and_then(|v| v.back()) {
+                    engs.insert(en.clone(), json!(eu));
+                }
+            }
+            if !engs.is_empty() {
+                events.push(Tracer::counter_event(
+                    &dinfo.eng_usage_title("Engines (%)"), pid, ts, Value::Object(engs)));
+            }
+
+            if let Some(fqs) = dstats.freqs.back() {
+                for (nr, fq) in fqs.iter().enumerate() {
+                    let fq_name = match dinfo.freq_limits.get(nr) {
+                        Some(fql) if !fql.name.is_empty() => fql.name.to_uppercase(),
+                        _ => nr.to_string(),
+                    };
+                    events.push(Tracer::counter_event(
+                        &format!("Freqs {} (MHz)", fq_name), pid, ts, json!({
+                            "act": fq.act_freq,
+                            "req": fq.cur_freq,
+                        })));
+                }
+            }
+
+            if let Some(pwr) = dstats.power.back() {
+                events.push(Tracer::counter_event("Power (W)", pid, ts, json!({
+                    "gpu": pwr.gpu_cur_power,
+                    "pkg": pwr.pkg_cur_power,
+                })));
+            }
+
+            for cli in dinfo.clis_stats.iter() {
+                let nr_tids = cli_tids.len();
+                let tid = *cli_tids
+                    .entry((cli.drm_minor, cli.client_id, cli.pid))
+                    .or_insert_with(|| {
+                        events.push(Tracer::metadata_event("thread_name",
+                            pid, nr_tids + 1, &cli.command()));
+                        nr_tids + 1
+                    });
+
+                if cli.engine_active {
+                    cli_periods.entry(tid)
+                        .and_modify(|prd| prd.1 = ts)
+                        .or_insert((ts, ts));
+                } else if let Some(prd) = cli_periods.remove(&tid) {
+                    // ends when it was first seen without engines activity
+                    events.push(Tracer::client_slice("active",
+                        pid, tid, (prd.0, ts)));
+                }
+            }
+        }
+
+        // close periods still open at the end of the capture
+        for (tid, prd) in cli_periods.into_iter() {
+            events.push(Tracer::client_slice("active", pid, tid, prd));
+        }
+    }
+
+    pub fn trace(&self) -> Result<()>
+    {
+        let nr_devices = self.jsondata
+            .states().front().unwrap().devs_state.len();
+
+        let mut events = Vec::new();
+        for idx in 0..nr_devices {
+            let di = &self.jsondata.states().front().unwrap().devs_state[idx];
+            if self.dev_slot.as_ref().is_some_and(|ds| *ds != di.pci_dev) {
+                continue;
+            }
+            self.device_events(idx, &mut events);
+        }
+
+        let nr_events = events.len();
+        let trace = json!({
+            "traceEvents": events,
+            "displayTimeUnit": "ms",
+        });
+        let mut tf = File::create(&self.out_file)?;
+        serde_json::to_writer(&mut tf, &trace)?;
+        writeln!(tf)?;
+
+        if !self.quiet {
+            println!("qmassa: Trace with {} events saved to {:?}",
+                nr_events, &self.out_file);
+        }
+
+        Ok(())
+    }
+
+    pub fn from(jsondata: AppDataJson, out_file: String,
+        dev_slot: Option<String>, quiet: bool) -> Result<Tracer>
+    {
+        if let Some(dev) = &dev_slot {
+            let valid = jsondata.states().front().unwrap().devs_state.iter()
+                .any(|d| d.pci_dev == *dev);
+            if !valid {
+                bail!("No DRM GPU device {:?} in the JSON file", dev);
+            }
+        }
+
+        Ok(Tracer {
+            jsondata,
+            out_file,
+            dev_slot,
+            quiet,
+        })
+    }
+}